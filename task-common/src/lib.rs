@@ -1,15 +1,253 @@
 #![no_std]
 pub static ARGV_LEN: usize = 32;
-pub static ARGV_OFFSET: usize = 4;
+// argv entries per record. Each slot costs ARGV_LEN + 9 bytes in every record (including the
+// continuations, which are full ExecEvents), 16 keeps an event around 1.5 KiB. That is too big
+// for the 512 byte BPF stack, the probe assembles it in the per-CPU EVENT_SCRATCH map instead
+pub static ARGV_OFFSET: usize = 16;
 pub static COMMAND_LEN: usize = 64;
+// a container runtime's `<prefix>-<64 hex id>.scope` doesn't always fit, see ExecEvent::cgroup_name
+pub static CGROUP_NAME_LEN: usize = 72;
+pub static COMM_LEN: usize = 16;
+pub static CWD_LEN: usize = 128;
+pub static ENV_LEN: usize = 128;
+pub static ENV_PREFIX_LEN: usize = 32;
+pub static MAX_ENV_MATCHES: usize = 4;
+pub static MAX_ENV_PREFIXES: usize = 4;
+// entries of EXCLUDED_PREFIXES, exclude_prefixes in the config file
+pub static MAX_EXCLUDED_PREFIXES: usize = 16;
+// entries of EXCLUDED_UIDS, --exclude-uid and PUT /exclusions/uid/:uid
+pub static MAX_EXCLUDED_UIDS: usize = 10;
+// entries of EXCLUDED_PIDS, PUT /exclusions/pid/:pid
+pub static MAX_EXCLUDED_PIDS: usize = 64;
+// entries of EXCLUDED_ROOTS, POST /exclusions/subtree/:pid
+pub static MAX_EXCLUDED_ROOTS: usize = 16;
+// entries of EXCLUDED_DESCENDANTS, the processes (and threads) below those roots
+pub static MAX_EXCLUDED_DESCENDANTS: usize = 8192;
+// ExecEvent::syscall
+pub const SYSCALL_EXECVE: u32 = 0;
+pub const SYSCALL_EXECVEAT: u32 = 1;
+// sched_process_exec doesn't tell which of the two it was
+pub const SYSCALL_UNKNOWN: u32 = 2;
+// ExecEvent::source, the tracepoint the event came from
+pub const SOURCE_SYS_ENTER: u32 = 0;
+pub const SOURCE_SCHED_EXEC: u32 = 1;
+pub static MAX_ARGC: usize = 64;
+// per-CPU buffer the probe reads each argument into to measure it, see ExecEvent::args_total_len
+pub static ARG_SCRATCH_LEN: usize = 4096;
 
+// Flags are u8, 1 for set and 0 otherwise, rather than bool: user space copies records out of the
+// ring buffer as they are, and any byte is a valid u8 but only 0 and 1 are valid bools
 #[repr(C)]
 #[derive(Clone)]
 pub struct ExecEvent {
-    pub pid: u32,
+    // process (thread group) id and id of the thread calling exec, they differ for an exec from
+    // a thread other than the main one
+    pub tgid: u32,
+    pub tid: u32,
+    pub ppid: u32,
+    // real ids, from bpf_get_current_uid_gid()
+    pub uid: u32,
+    pub gid: u32,
+    // effective ids, from current->cred
+    pub euid: u32,
+    pub egid: u32,
+    // audit login uid (current->loginuid), kept across su/sudo. AUDIT_UID_UNSET (u32::MAX) if
+    // there was no login, audit is disabled or it can't be read
+    pub loginuid: u32,
+    pub cgroup_id: u64,
+    // leaf directory name of the cgroup v2 cgroup (e.g. `docker-<id>.scope`), NUL padded. A name
+    // that doesn't fit is reread from after its last '-', which drops the runtime prefix and keeps
+    // the container id. All zeros if it can't be read
+    pub cgroup_name: [u8; CGROUP_NAME_LEN],
+    // inode of the pid namespace and the pid as seen inside it
+    pub pidns: u32,
+    pub ns_pid: u32,
+    // inode of the mount namespace
+    pub mntns: u64,
+    // session id (global pid of the session leader), 0 if unknown
+    pub sid: u32,
+    // the process has a controlling terminal
+    pub has_tty: u8,
     pub timestamp: u64,
+    // when the process (its thread group leader) was started, on the same clock as timestamp. With
+    // the pid it tells a reused pid apart, 0 if it can't be read
+    pub start_time: u64,
+    // inode number and device (the kernel's dev_t encoding, MAJOR << 20 | MINOR) of the
+    // executed file from current->mm->exe_file. Only known once the new program is in place: set
+    // for SOURCE_SCHED_EXEC, 0 for SOURCE_SYS_ENTER where they arrive with the ExecResultEvent.
+    // Also 0 if they can't be read
+    pub exe_ino: u64,
+    pub exe_dev: u64,
+    // directory fd an execveat() path is relative to (AT_FDCWD is -100), -1 for execve()
+    pub dirfd: i32,
+    // SYSCALL_EXECVE or SYSCALL_EXECVEAT
+    pub syscall: u32,
+    // execveat() flags (AT_EMPTY_PATH, AT_SYMLINK_NOFOLLOW), 0 for execve()
+    pub at_flags: u32,
     pub command: [u8; COMMAND_LEN],
     pub command_len: usize,
+    // command didn't fit in COMMAND_LEN and was cut short
+    pub command_truncated: u8,
+    // working directory of the calling task, cwd_len is 0 if it couldn't be resolved
+    pub cwd: [u8; CWD_LEN],
+    pub cwd_len: usize,
+    // comm of the task calling execve, NUL padded
+    pub comm: [u8; COMM_LEN],
+    // comm of current->real_parent, all zeros if it couldn't be read
+    pub parent_comm: [u8; COMM_LEN],
     pub argvs: [[u8; ARGV_LEN]; ARGV_OFFSET],
     pub argvs_offset: [usize; ARGV_OFFSET],
-}
\ No newline at end of file
+    // argument didn't fit in ARGV_LEN and was cut short
+    pub argvs_truncated: [u8; ARGV_OFFSET],
+    // number of argv entries, counted up to MAX_ARGC
+    pub argc: u32,
+    // argv had more than MAX_ARGC entries, the rest were dropped
+    pub argv_truncated: u8,
+    // argv (and envp) couldn't be read at all, argc is 0 though the program may have had some
+    pub argv_missing: u8,
+    // summed length of the argv strings without their NULs, however long they were. Only the
+    // first MAX_ARGC entries and ARG_SCRATCH_LEN - 1 bytes of each are measured, a longer argv
+    // sets args_len_overflow and makes this a lower bound
+    pub args_total_len: u32,
+    pub args_len_overflow: u8,
+    // environment entries (`NAME=value`) matching one of the configured prefixes, only the
+    // first env_len entries with a non-zero length are set
+    pub env: [[u8; ENV_LEN]; MAX_ENV_MATCHES],
+    pub env_len: [usize; MAX_ENV_MATCHES],
+    pub env_truncated: [u8; MAX_ENV_MATCHES],
+    // argv is sent ARGV_OFFSET entries at a time: record 0 is the full event, records
+    // 1..chunks only carry pid, timestamp and the next argvs and directly follow it
+    pub seq: u32,
+    pub chunks: u32,
+    // CPU the probe ran on. Records of one exec are written back to back from the same CPU, but
+    // the ring buffer is shared so records of different CPUs interleave
+    pub cpu: u32,
+    // SOURCE_SYS_ENTER or SOURCE_SCHED_EXEC
+    pub source: u32,
+}
+
+// Ring buffer records are 8-byte aligned, a size that isn't a multiple of it would leave padding
+// between records that ExecEvent doesn't account for
+const _: () = assert!(core::mem::size_of::<ExecEvent>().is_multiple_of(8));
+
+// Sent when a process (its thread group leader) exits, on a ring buffer of its own
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct ExitEvent {
+    pub pid: u32,
+    // task_struct::exit_code, a wait(2) status: exit status << 8 | terminating signal
+    pub exit_code: i32,
+    pub timestamp: u64,
+}
+
+// A process or thread created by the current process, on a ring buffer of its own
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct ForkEvent {
+    // tgid of the forking process
+    pub parent_pid: u32,
+    // pid (tid) of the new task
+    pub child_pid: u32,
+    pub timestamp: u64,
+}
+
+// ExecEvent::loginuid of a task that never logged in, (uid_t)-1
+pub const AUDIT_UID_UNSET: u32 = u32::MAX;
+
+// Return value of an exec, sent from sys_exit_execve{,at} on the ring of the ExecEvent with the
+// same pid and timestamp, always after its records
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct ExecResultEvent {
+    pub pid: u32,
+    // 0 on success, -errno on failure
+    pub ret: i32,
+    pub timestamp: u64,
+    // ExecEvent::exe_ino and exe_dev of the new program, 0 unless the exec succeeded
+    pub exe_ino: u64,
+    pub exe_dev: u64,
+}
+
+// An exec that changed the effective uid, i.e. ran a setuid binary. Sent from sys_exit_execve{,at}
+// on its own ring, matched to the ExecEvent with the same pid and timestamp
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct SetuidEvent {
+    pub pid: u32,
+    // euid when the exec was entered and once it returned
+    pub before_uid: u32,
+    pub after_uid: u32,
+    pub timestamp: u64,
+    // comm of the new program
+    pub command: [u8; COMM_LEN],
+}
+
+/// Byte offsets of the kernel struct fields read by the probe. Resolved from the
+/// running kernel's BTF in user space and patched into the program before it loads.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct KernelOffsets {
+    pub task_real_parent: u32,
+    pub task_tgid: u32,
+    pub task_comm: u32,
+    pub task_exit_code: u32,
+    pub task_mm: u32,
+    pub mm_start_stack: u32,
+    pub task_cred: u32,
+    pub cred_euid: u32,
+    pub cred_egid: u32,
+    pub task_thread_pid: u32,
+    pub pid_level: u32,
+    pub pid_numbers: u32,
+    pub upid_size: u32,
+    pub upid_nr: u32,
+    pub upid_ns: u32,
+    pub pid_ns_inum: u32,
+    pub task_nsproxy: u32,
+    pub nsproxy_mnt_ns: u32,
+    pub mnt_ns_inum: u32,
+    pub task_signal: u32,
+    pub signal_pids: u32,
+    pub signal_tty: u32,
+    pub task_fs: u32,
+    pub fs_pwd: u32,
+    pub path_mnt: u32,
+    pub path_dentry: u32,
+    pub dentry_parent: u32,
+    pub dentry_name: u32,
+    pub vfsmount_mnt_root: u32,
+    pub mount_mnt: u32,
+    pub mount_parent: u32,
+    pub mount_mountpoint: u32,
+    // 0 without CONFIG_AUDITSYSCALL, which is where task_struct::loginuid lives
+    pub task_loginuid: u32,
+    pub mm_exe_file: u32,
+    pub file_inode: u32,
+    pub inode_ino: u32,
+    pub inode_sb: u32,
+    pub sb_dev: u32,
+    pub task_flags: u32,
+    // current->cgroups->dfl_cgrp->kn->name, task_cgroups is 0 without CONFIG_CGROUPS
+    pub task_cgroups: u32,
+    pub css_set_dfl_cgrp: u32,
+    pub cgroup_kn: u32,
+    pub kernfs_node_name: u32,
+    // current->group_leader->start_time
+    pub task_group_leader: u32,
+    pub task_start_time: u32,
+}
+
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for KernelOffsets {}
+
+// The part of a NUL padded command path after its last `/`, NUL padded the same way: the key of
+// EXCLUDED_BASENAMES for `/usr/bin/ls` is `ls`. A path without `/` is its own basename. Used by
+// the probe, so only loops bounded by COMMAND_LEN
+pub fn basename_key(path: &[u8; COMMAND_LEN]) -> [u8; COMMAND_LEN] {
+    let start = path.iter().rposition(|&b| b == b'/').map_or(0, |slash| slash + 1);
+    let mut key = [0u8; COMMAND_LEN];
+    for (dst, src) in key.iter_mut().zip(path.iter().skip(start)) {
+        *dst = *src;
+    }
+    key
+}
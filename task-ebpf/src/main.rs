@@ -2,52 +2,752 @@
 #![no_main]
 
 use aya_ebpf::{
-    helpers::{bpf_get_current_pid_tgid, bpf_probe_read_user, bpf_probe_read_user_str_bytes, r#gen::bpf_ktime_get_ns},
+    helpers::{
+        bpf_get_current_comm, bpf_get_current_pid_tgid, bpf_get_current_uid_gid, bpf_probe_read_kernel, bpf_probe_read_kernel_str_bytes,
+        bpf_probe_read_user, bpf_probe_read_user_str_bytes,
+        r#gen::{bpf_get_current_cgroup_id, bpf_get_current_task, bpf_get_smp_processor_id, bpf_ktime_get_ns},
+    },
     macros::{map, tracepoint},
-    maps::{HashMap, PerfEventArray},
+    maps::{lpm_trie::Key, Array, HashMap, LpmTrie, LruHashMap, PerCpuArray, RingBuf},
     programs::TracePointContext,
+    EbpfContext,
 };
-use task_common::{ExecEvent, ARGV_LEN, ARGV_OFFSET, COMMAND_LEN};
+use task_common::{
+    ExecEvent, AUDIT_UID_UNSET, ExecResultEvent, ExitEvent, ForkEvent, KernelOffsets, SetuidEvent, ARG_SCRATCH_LEN,
+    ARGV_LEN, ARGV_OFFSET, CGROUP_NAME_LEN, COMMAND_LEN, COMM_LEN, CWD_LEN, SOURCE_SCHED_EXEC, SOURCE_SYS_ENTER,
+    SYSCALL_EXECVE, SYSCALL_EXECVEAT, SYSCALL_UNKNOWN, ENV_LEN, ENV_PREFIX_LEN, MAX_ARGC, MAX_ENV_MATCHES,
+    MAX_ENV_PREFIXES, MAX_EXCLUDED_DESCENDANTS, MAX_EXCLUDED_PIDS, MAX_EXCLUDED_PREFIXES, MAX_EXCLUDED_ROOTS,
+    MAX_EXCLUDED_UIDS, basename_key,
+};
+
+// Where the arguments sit in the syscalls/sys_enter_* records, see
+// /sys/kernel/tracing/events/syscalls/sys_enter_execve{,at}/format
+struct SyscallLayout {
+    syscall: u32,
+    dirfd: Option<usize>,
+    flags: Option<usize>,
+    filename: usize,
+    argv: usize,
+    envp: usize,
+}
+
+const EXECVE: SyscallLayout = SyscallLayout { syscall: SYSCALL_EXECVE, dirfd: None, flags: None, filename: 16, argv: 24, envp: 32 };
+// execveat(dirfd, filename, argv, envp, flags), everything after dirfd moves up by 8
+const EXECVEAT: SyscallLayout =
+    SyscallLayout { syscall: SYSCALL_EXECVEAT, dirfd: Some(16), flags: Some(48), filename: 24, argv: 32, envp: 40 };
+// offset of the __data_loc filename in the sched/sched_process_exec record, see
+// /sys/kernel/tracing/events/sched/sched_process_exec/format
+const SCHED_EXEC_FILENAME: usize = 8;
+// offset of `long ret` in the syscalls/sys_exit_* records, after the syscall number
+const SYS_EXIT_RET: usize = 16;
+// offset of `pid_t child_pid` in the sched/sched_process_fork record, after two 16 byte comms
+// and the parent pid
+const SCHED_FORK_CHILD_PID: usize = 44;
+// environment entries checked against ENV_PREFIXES, later ones are never reported
+const MAX_ENVC: usize = 32;
+// MAX_PID_NS_LEVEL in the kernel
+const MAX_PID_NS_LEVEL: u32 = 32;
+// index of PIDTYPE_SID in enum pid_type
+const PIDTYPE_SID: usize = 3;
+// task_struct::flags bit of kernel threads
+const PF_KTHREAD: u32 = 0x0020_0000;
+// execs a process may report per second, further ones in the same second are dropped
+const MAX_EXECS_PER_SEC: u64 = 100;
+// path components collected for the cwd, deeper directories are left empty
+const CWD_MAX_DEPTH: usize = 16;
+
+// Patched by user space at load time (see task/src/btf.rs)
+#[unsafe(no_mangle)]
+static KERNEL_OFFSETS: KernelOffsets = KernelOffsets {
+    task_real_parent: 0,
+    task_tgid: 0,
+    task_comm: 0,
+    task_exit_code: 0,
+    task_mm: 0,
+    mm_start_stack: 0,
+    task_cred: 0,
+    cred_euid: 0,
+    cred_egid: 0,
+    task_thread_pid: 0,
+    pid_level: 0,
+    pid_numbers: 0,
+    upid_size: 0,
+    upid_nr: 0,
+    upid_ns: 0,
+    pid_ns_inum: 0,
+    task_nsproxy: 0,
+    nsproxy_mnt_ns: 0,
+    mnt_ns_inum: 0,
+    task_signal: 0,
+    signal_pids: 0,
+    signal_tty: 0,
+    task_fs: 0,
+    fs_pwd: 0,
+    path_mnt: 0,
+    path_dentry: 0,
+    dentry_parent: 0,
+    dentry_name: 0,
+    vfsmount_mnt_root: 0,
+    mount_mnt: 0,
+    mount_parent: 0,
+    mount_mountpoint: 0,
+    task_loginuid: 0,
+    mm_exe_file: 0,
+    file_inode: 0,
+    inode_ino: 0,
+    inode_sb: 0,
+    sb_dev: 0,
+    task_flags: 0,
+    task_cgroups: 0,
+    css_set_dfl_cgrp: 0,
+    cgroup_kn: 0,
+    kernfs_node_name: 0,
+    task_group_leader: 0,
+    task_start_time: 0,
+};
+
+// Shared by all CPUs, sized for a few hundred ExecEvents
+#[map]
+static COMMAND_EVENTS: RingBuf = RingBuf::with_byte_size(1 << 20, 0);
+
+// ExitEvents are small and only needed to time executions, so they get their own smaller ring
+#[map]
+static EXIT_EVENTS: RingBuf = RingBuf::with_byte_size(1 << 16, 0);
+
+// ForkEvents only feed process ancestry, like exits they get their own ring
+#[map]
+static FORK_EVENTS: RingBuf = RingBuf::with_byte_size(1 << 16, 0);
+
+// SetuidEvents are rare, a full ring only leaves an execution without setuid_detected
+#[map]
+static SETUID_EVENTS: RingBuf = RingBuf::with_byte_size(1 << 12, 0);
+
+// Records that didn't fit in COMMAND_EVENTS, per CPU so user space knows which exec is incomplete
+#[map]
+static mut DROPPED_EVENTS: PerCpuArray<u64> = PerCpuArray::<u64>::with_max_entries(1, 0);
+
+// Execs dropped for coming from a kernel thread, see is_kernel_thread
+#[map]
+static mut KERNEL_THREAD_EXECS: PerCpuArray<u64> = PerCpuArray::<u64>::with_max_entries(1, 0);
+
+// Execs dropped for their uid being in EXCLUDED_UIDS
+#[map]
+static mut UID_EXCLUDED_EXECS: PerCpuArray<u64> = PerCpuArray::<u64>::with_max_entries(1, 0);
+
+// Patched to 1 by user space with --include-kernel-threads
+#[unsafe(no_mangle)]
+static INCLUDE_KERNEL_THREADS: u8 = 0;
+
+// Patched to the pid of the monitor itself by user space, 0 (--include-self) keeps its execs
+#[unsafe(no_mangle)]
+static MONITOR_PID: u32 = 0;
+
+// The exec each thread group is in the middle of, from sys_enter to sys_exit. LRU so entries of
+// execs whose exit was never seen don't fill it up
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct PendingExec {
+    pid: u32,
+    timestamp: u64,
+    // euid of the caller, compared to the one the exec leaves behind
+    euid: u32,
+}
+
+#[map]
+static mut PENDING_EXECS: LruHashMap<u32, PendingExec> = LruHashMap::<u32, PendingExec>::with_max_entries(10240, 0);
+
+// Execs of a thread group since window_start, see is_throttled
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct ExecCount {
+    window_start: u64,
+    count: u64,
+}
+
+// LRU so processes that are gone age out
+#[map]
+static mut PID_EXEC_COUNT: LruHashMap<u32, ExecCount> = LruHashMap::<u32, ExecCount>::with_max_entries(10240, 0);
+
+// Execs dropped by is_throttled, per CPU like DROPPED_EVENTS
+#[map]
+static mut THROTTLE_HITS: PerCpuArray<u64> = PerCpuArray::<u64>::with_max_entries(1, 0);
 
-const FILENAME_OFFSET: usize = 16;
+// ExecEvent is larger than the 512 byte eBPF stack, so it's assembled in this per-CPU slot instead
+#[map]
+static mut EVENT_SCRATCH: PerCpuArray<ExecEvent> = PerCpuArray::<ExecEvent>::with_max_entries(1, 0);
 
+// Each argument is read in here to measure it for ExecEvent::args_total_len, only the length is kept
 #[map]
-static mut COMMAND_EVENTS: PerfEventArray<ExecEvent> = PerfEventArray::<ExecEvent>::new(0);
+static mut ARG_SCRATCH: PerCpuArray<[u8; ARG_SCRATCH_LEN]> = PerCpuArray::<[u8; ARG_SCRATCH_LEN]>::with_max_entries(1, 0);
 
 #[map]
 static mut EXCLUDED_CMDS: HashMap<[u8; COMMAND_LEN], u8> = HashMap::<[u8; COMMAND_LEN], u8>::with_max_entries(10, 0);
 
+// Bare command names from the exclusions (`ls`), matched on the basename of the path, see basename_key
+#[map]
+static mut EXCLUDED_BASENAMES: HashMap<[u8; COMMAND_LEN], u8> = HashMap::<[u8; COMMAND_LEN], u8>::with_max_entries(10, 0);
+
+// Commands dropped by path prefix, e.g. `/usr/lib/`, from exclude_prefixes in the config file.
+// Keyed by the NUL padded path with the prefix length in bits, a lookup with the whole path finds
+// the longest prefix it starts with
+#[map]
+static mut EXCLUDED_PREFIXES: LpmTrie<[u8; COMMAND_LEN], u8> = LpmTrie::<[u8; COMMAND_LEN], u8>::with_max_entries(MAX_EXCLUDED_PREFIXES as u32, 0);
+
+// Only these commands are reported when INCLUDE_ONLY is set, filled from --only (--include-cmd),
+// --allowlist-file or `only` in the config file
+#[map]
+static mut INCLUDED_CMDS: HashMap<[u8; COMMAND_LEN], u8> = HashMap::<[u8; COMMAND_LEN], u8>::with_max_entries(10, 0);
+
+// Patched to 1 by user space in allowlist mode, when that list is not empty
+#[unsafe(no_mangle)]
+static INCLUDE_ONLY: u8 = 0;
+
+// Real uids whose executions are dropped in-kernel, filled from --exclude-uid and edited on
+// /exclusions/uid. Empty excludes nobody
+#[map]
+static mut EXCLUDED_UIDS: HashMap<u32, u8> = HashMap::<u32, u8>::with_max_entries(MAX_EXCLUDED_UIDS as u32, 0);
+
+// Processes (tgids) whose execs, and those of their children, are dropped in-kernel. Edited on
+// /exclusions/pid, an entry goes away when its process exits so a reused pid isn't excluded
+#[map]
+static mut EXCLUDED_PIDS: HashMap<u32, u8> = HashMap::<u32, u8>::with_max_entries(MAX_EXCLUDED_PIDS as u32, 0);
+
+// Processes whose whole subtree is dropped in-kernel, edited on /exclusions/subtree. Like
+// EXCLUDED_PIDS an entry goes away when its process exits
+#[map]
+static mut EXCLUDED_ROOTS: HashMap<u32, u8> = HashMap::<u32, u8>::with_max_entries(MAX_EXCLUDED_ROOTS as u32, 0);
+
+// Descendants of EXCLUDED_ROOTS (pid or tid -> root), added by task_fork and, for the ones that
+// existed before, by user space. Only counts while its root is still in EXCLUDED_ROOTS, so removing
+// a root needs no cleanup here. Removed on exit, LRU so a busy subtree can't fill it up
+#[map]
+static mut EXCLUDED_DESCENDANTS: LruHashMap<u32, u32> = LruHashMap::<u32, u32>::with_max_entries(MAX_EXCLUDED_DESCENDANTS as u32, 0);
+
+// `NAME=` prefixes of the environment entries to report, NUL padded, an all-zero slot is unused.
+// Filled from --env-prefix by user space.
+#[map]
+static mut ENV_PREFIXES: Array<[u8; ENV_PREFIX_LEN]> = Array::<[u8; ENV_PREFIX_LEN]>::with_max_entries(MAX_ENV_PREFIXES as u32, 0);
+
 #[tracepoint]
 pub fn task(ctx: TracePointContext) -> u32 {
-    match try_task(ctx) {
+    match try_task(ctx, &EXECVE) {
+        Ok(ret) => ret,
+        Err(_) => 1,
+    }
+}
+
+#[tracepoint]
+pub fn task_execveat(ctx: TracePointContext) -> u32 {
+    match try_task(ctx, &EXECVEAT) {
         Ok(ret) => ret,
         Err(_) => 1,
     }
 }
 
+// Attached to both sys_exit_execve and sys_exit_execveat
+#[tracepoint]
+pub fn task_exec_result(ctx: TracePointContext) -> u32 {
+    match try_task_exec_result(ctx) {
+        Ok(ret) => ret,
+        Err(_) => 1,
+    }
+}
+
+// Alternative to the two syscall programs, see try_task_sched_exec
+#[tracepoint]
+pub fn task_sched_exec(ctx: TracePointContext) -> u32 {
+    match try_task_sched_exec(ctx) {
+        Ok(ret) => ret,
+        Err(_) => 1,
+    }
+}
+
+#[tracepoint]
+pub fn task_exit(_ctx: TracePointContext) -> u32 {
+    match try_task_exit() {
+        Ok(ret) => ret,
+        Err(_) => 1,
+    }
+}
+
+#[tracepoint]
+pub fn task_fork(ctx: TracePointContext) -> u32 {
+    match try_task_fork(&ctx) {
+        Ok(ret) => ret,
+        Err(_) => 1,
+    }
+}
+
+// Commands outside INCLUDED_CMDS when it's in use, then the ones in EXCLUDED_CMDS
 fn is_excluded(command: &[u8], command_len: usize) -> bool {
     let mut key = [0u8; COMMAND_LEN];
     let len = core::cmp::min(command_len, COMMAND_LEN);
     key[..len].copy_from_slice(&command[..len]);
+    // volatile read so the compiler can't fold the zero initializer
+    let include_only = unsafe { core::ptr::read_volatile(&INCLUDE_ONLY) } != 0;
+    unsafe {
+        if include_only && (*core::ptr::addr_of_mut!(INCLUDED_CMDS)).get(&key).is_none() {
+            return true;
+        }
+        if (*core::ptr::addr_of_mut!(EXCLUDED_CMDS)).get(&key).is_some() {
+            return true;
+        }
+        if (*core::ptr::addr_of_mut!(EXCLUDED_BASENAMES)).get(&basename_key(&key)).is_some() {
+            return true;
+        }
+        // the padding is NUL and prefixes contain none, so one can't match past the end of the path
+        let path = Key::new((COMMAND_LEN * 8) as u32, key);
+        (*core::ptr::addr_of!(EXCLUDED_PREFIXES)).get(&path).is_some()
+    }
+}
+
+// Kernel threads, and usermode helpers (modprobe, core dump pipes, ...) which the kernel starts
+// from one. Their exec clears PF_KTHREAD, so the parent is checked too. Not for pid 1, whose
+// parent is the idle task
+fn is_kernel_thread(offsets: &KernelOffsets, parent: Option<*const u8>, ppid: u32) -> bool {
+    if offsets.task_flags == 0 {
+        return false;
+    }
+    let flags = |task: *const u8| unsafe { bpf_probe_read_kernel(task.add(offsets.task_flags as usize) as *const u32).unwrap_or(0) };
+    let task = unsafe { bpf_get_current_task() as *const u8 };
+    if flags(task) & PF_KTHREAD != 0 {
+        return true;
+    }
+    match parent {
+        Some(parent) if ppid != 0 => flags(parent) & PF_KTHREAD != 0,
+        _ => false,
+    }
+}
+
+fn is_excluded_uid(uid: u32) -> bool {
     unsafe {
-        (*core::ptr::addr_of_mut!(EXCLUDED_CMDS)).get(&key).is_some()
+        (*core::ptr::addr_of_mut!(EXCLUDED_UIDS)).get(&uid).is_some()
     }
 }
 
-fn try_task(ctx: TracePointContext) -> Result<u32, i64> {
+// The child check covers a process that forks for every exec, like a log shipper running gzip
+fn is_excluded_pid(tgid: u32, ppid: u32) -> bool {
+    unsafe {
+        let pids = &*core::ptr::addr_of_mut!(EXCLUDED_PIDS);
+        pids.get(&tgid).is_some() || pids.get(&ppid).is_some()
+    }
+}
+
+// The root of the excluded subtree `pid` is in, if any
+fn excluded_root(pid: u32) -> Option<u32> {
+    unsafe {
+        let roots = &*core::ptr::addr_of_mut!(EXCLUDED_ROOTS);
+        if roots.get(&pid).is_some() {
+            return Some(pid);
+        }
+        let root = *(*core::ptr::addr_of_mut!(EXCLUDED_DESCENDANTS)).get(&pid)?;
+        roots.get(&root).map(|_| root)
+    }
+}
+
+fn kernel_offsets() -> KernelOffsets {
+    // volatile read so the compiler can't fold the zero initializer
+    unsafe { core::ptr::read_volatile(&KERNEL_OFFSETS) }
+}
+
+// current->real_parent, None if it can't be read
+fn read_parent(offsets: &KernelOffsets) -> Option<*const u8> {
+    if offsets.task_real_parent == 0 {
+        return None;
+    }
+    unsafe {
+        let task = bpf_get_current_task() as *const u8;
+        match bpf_probe_read_kernel(task.add(offsets.task_real_parent as usize) as *const *const u8) {
+            Ok(parent) if !parent.is_null() => Some(parent),
+            _ => None,
+        }
+    }
+}
+
+// tgid of the parent task, 0 if it can't be read
+fn read_ppid(offsets: &KernelOffsets, parent: *const u8) -> u32 {
+    unsafe { bpf_probe_read_kernel(parent.add(offsets.task_tgid as usize) as *const i32).unwrap_or(0) as u32 }
+}
+
+// comm of the parent task, zeroed if it can't be read
+fn read_comm(offsets: &KernelOffsets, parent: *const u8) -> [u8; COMM_LEN] {
+    if offsets.task_comm == 0 {
+        return [0; COMM_LEN];
+    }
+    unsafe { bpf_probe_read_kernel(parent.add(offsets.task_comm as usize) as *const [u8; COMM_LEN]).unwrap_or([0; COMM_LEN]) }
+}
+
+// (euid, egid) of current->cred, falls back to the real ids if they can't be read
+fn read_effective_ids(offsets: &KernelOffsets, uid: u32, gid: u32) -> (u32, u32) {
+    if offsets.task_cred == 0 {
+        return (uid, gid);
+    }
+    unsafe {
+        let task = bpf_get_current_task() as *const u8;
+        let cred = match bpf_probe_read_kernel(task.add(offsets.task_cred as usize) as *const *const u8) {
+            Ok(cred) => cred,
+            Err(_) => return (uid, gid),
+        };
+        let euid = bpf_probe_read_kernel(cred.add(offsets.cred_euid as usize) as *const u32).unwrap_or(uid);
+        let egid = bpf_probe_read_kernel(cred.add(offsets.cred_egid as usize) as *const u32).unwrap_or(gid);
+        (euid, egid)
+    }
+}
+
+// (pid namespace inode, pid inside that namespace) of the current thread, zeros if they can't be read.
+// Walks current->thread_pid->numbers[level], the innermost namespace the thread is visible in.
+fn read_pid_ns(offsets: &KernelOffsets) -> (u32, u32) {
+    if offsets.task_thread_pid == 0 {
+        return (0, 0);
+    }
+    unsafe {
+        let task = bpf_get_current_task() as *const u8;
+        let pid = match bpf_probe_read_kernel(task.add(offsets.task_thread_pid as usize) as *const *const u8) {
+            Ok(pid) => pid,
+            Err(_) => return (0, 0),
+        };
+        let level = match bpf_probe_read_kernel(pid.add(offsets.pid_level as usize) as *const u32) {
+            // keeps the index bounded for the verifier
+            Ok(level) if level <= MAX_PID_NS_LEVEL => level,
+            _ => return (0, 0),
+        };
+        let upid = pid.add(offsets.pid_numbers as usize + level as usize * offsets.upid_size as usize);
+        let ns_pid = bpf_probe_read_kernel(upid.add(offsets.upid_nr as usize) as *const i32).unwrap_or(0) as u32;
+        let ns = match bpf_probe_read_kernel(upid.add(offsets.upid_ns as usize) as *const *const u8) {
+            Ok(ns) => ns,
+            Err(_) => return (0, ns_pid),
+        };
+        let inum = bpf_probe_read_kernel(ns.add(offsets.pid_ns_inum as usize) as *const u32).unwrap_or(0);
+        (inum, ns_pid)
+    }
+}
+
+// current->nsproxy->mnt_ns->ns.inum, 0 if it can't be read
+fn read_mnt_ns(offsets: &KernelOffsets) -> u64 {
+    if offsets.task_nsproxy == 0 {
+        return 0;
+    }
+    unsafe {
+        let task = bpf_get_current_task() as *const u8;
+        let nsproxy = match bpf_probe_read_kernel(task.add(offsets.task_nsproxy as usize) as *const *const u8) {
+            Ok(nsproxy) if !nsproxy.is_null() => nsproxy,
+            _ => return 0,
+        };
+        let mnt_ns = match bpf_probe_read_kernel(nsproxy.add(offsets.nsproxy_mnt_ns as usize) as *const *const u8) {
+            Ok(mnt_ns) if !mnt_ns.is_null() => mnt_ns,
+            _ => return 0,
+        };
+        bpf_probe_read_kernel(mnt_ns.add(offsets.mnt_ns_inum as usize) as *const u32).unwrap_or(0) as u64
+    }
+}
+
+// (session id, has a controlling tty) from current->signal, (0, false) if it can't be read.
+// The sid is the global pid of the session leader, current->signal->pids[PIDTYPE_SID]->numbers[0].nr
+fn read_session(offsets: &KernelOffsets) -> (u32, bool) {
+    if offsets.task_signal == 0 {
+        return (0, false);
+    }
+    unsafe {
+        let task = bpf_get_current_task() as *const u8;
+        let signal = match bpf_probe_read_kernel(task.add(offsets.task_signal as usize) as *const *const u8) {
+            Ok(signal) if !signal.is_null() => signal,
+            _ => return (0, false),
+        };
+        let tty = bpf_probe_read_kernel(signal.add(offsets.signal_tty as usize) as *const *const u8).unwrap_or(core::ptr::null());
+        let pids = signal.add(offsets.signal_pids as usize) as *const *const u8;
+        let sid = match bpf_probe_read_kernel(pids.add(PIDTYPE_SID)) {
+            Ok(pid) if !pid.is_null() => {
+                bpf_probe_read_kernel(pid.add(offsets.pid_numbers as usize + offsets.upid_nr as usize) as *const i32).unwrap_or(0) as u32
+            }
+            _ => 0,
+        };
+        (sid, !tty.is_null())
+    }
+}
+
+// current->group_leader->start_time, the start of the process rather than of the calling thread.
+// 0 if it can't be read
+fn read_start_time(offsets: &KernelOffsets) -> u64 {
+    if offsets.task_start_time == 0 {
+        return 0;
+    }
+    let task = unsafe { bpf_get_current_task() as *const u8 };
+    match read_kernel_ptr(task, offsets.task_group_leader) {
+        Some(leader) => unsafe { bpf_probe_read_kernel(leader.add(offsets.task_start_time as usize) as *const u64).unwrap_or(0) },
+        None => 0,
+    }
+}
+
+// current->loginuid, AUDIT_UID_UNSET without audit support or if it can't be read
+fn read_loginuid(offsets: &KernelOffsets) -> u32 {
+    if offsets.task_loginuid == 0 {
+        return AUDIT_UID_UNSET;
+    }
+    unsafe {
+        let task = bpf_get_current_task() as *const u8;
+        bpf_probe_read_kernel(task.add(offsets.task_loginuid as usize) as *const u32).unwrap_or(AUDIT_UID_UNSET)
+    }
+}
+
+// Leaf name of current's cgroup v2 cgroup into `name`, all zeros if it can't be read. Runtimes
+// name a container's cgroup `cri-containerd-<64 hex id>.scope` and the like, longer than
+// CGROUP_NAME_LEN. A name that fills the buffer is read again from after its last '-' so the id
+// isn't cut off
+fn read_cgroup_name(offsets: &KernelOffsets, name: &mut [u8; CGROUP_NAME_LEN]) {
+    name.fill(0);
+    if offsets.task_cgroups == 0 {
+        return;
+    }
+    let ptr = unsafe {
+        let task = bpf_get_current_task() as *const u8;
+        let Some(ptr) = read_kernel_ptr(task, offsets.task_cgroups)
+            .and_then(|cset| read_kernel_ptr(cset, offsets.css_set_dfl_cgrp))
+            .and_then(|cgroup| read_kernel_ptr(cgroup, offsets.cgroup_kn))
+            .and_then(|kn| read_kernel_ptr(kn, offsets.kernfs_node_name))
+        else {
+            return;
+        };
+        ptr
+    };
+    // the helper keeps the last byte for the NUL, a shorter read got the whole name
+    match unsafe { bpf_probe_read_kernel_str_bytes(ptr, name) } {
+        Ok(read) if read.len() < CGROUP_NAME_LEN - 1 => return,
+        Ok(_) => {}
+        Err(_) => {
+            name.fill(0);
+            return;
+        }
+    }
+    let mut dash = None;
+    for (i, &b) in name.iter().enumerate() {
+        if b == b'-' {
+            dash = Some(i);
+        }
+    }
+    if let Some(i) = dash {
+        name.fill(0);
+        if unsafe { bpf_probe_read_kernel_str_bytes(ptr.wrapping_add(i + 1), name) }.is_err() {
+            name.fill(0);
+        }
+    }
+}
+
+// pointer stored at base + offset, None if it can't be read or is null
+fn read_kernel_ptr(base: *const u8, offset: u32) -> Option<*const u8> {
+    match unsafe { bpf_probe_read_kernel(base.wrapping_add(offset as usize) as *const *const u8) } {
+        Ok(ptr) if !ptr.is_null() => Some(ptr),
+        _ => None,
+    }
+}
+
+// Writes current->fs->pwd into `cwd` the way d_path() would and returns its length, None if it
+// can't be read, is deeper than CWD_MAX_DEPTH or doesn't fit. Walks d_parent up to the root of
+// each mount and continues from its mountpoint in the parent mount, collecting the names leaf
+// first, then copies them out root first.
+fn read_cwd(offsets: &KernelOffsets, cwd: &mut [u8; CWD_LEN]) -> Option<usize> {
+    if offsets.task_fs == 0 {
+        return None;
+    }
+    let mut names = [core::ptr::null::<u8>(); CWD_MAX_DEPTH];
+    let mut depth = 0;
+    unsafe {
+        let task = bpf_get_current_task() as *const u8;
+        let fs = read_kernel_ptr(task, offsets.task_fs)?;
+        let pwd = fs.add(offsets.fs_pwd as usize);
+        let mut dentry = read_kernel_ptr(pwd, offsets.path_dentry)?;
+        let vfsmount = read_kernel_ptr(pwd, offsets.path_mnt)?;
+        let mut mount = vfsmount.sub(offsets.mount_mnt as usize);
+        let mut complete = false;
+        // every step either adds a name or crosses a mount
+        for _ in 0..CWD_MAX_DEPTH * 2 {
+            let mnt_root = read_kernel_ptr(mount, offsets.mount_mnt + offsets.vfsmount_mnt_root)?;
+            if dentry == mnt_root {
+                let parent = read_kernel_ptr(mount, offsets.mount_parent)?;
+                // the root mount is its own parent
+                if parent == mount {
+                    complete = true;
+                    break;
+                }
+                dentry = read_kernel_ptr(mount, offsets.mount_mountpoint)?;
+                mount = parent;
+                continue;
+            }
+            let parent = read_kernel_ptr(dentry, offsets.dentry_parent)?;
+            if parent == dentry {
+                complete = true;
+                break;
+            }
+            if depth == CWD_MAX_DEPTH {
+                break;
+            }
+            names[depth] = read_kernel_ptr(dentry, offsets.dentry_name)?;
+            depth += 1;
+            dentry = parent;
+        }
+        if !complete {
+            return None;
+        }
+        if depth == 0 {
+            cwd[0] = b'/';
+            return Some(1);
+        }
+        let mut len = 0;
+        for i in (0..CWD_MAX_DEPTH).rev() {
+            if i >= depth { continue; }
+            // leaves room for the separator and at least one byte of the name
+            if len >= CWD_LEN - 2 { return None; }
+            cwd[len] = b'/';
+            len += 1;
+            len += bpf_probe_read_kernel_str_bytes(names[i], &mut cwd[len..]).ok()?.len();
+        }
+        // a name that filled the rest of the buffer may have been cut
+        if len >= CWD_LEN - 1 { return None; }
+        Some(len)
+    }
+}
+
+// sched_process_exit fires for every thread, only the thread group leader exiting ends the process.
+// A full EXIT_EVENTS only loses the exit, it isn't counted in DROPPED_EVENTS as no exec is affected.
+fn try_task_exit() -> Result<u32, i64> {
+    let pid_tgid = bpf_get_current_pid_tgid();
+    let (tid, tgid) = (pid_tgid as u32, (pid_tgid >> 32) as u32);
+    // threads get an entry from task_fork as well
+    unsafe {
+        let _ = (*core::ptr::addr_of_mut!(EXCLUDED_DESCENDANTS)).remove(&tid);
+    }
+    if tid != tgid {
+        return Ok(0);
+    }
+    // the pid can be reused from here on, its exclusion ends with the process
+    unsafe {
+        let _ = (*core::ptr::addr_of_mut!(EXCLUDED_PIDS)).remove(&tgid);
+        let _ = (*core::ptr::addr_of_mut!(EXCLUDED_ROOTS)).remove(&tgid);
+    }
+    if is_excluded_uid(bpf_get_current_uid_gid() as u32) {
+        return Ok(0);
+    }
+    let offsets = kernel_offsets();
+    // do_exit() stores the code on current before the tracepoint fires
+    let exit_code = if offsets.task_exit_code == 0 {
+        0
+    } else {
+        unsafe {
+            let task = bpf_get_current_task() as *const u8;
+            bpf_probe_read_kernel(task.add(offsets.task_exit_code as usize) as *const i32).unwrap_or(0)
+        }
+    };
+    let event = ExitEvent { pid: tgid, exit_code, timestamp: unsafe { bpf_ktime_get_ns() } };
+    let _ = EXIT_EVENTS.output(&event, 0);
+    Ok(0)
+}
+
+// sched_process_fork fires in the parent for new processes and threads alike, the tracepoint
+// doesn't say which. Threads are recorded too, keyed by their tid. A full FORK_EVENTS only loses
+// a step of some ancestry.
+fn try_task_fork(ctx: &TracePointContext) -> Result<u32, i64> {
+    let parent_pid = (bpf_get_current_pid_tgid() >> 32) as u32;
+    let child_pid: i32 = unsafe { ctx.read_at(SCHED_FORK_CHILD_PID)? };
+    // a child of an excluded subtree is part of it, whoever runs it. A full map evicts the
+    // entry used least recently, which lets that process's execs through again
+    if let Some(root) = excluded_root(parent_pid) {
+        unsafe {
+            let _ = (*core::ptr::addr_of_mut!(EXCLUDED_DESCENDANTS)).insert(&(child_pid as u32), &root, 0);
+        }
+    }
+    if is_excluded_uid(bpf_get_current_uid_gid() as u32) {
+        return Ok(0);
+    }
+    let event = ForkEvent { parent_pid, child_pid: child_pid as u32, timestamp: unsafe { bpf_ktime_get_ns() } };
+    let _ = FORK_EVENTS.output(&event, 0);
+    Ok(0)
+}
+
+// An exec by the monitor, or by a child it started, which would otherwise report itself
+fn is_monitor(tgid: u32, ppid: u32) -> bool {
+    // volatile read so the compiler can't fold the zero initializer
+    let monitor = unsafe { core::ptr::read_volatile(&MONITOR_PID) };
+    monitor != 0 && (tgid == monitor || ppid == monitor)
+}
+
+// Fills in who is running the exec and returns the scratch slot, None if the uid or pid is excluded
+fn start_event(offsets: &KernelOffsets, source: u32) -> Option<&'static mut ExecEvent> {
     let timestamp = unsafe { bpf_ktime_get_ns() };
-    let pid = bpf_get_current_pid_tgid() as u32;
-
-    let mut event = ExecEvent {
-        pid,
-        timestamp,
-        command: [0; COMMAND_LEN],
-        command_len: 0,
-        argvs: [[0; ARGV_LEN]; ARGV_OFFSET],
-        argvs_offset: [0; ARGV_OFFSET],
+    let pid_tgid = bpf_get_current_pid_tgid();
+    let uid_gid = bpf_get_current_uid_gid();
+    let (uid, gid) = (uid_gid as u32, (uid_gid >> 32) as u32);
+    if is_excluded_uid(uid) {
+        count_uid_excluded_exec();
+        return None;
+    }
+    let (euid, egid) = read_effective_ids(offsets, uid, gid);
+    let cgroup_id = unsafe { bpf_get_current_cgroup_id() };
+    let (pidns, ns_pid) = read_pid_ns(offsets);
+
+    let event = unsafe { &mut *(*core::ptr::addr_of!(EVENT_SCRATCH)).get_ptr_mut(0)? };
+    // The slot is reused across calls. Buffers are only read up to their recorded lengths,
+    // so resetting the lengths is enough and avoids a large memset.
+    event.tgid = (pid_tgid >> 32) as u32;
+    event.tid = pid_tgid as u32;
+    // The parent may already be exiting, a failed read only leaves its fields empty
+    let parent = read_parent(offsets);
+    event.ppid = parent.map_or(0, |parent| read_ppid(offsets, parent));
+    // volatile read so the compiler can't fold the zero initializer
+    let include_kernel_threads = unsafe { core::ptr::read_volatile(&INCLUDE_KERNEL_THREADS) } != 0;
+    if !include_kernel_threads && is_kernel_thread(offsets, parent, event.ppid) {
+        count_kernel_thread_exec();
+        return None;
+    }
+    if is_monitor(event.tgid, event.ppid) || is_excluded_pid(event.tgid, event.ppid) || excluded_root(event.tgid).is_some() {
+        return None;
+    }
+    event.parent_comm = parent.map_or([0; COMM_LEN], |parent| read_comm(offsets, parent));
+    event.uid = uid;
+    event.gid = gid;
+    event.euid = euid;
+    event.egid = egid;
+    event.loginuid = read_loginuid(offsets);
+    event.cgroup_id = cgroup_id;
+    read_cgroup_name(offsets, &mut event.cgroup_name);
+    event.pidns = pidns;
+    event.ns_pid = ns_pid;
+    event.mntns = read_mnt_ns(offsets);
+    let (sid, has_tty) = read_session(offsets);
+    event.sid = sid;
+    event.has_tty = has_tty as u8;
+    event.timestamp = timestamp;
+    event.start_time = read_start_time(offsets);
+    event.command_len = 0;
+    event.comm = bpf_get_current_comm().unwrap_or([0; COMM_LEN]);
+    event.argc = 0;
+    event.argv_truncated = 0;
+    event.argv_missing = 0;
+    event.seq = 0;
+    event.chunks = 0;
+    event.cpu = unsafe { bpf_get_smp_processor_id() };
+    event.source = source;
+    Some(event)
+}
+
+fn try_task(ctx: TracePointContext, layout: &SyscallLayout) -> Result<u32, i64> {
+    let offsets = kernel_offsets();
+    let Some(event) = start_event(&offsets, SOURCE_SYS_ENTER) else {
+        return Ok(0);
+    };
+    // the syscall argument is a long, the fd lives in its low 32 bits
+    event.dirfd = match layout.dirfd {
+        Some(offset) => unsafe { ctx.read_at::<i64>(offset)? as i32 },
+        None => -1,
+    };
+    // still the caller's mm here, see task_exec_result
+    event.exe_ino = 0;
+    event.exe_dev = 0;
+    event.syscall = layout.syscall;
+    event.at_flags = match layout.flags {
+        Some(offset) => unsafe { ctx.read_at::<i64>(offset)? as u32 },
+        None => 0,
     };
 
-    let command_ptr = unsafe { ctx.read_at::<*const u8>(FILENAME_OFFSET)? };
+    let command_ptr = unsafe { ctx.read_at::<*const u8>(layout.filename)? };
     let command_slice = unsafe { bpf_probe_read_user_str_bytes(command_ptr, &mut event.command)? };
     event.command_len = command_slice.len();
 
@@ -56,22 +756,328 @@ fn try_task(ctx: TracePointContext) -> Result<u32, i64> {
         return Ok(0);
     }
 
-    let argv_ptrs = unsafe { ctx.read_at::<*const *const u8>(24)? };
-    for i in 0..ARGV_OFFSET {
-        let ptr: *const u8 = unsafe { bpf_probe_read_user(argv_ptrs.add(i))? };
-        if ptr.is_null() { break; }
-        let slice = unsafe { bpf_probe_read_user_str_bytes(ptr, &mut event.argvs[i])? };
-        let len = slice.len();
-        event.argvs_offset[i] = if len >= ARGV_LEN { ARGV_LEN } else { len };
+    // same exact-fit check as for the arguments in read_argv_chunk
+    event.command_truncated = 0;
+    if event.command_len == COMMAND_LEN - 1 {
+        let next: u8 = unsafe { bpf_probe_read_user(command_ptr.add(COMMAND_LEN - 1)).unwrap_or(0) };
+        event.command_truncated = (next != 0) as u8;
+    }
+
+    // counted once the filters passed, so excluded commands don't use up the budget
+    if is_throttled(event.tgid, event.timestamp) {
+        return Ok(0);
+    }
+
+    // the environment is optional, an unreadable envp only leaves env empty
+    let envp = unsafe { ctx.read_at::<*const *const u8>(layout.envp).unwrap_or(core::ptr::null()) };
+    let argv_ptrs = unsafe { ctx.read_at::<*const *const u8>(layout.argv)? };
+    send_exec(&offsets, event, argv_ptrs, envp)?;
+
+    // Keyed by tgid: a successful exec from another thread returns as the thread group leader
+    let pending = PendingExec { pid: event.tgid, timestamp: event.timestamp, euid: event.euid };
+    let _ = unsafe { (*core::ptr::addr_of!(PENDING_EXECS)).insert(&event.tgid, &pending, 0) };
+    Ok(0)
+}
+
+// A failed exec returns to the caller, a successful one returns 0 in the new program. A full ring
+// buffer only leaves the result unknown, it isn't counted in DROPPED_EVENTS.
+fn try_task_exec_result(ctx: TracePointContext) -> Result<u32, i64> {
+    let tgid = (bpf_get_current_pid_tgid() >> 32) as u32;
+    let pending = unsafe {
+        let pending_execs = &*core::ptr::addr_of!(PENDING_EXECS);
+        let pending = match pending_execs.get(&tgid) {
+            Some(pending) => *pending,
+            None => return Ok(0),
+        };
+        let _ = pending_execs.remove(&tgid);
+        pending
+    };
+    let ret = unsafe { ctx.read_at::<i64>(SYS_EXIT_RET)? };
+    // after a successful exec current->mm is the new program's
+    let (exe_ino, exe_dev) = if ret == 0 { read_exe(&kernel_offsets()).unwrap_or((0, 0)) } else { (0, 0) };
+    let result = ExecResultEvent { pid: pending.pid, ret: ret as i32, timestamp: pending.timestamp, exe_ino, exe_dev };
+    let _ = COMMAND_EVENTS.output(&result, 0);
+    if ret == 0 {
+        check_setuid(&pending);
+    }
+    Ok(0)
+}
+
+// The new credentials are committed by the time a successful exec returns, an euid that changed
+// came from the setuid bit of the binary. Not known with --attach sched-exec, which has no
+// sys_enter to compare to.
+fn check_setuid(pending: &PendingExec) {
+    let uid_gid = bpf_get_current_uid_gid();
+    let (after_uid, _) = read_effective_ids(&kernel_offsets(), uid_gid as u32, (uid_gid >> 32) as u32);
+    if after_uid == pending.euid {
+        return;
+    }
+    let event = SetuidEvent {
+        pid: pending.pid,
+        before_uid: pending.euid,
+        after_uid,
+        timestamp: pending.timestamp,
+        command: bpf_get_current_comm().unwrap_or([0; COMM_LEN]),
+    };
+    let _ = SETUID_EVENTS.output(&event, 0);
+}
+
+// sched_process_exec fires once the new program is in place, so only for execs that succeeded.
+// It doesn't carry the syscall arguments: the filename comes from the record and argv/envp from
+// the initial stack of the new program, which nothing can change before it first runs.
+fn try_task_sched_exec(ctx: TracePointContext) -> Result<u32, i64> {
+    let offsets = kernel_offsets();
+    let Some(event) = start_event(&offsets, SOURCE_SCHED_EXEC) else {
+        return Ok(0);
+    };
+    // execveat() with a dirfd shows up as a /dev/fd/<dirfd>/... filename instead
+    event.dirfd = -1;
+    event.syscall = SYSCALL_UNKNOWN;
+    event.at_flags = 0;
+
+    // __data_loc: offset of the string in the record in the low 16 bits, its length with the NUL
+    // in the high 16 bits
+    let loc = unsafe { ctx.read_at::<u32>(SCHED_EXEC_FILENAME)? };
+    let filename = ctx.as_ptr().wrapping_add((loc & 0xffff) as usize) as *const u8;
+    let command_slice = unsafe { bpf_probe_read_kernel_str_bytes(filename, &mut event.command)? };
+    event.command_len = command_slice.len();
+    if is_excluded(command_slice, command_slice.len()) {
+        return Ok(0);
+    }
+    event.command_truncated = ((loc >> 16) as usize > COMMAND_LEN) as u8;
+    (event.exe_ino, event.exe_dev) = read_exe(&offsets).unwrap_or((0, 0));
+
+    if is_throttled(event.tgid, event.timestamp) {
+        return Ok(0);
+    }
+
+    // mm->start_stack points at argc, followed by argv, a NULL and envp. An unreadable stack still
+    // sends what was read so far, with argc 0 and argv_missing set
+    let stack = read_start_stack(&offsets).and_then(|stack| Some((stack, unsafe { bpf_probe_read_user::<u64>(stack as *const u64).ok()? })));
+    let (argv_ptrs, envp) = match stack {
+        Some((stack, argc)) => {
+            let argv_ptrs = stack.wrapping_add(8) as *const *const u8;
+            (argv_ptrs, argv_ptrs.wrapping_add(argc as usize + 1))
+        }
+        None => (core::ptr::null(), core::ptr::null()),
+    };
+    event.argv_missing = argv_ptrs.is_null() as u8;
+    send_exec(&offsets, event, argv_ptrs, envp)
+}
+
+// current->mm->start_stack, None if it can't be read
+fn read_start_stack(offsets: &KernelOffsets) -> Option<*const u8> {
+    if offsets.task_mm == 0 {
+        return None;
+    }
+    unsafe {
+        let task = bpf_get_current_task() as *const u8;
+        let mm = read_kernel_ptr(task, offsets.task_mm)?;
+        match bpf_probe_read_kernel(mm.add(offsets.mm_start_stack as usize) as *const u64) {
+            Ok(stack) if stack != 0 => Some(stack as *const u8),
+            _ => None,
+        }
     }
+}
 
+// (inode number, device) of current->mm->exe_file, None if it can't be read
+fn read_exe(offsets: &KernelOffsets) -> Option<(u64, u64)> {
+    if offsets.mm_exe_file == 0 {
+        return None;
+    }
     unsafe {
-        let map_ptr: *mut PerfEventArray<ExecEvent> = core::ptr::addr_of_mut!(COMMAND_EVENTS);
-        (*map_ptr).output(&ctx, &event, 0);
+        let task = bpf_get_current_task() as *const u8;
+        let mm = read_kernel_ptr(task, offsets.task_mm)?;
+        let file = read_kernel_ptr(mm, offsets.mm_exe_file)?;
+        let inode = read_kernel_ptr(file, offsets.file_inode)?;
+        let ino = bpf_probe_read_kernel(inode.add(offsets.inode_ino as usize) as *const u64).ok()?;
+        let sb = read_kernel_ptr(inode, offsets.inode_sb)?;
+        let dev = bpf_probe_read_kernel(sb.add(offsets.sb_dev as usize) as *const u32).ok()?;
+        Some((ino, dev as u64))
+    }
+}
+
+// Adds the cwd, environment and arguments to an event and sends it, ARGV_OFFSET arguments per record.
+// Null argv_ptrs/envp leave them out
+fn send_exec(offsets: &KernelOffsets, event: &mut ExecEvent, argv_ptrs: *const *const u8, envp: *const *const u8) -> Result<u32, i64> {
+    // an unreadable cwd only leaves it empty
+    event.cwd_len = read_cwd(offsets, &mut event.cwd).unwrap_or(0);
+    read_env(event, envp);
+
+    // Count first so the primary record can tell user space how many continuations follow. Each
+    // argument is measured on the way, an unreadable one counts as empty
+    let scratch = unsafe { (*core::ptr::addr_of!(ARG_SCRATCH)).get_ptr_mut(0) };
+    let mut argc = 0;
+    let mut total_len = 0u32;
+    let mut overflow = scratch.is_none();
+    for i in 0..MAX_ARGC {
+        let ptr: *const u8 = unsafe { bpf_probe_read_user(argv_ptrs.add(i)).unwrap_or(core::ptr::null()) };
+        if ptr.is_null() { break; }
+        argc += 1;
+        if let Some(scratch) = scratch
+            && let Ok(arg) = unsafe { bpf_probe_read_user_str_bytes(ptr, &mut *scratch) }
+        {
+            total_len += arg.len() as u32;
+            // filled up to the NUL the helper keeps, there may be more
+            overflow |= arg.len() == ARG_SCRATCH_LEN - 1;
+        }
+    }
+    if argc == MAX_ARGC {
+        // every slot used, peek at the next pointer to see if argv goes on
+        let next: *const u8 = unsafe { bpf_probe_read_user(argv_ptrs.add(MAX_ARGC)).unwrap_or(core::ptr::null()) };
+        event.argv_truncated = !next.is_null() as u8;
+    }
+    event.argc = argc as u32;
+    event.args_total_len = total_len;
+    event.args_len_overflow = (overflow || event.argv_truncated != 0) as u8;
+    let chunks = if argc == 0 { 1 } else { argc.div_ceil(ARGV_OFFSET) };
+    event.chunks = chunks as u32;
+
+    // The slot is rewritten and sent again for each chunk. A full ring buffer stops here and is
+    // counted, user space notices the missing continuations and keeps what it got.
+    for seq in 0..MAX_ARGC / ARGV_OFFSET {
+        if seq >= chunks { break; }
+        event.seq = seq as u32;
+        read_argv_chunk(event, argv_ptrs, seq * ARGV_OFFSET);
+        if COMMAND_EVENTS.output(event, 0).is_err() {
+            count_dropped();
+            break;
+        }
     }
     Ok(0)
 }
 
+fn count_dropped() {
+    unsafe {
+        if let Some(dropped) = (*core::ptr::addr_of!(DROPPED_EVENTS)).get_ptr_mut(0) {
+            *dropped += 1;
+        }
+    }
+}
+
+// Counts an exec of `tgid`, true (and counted in THROTTLE_HITS) once it's past MAX_EXECS_PER_SEC
+// in the current one second window. The count isn't atomic, CPUs racing on one process can let a
+// few more through
+fn is_throttled(tgid: u32, timestamp: u64) -> bool {
+    unsafe {
+        let counts = &*core::ptr::addr_of!(PID_EXEC_COUNT);
+        let Some(entry) = counts.get_ptr_mut(&tgid) else {
+            let _ = counts.insert(&tgid, &ExecCount { window_start: timestamp, count: 1 }, 0);
+            return false;
+        };
+        if timestamp.saturating_sub((*entry).window_start) > 1_000_000_000 {
+            (*entry).window_start = timestamp;
+            (*entry).count = 1;
+            return false;
+        }
+        (*entry).count += 1;
+        if (*entry).count <= MAX_EXECS_PER_SEC {
+            return false;
+        }
+        if let Some(hits) = (*core::ptr::addr_of!(THROTTLE_HITS)).get_ptr_mut(0) {
+            *hits += 1;
+        }
+        true
+    }
+}
+
+fn count_kernel_thread_exec() {
+    unsafe {
+        if let Some(count) = (*core::ptr::addr_of!(KERNEL_THREAD_EXECS)).get_ptr_mut(0) {
+            *count += 1;
+        }
+    }
+}
+
+fn count_uid_excluded_exec() {
+    unsafe {
+        if let Some(count) = (*core::ptr::addr_of!(UID_EXCLUDED_EXECS)).get_ptr_mut(0) {
+            *count += 1;
+        }
+    }
+}
+
+// Does `head` (the start of an environment entry) begin with one of the ENV_PREFIXES
+fn matches_env_prefix(head: &[u8; ENV_PREFIX_LEN]) -> bool {
+    for i in 0..MAX_ENV_PREFIXES {
+        let prefix = match unsafe { (*core::ptr::addr_of!(ENV_PREFIXES)).get(i as u32) } {
+            Some(prefix) if prefix[0] != 0 => prefix,
+            _ => continue,
+        };
+        let mut matched = true;
+        for j in 0..ENV_PREFIX_LEN {
+            if prefix[j] == 0 { break; }
+            if head[j] != prefix[j] {
+                matched = false;
+                break;
+            }
+        }
+        if matched {
+            return true;
+        }
+    }
+    false
+}
+
+// Copies the first MAX_ENV_MATCHES environment entries matching ENV_PREFIXES into the env slots.
+// Only the first ENV_PREFIX_LEN bytes are read to match, the whole entry only for a match.
+fn read_env(event: &mut ExecEvent, envp: *const *const u8) {
+    for i in 0..MAX_ENV_MATCHES {
+        event.env_len[i] = 0;
+        event.env_truncated[i] = 0;
+    }
+    if envp.is_null() {
+        return;
+    }
+    let mut found = 0;
+    for i in 0..MAX_ENVC {
+        if found >= MAX_ENV_MATCHES { break; }
+        let ptr: *const u8 = unsafe { bpf_probe_read_user(envp.add(i)).unwrap_or(core::ptr::null()) };
+        if ptr.is_null() { break; }
+        // a short entry leaves the rest zeroed, which no prefix byte matches
+        let mut head = [0u8; ENV_PREFIX_LEN];
+        if unsafe { bpf_probe_read_user_str_bytes(ptr, &mut head) }.is_err() || !matches_env_prefix(&head) {
+            continue;
+        }
+        let len = match unsafe { bpf_probe_read_user_str_bytes(ptr, &mut event.env[found]) } {
+            Ok(entry) => entry.len(),
+            Err(_) => continue,
+        };
+        event.env_len[found] = len;
+        // same exact-fit check as for the arguments
+        if len == ENV_LEN - 1 {
+            let next: u8 = unsafe { bpf_probe_read_user(ptr.add(len)).unwrap_or(0) };
+            event.env_truncated[found] = (next != 0) as u8;
+        }
+        found += 1;
+    }
+}
+
+// Fills the argv slots with argv[start..start + ARGV_OFFSET]. An argument that can't be read, e.g.
+// on a page not faulted in yet at sys_enter, is left empty like in the count of send_exec
+fn read_argv_chunk(event: &mut ExecEvent, argv_ptrs: *const *const u8, start: usize) {
+    for i in 0..ARGV_OFFSET {
+        event.argvs_offset[i] = 0;
+        event.argvs_truncated[i] = 0;
+    }
+    if argv_ptrs.is_null() {
+        return;
+    }
+    for i in 0..ARGV_OFFSET {
+        let Ok(ptr) = (unsafe { bpf_probe_read_user::<*const u8>(argv_ptrs.add(start + i)) }) else { continue };
+        if ptr.is_null() { break; }
+        let Ok(slice) = (unsafe { bpf_probe_read_user_str_bytes(ptr, &mut event.argvs[i]) }) else { continue };
+        let len = slice.len();
+        event.argvs_offset[i] = if len >= ARGV_LEN { ARGV_LEN } else { len };
+        // A full buffer holds ARGV_LEN - 1 bytes plus the NUL, which is also what an arg of exactly
+        // that length looks like, so check whether the source string really ends there
+        if len == ARGV_LEN - 1 {
+            let next: u8 = unsafe { bpf_probe_read_user(ptr.add(len)).unwrap_or(0) };
+            event.argvs_truncated[i] = (next != 0) as u8;
+        }
+    }
+}
+
 #[cfg(not(test))]
 #[panic_handler]
 fn panic(_info: &core::panic::PanicInfo) -> ! {
@@ -0,0 +1,53 @@
+// Writes a self-signed certificate and its key for trying out `task --tls-cert --tls-key` locally
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use clap::Parser;
+
+#[derive(Debug, Parser)]
+struct Opt {
+    /// Directory the cert.pem and key.pem files are written to
+    #[clap(long, default_value = ".")]
+    out_dir: PathBuf,
+    /// Name or address the certificate is valid for (repeatable)
+    #[clap(long = "host", value_name = "NAME", default_values = ["localhost", "127.0.0.1", "::1"])]
+    hosts: Vec<String>,
+    /// Replace cert.pem and key.pem if they already exist
+    #[clap(long)]
+    force: bool,
+}
+
+fn main() -> anyhow::Result<()> {
+    let opt = Opt::parse();
+    let rcgen::CertifiedKey { cert, key_pair } =
+        rcgen::generate_simple_self_signed(opt.hosts.clone()).context("failed to generate the certificate")?;
+
+    fs::create_dir_all(&opt.out_dir).with_context(|| format!("failed to create {}", opt.out_dir.display()))?;
+    let cert_path = opt.out_dir.join("cert.pem");
+    let key_path = opt.out_dir.join("key.pem");
+    write(&cert_path, &cert.pem(), 0o644, opt.force)?;
+    // only the owner (the user running task) may read the key
+    write(&key_path, &key_pair.serialize_pem(), 0o600, opt.force)?;
+
+    println!("Certificate for {} written to {}", opt.hosts.join(", "), cert_path.display());
+    println!("Key written to {}", key_path.display());
+    println!("Run: task --tls-cert {} --tls-key {}", cert_path.display(), key_path.display());
+    Ok(())
+}
+
+fn write(path: &PathBuf, contents: &str, mode: u32, force: bool) -> anyhow::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).mode(mode);
+    if force {
+        options.create(true).truncate(true);
+    } else {
+        options.create_new(true);
+    }
+    let mut file = options
+        .open(path)
+        .with_context(|| format!("failed to create {} (use --force to replace it)", path.display()))?;
+    file.write_all(contents.as_bytes()).with_context(|| format!("failed to write {}", path.display()))
+}
@@ -10,5 +10,19 @@ fn main() -> anyhow::Result<()> {
         .into_iter()
         .find(|cargo_metadata::Package { name, .. }| name == "task-ebpf")
         .ok_or_else(|| anyhow!("task-ebpf package not found"))?;
+    // `sqlx::migrate!` embeds the migrations at compile time
+    println!("cargo:rerun-if-changed=migrations");
+    compile_protos()?;
     aya_build::build_ebpf([ebpf_package])
 }
+
+// gRPC stubs for server/grpc.rs, with a bundled protoc so none has to be installed
+fn compile_protos() -> anyhow::Result<()> {
+    let mut config = prost_build::Config::new();
+    config.protoc_executable(protoc_bin_vendored::protoc_bin_path().context("no bundled protoc for this host")?);
+    let well_known = protoc_bin_vendored::include_path().context("no bundled protobuf includes")?;
+    tonic_build::configure()
+        .compile_protos_with_config(config, &["proto/task.proto"], &[std::path::Path::new("proto"), &well_known])
+        .context("failed to compile proto/task.proto")?;
+    Ok(())
+}
@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS executions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    pid INTEGER NOT NULL,
+    -- RFC 3339 / ISO-8601 with fixed nanosecond precision so rows sort lexicographically
+    timestamp TEXT NOT NULL,
+    commandstr TEXT NOT NULL,
+    argstr TEXT NOT NULL,
+    full_command TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_executions_pid ON executions (pid);
+CREATE INDEX IF NOT EXISTS idx_executions_timestamp ON executions (timestamp);
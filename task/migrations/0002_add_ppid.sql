@@ -0,0 +1 @@
+ALTER TABLE executions ADD COLUMN ppid INTEGER NOT NULL DEFAULT 0;
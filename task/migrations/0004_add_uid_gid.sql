@@ -0,0 +1,2 @@
+ALTER TABLE executions ADD COLUMN uid INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE executions ADD COLUMN gid INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,2 @@
+ALTER TABLE executions ADD COLUMN euid INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE executions ADD COLUMN egid INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1 @@
+ALTER TABLE executions ADD COLUMN cgroup_id INTEGER NOT NULL DEFAULT 0;
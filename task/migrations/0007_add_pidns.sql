@@ -0,0 +1,2 @@
+ALTER TABLE executions ADD COLUMN pidns INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE executions ADD COLUMN ns_pid INTEGER NOT NULL DEFAULT 0;
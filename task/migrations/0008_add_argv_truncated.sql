@@ -0,0 +1 @@
+ALTER TABLE executions ADD COLUMN argv_truncated BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1 @@
+ALTER TABLE executions ADD COLUMN mntns INTEGER NOT NULL DEFAULT 0;
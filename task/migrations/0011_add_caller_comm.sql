@@ -0,0 +1 @@
+ALTER TABLE executions ADD COLUMN caller_comm TEXT NOT NULL DEFAULT '';
@@ -0,0 +1 @@
+ALTER TABLE executions ADD COLUMN parent_command TEXT NOT NULL DEFAULT '';
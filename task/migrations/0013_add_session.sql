@@ -0,0 +1,2 @@
+ALTER TABLE executions ADD COLUMN sid INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE executions ADD COLUMN has_tty BOOLEAN NOT NULL DEFAULT FALSE;
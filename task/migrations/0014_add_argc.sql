@@ -0,0 +1 @@
+ALTER TABLE executions ADD COLUMN argc INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1 @@
+ALTER TABLE executions ADD COLUMN argv_partial BOOLEAN NOT NULL DEFAULT FALSE;
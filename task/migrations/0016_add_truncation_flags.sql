@@ -0,0 +1,2 @@
+ALTER TABLE executions ADD COLUMN command_truncated BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE executions ADD COLUMN args_truncated BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1 @@
+ALTER TABLE executions ADD COLUMN cwd TEXT NOT NULL DEFAULT '';
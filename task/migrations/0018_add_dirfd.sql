@@ -0,0 +1 @@
+ALTER TABLE executions ADD COLUMN dirfd INTEGER NOT NULL DEFAULT -1;
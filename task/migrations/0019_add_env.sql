@@ -0,0 +1 @@
+ALTER TABLE executions ADD COLUMN env TEXT NOT NULL DEFAULT '{}';
@@ -0,0 +1 @@
+ALTER TABLE executions ADD COLUMN syscall TEXT NOT NULL DEFAULT 'execve';
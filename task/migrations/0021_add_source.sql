@@ -0,0 +1 @@
+ALTER TABLE executions ADD COLUMN source TEXT NOT NULL DEFAULT 'sys_enter';
@@ -0,0 +1 @@
+ALTER TABLE executions ADD COLUMN result INTEGER;
@@ -0,0 +1,2 @@
+ALTER TABLE executions ADD COLUMN exited_at TEXT;
+ALTER TABLE executions ADD COLUMN duration_ms INTEGER;
@@ -0,0 +1 @@
+ALTER TABLE executions ADD COLUMN count INTEGER NOT NULL DEFAULT 1;
@@ -0,0 +1,3 @@
+ALTER TABLE executions ADD COLUMN tid INTEGER NOT NULL DEFAULT 0;
+-- rows from before tid was recorded stored the calling thread as pid
+UPDATE executions SET tid = pid;
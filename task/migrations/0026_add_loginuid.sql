@@ -0,0 +1,2 @@
+-- NULL when unset (no login, or audit not built in)
+ALTER TABLE executions ADD COLUMN loginuid INTEGER;
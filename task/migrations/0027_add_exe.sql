@@ -0,0 +1,3 @@
+-- 0 when unknown, as for events recorded before these were
+ALTER TABLE executions ADD COLUMN exe_ino INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE executions ADD COLUMN exe_dev INTEGER NOT NULL DEFAULT 0;
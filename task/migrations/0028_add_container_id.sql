@@ -0,0 +1,2 @@
+-- NULL outside a container, as for events recorded before it was
+ALTER TABLE executions ADD COLUMN container_id TEXT;
@@ -0,0 +1,3 @@
+-- 0 when unknown, as for events recorded before these were
+ALTER TABLE executions ADD COLUMN args_total_len INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE executions ADD COLUMN args_len_overflow BOOLEAN NOT NULL DEFAULT FALSE;
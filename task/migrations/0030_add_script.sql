@@ -0,0 +1,2 @@
+-- NULL for commands that aren't interpreters, as for events recorded before it was
+ALTER TABLE executions ADD COLUMN script TEXT;
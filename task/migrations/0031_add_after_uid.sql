@@ -0,0 +1,2 @@
+-- euid a setuid binary switched to, NULL for every other exec. setuid_detected is derived from it
+ALTER TABLE executions ADD COLUMN after_uid INTEGER;
@@ -0,0 +1,3 @@
+-- NULL when the file couldn't be looked up, as for events recorded before these were
+ALTER TABLE executions ADD COLUMN setuid BOOLEAN;
+ALTER TABLE executions ADD COLUMN setgid BOOLEAN;
@@ -0,0 +1,2 @@
+-- NULL when unknown, as for events recorded before it was
+ALTER TABLE executions ADD COLUMN process_start TEXT;
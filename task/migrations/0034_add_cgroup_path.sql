@@ -0,0 +1,2 @@
+-- NULL when it couldn't be resolved, as for events recorded before it was
+ALTER TABLE executions ADD COLUMN cgroup_path TEXT;
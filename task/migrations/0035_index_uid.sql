@@ -0,0 +1 @@
+CREATE INDEX IF NOT EXISTS idx_executions_uid ON executions (uid);
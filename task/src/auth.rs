@@ -0,0 +1,51 @@
+// Bearer token required on the HTTP and gRPC APIs when --api-key (or TASK_API_KEY) is set
+use std::sync::Arc;
+
+use axum::{
+    extract::{Request, State},
+    http::{HeaderValue, StatusCode, header},
+    middleware::Next,
+    response::Response,
+};
+use subtle::ConstantTimeEq;
+
+use crate::store::error_response;
+
+// Served without a key, for liveness probes and clients discovering the API
+pub const PUBLIC_PATHS: [&str; 3] = ["/health", "/healthz", "/openapi.json"];
+
+#[derive(Clone)]
+pub struct ApiKey(Arc<[u8]>);
+
+impl ApiKey {
+    pub fn new(key: &str) -> Self {
+        Self(key.as_bytes().into())
+    }
+
+    // Constant time over the contents, only a length mismatch returns early
+    fn matches(&self, token: &str) -> bool {
+        self.0.ct_eq(token.as_bytes()).into()
+    }
+
+    // An `Authorization` value (or gRPC `authorization` metadata) carrying this key
+    pub fn authorizes(&self, authorization: &str) -> bool {
+        bearer_token(authorization).is_some_and(|token| self.matches(token))
+    }
+}
+
+// `Bearer <token>`, the scheme is case-insensitive
+fn bearer_token(authorization: &str) -> Option<&str> {
+    let (scheme, token) = authorization.split_once(' ')?;
+    scheme.eq_ignore_ascii_case("bearer").then(|| token.trim())
+}
+
+// Middleware for every route, 401 with `WWW-Authenticate: Bearer` unless the token matches
+pub async fn require_api_key(State(key): State<ApiKey>, request: Request, next: Next) -> Response {
+    let authorization = request.headers().get(header::AUTHORIZATION).and_then(|value| value.to_str().ok());
+    if PUBLIC_PATHS.contains(&request.uri().path()) || authorization.is_some_and(|value| key.authorizes(value)) {
+        return next.run(request).await;
+    }
+    let mut response = error_response(StatusCode::UNAUTHORIZED, "missing or invalid API key".to_string());
+    response.headers_mut().insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+    response
+}
@@ -0,0 +1,281 @@
+// Minimal reader for the running kernel's BTF (/sys/kernel/btf/vmlinux).
+// We only need struct member offsets so the eBPF program can walk `task_struct`
+// without being compiled against one specific kernel layout.
+use anyhow::{Context as _, anyhow, bail};
+use task_common::KernelOffsets;
+
+const BTF_MAGIC: u16 = 0xeb9f;
+
+const BTF_KIND_INT: u32 = 1;
+const BTF_KIND_ARRAY: u32 = 3;
+const BTF_KIND_STRUCT: u32 = 4;
+const BTF_KIND_UNION: u32 = 5;
+const BTF_KIND_ENUM: u32 = 6;
+const BTF_KIND_TYPEDEF: u32 = 8;
+const BTF_KIND_VOLATILE: u32 = 9;
+const BTF_KIND_CONST: u32 = 10;
+const BTF_KIND_RESTRICT: u32 = 11;
+const BTF_KIND_FUNC_PROTO: u32 = 13;
+const BTF_KIND_VAR: u32 = 14;
+const BTF_KIND_DATASEC: u32 = 15;
+const BTF_KIND_DECL_TAG: u32 = 17;
+const BTF_KIND_TYPE_TAG: u32 = 18;
+const BTF_KIND_ENUM64: u32 = 19;
+
+struct Member {
+    name_off: u32,
+    type_id: u32,
+    bit_offset: u32,
+}
+
+struct Type {
+    name_off: u32,
+    kind: u32,
+    type_id: u32,
+    members: Vec<Member>,
+}
+
+pub struct Btf {
+    // index == BTF type id, id 0 is `void`
+    types: Vec<Type>,
+    strings: Vec<u8>,
+}
+
+impl Btf {
+    pub fn from_sys_fs() -> anyhow::Result<Self> {
+        let data = std::fs::read("/sys/kernel/btf/vmlinux").context("reading /sys/kernel/btf/vmlinux")?;
+        Self::parse(&data)
+    }
+
+    pub fn parse(data: &[u8]) -> anyhow::Result<Self> {
+        let u32_at = |off: usize| -> anyhow::Result<u32> {
+            let bytes = data.get(off..off + 4).ok_or_else(|| anyhow!("truncated BTF at offset {off}"))?;
+            Ok(u32::from_ne_bytes(bytes.try_into().unwrap()))
+        };
+
+        if data.len() < 24 || u16::from_ne_bytes([data[0], data[1]]) != BTF_MAGIC {
+            bail!("invalid BTF header");
+        }
+        let hdr_len = u32_at(4)? as usize;
+        let type_off = u32_at(8)? as usize;
+        let type_len = u32_at(12)? as usize;
+        let str_off = u32_at(16)? as usize;
+        let str_len = u32_at(20)? as usize;
+
+        let str_start = hdr_len + str_off;
+        let strings = data
+            .get(str_start..str_start + str_len)
+            .ok_or_else(|| anyhow!("truncated BTF string section"))?
+            .to_vec();
+
+        let mut types = vec![Type { name_off: 0, kind: 0, type_id: 0, members: Vec::new() }];
+        let mut off = hdr_len + type_off;
+        let end = off + type_len;
+        while off < end {
+            let name_off = u32_at(off)?;
+            let info = u32_at(off + 4)?;
+            let type_id = u32_at(off + 8)?;
+            off += 12;
+
+            let vlen = (info & 0xffff) as usize;
+            let kind = (info >> 24) & 0x1f;
+            let kind_flag = info >> 31 == 1;
+            let mut members = Vec::new();
+            match kind {
+                BTF_KIND_INT | BTF_KIND_VAR | BTF_KIND_DECL_TAG => off += 4,
+                BTF_KIND_ARRAY => off += 12,
+                BTF_KIND_STRUCT | BTF_KIND_UNION => {
+                    for _ in 0..vlen {
+                        let offset = u32_at(off + 8)?;
+                        members.push(Member {
+                            name_off: u32_at(off)?,
+                            type_id: u32_at(off + 4)?,
+                            // with kind_flag set the top 8 bits hold the bitfield size
+                            bit_offset: if kind_flag { offset & 0xff_ffff } else { offset },
+                        });
+                        off += 12;
+                    }
+                }
+                BTF_KIND_ENUM | BTF_KIND_FUNC_PROTO => off += vlen * 8,
+                BTF_KIND_DATASEC | BTF_KIND_ENUM64 => off += vlen * 12,
+                _ => {}
+            }
+            types.push(Type { name_off, kind, type_id, members });
+        }
+
+        Ok(Self { types, strings })
+    }
+
+    fn name(&self, name_off: u32) -> &[u8] {
+        let rest = self.strings.get(name_off as usize..).unwrap_or_default();
+        &rest[..rest.iter().position(|&b| b == 0).unwrap_or(rest.len())]
+    }
+
+    // Skip typedefs and qualifiers down to the underlying type
+    fn resolve(&self, mut id: u32) -> &Type {
+        loop {
+            let ty = &self.types[id as usize];
+            match ty.kind {
+                BTF_KIND_TYPEDEF | BTF_KIND_VOLATILE | BTF_KIND_CONST | BTF_KIND_RESTRICT | BTF_KIND_TYPE_TAG => {
+                    id = ty.type_id
+                }
+                _ => return ty,
+            }
+        }
+    }
+
+    // Bit offset of the member called `name`, descending into anonymous structs/unions
+    fn member<'a>(&'a self, ty: &'a Type, name: &str) -> Option<(u32, &'a Member)> {
+        for m in &ty.members {
+            if self.name(m.name_off) == name.as_bytes() {
+                return Some((m.bit_offset, m));
+            }
+            if m.name_off == 0 {
+                let inner = self.resolve(m.type_id);
+                if let Some((bits, found)) = self.member(inner, name) {
+                    return Some((m.bit_offset + bits, found));
+                }
+            }
+        }
+        None
+    }
+
+    // First non-empty definition of `struct <name>`
+    fn find_struct(&self, name: &str) -> anyhow::Result<&Type> {
+        self.types
+            .iter()
+            .find(|t| t.kind == BTF_KIND_STRUCT && !t.members.is_empty() && self.name(t.name_off) == name.as_bytes())
+            .ok_or_else(|| anyhow!("struct {name} not found in BTF"))
+    }
+
+    /// Byte offset of `path` (e.g. `real_parent` or `ns.inum`) inside `struct <struct_name>`.
+    pub fn field_offset(&self, struct_name: &str, path: &str) -> anyhow::Result<u32> {
+        let mut ty = self.find_struct(struct_name)?;
+        let mut bits = 0;
+        for field in path.split('.') {
+            let (offset, member) = self
+                .member(ty, field)
+                .ok_or_else(|| anyhow!("{struct_name}.{path}: no member {field}"))?;
+            bits += offset;
+            ty = self.resolve(member.type_id);
+        }
+        Ok(bits / 8)
+    }
+
+    /// `sizeof(struct <struct_name>)`, for indexing arrays of it.
+    pub fn struct_size(&self, struct_name: &str) -> anyhow::Result<u32> {
+        // for structs the size/type field holds the size
+        Ok(self.find_struct(struct_name)?.type_id)
+    }
+}
+
+// Resolve every offset the eBPF program needs
+pub fn kernel_offsets(btf: &Btf) -> anyhow::Result<KernelOffsets> {
+    Ok(KernelOffsets {
+        task_real_parent: btf.field_offset("task_struct", "real_parent")?,
+        task_tgid: btf.field_offset("task_struct", "tgid")?,
+        task_comm: btf.field_offset("task_struct", "comm")?,
+        task_exit_code: btf.field_offset("task_struct", "exit_code")?,
+        task_mm: btf.field_offset("task_struct", "mm")?,
+        mm_start_stack: btf.field_offset("mm_struct", "start_stack")?,
+        task_cred: btf.field_offset("task_struct", "cred")?,
+        // kuid_t/kgid_t wrap a single `val`, so the member offset is the id itself
+        cred_euid: btf.field_offset("cred", "euid")?,
+        cred_egid: btf.field_offset("cred", "egid")?,
+        task_thread_pid: btf.field_offset("task_struct", "thread_pid")?,
+        pid_level: btf.field_offset("pid", "level")?,
+        pid_numbers: btf.field_offset("pid", "numbers")?,
+        upid_size: btf.struct_size("upid")?,
+        upid_nr: btf.field_offset("upid", "nr")?,
+        upid_ns: btf.field_offset("upid", "ns")?,
+        pid_ns_inum: btf.field_offset("pid_namespace", "ns.inum")?,
+        task_nsproxy: btf.field_offset("task_struct", "nsproxy")?,
+        nsproxy_mnt_ns: btf.field_offset("nsproxy", "mnt_ns")?,
+        mnt_ns_inum: btf.field_offset("mnt_namespace", "ns.inum")?,
+        task_signal: btf.field_offset("task_struct", "signal")?,
+        // array of struct pid *, indexed by enum pid_type
+        signal_pids: btf.field_offset("signal_struct", "pids")?,
+        signal_tty: btf.field_offset("signal_struct", "tty")?,
+        task_fs: btf.field_offset("task_struct", "fs")?,
+        fs_pwd: btf.field_offset("fs_struct", "pwd")?,
+        path_mnt: btf.field_offset("path", "mnt")?,
+        path_dentry: btf.field_offset("path", "dentry")?,
+        dentry_parent: btf.field_offset("dentry", "d_parent")?,
+        dentry_name: btf.field_offset("dentry", "d_name.name")?,
+        vfsmount_mnt_root: btf.field_offset("vfsmount", "mnt_root")?,
+        // struct mount is private to fs/, a vfsmount is embedded in it as `mnt`
+        mount_mnt: btf.field_offset("mount", "mnt")?,
+        mount_parent: btf.field_offset("mount", "mnt_parent")?,
+        mount_mountpoint: btf.field_offset("mount", "mnt_mountpoint")?,
+        // only there with CONFIG_AUDITSYSCALL, 0 makes the probe report it unset
+        task_loginuid: btf.field_offset("task_struct", "loginuid").unwrap_or(0),
+        mm_exe_file: btf.field_offset("mm_struct", "exe_file")?,
+        file_inode: btf.field_offset("file", "f_inode")?,
+        inode_ino: btf.field_offset("inode", "i_ino")?,
+        inode_sb: btf.field_offset("inode", "i_sb")?,
+        // dev_t, a u32
+        sb_dev: btf.field_offset("super_block", "s_dev")?,
+        task_flags: btf.field_offset("task_struct", "flags")?,
+        // only there with CONFIG_CGROUPS, 0 leaves the cgroup name empty
+        task_cgroups: btf.field_offset("task_struct", "cgroups").unwrap_or(0),
+        css_set_dfl_cgrp: btf.field_offset("css_set", "dfl_cgrp").unwrap_or(0),
+        cgroup_kn: btf.field_offset("cgroup", "kn").unwrap_or(0),
+        kernfs_node_name: btf.field_offset("kernfs_node", "name").unwrap_or(0),
+        task_group_leader: btf.field_offset("task_struct", "group_leader")?,
+        // CLOCK_MONOTONIC like bpf_ktime_get_ns(), unlike start_boottime (what /proc shows) which
+        // also counts time suspended
+        task_start_time: btf.field_offset("task_struct", "start_time")?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Hand-built BTF for:
+    //   struct inner { int a; int b; };
+    //   struct outer { int x; union { int y; struct inner in; }; };
+    fn sample() -> Vec<u8> {
+        let strings = b"\0int\0a\0b\0inner\0x\0y\0in\0outer\0";
+        let s = |name: &str| -> u32 {
+            let needle = [&[0u8][..], name.as_bytes(), &[0u8][..]].concat();
+            strings.windows(needle.len()).position(|w| w == needle).unwrap() as u32 + 1
+        };
+        let mut types: Vec<u32> = Vec::new();
+        // [1] int
+        types.extend([s("int"), BTF_KIND_INT << 24, 4, 32]);
+        // [2] struct inner
+        types.extend([s("inner"), BTF_KIND_STRUCT << 24 | 2, 8]);
+        types.extend([s("a"), 1, 0, s("b"), 1, 32]);
+        // [3] anonymous union
+        types.extend([0, BTF_KIND_UNION << 24 | 2, 8]);
+        types.extend([s("y"), 1, 0, s("in"), 2, 0]);
+        // [4] struct outer
+        types.extend([s("outer"), BTF_KIND_STRUCT << 24 | 2, 12]);
+        types.extend([s("x"), 1, 0, 0, 3, 32]);
+
+        let type_bytes: Vec<u8> = types.iter().flat_map(|v| v.to_ne_bytes()).collect();
+        let mut data = Vec::new();
+        data.extend(BTF_MAGIC.to_ne_bytes());
+        data.extend([1u8, 0]);
+        for v in [24u32, 0, type_bytes.len() as u32, type_bytes.len() as u32, strings.len() as u32] {
+            data.extend(v.to_ne_bytes());
+        }
+        data.extend(type_bytes);
+        data.extend(strings);
+        data
+    }
+
+    #[test]
+    fn field_offsets() {
+        let btf = Btf::parse(&sample()).unwrap();
+        assert_eq!(btf.field_offset("outer", "x").unwrap(), 0);
+        // members of anonymous unions are found through the parent
+        assert_eq!(btf.field_offset("outer", "y").unwrap(), 4);
+        assert_eq!(btf.field_offset("outer", "in.b").unwrap(), 8);
+        assert!(btf.field_offset("outer", "z").is_err());
+        assert!(btf.field_offset("missing", "x").is_err());
+        assert_eq!(btf.struct_size("inner").unwrap(), 8);
+        assert_eq!(btf.struct_size("outer").unwrap(), 12);
+    }
+}
@@ -0,0 +1,110 @@
+// cgroup v2 paths of executions (ProcessExecution::cgroup_path). The id the probe reads is the
+// inode number of the cgroup's directory under /sys/fs/cgroup, so an id is resolved by walking the
+// hierarchy. A walk caches every cgroup it sees, an id that isn't cached yet (a container started
+// since) walks it again
+use std::collections::HashMap;
+use std::os::unix::fs::{DirEntryExt, MetadataExt};
+use std::path::{Path, PathBuf};
+
+use crate::store::ProcessExecution;
+
+pub const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+// Owned by the ring buffer reader, which resolves the id of every execution it stores
+pub struct CgroupPaths {
+    root: PathBuf,
+    // None for ids that weren't found on the last walk, so they don't walk it over and over
+    cache: HashMap<u64, Option<String>>,
+}
+
+impl CgroupPaths {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into(), cache: HashMap::new() }
+    }
+
+    // The path below the root with a leading `/` like in /proc/<pid>/cgroup, e.g.
+    // `/system.slice/docker-<id>.scope`. None for an id that's gone (or never was, on cgroup v1)
+    pub fn resolve(&mut self, id: u64) -> Option<String> {
+        if let Some(path) = self.cache.get(&id) {
+            return path.clone();
+        }
+        self.walk();
+        self.cache.entry(id).or_insert(None).clone()
+    }
+
+    pub fn fill(&mut self, execution: &mut ProcessExecution) {
+        execution.cgroup_path = self.resolve(execution.cgroup_id);
+    }
+
+    // Starts over so removed cgroups don't pile up
+    fn walk(&mut self) {
+        self.cache.clear();
+        let Ok(root) = std::fs::metadata(&self.root) else {
+            return;
+        };
+        self.cache.insert(root.ino(), Some("/".to_string()));
+        let mut pending = vec![self.root.clone()];
+        while let Some(dir) = pending.pop() {
+            // a cgroup removed during the walk is skipped
+            let Ok(entries) = std::fs::read_dir(&dir) else { continue };
+            for entry in entries.flatten() {
+                if !entry.file_type().is_ok_and(|t| t.is_dir()) {
+                    continue;
+                }
+                let path = entry.path();
+                if let Some(relative) = relative_path(&self.root, &path) {
+                    self.cache.insert(entry.ino(), Some(relative));
+                }
+                pending.push(path);
+            }
+        }
+    }
+}
+
+fn relative_path(root: &Path, path: &Path) -> Option<String> {
+    Some(format!("/{}", path.strip_prefix(root).ok()?.to_str()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ino(path: &Path) -> u64 {
+        std::fs::metadata(path).unwrap().ino()
+    }
+
+    #[test]
+    fn resolves_by_inode() {
+        let dir = tempfile::tempdir().unwrap();
+        let scope = dir.path().join("system.slice/docker-3f4e1b9c.scope");
+        std::fs::create_dir_all(&scope).unwrap();
+        std::fs::write(dir.path().join("system.slice/cgroup.procs"), "").unwrap();
+        let mut cgroups = CgroupPaths::new(dir.path());
+        assert_eq!(cgroups.resolve(ino(&scope)).as_deref(), Some("/system.slice/docker-3f4e1b9c.scope"));
+        assert_eq!(cgroups.resolve(ino(&dir.path().join("system.slice"))).as_deref(), Some("/system.slice"));
+        assert_eq!(cgroups.resolve(ino(dir.path())).as_deref(), Some("/"));
+        assert_eq!(cgroups.resolve(ino(&dir.path().join("system.slice/cgroup.procs"))), None);
+
+        // created after the first walk, found on the next one
+        let session = dir.path().join("user.slice/session-3.scope");
+        std::fs::create_dir_all(&session).unwrap();
+        assert_eq!(cgroups.resolve(ino(&session)).as_deref(), Some("/user.slice/session-3.scope"));
+        assert_eq!(cgroups.resolve(u64::MAX), None);
+    }
+
+    #[test]
+    fn fills_executions() {
+        let dir = tempfile::tempdir().unwrap();
+        let scope = dir.path().join("kubepods.slice/pod1");
+        std::fs::create_dir_all(&scope).unwrap();
+        let mut cgroups = CgroupPaths::new(dir.path());
+        let mut execution = crate::server::tests::mk_exec(7, "/bin/true");
+        execution.cgroup_id = ino(&scope);
+        cgroups.fill(&mut execution);
+        assert_eq!(execution.cgroup_path.as_deref(), Some("/kubepods.slice/pod1"));
+        // a missing root resolves nothing
+        let mut cgroups = CgroupPaths::new(dir.path().join("missing"));
+        cgroups.fill(&mut execution);
+        assert_eq!(execution.cgroup_path, None);
+    }
+}
@@ -0,0 +1,374 @@
+// Settings read from a TOML file at startup (--config). Command-line flags override the file,
+// which overrides the defaults below. Without --config the defaults are used as they are.
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::{Path, PathBuf};
+
+use anyhow::Context as _;
+use serde::{Deserialize, Serialize};
+
+use crate::constant::{DEFAULT_INTERPRETERS, EXCLUDE_LIST};
+
+// Upper bound for max_events, keeps a typo from reserving gigabytes up front
+pub const MAX_EVENTS_LIMIT: usize = 1_000_000;
+
+// Entries of EXCLUDED_CMDS in the probe
+pub const MAX_EXCLUDED_CMDS: usize = 10;
+
+// Entries of INCLUDED_CMDS in the probe, the allowlist
+pub const MAX_ALLOWED_CMDS: usize = 10;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    // address and port the HTTP API listens on
+    pub bind: IpAddr,
+    pub port: u16,
+    // port of the gRPC API (see proto/task.proto), on the same address as the HTTP API
+    pub grpc_port: u16,
+    // executions kept in memory (and returned by GET /executions)
+    pub max_events: usize,
+    // executions older than this are evicted even below max_events
+    pub event_ttl_seconds: Option<u64>,
+    // tracing filter used when RUST_LOG isn't set, e.g. `info` or `task=debug`
+    pub log_level: String,
+    // commands excluded in the kernel by exact path, or by name on any path when there's no `/`
+    // (`ls`). Replaces EXCLUDE_LIST
+    pub exclude_list: Vec<String>,
+    // commands excluded in the kernel by path prefix, e.g. `/usr/lib/`
+    pub exclude_prefixes: Vec<String>,
+    // commands excluded after the probe by a glob on commandstr, e.g. `/usr/lib/jvm/*`. `globs` of
+    // the `[exclusions]` table
+    pub glob_excludes: Vec<String>,
+    // the allowlist, only these commands are reported when it's not empty. --include-cmd (--only)
+    // and --allowlist-file replace it, and it can't be combined with an exclude_list
+    pub only: Vec<String>,
+    // SQLite database executions are persisted to, in memory only if None
+    pub db_path: Option<PathBuf>,
+    // commands whose executions get a `script`, replaces DEFAULT_INTERPRETERS. Editable at runtime
+    // on /config/interpreters
+    pub interpreters: Vec<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            bind: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            port: 3000,
+            grpc_port: 50051,
+            max_events: 500,
+            event_ttl_seconds: None,
+            log_level: "info".to_string(),
+            exclude_list: EXCLUDE_LIST.iter().map(|cmd| cmd.to_string()).collect(),
+            exclude_prefixes: Vec::new(),
+            glob_excludes: Vec::new(),
+            only: Vec::new(),
+            db_path: None,
+            interpreters: DEFAULT_INTERPRETERS.iter().map(|cmd| cmd.to_string()).collect(),
+        }
+    }
+}
+
+// The file as written, every setting is optional. Unknown keys are an error so typos don't go unnoticed
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    bind: Option<IpAddr>,
+    port: Option<u16>,
+    grpc_port: Option<u16>,
+    max_events: Option<usize>,
+    event_ttl_seconds: Option<u64>,
+    log_level: Option<String>,
+    exclude_list: Option<Vec<String>>,
+    exclude_prefixes: Option<Vec<String>>,
+    only: Option<Vec<String>>,
+    db_path: Option<String>,
+    interpreters: Option<Vec<String>>,
+    // tables come after the top-level keys
+    exclusions: Option<ExclusionsTable>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ExclusionsTable {
+    globs: Option<Vec<String>>,
+}
+
+impl Config {
+    // `path` is --config, which must exist. Without it no file is read and the defaults apply.
+    pub fn load(path: Option<&Path>) -> anyhow::Result<Self> {
+        let Some(path) = path else {
+            return Ok(Self::default());
+        };
+        let text = std::fs::read_to_string(path).with_context(|| format!("failed to read config file {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("invalid config file {}", path.display()))
+    }
+
+    // Keys that are left out keep their defaults
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let file: ConfigFile = toml::from_str(text)?;
+        let defaults = Self::default();
+        Ok(Self {
+            bind: file.bind.unwrap_or(defaults.bind),
+            port: file.port.unwrap_or(defaults.port),
+            grpc_port: file.grpc_port.unwrap_or(defaults.grpc_port),
+            max_events: file.max_events.unwrap_or(defaults.max_events),
+            event_ttl_seconds: file.event_ttl_seconds,
+            log_level: file.log_level.unwrap_or(defaults.log_level),
+            exclude_list: file.exclude_list.unwrap_or(defaults.exclude_list),
+            exclude_prefixes: file.exclude_prefixes.unwrap_or(defaults.exclude_prefixes),
+            glob_excludes: file.exclusions.and_then(|table| table.globs).unwrap_or(defaults.glob_excludes),
+            only: file.only.unwrap_or(defaults.only),
+            db_path: file.db_path.map(PathBuf::from),
+            interpreters: file.interpreters.unwrap_or(defaults.interpreters),
+        })
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            (1..=MAX_EVENTS_LIMIT).contains(&self.max_events),
+            "max_events must be between 1 and {MAX_EVENTS_LIMIT}, got {}",
+            self.max_events
+        );
+        anyhow::ensure!(self.event_ttl_seconds != Some(0), "event_ttl_seconds must be at least 1");
+        anyhow::ensure!(self.port != 0, "port must not be 0");
+        anyhow::ensure!(self.grpc_port != 0, "grpc_port must not be 0");
+        anyhow::ensure!(self.grpc_port != self.port, "grpc_port must differ from port {}", self.port);
+        // paths and bare names go into separate maps of MAX_EXCLUDED_CMDS each
+        let (names, paths): (Vec<&String>, Vec<&String>) = self.exclude_list.iter().partition(|cmd| !cmd.contains('/'));
+        anyhow::ensure!(
+            paths.len() <= MAX_EXCLUDED_CMDS && names.len() <= MAX_EXCLUDED_CMDS,
+            "exclude_list can have at most {MAX_EXCLUDED_CMDS} paths and {MAX_EXCLUDED_CMDS} names"
+        );
+        for cmd in &self.exclude_list {
+            anyhow::ensure!(cmd.len() < task_common::COMMAND_LEN, "exclude_list entry {cmd:?} is longer than {} bytes", task_common::COMMAND_LEN - 1);
+        }
+        let max_prefixes = task_common::MAX_EXCLUDED_PREFIXES;
+        anyhow::ensure!(self.exclude_prefixes.len() <= max_prefixes, "exclude_prefixes can have at most {max_prefixes} entries");
+        for prefix in &self.exclude_prefixes {
+            // an empty prefix would drop everything
+            anyhow::ensure!(
+                !prefix.is_empty() && prefix.len() < task_common::COMMAND_LEN && !prefix.contains('\0'),
+                "exclude_prefixes entry {prefix:?} must be 1 to {} bytes without NUL",
+                task_common::COMMAND_LEN - 1
+            );
+        }
+        crate::globs::compile(&self.glob_excludes).context("invalid [exclusions] globs")?;
+        for cmd in &self.interpreters {
+            anyhow::ensure!(cmd.starts_with('/'), "interpreters entry {cmd:?} isn't an absolute path");
+        }
+        tracing_subscriber::EnvFilter::try_new(&self.log_level).with_context(|| format!("invalid log_level {:?}", self.log_level))?;
+        self.check_allowlist()
+    }
+
+    // The allowlist (`only`, or --include-cmd and --allowlist-file) replaces the exclusions, so an
+    // exclude_list other than the built-in one can't be combined with it
+    fn check_allowlist(&self) -> anyhow::Result<()> {
+        let allowlist = &self.only;
+        if allowlist.is_empty() {
+            return Ok(());
+        }
+        anyhow::ensure!(
+            self.exclude_list == Self::default().exclude_list,
+            "exclude_list can't be combined with an allowlist, only allowed commands are reported"
+        );
+        anyhow::ensure!(self.exclude_prefixes.is_empty(), "exclude_prefixes can't be combined with an allowlist");
+        anyhow::ensure!(allowlist.len() <= MAX_ALLOWED_CMDS, "the allowlist can have at most {MAX_ALLOWED_CMDS} entries, got {}", allowlist.len());
+        for cmd in allowlist {
+            anyhow::ensure!(cmd.len() < task_common::COMMAND_LEN, "allowed command {cmd:?} is longer than {} bytes", task_common::COMMAND_LEN - 1);
+        }
+        Ok(())
+    }
+
+    // --include-cmd, --only and --allowlist-file replace `only` from the file when given
+    pub fn with_allowlist(mut self, allowlist: Vec<String>) -> Self {
+        if !allowlist.is_empty() {
+            self.only = allowlist;
+        }
+        self
+    }
+
+    // The config as a file parse() reads back, for writing out the effective settings
+    pub fn to_toml(&self) -> String {
+        let non_empty = |list: &Vec<String>| (!list.is_empty()).then(|| list.clone());
+        let file = ConfigFile {
+            bind: Some(self.bind),
+            port: Some(self.port),
+            grpc_port: Some(self.grpc_port),
+            max_events: Some(self.max_events),
+            event_ttl_seconds: self.event_ttl_seconds,
+            log_level: Some(self.log_level.clone()),
+            exclude_list: Some(self.exclude_list.clone()),
+            exclude_prefixes: non_empty(&self.exclude_prefixes),
+            only: non_empty(&self.only),
+            db_path: self.db_path.as_ref().map(|path| path.to_string_lossy().into_owned()),
+            interpreters: Some(self.interpreters.clone()),
+            exclusions: non_empty(&self.glob_excludes).map(|globs| ExclusionsTable { globs: Some(globs) }),
+        };
+        // strings, integers and arrays of them, all of which TOML has
+        toml::to_string(&file).expect("config serializes to TOML")
+    }
+}
+
+// --allowlist-file: one command path per line, blank lines and lines starting with `#` are skipped
+pub fn read_allowlist(path: &Path) -> anyhow::Result<Vec<String>> {
+    let text = std::fs::read_to_string(path).with_context(|| format!("failed to read allowlist {}", path.display()))?;
+    Ok(parse_allowlist(&text))
+}
+
+fn parse_allowlist(text: &str) -> Vec<String> {
+    text.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')).map(String::from).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let config = Config {
+            bind: "127.0.0.1".parse().unwrap(),
+            port: 3001,
+            grpc_port: 50052,
+            max_events: 10_000,
+            event_ttl_seconds: Some(3600),
+            log_level: "task=debug".to_string(),
+            exclude_list: vec!["/usr/bin/cat".to_string(), "/opt/odd \"name\"".to_string()],
+            exclude_prefixes: vec!["/usr/lib/".to_string(), "/opt/monitoring/".to_string()],
+            glob_excludes: vec!["/usr/lib/jvm/*".to_string(), "/usr/bin/python3.[0-9]*".to_string()],
+            only: Vec::new(),
+            db_path: Some("/var/lib/task/executions.db".into()),
+            interpreters: vec!["/usr/local/bin/python3.12".to_string()],
+        };
+        config.validate().unwrap();
+        assert_eq!(Config::parse(&config.to_toml()).unwrap(), config);
+        assert_eq!(Config::parse(&Config::default().to_toml()).unwrap(), Config::default());
+        let allowlist = Config { only: vec!["/usr/bin/ssh".to_string()], ..Config::default() };
+        assert_eq!(Config::parse(&allowlist.to_toml()).unwrap(), allowlist);
+    }
+
+    #[test]
+    fn parses_toml() {
+        let config = Config::parse(
+            r#"
+            # comments and blank lines are skipped
+            port = 8080  # trailing comment
+            max_events = 1_000
+            exclude_list = [
+                "/bin/true",  # no # in here
+                "/bin/#odd",
+            ]
+            "#,
+        )
+        .unwrap();
+        assert_eq!((config.port, config.max_events), (8080, 1000));
+        assert_eq!(config.exclude_list, vec!["/bin/true", "/bin/#odd"]);
+        // the rest keeps its defaults
+        assert_eq!(config.bind, Config::default().bind);
+        assert_eq!(config.db_path, None);
+
+        for bad in ["port = \"80\"", "port = 70000", "prot = 80", "port", "exclude_list = [\"/bin/a\"", "[server]", "port = 80\nport = 81"] {
+            assert!(Config::parse(bad).is_err(), "{bad}");
+        }
+
+        // any TOML spelling of the same settings works
+        let config = Config::parse(
+            r#"
+            log_level = 'task=debug'
+            db_path = """/var/lib/task/executions.db"""
+            exclusions = { globs = ['/usr/lib/jvm/*'] }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.log_level, "task=debug");
+        assert_eq!(config.db_path, Some("/var/lib/task/executions.db".into()));
+        assert_eq!(config.glob_excludes, vec!["/usr/lib/jvm/*"]);
+    }
+
+    #[test]
+    fn validate() {
+        assert!(Config::default().validate().is_ok());
+        for config in [
+            Config { max_events: 0, ..Config::default() },
+            Config { max_events: MAX_EVENTS_LIMIT + 1, ..Config::default() },
+            Config { event_ttl_seconds: Some(0), ..Config::default() },
+            Config { grpc_port: 3000, ..Config::default() },
+            Config { grpc_port: 0, ..Config::default() },
+            Config { exclude_list: vec!["/bin/true".to_string(); MAX_EXCLUDED_CMDS + 1], ..Config::default() },
+            Config { exclude_list: vec!["true".to_string(); MAX_EXCLUDED_CMDS + 1], ..Config::default() },
+            Config { exclude_prefixes: vec!["/usr/lib/".to_string(); task_common::MAX_EXCLUDED_PREFIXES + 1], ..Config::default() },
+            Config { exclude_prefixes: vec![String::new()], ..Config::default() },
+            Config { exclude_prefixes: vec!["/".repeat(task_common::COMMAND_LEN)], ..Config::default() },
+            Config { log_level: "=[".to_string(), ..Config::default() },
+            Config { interpreters: vec!["python3".to_string()], ..Config::default() },
+            Config { glob_excludes: vec!["/usr/bin/[a".to_string()], ..Config::default() },
+        ] {
+            assert!(config.validate().is_err(), "{config:?}");
+        }
+    }
+
+    #[test]
+    fn allowlist() {
+        let allowlist = parse_allowlist("# sensitive binaries\n/usr/bin/ssh\n\n  /bin/su  \n");
+        assert_eq!(allowlist, vec!["/usr/bin/ssh", "/bin/su"]);
+        let only = |only: Vec<String>| Config { only, ..Config::default() };
+        only(allowlist.clone()).validate().unwrap();
+
+        // mutually exclusive with a configured exclude_list, fine without an allowlist
+        let config = Config { exclude_list: vec!["/bin/true".to_string()], ..only(allowlist) };
+        assert!(config.validate().is_err());
+        Config { only: Vec::new(), ..config }.validate().unwrap();
+        let prefixes = Config { exclude_prefixes: vec!["/usr/lib/".to_string()], ..Config::default() };
+        prefixes.validate().unwrap();
+        assert!(Config { only: vec!["/usr/bin/ssh".to_string()], ..prefixes }.validate().is_err());
+
+        assert!(only(vec!["/bin/true".to_string(); MAX_ALLOWED_CMDS + 1]).validate().is_err());
+        assert!(only(vec!["x".repeat(task_common::COMMAND_LEN)]).validate().is_err());
+    }
+
+    #[test]
+    fn allowlist_precedence() {
+        // `only` in the file needs the built-in exclude_list, either one alone is fine
+        let text = "only = [\"/usr/bin/ssh\", \"/usr/bin/curl\"]\n";
+        let config = Config::parse(text).unwrap();
+        config.validate().unwrap();
+        assert_eq!(config.only, vec!["/usr/bin/ssh", "/usr/bin/curl"]);
+        let both = Config::parse(&format!("{text}exclude_list = [\"/bin/sleep\"]\n")).unwrap();
+        let err = both.validate().unwrap_err();
+        assert!(err.to_string().contains("exclude_list can't be combined"), "{err}");
+
+        // the flags replace the file's allowlist instead of adding to it
+        let config = config.with_allowlist(vec!["/usr/bin/nc".to_string()]);
+        assert_eq!(config.only, vec!["/usr/bin/nc"]);
+        assert_eq!(config.with_allowlist(Vec::new()).only, vec!["/usr/bin/nc"]);
+        // and are still checked against the file's exclude_list
+        assert!(Config::parse("exclude_list = [\"/bin/sleep\"]").unwrap().with_allowlist(vec!["/usr/bin/nc".to_string()]).validate().is_err());
+    }
+
+    #[test]
+    fn exclusions_table() {
+        let config = Config::parse(
+            r#"
+            port = 8080
+            [exclusions]  # globs only apply to this table
+            globs = ["/usr/lib/jvm/*", "/usr/bin/python3.*"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.port, 8080);
+        assert_eq!(config.glob_excludes, vec!["/usr/lib/jvm/*", "/usr/bin/python3.*"]);
+        // keys of the table aren't top-level keys and the other way around
+        assert!(Config::parse("globs = [\"/usr/lib/jvm/*\"]").is_err());
+        assert!(Config::parse("[exclusions]\nport = 8080").is_err());
+        assert!(Config::parse("[exclusions\nglobs = []").is_err());
+    }
+
+    #[test]
+    fn load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("task.toml");
+        // no file is looked for without --config, an explicit path must exist
+        assert_eq!(Config::load(None).unwrap(), Config::default());
+        assert!(Config::load(Some(&path)).is_err());
+        std::fs::write(&path, "port = 4000\n").unwrap();
+        assert_eq!(Config::load(Some(&path)).unwrap().port, 4000);
+    }
+}
@@ -1,2 +1,12 @@
 pub const EXCLUDE_LIST: [&str; 7] = ["/usr/bin/bash", "/bin/sleep", "/usr/bin/sleep", "/usr/bin/cat", "/bin/sh", "/usr/sbin/ip6tables", "/usr/sbin/iptables"];
-// NOTE(Aditya): Pre-loaded these because these were the most noisy commands on my device
\ No newline at end of file
+// NOTE(Aditya): Pre-loaded these because these were the most noisy commands on my device
+
+// Environment variables reported in `env` unless --env-prefix is given. The `=` keeps `PATH=`
+// from also matching `PATHEXT`. At most MAX_ENV_PREFIXES, see ENV_PREFIXES in the probe
+pub const DEFAULT_ENV_PREFIXES: [&str; 3] = ["LD_PRELOAD=", "LD_LIBRARY_PATH=", "PATH="];
+
+// Interpreters whose executions get the script they run, see the `interpreters` setting
+pub const DEFAULT_INTERPRETERS: [&str; 10] = [
+    "/bin/sh", "/usr/bin/sh", "/bin/bash", "/usr/bin/bash", "/bin/dash", "/usr/bin/zsh", "/usr/bin/python3", "/usr/bin/perl",
+    "/usr/bin/ruby", "/usr/bin/node",
+];
@@ -0,0 +1,901 @@
+// Commands the probe drops by exact path (EXCLUDED_CMDS), editable at runtime on /exclusions,
+// processes it drops by pid (EXCLUDED_PIDS) on /exclusions/pid, with their subtree on
+// /exclusions/subtree (see subtrees.rs), and users it drops by real uid (EXCLUDED_UIDS) on
+// /exclusions/uid
+use std::sync::{Arc, Mutex};
+
+use anyhow::Context as _;
+use aya::maps::{HashMap, MapData, lpm_trie::{Key, LpmTrie}};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+};
+use serde::{Deserialize, Serialize};
+use task_common::{COMMAND_LEN, MAX_EXCLUDED_PIDS, MAX_EXCLUDED_UIDS};
+use tracing::{error, info};
+use utoipa::ToSchema;
+
+use crate::store::error_response;
+use crate::subtrees::Subtrees;
+
+// NUL padded command path, the key of EXCLUDED_CMDS, EXCLUDED_BASENAMES and INCLUDED_CMDS
+pub fn cmd_to_key(cmd: &str) -> [u8; COMMAND_LEN] {
+    let mut key = [0u8; COMMAND_LEN];
+    let bytes = cmd.as_bytes();
+    key[..bytes.len()].copy_from_slice(bytes);
+    key
+}
+
+fn key_to_cmd(key: &[u8; COMMAND_LEN]) -> String {
+    let len = key.iter().position(|&b| b == 0).unwrap_or(COMMAND_LEN);
+    String::from_utf8_lossy(&key[..len]).into_owned()
+}
+
+// What Exclusions needs from the map, so it can be exercised without loading the probe
+pub trait CommandMap: Send {
+    fn insert(&mut self, key: [u8; COMMAND_LEN]) -> anyhow::Result<()>;
+    fn remove(&mut self, key: &[u8; COMMAND_LEN]) -> anyhow::Result<()>;
+    fn keys(&self) -> anyhow::Result<Vec<[u8; COMMAND_LEN]>>;
+}
+
+impl CommandMap for HashMap<MapData, [u8; COMMAND_LEN], u8> {
+    fn insert(&mut self, key: [u8; COMMAND_LEN]) -> anyhow::Result<()> {
+        Ok(HashMap::insert(self, key, 1, 0)?)
+    }
+
+    fn remove(&mut self, key: &[u8; COMMAND_LEN]) -> anyhow::Result<()> {
+        Ok(HashMap::remove(self, key)?)
+    }
+
+    fn keys(&self) -> anyhow::Result<Vec<[u8; COMMAND_LEN]>> {
+        Ok(HashMap::keys(self).collect::<Result<_, _>>()?)
+    }
+}
+
+// What Exclusions needs from EXCLUDED_PIDS, and from EXCLUDED_UIDS keyed by uid
+pub trait PidMap: Send {
+    fn insert(&mut self, pid: u32) -> anyhow::Result<()>;
+    fn remove(&mut self, pid: u32) -> anyhow::Result<()>;
+    fn pids(&self) -> anyhow::Result<Vec<u32>>;
+}
+
+impl PidMap for HashMap<MapData, u32, u8> {
+    fn insert(&mut self, pid: u32) -> anyhow::Result<()> {
+        Ok(HashMap::insert(self, pid, 1, 0)?)
+    }
+
+    fn remove(&mut self, pid: u32) -> anyhow::Result<()> {
+        Ok(HashMap::remove(self, &pid)?)
+    }
+
+    fn pids(&self) -> anyhow::Result<Vec<u32>> {
+        Ok(HashMap::keys(self).collect::<Result<_, _>>()?)
+    }
+}
+
+// Which command filter is in use, they're exclusive. With an allowlist (INCLUDED_CMDS) only its
+// commands are reported and EXCLUDED_CMDS stays empty
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterMode {
+    Allowlist,
+    Denylist,
+}
+
+// Fills INCLUDED_CMDS. With INCLUDE_ONLY set the probe drops every command whose key isn't in it
+pub fn allow(map: &mut impl CommandMap, allowlist: &[String]) -> anyhow::Result<()> {
+    for cmd in allowlist {
+        map.insert(cmd_to_key(cmd)).with_context(|| format!("failed to allow {cmd}"))?;
+    }
+    Ok(())
+}
+
+// Key of EXCLUDED_PREFIXES: the NUL padded prefix, matched on its length in bits
+pub fn prefix_key(prefix: &str) -> Key<[u8; COMMAND_LEN]> {
+    Key::new((prefix.len() * 8) as u32, cmd_to_key(prefix))
+}
+
+// What Prefixes needs from EXCLUDED_PREFIXES
+pub trait PrefixMap: Send {
+    fn insert(&mut self, prefix: &str) -> anyhow::Result<()>;
+    fn remove(&mut self, prefix: &str) -> anyhow::Result<()>;
+    fn prefixes(&self) -> anyhow::Result<Vec<String>>;
+}
+
+impl PrefixMap for LpmTrie<MapData, [u8; COMMAND_LEN], u8> {
+    fn insert(&mut self, prefix: &str) -> anyhow::Result<()> {
+        LpmTrie::insert(self, &prefix_key(prefix), 1, 0).with_context(|| format!("failed to exclude prefix {prefix}"))
+    }
+
+    fn remove(&mut self, prefix: &str) -> anyhow::Result<()> {
+        Ok(LpmTrie::remove(self, &prefix_key(prefix))?)
+    }
+
+    fn prefixes(&self) -> anyhow::Result<Vec<String>> {
+        let keys = self.keys().collect::<Result<Vec<_>, _>>()?;
+        Ok(keys.iter().map(|key| String::from_utf8_lossy(&key.data()[..key.prefix_len() as usize / 8]).into_owned()).collect())
+    }
+}
+
+// EXCLUDED_PREFIXES, commands starting with one of them are dropped in the probe. Filled from
+// exclude_prefixes at startup and on SIGHUP
+#[derive(Clone)]
+pub struct Prefixes(Arc<Mutex<Box<dyn PrefixMap>>>);
+
+impl Prefixes {
+    pub fn new(map: impl PrefixMap + 'static) -> Self {
+        Self(Arc::new(Mutex::new(Box::new(map))))
+    }
+
+    // Like Exclusions::replace, only the prefixes that differ are touched. `prefixes` is validated
+    // with the config
+    pub fn replace(&self, prefixes: &[String]) -> anyhow::Result<Changes> {
+        let mut map = self.0.lock().unwrap();
+        let current = map.prefixes()?;
+        let mut changes = Changes::default();
+        // removed first so the map never goes over max_entries
+        for prefix in current.iter().filter(|prefix| !prefixes.contains(prefix)) {
+            map.remove(prefix)?;
+            changes.removed.push(prefix.clone());
+        }
+        for prefix in prefixes.iter().filter(|prefix| !current.contains(prefix)) {
+            if !changes.added.contains(prefix) {
+                map.insert(prefix)?;
+                changes.added.push(prefix.clone());
+            }
+        }
+        changes.added.sort();
+        changes.removed.sort();
+        Ok(changes)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Added {
+    New,
+    // was already excluded
+    Existing,
+}
+
+// What replace() changed, sorted
+#[derive(Debug, Default, PartialEq)]
+pub struct Changes {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+#[derive(Debug)]
+pub enum ExclusionError {
+    // empty, too long or containing a NUL
+    Invalid(String),
+    // the map holds `max` commands already
+    Full(usize),
+    // EXCLUDED_PIDS holds MAX_EXCLUDED_PIDS already
+    PidsFull(usize),
+    // EXCLUDED_UIDS holds MAX_EXCLUDED_UIDS already
+    UidsFull(usize),
+    // EXCLUDED_ROOTS holds MAX_EXCLUDED_ROOTS already
+    SubtreesFull(usize),
+    // no running process has this pid
+    NoProcess(u32),
+    NotFound,
+    // exclusions can't be added in allowlist mode
+    Allowlist,
+    Map(anyhow::Error),
+}
+
+// Shared by main, which fills it from the config at startup, and the HTTP server. Commands with a
+// `/` are matched on the whole path (EXCLUDED_CMDS), bare names like `ls` on the basename of the
+// path that was run (EXCLUDED_BASENAMES). Pids and uids are excluded whatever the mode
+// (EXCLUDED_PIDS, EXCLUDED_UIDS)
+#[derive(Clone)]
+pub struct Exclusions {
+    map: Arc<Mutex<Box<dyn CommandMap>>>,
+    basenames: Arc<Mutex<Box<dyn CommandMap>>>,
+    pids: Arc<Mutex<Box<dyn PidMap>>>,
+    uids: Arc<Mutex<Box<dyn PidMap>>>,
+    subtrees: Subtrees,
+    // max_entries of each map, inserting past it fails
+    max: usize,
+    mode: FilterMode,
+}
+
+impl Exclusions {
+    pub fn new(
+        map: impl CommandMap + 'static,
+        basenames: impl CommandMap + 'static,
+        pids: impl PidMap + 'static,
+        uids: impl PidMap + 'static,
+        subtrees: Subtrees,
+        max: usize,
+    ) -> Self {
+        Self {
+            map: Arc::new(Mutex::new(Box::new(map))),
+            basenames: Arc::new(Mutex::new(Box::new(basenames))),
+            pids: Arc::new(Mutex::new(Box::new(pids))),
+            uids: Arc::new(Mutex::new(Box::new(uids))),
+            subtrees,
+            max,
+            mode: FilterMode::Denylist,
+        }
+    }
+
+    pub fn with_mode(mut self, mode: FilterMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn mode(&self) -> FilterMode {
+        self.mode
+    }
+
+    // The map `cmd` belongs in
+    fn map_for(&self, cmd: &str) -> &Mutex<Box<dyn CommandMap>> {
+        if is_basename(cmd) { &self.basenames } else { &self.map }
+    }
+
+    pub fn add(&self, cmd: &str) -> Result<Added, ExclusionError> {
+        if self.mode == FilterMode::Allowlist {
+            return Err(ExclusionError::Allowlist);
+        }
+        let key = validate(cmd)?;
+        let mut map = self.map_for(cmd).lock().unwrap();
+        let keys = map.keys().map_err(ExclusionError::Map)?;
+        if keys.contains(&key) {
+            return Ok(Added::Existing);
+        }
+        if keys.len() >= self.max {
+            return Err(ExclusionError::Full(self.max));
+        }
+        map.insert(key).map_err(ExclusionError::Map)?;
+        Ok(Added::New)
+    }
+
+    pub fn remove(&self, cmd: &str) -> Result<(), ExclusionError> {
+        let key = validate(cmd)?;
+        let mut map = self.map_for(cmd).lock().unwrap();
+        if !map.keys().map_err(ExclusionError::Map)?.contains(&key) {
+            return Err(ExclusionError::NotFound);
+        }
+        map.remove(&key).map_err(ExclusionError::Map)
+    }
+
+    // Makes the maps hold exactly `commands`, touching only the keys that differ so the probe never
+    // sees a half-empty map. Exclusions added on /exclusions since are dropped unless listed
+    pub fn replace(&self, commands: &[String]) -> Result<Changes, ExclusionError> {
+        if self.mode == FilterMode::Allowlist {
+            return Err(ExclusionError::Allowlist);
+        }
+        let (names, paths): (Vec<&String>, Vec<&String>) = commands.iter().partition(|cmd| is_basename(cmd));
+        let mut wanted = Vec::new();
+        for (map, commands) in [(&self.map, paths), (&self.basenames, names)] {
+            let mut keys = commands.iter().map(|cmd| validate(cmd)).collect::<Result<Vec<_>, _>>()?;
+            keys.sort();
+            keys.dedup();
+            if keys.len() > self.max {
+                return Err(ExclusionError::Full(self.max));
+            }
+            wanted.push((map, keys));
+        }
+        let mut changes = Changes::default();
+        for (map, wanted) in wanted {
+            let mut map = map.lock().unwrap();
+            let keys = map.keys().map_err(ExclusionError::Map)?;
+            // removed first so the map never goes over max_entries
+            for key in keys.iter().filter(|key| !wanted.contains(key)) {
+                map.remove(key).map_err(ExclusionError::Map)?;
+                changes.removed.push(key_to_cmd(key));
+            }
+            for key in wanted.iter().filter(|key| !keys.contains(key)) {
+                map.insert(*key).map_err(ExclusionError::Map)?;
+                changes.added.push(key_to_cmd(key));
+            }
+        }
+        changes.added.sort();
+        changes.removed.sort();
+        Ok(changes)
+    }
+
+    // Paths and names together, sorted, the maps have no order of their own
+    pub fn list(&self) -> Result<Vec<String>, ExclusionError> {
+        let mut commands = Vec::new();
+        for map in [&self.map, &self.basenames] {
+            let keys = map.lock().unwrap().keys().map_err(ExclusionError::Map)?;
+            commands.extend(keys.iter().map(key_to_cmd));
+        }
+        commands.sort();
+        Ok(commands)
+    }
+
+    // Only running processes, the probe drops the entry when the process exits. A process that
+    // exits while it's being added is taken out again, its pid could be handed out next
+    pub fn exclude_pid(&self, pid: u32) -> Result<Added, ExclusionError> {
+        if !is_process(pid) {
+            return Err(ExclusionError::NoProcess(pid));
+        }
+        let mut map = self.pids.lock().unwrap();
+        let pids = map.pids().map_err(ExclusionError::Map)?;
+        if pids.contains(&pid) {
+            return Ok(Added::Existing);
+        }
+        if pids.len() >= MAX_EXCLUDED_PIDS {
+            return Err(ExclusionError::PidsFull(MAX_EXCLUDED_PIDS));
+        }
+        map.insert(pid).map_err(ExclusionError::Map)?;
+        if !is_process(pid) {
+            map.remove(pid).map_err(ExclusionError::Map)?;
+            return Err(ExclusionError::NoProcess(pid));
+        }
+        Ok(Added::New)
+    }
+
+    pub fn include_pid(&self, pid: u32) -> Result<(), ExclusionError> {
+        let mut map = self.pids.lock().unwrap();
+        if !map.pids().map_err(ExclusionError::Map)?.contains(&pid) {
+            return Err(ExclusionError::NotFound);
+        }
+        map.remove(pid).map_err(ExclusionError::Map)
+    }
+
+    pub fn subtrees(&self) -> &Subtrees {
+        &self.subtrees
+    }
+
+    pub fn pids(&self) -> Result<Vec<u32>, ExclusionError> {
+        let mut pids = self.pids.lock().unwrap().pids().map_err(ExclusionError::Map)?;
+        pids.sort();
+        Ok(pids)
+    }
+
+    // Any uid, 0 included, whether or not a user has it. Unlike pids these stay until removed
+    pub fn exclude_uid(&self, uid: u32) -> Result<Added, ExclusionError> {
+        let mut map = self.uids.lock().unwrap();
+        let uids = map.pids().map_err(ExclusionError::Map)?;
+        if uids.contains(&uid) {
+            return Ok(Added::Existing);
+        }
+        if uids.len() >= MAX_EXCLUDED_UIDS {
+            return Err(ExclusionError::UidsFull(MAX_EXCLUDED_UIDS));
+        }
+        map.insert(uid).map_err(ExclusionError::Map)?;
+        Ok(Added::New)
+    }
+
+    pub fn include_uid(&self, uid: u32) -> Result<(), ExclusionError> {
+        let mut map = self.uids.lock().unwrap();
+        if !map.pids().map_err(ExclusionError::Map)?.contains(&uid) {
+            return Err(ExclusionError::NotFound);
+        }
+        map.remove(uid).map_err(ExclusionError::Map)
+    }
+
+    pub fn uids(&self) -> Result<Vec<u32>, ExclusionError> {
+        let mut uids = self.uids.lock().unwrap().pids().map_err(ExclusionError::Map)?;
+        uids.sort();
+        Ok(uids)
+    }
+}
+
+// A running process rather than a thread of one, /proc also resolves thread ids
+pub fn is_process(pid: u32) -> bool {
+    let Ok(status) = std::fs::read_to_string(format!("/proc/{pid}/status")) else {
+        return false;
+    };
+    status.lines().any(|line| line.strip_prefix("Tgid:").is_some_and(|tgid| tgid.trim() == pid.to_string()))
+}
+
+// `ls` rather than `/bin/ls`, excludes the command whatever directory it was run from
+pub fn is_basename(cmd: &str) -> bool {
+    !cmd.contains('/')
+}
+
+fn validate(cmd: &str) -> Result<[u8; COMMAND_LEN], ExclusionError> {
+    if cmd.is_empty() || cmd.len() >= COMMAND_LEN || cmd.contains('\0') {
+        return Err(ExclusionError::Invalid(format!("`command` must be 1 to {} bytes without NUL, got {cmd:?}", COMMAND_LEN - 1)));
+    }
+    Ok(cmd_to_key(cmd))
+}
+
+impl IntoResponse for ExclusionError {
+    fn into_response(self) -> Response {
+        match self {
+            Self::Invalid(error) => error_response(StatusCode::BAD_REQUEST, error),
+            Self::Full(max) => error_response(StatusCode::INSUFFICIENT_STORAGE, format!("at most {max} commands can be excluded")),
+            Self::PidsFull(max) => error_response(StatusCode::INSUFFICIENT_STORAGE, format!("at most {max} pids can be excluded")),
+            Self::UidsFull(max) => error_response(StatusCode::INSUFFICIENT_STORAGE, format!("at most {max} uids can be excluded")),
+            Self::SubtreesFull(max) => error_response(StatusCode::INSUFFICIENT_STORAGE, format!("at most {max} subtrees can be excluded")),
+            Self::NoProcess(pid) => error_response(StatusCode::NOT_FOUND, format!("no running process has pid {pid}")),
+            Self::NotFound => StatusCode::NOT_FOUND.into_response(),
+            Self::Allowlist => error_response(StatusCode::CONFLICT, "running with an allowlist, exclusions don't apply".to_string()),
+            Self::Map(e) => {
+                error!("Failed to access the exclusion maps: {:#}", e);
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct Exclusion {
+    /// Exact command path, or a name without `/` to match it on any path
+    pub command: String,
+}
+
+/// Commands the probe drops, by exact path or by name (no `/`) on any path
+#[utoipa::path(
+    get,
+    path = "/exclusions",
+    tag = "exclusions",
+    responses((status = 200, description = "The excluded commands, sorted", body = [String]))
+)]
+pub async fn list_exclusions(State(exclusions): State<Exclusions>) -> Result<Json<Vec<String>>, ExclusionError> {
+    exclusions.list().map(Json)
+}
+
+/// Exclude a command by path, or by name without a `/`
+// 201 for a new exclusion, 200 if it was already there
+#[utoipa::path(
+    post,
+    path = "/exclusions",
+    tag = "exclusions",
+    request_body = Exclusion,
+    responses(
+        (status = 200, description = "Already excluded", body = Exclusion),
+        (status = 201, description = "Excluded", body = Exclusion),
+        (status = 400, description = "Empty, too long or containing a NUL", body = ErrorBody),
+        (status = 409, description = "Running with an allowlist", body = ErrorBody),
+        (status = 507, description = "The exclusion map is full", body = ErrorBody),
+    )
+)]
+pub async fn add_exclusion(State(exclusions): State<Exclusions>, Json(exclusion): Json<Exclusion>) -> Result<Response, ExclusionError> {
+    let status = match exclusions.add(&exclusion.command)? {
+        Added::New => {
+            info!("Excluding {}", exclusion.command);
+            StatusCode::CREATED
+        }
+        Added::Existing => StatusCode::OK,
+    };
+    Ok((status, Json(exclusion)).into_response())
+}
+
+/// Stop excluding a command
+// `command` is percent-encoded, e.g. DELETE /exclusions/%2Fusr%2Fbin%2Ffoo
+#[utoipa::path(
+    delete,
+    path = "/exclusions/{command}",
+    tag = "exclusions",
+    params(("command" = String, Path, description = "Percent-encoded command path")),
+    responses(
+        (status = 204, description = "No longer excluded"),
+        (status = 400, description = "Empty, too long or containing a NUL", body = ErrorBody),
+        (status = 404, description = "Not excluded"),
+    )
+)]
+pub async fn remove_exclusion(State(exclusions): State<Exclusions>, Path(command): Path<String>) -> Result<StatusCode, ExclusionError> {
+    exclusions.remove(&command)?;
+    info!("No longer excluding {}", command);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct PidExclusion {
+    pub pid: u32,
+}
+
+/// Processes the probe drops, with the execs of their children
+#[utoipa::path(
+    get,
+    path = "/exclusions/pid",
+    tag = "exclusions",
+    responses((status = 200, description = "The excluded pids, sorted", body = [u32]))
+)]
+pub async fn list_pid_exclusions(State(exclusions): State<Exclusions>) -> Result<Json<Vec<u32>>, ExclusionError> {
+    exclusions.pids().map(Json)
+}
+
+/// Exclude a running process until it exits
+// 201 for a new exclusion, 200 if it was already there, 404 without a running process
+#[utoipa::path(
+    put,
+    path = "/exclusions/pid/{pid}",
+    tag = "exclusions",
+    params(("pid" = u32, Path, description = "Process id (tgid)")),
+    responses(
+        (status = 200, description = "Already excluded", body = PidExclusion),
+        (status = 201, description = "Excluded", body = PidExclusion),
+        (status = 404, description = "No running process with that pid", body = ErrorBody),
+        (status = 507, description = "The pid exclusion map is full", body = ErrorBody),
+    )
+)]
+pub async fn add_pid_exclusion(State(exclusions): State<Exclusions>, Path(pid): Path<u32>) -> Result<Response, ExclusionError> {
+    let status = match exclusions.exclude_pid(pid)? {
+        Added::New => {
+            info!("Excluding pid {}", pid);
+            StatusCode::CREATED
+        }
+        Added::Existing => StatusCode::OK,
+    };
+    Ok((status, Json(PidExclusion { pid })).into_response())
+}
+
+/// Stop excluding a process
+#[utoipa::path(
+    delete,
+    path = "/exclusions/pid/{pid}",
+    tag = "exclusions",
+    params(("pid" = u32, Path, description = "Process id (tgid)")),
+    responses((status = 204, description = "No longer excluded"), (status = 404, description = "Not excluded"))
+)]
+pub async fn remove_pid_exclusion(State(exclusions): State<Exclusions>, Path(pid): Path<u32>) -> Result<StatusCode, ExclusionError> {
+    exclusions.include_pid(pid)?;
+    info!("No longer excluding pid {}", pid);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct UidExclusion {
+    pub uid: u32,
+}
+
+/// Real uids whose execs the probe drops
+#[utoipa::path(
+    get,
+    path = "/exclusions/uid",
+    tag = "exclusions",
+    responses((status = 200, description = "The excluded uids, sorted", body = [u32]))
+)]
+pub async fn list_uid_exclusions(State(exclusions): State<Exclusions>) -> Result<Json<Vec<u32>>, ExclusionError> {
+    exclusions.uids().map(Json)
+}
+
+/// Exclude a real uid, 0 included
+// 201 for a new exclusion, 200 if it was already there
+#[utoipa::path(
+    put,
+    path = "/exclusions/uid/{uid}",
+    tag = "exclusions",
+    params(("uid" = u32, Path, description = "Real uid")),
+    responses(
+        (status = 200, description = "Already excluded", body = UidExclusion),
+        (status = 201, description = "Excluded", body = UidExclusion),
+        (status = 507, description = "The uid exclusion map is full", body = ErrorBody),
+    )
+)]
+pub async fn add_uid_exclusion(State(exclusions): State<Exclusions>, Path(uid): Path<u32>) -> Result<Response, ExclusionError> {
+    let status = match exclusions.exclude_uid(uid)? {
+        Added::New => {
+            info!("Excluding uid {}", uid);
+            StatusCode::CREATED
+        }
+        Added::Existing => StatusCode::OK,
+    };
+    Ok((status, Json(UidExclusion { uid })).into_response())
+}
+
+/// Stop excluding a uid
+#[utoipa::path(
+    delete,
+    path = "/exclusions/uid/{uid}",
+    tag = "exclusions",
+    params(("uid" = u32, Path, description = "Real uid")),
+    responses((status = 204, description = "No longer excluded"), (status = 404, description = "Not excluded"))
+)]
+pub async fn remove_uid_exclusion(State(exclusions): State<Exclusions>, Path(uid): Path<u32>) -> Result<StatusCode, ExclusionError> {
+    exclusions.include_uid(uid)?;
+    info!("No longer excluding uid {}", uid);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Whether only allowlisted commands are reported or the exclusions apply
+#[utoipa::path(get, path = "/config/mode", tag = "config", responses((status = 200, description = "The filter mode", body = FilterMode)))]
+pub async fn get_mode(State(exclusions): State<Exclusions>) -> Json<FilterMode> {
+    Json(exclusions.mode())
+}
+
+// Stands in for EXCLUDED_CMDS in tests
+#[cfg(test)]
+impl CommandMap for std::collections::BTreeSet<[u8; COMMAND_LEN]> {
+    fn insert(&mut self, key: [u8; COMMAND_LEN]) -> anyhow::Result<()> {
+        std::collections::BTreeSet::insert(self, key);
+        Ok(())
+    }
+
+    fn remove(&mut self, key: &[u8; COMMAND_LEN]) -> anyhow::Result<()> {
+        std::collections::BTreeSet::remove(self, key);
+        Ok(())
+    }
+
+    fn keys(&self) -> anyhow::Result<Vec<[u8; COMMAND_LEN]>> {
+        Ok(self.iter().copied().collect())
+    }
+}
+
+// Stands in for EXCLUDED_PIDS in tests
+#[cfg(test)]
+impl PidMap for std::collections::BTreeSet<u32> {
+    fn insert(&mut self, pid: u32) -> anyhow::Result<()> {
+        std::collections::BTreeSet::insert(self, pid);
+        Ok(())
+    }
+
+    fn remove(&mut self, pid: u32) -> anyhow::Result<()> {
+        std::collections::BTreeSet::remove(self, &pid);
+        Ok(())
+    }
+
+    fn pids(&self) -> anyhow::Result<Vec<u32>> {
+        Ok(self.iter().copied().collect())
+    }
+}
+
+// Stands in for EXCLUDED_PREFIXES in tests
+#[cfg(test)]
+impl PrefixMap for std::collections::BTreeSet<String> {
+    fn insert(&mut self, prefix: &str) -> anyhow::Result<()> {
+        std::collections::BTreeSet::insert(self, prefix.to_string());
+        Ok(())
+    }
+
+    fn remove(&mut self, prefix: &str) -> anyhow::Result<()> {
+        std::collections::BTreeSet::remove(self, prefix);
+        Ok(())
+    }
+
+    fn prefixes(&self) -> anyhow::Result<Vec<String>> {
+        Ok(self.iter().cloned().collect())
+    }
+}
+
+#[cfg(test)]
+impl Prefixes {
+    pub fn list(&self) -> anyhow::Result<Vec<String>> {
+        let mut prefixes = self.0.lock().unwrap().prefixes()?;
+        prefixes.sort();
+        Ok(prefixes)
+    }
+}
+
+#[cfg(test)]
+impl Exclusions {
+    pub fn in_memory(max: usize) -> Self {
+        Self::new(
+            std::collections::BTreeSet::new(),
+            std::collections::BTreeSet::new(),
+            std::collections::BTreeSet::new(),
+            std::collections::BTreeSet::new(),
+            Subtrees::in_memory(),
+            max,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn add(exclusions: &Exclusions, command: &str) -> StatusCode {
+        match add_exclusion(State(exclusions.clone()), Json(Exclusion { command: command.to_string() })).await {
+            Ok(response) => response.status(),
+            Err(e) => e.into_response().status(),
+        }
+    }
+
+    #[tokio::test]
+    async fn insert_and_duplicate() {
+        let exclusions = Exclusions::in_memory(10);
+        assert_eq!(add(&exclusions, "/usr/bin/foo").await, StatusCode::CREATED);
+        assert_eq!(add(&exclusions, "/usr/bin/foo").await, StatusCode::OK);
+        assert_eq!(add(&exclusions, "/bin/bar").await, StatusCode::CREATED);
+        let Json(listed) = list_exclusions(State(exclusions.clone())).await.unwrap();
+        assert_eq!(listed, vec!["/bin/bar", "/usr/bin/foo"]);
+
+        assert_eq!(add(&exclusions, "").await, StatusCode::BAD_REQUEST);
+        assert_eq!(add(&exclusions, &"x".repeat(COMMAND_LEN)).await, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn delete() {
+        let exclusions = Exclusions::in_memory(10);
+        add(&exclusions, "/usr/bin/foo").await;
+        let removed = remove_exclusion(State(exclusions.clone()), Path("/usr/bin/foo".to_string())).await.unwrap();
+        assert_eq!(removed, StatusCode::NO_CONTENT);
+        assert!(exclusions.list().unwrap().is_empty());
+        let missing = remove_exclusion(State(exclusions), Path("/usr/bin/foo".to_string())).await.unwrap_err();
+        assert_eq!(missing.into_response().status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn overflow() {
+        let exclusions = Exclusions::in_memory(2);
+        assert_eq!(add(&exclusions, "/bin/a").await, StatusCode::CREATED);
+        assert_eq!(add(&exclusions, "/bin/b").await, StatusCode::CREATED);
+        assert_eq!(add(&exclusions, "/bin/c").await, StatusCode::INSUFFICIENT_STORAGE);
+        // re-adding one that's there isn't an insert
+        assert_eq!(add(&exclusions, "/bin/a").await, StatusCode::OK);
+        // room again after a delete
+        exclusions.remove("/bin/b").unwrap();
+        assert_eq!(add(&exclusions, "/bin/c").await, StatusCode::CREATED);
+    }
+
+    #[test]
+    fn replace() {
+        let exclusions = Exclusions::in_memory(3);
+        exclusions.add("/bin/a").unwrap();
+        exclusions.add("/bin/b").unwrap();
+        let list = |commands: &[&str]| commands.iter().map(|cmd| cmd.to_string()).collect::<Vec<_>>();
+
+        let changes = exclusions.replace(&list(&["/bin/c", "/bin/b", "/bin/d", "/bin/c"])).unwrap();
+        assert_eq!(changes, Changes { added: list(&["/bin/c", "/bin/d"]), removed: list(&["/bin/a"]) });
+        assert_eq!(exclusions.list().unwrap(), list(&["/bin/b", "/bin/c", "/bin/d"]));
+        assert_eq!(exclusions.replace(&list(&["/bin/d", "/bin/c", "/bin/b"])).unwrap(), Changes::default());
+
+        assert!(matches!(exclusions.replace(&list(&["/bin/a", "/bin/b", "/bin/c", "/bin/d"])), Err(ExclusionError::Full(3))));
+        // an invalid entry changes nothing
+        assert!(matches!(exclusions.replace(&list(&["/bin/a", ""])), Err(ExclusionError::Invalid(_))));
+        assert_eq!(exclusions.list().unwrap(), list(&["/bin/b", "/bin/c", "/bin/d"]));
+        assert!(matches!(Exclusions::in_memory(3).with_mode(FilterMode::Allowlist).replace(&[]), Err(ExclusionError::Allowlist)));
+    }
+
+    #[test]
+    fn basenames() {
+        // what the probe looks up in EXCLUDED_BASENAMES for the path that was run
+        let excludes = |name: &str, path: &str| task_common::basename_key(&cmd_to_key(path)) == cmd_to_key(name);
+        assert!(excludes("ls", "/bin/ls"));
+        assert!(excludes("ls", "/usr/bin/ls"));
+        assert!(excludes("ls", "ls"));
+        assert!(!excludes("ls", "/bin/lsattr"));
+        assert!(!excludes("ls", "/usr/bin/als"));
+        assert!(!excludes("ls", "/bin/ls/"));
+        assert!(excludes("lsattr", "/bin/lsattr"));
+
+        let exclusions = Exclusions::in_memory(2);
+        exclusions.add("ls").unwrap();
+        exclusions.add("/bin/true").unwrap();
+        exclusions.add("cat").unwrap();
+        // each map has its own max_entries
+        assert!(matches!(exclusions.add("sleep"), Err(ExclusionError::Full(2))));
+        exclusions.add("/bin/false").unwrap();
+        assert!(exclusions.basenames.lock().unwrap().keys().unwrap().contains(&cmd_to_key("ls")));
+        assert!(!exclusions.map.lock().unwrap().keys().unwrap().contains(&cmd_to_key("ls")));
+        assert_eq!(exclusions.list().unwrap(), vec!["/bin/false", "/bin/true", "cat", "ls"]);
+
+        let changes = exclusions.replace(&["/bin/true".to_string(), "sleep".to_string(), "ls".to_string()]).unwrap();
+        assert_eq!(changes.added, vec!["sleep"]);
+        assert_eq!(changes.removed, vec!["/bin/false", "cat"]);
+        exclusions.remove("ls").unwrap();
+        assert_eq!(exclusions.list().unwrap(), vec!["/bin/true", "sleep"]);
+    }
+
+    #[test]
+    fn prefix_keys() {
+        let key = prefix_key("/usr/lib/");
+        assert_eq!(key.prefix_len(), 72);
+        assert_eq!(&key.data()[..9], b"/usr/lib/");
+        assert!(key.data()[9..].iter().all(|&b| b == 0));
+
+        // what the trie does with the probe's lookup key, the whole padded path: compare the
+        // first prefix_len bits
+        let matches = |prefix: &str, command: &str| {
+            let key = prefix_key(prefix);
+            let len = key.prefix_len() as usize / 8;
+            key.data()[..len] == cmd_to_key(command)[..len]
+        };
+        assert!(matches("/usr/lib/", "/usr/lib/git-core/git"));
+        assert!(matches("/opt/monitoring/", "/opt/monitoring/agent"));
+        assert!(!matches("/usr/lib/", "/usr/bin/ls"));
+        // a path shorter than the prefix has NUL where the prefix continues
+        assert!(!matches("/usr/lib/", "/usr/lib"));
+        // plain string prefixes, no path component boundary
+        assert!(matches("/usr/lib", "/usr/libexec/foo"));
+    }
+
+    #[test]
+    fn replace_prefixes() {
+        let prefixes = Prefixes::new(std::collections::BTreeSet::new());
+        let strings = |prefixes: &[&str]| prefixes.iter().map(|prefix| prefix.to_string()).collect::<Vec<_>>();
+        let changes = prefixes.replace(&strings(&["/usr/lib/", "/opt/monitoring/", "/usr/lib/"])).unwrap();
+        assert_eq!(changes.added, vec!["/opt/monitoring/", "/usr/lib/"]);
+        assert!(changes.removed.is_empty());
+
+        let changes = prefixes.replace(&strings(&["/usr/lib/", "/snap/"])).unwrap();
+        assert_eq!(changes, Changes { added: strings(&["/snap/"]), removed: strings(&["/opt/monitoring/"]) });
+        assert_eq!(prefixes.list().unwrap(), vec!["/snap/", "/usr/lib/"]);
+        assert_eq!(prefixes.replace(&strings(&["/usr/lib/", "/snap/"])).unwrap(), Changes::default());
+        prefixes.replace(&[]).unwrap();
+        assert!(prefixes.list().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn allowlist_mode() {
+        assert_eq!(get_mode(State(Exclusions::in_memory(10))).await.0, FilterMode::Denylist);
+        let exclusions = Exclusions::in_memory(10).with_mode(FilterMode::Allowlist);
+        let Json(mode) = get_mode(State(exclusions.clone())).await;
+        assert_eq!(serde_json::to_value(mode).unwrap(), "allowlist");
+        assert_eq!(add(&exclusions, "/usr/bin/foo").await, StatusCode::CONFLICT);
+        assert!(exclusions.list().unwrap().is_empty());
+
+        // what the probe looks up, an unlisted command has no key and produces no event
+        let mut allowed = std::collections::BTreeSet::new();
+        allow(&mut allowed, &["/usr/bin/ssh".to_string(), "/bin/su".to_string()]).unwrap();
+        assert!(allowed.contains(&cmd_to_key("/usr/bin/ssh")));
+        assert!(!allowed.contains(&cmd_to_key("/usr/bin/ls")));
+        assert!(!allowed.contains(&cmd_to_key("/usr/bin/ssh-agent")));
+    }
+
+    #[tokio::test]
+    async fn pids() {
+        let add = |exclusions: &Exclusions, pid: u32| {
+            let exclusions = exclusions.clone();
+            async move {
+                match add_pid_exclusion(State(exclusions), Path(pid)).await {
+                    Ok(response) => response.status(),
+                    Err(e) => e.into_response().status(),
+                }
+            }
+        };
+        // not tied to the command filter
+        let exclusions = Exclusions::in_memory(10).with_mode(FilterMode::Allowlist);
+        let mut child = std::process::Command::new("sleep").arg("10").spawn().unwrap();
+        assert_eq!(add(&exclusions, child.id()).await, StatusCode::CREATED);
+        assert_eq!(add(&exclusions, child.id()).await, StatusCode::OK);
+        assert_eq!(add(&exclusions, std::process::id()).await, StatusCode::CREATED);
+        let Json(listed) = list_pid_exclusions(State(exclusions.clone())).await.unwrap();
+        let mut expected = vec![child.id(), std::process::id()];
+        expected.sort();
+        assert_eq!(listed, expected);
+
+        // gone (and reaped), or a thread rather than a process
+        child.kill().unwrap();
+        child.wait().unwrap();
+        let removed = remove_pid_exclusion(State(exclusions.clone()), Path(child.id())).await.unwrap();
+        assert_eq!(removed, StatusCode::NO_CONTENT);
+        assert_eq!(add(&exclusions, child.id()).await, StatusCode::NOT_FOUND);
+        let (tid_tx, tid_rx) = std::sync::mpsc::channel();
+        let (done_tx, done_rx) = std::sync::mpsc::channel::<()>();
+        let thread = std::thread::spawn(move || {
+            tid_tx.send(unsafe { libc::gettid() } as u32).unwrap();
+            let _ = done_rx.recv();
+        });
+        assert_eq!(add(&exclusions, tid_rx.recv().unwrap()).await, StatusCode::NOT_FOUND);
+        drop(done_tx);
+        thread.join().unwrap();
+        let missing = remove_pid_exclusion(State(exclusions.clone()), Path(child.id())).await.unwrap_err();
+        assert_eq!(missing.into_response().status(), StatusCode::NOT_FOUND);
+        assert_eq!(exclusions.pids().unwrap(), vec![std::process::id()]);
+    }
+
+    #[tokio::test]
+    async fn uids() {
+        let add = |exclusions: &Exclusions, uid: u32| {
+            let exclusions = exclusions.clone();
+            async move {
+                match add_uid_exclusion(State(exclusions), Path(uid)).await {
+                    Ok(response) => response.status(),
+                    Err(e) => e.into_response().status(),
+                }
+            }
+        };
+        let exclusions = Exclusions::in_memory(10);
+        // empty excludes nobody
+        let Json(listed) = list_uid_exclusions(State(exclusions.clone())).await.unwrap();
+        assert!(listed.is_empty());
+        // root too, and uids no user has
+        assert_eq!(add(&exclusions, 1500).await, StatusCode::CREATED);
+        assert_eq!(add(&exclusions, 0).await, StatusCode::CREATED);
+        assert_eq!(add(&exclusions, 0).await, StatusCode::OK);
+        assert_eq!(exclusions.uids().unwrap(), vec![0, 1500]);
+
+        for uid in 2000..2000 + MAX_EXCLUDED_UIDS as u32 - 2 {
+            assert_eq!(add(&exclusions, uid).await, StatusCode::CREATED);
+        }
+        assert_eq!(add(&exclusions, 3000).await, StatusCode::INSUFFICIENT_STORAGE);
+        assert_eq!(remove_uid_exclusion(State(exclusions.clone()), Path(0)).await.unwrap(), StatusCode::NO_CONTENT);
+        let missing = remove_uid_exclusion(State(exclusions.clone()), Path(0)).await.unwrap_err();
+        assert_eq!(missing.into_response().status(), StatusCode::NOT_FOUND);
+        assert_eq!(add(&exclusions, 3000).await, StatusCode::CREATED);
+        assert!(!exclusions.uids().unwrap().contains(&0));
+        // kept apart from the pids
+        assert!(exclusions.pids().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,84 @@
+// Commands excluded by glob (`[exclusions] globs` in the config), e.g. `/usr/lib/jvm/*` or
+// `/usr/bin/python3.*`. Too involved for the probe, so they're matched in userspace on commandstr
+// before an execution is stored. `*` also matches `/`, a directory glob covers its subdirectories
+use std::sync::{Arc, RwLock};
+
+use anyhow::Context as _;
+use glob::Pattern;
+
+// Shared by main, which filters executions as they come in, and the SIGHUP reload
+#[derive(Clone, Default)]
+pub struct GlobExclusions(Arc<RwLock<Vec<Pattern>>>);
+
+impl GlobExclusions {
+    pub fn new(globs: &[String]) -> anyhow::Result<Self> {
+        Ok(Self(Arc::new(RwLock::new(compile(globs)?))))
+    }
+
+    pub fn list(&self) -> Vec<String> {
+        self.0.read().unwrap().iter().map(|pattern| pattern.as_str().to_string()).collect()
+    }
+
+    // Nothing changes unless every glob is valid
+    pub fn set(&self, globs: &[String]) -> anyhow::Result<()> {
+        *self.0.write().unwrap() = compile(globs)?;
+        Ok(())
+    }
+
+    pub fn is_match(&self, command: &str) -> bool {
+        self.0.read().unwrap().iter().any(|pattern| pattern.matches(command))
+    }
+}
+
+pub fn compile(globs: &[String]) -> anyhow::Result<Vec<Pattern>> {
+    globs.iter().map(|glob| Pattern::new(glob).with_context(|| format!("invalid glob {glob:?}"))).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn globs(globs: &[&str]) -> GlobExclusions {
+        GlobExclusions::new(&globs.iter().map(|glob| glob.to_string()).collect::<Vec<_>>()).unwrap()
+    }
+
+    #[test]
+    fn star() {
+        let jvm = globs(&["/usr/lib/jvm/*"]);
+        assert!(jvm.is_match("/usr/lib/jvm/java"));
+        assert!(jvm.is_match("/usr/lib/jvm/java-17-openjdk/bin/java"));
+        assert!(!jvm.is_match("/usr/lib/jvm"));
+        assert!(!jvm.is_match("/usr/lib/jvmstat"));
+        let python = globs(&["/usr/bin/python3.*"]);
+        assert!(python.is_match("/usr/bin/python3.12"));
+        assert!(!python.is_match("/usr/bin/python3"));
+    }
+
+    #[test]
+    fn question_mark() {
+        let gcc = globs(&["/usr/bin/gcc-1?"]);
+        assert!(gcc.is_match("/usr/bin/gcc-12"));
+        assert!(!gcc.is_match("/usr/bin/gcc-9"));
+        assert!(!gcc.is_match("/usr/bin/gcc-123"));
+    }
+
+    #[test]
+    fn character_classes() {
+        let versions = globs(&["/usr/bin/python3.[0-9]", "/opt/[!.]*/run"]);
+        assert!(versions.is_match("/usr/bin/python3.8"));
+        assert!(!versions.is_match("/usr/bin/python3.x"));
+        assert!(!versions.is_match("/usr/bin/python3.12"));
+        assert!(versions.is_match("/opt/app/run"));
+        assert!(!versions.is_match("/opt/.hidden/run"));
+    }
+
+    #[test]
+    fn invalid_globs_change_nothing() {
+        let exclusions = globs(&["/usr/lib/jvm/*"]);
+        let err = exclusions.set(&["/bin/*".to_string(), "/opt/[a".to_string()]).unwrap_err();
+        assert!(format!("{err:#}").contains("/opt/[a"), "{err:#}");
+        assert_eq!(exclusions.list(), vec!["/usr/lib/jvm/*"]);
+        exclusions.set(&[]).unwrap();
+        assert!(!exclusions.is_match("/usr/lib/jvm/java"));
+    }
+}
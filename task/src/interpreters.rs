@@ -0,0 +1,118 @@
+// Interpreters whose executions get a `script` (ProcessExecution::script), from the config and
+// editable at runtime on /config/interpreters. Only executions seen after a change are affected
+use std::sync::{Arc, RwLock};
+
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+};
+use tracing::info;
+
+use crate::store::{ProcessExecution, error_response};
+
+// The script an interpreter was started on: the first argument after argv[0] that isn't an option.
+// None for code given inline (`sh -c`, `perl -e`, `python3 -m`) or read from stdin. argstr is split
+// on spaces, a script path containing one is cut there
+pub fn script_path(argstr: &str) -> Option<String> {
+    for arg in argstr.split(' ').skip(1) {
+        match arg {
+            "-c" | "-e" | "-m" | "-" => return None,
+            "" | "--" => continue,
+            arg if arg.starts_with('-') => continue,
+            arg => return Some(arg.to_string()),
+        }
+    }
+    None
+}
+
+// Shared by main, which fills in scripts as executions come in, and the HTTP server
+#[derive(Clone)]
+pub struct Interpreters(Arc<RwLock<Vec<String>>>);
+
+impl Interpreters {
+    pub fn new(interpreters: Vec<String>) -> Self {
+        Self(Arc::new(RwLock::new(interpreters)))
+    }
+
+    pub fn list(&self) -> Vec<String> {
+        self.0.read().unwrap().clone()
+    }
+
+    pub fn set(&self, interpreters: Vec<String>) {
+        *self.0.write().unwrap() = interpreters;
+    }
+
+    fn contains(&self, command: &str) -> bool {
+        self.0.read().unwrap().iter().any(|i| i == command)
+    }
+
+    // Sets `script` when the command (as run or resolved against the cwd) is one of the interpreters
+    pub fn detect(&self, execution: &mut ProcessExecution) {
+        if self.contains(&execution.commandstr) || self.contains(&execution.resolved_command) {
+            execution.script = script_path(&execution.argstr);
+        }
+    }
+}
+
+/// Commands whose executions get a script
+#[utoipa::path(
+    get,
+    path = "/config/interpreters",
+    tag = "config",
+    responses((status = 200, description = "The interpreters", body = [String]))
+)]
+pub async fn get_interpreters(State(interpreters): State<Interpreters>) -> Json<Vec<String>> {
+    Json(interpreters.list())
+}
+
+/// Replace the interpreters, for executions seen from now on
+// Replaces the whole list with absolute paths
+#[utoipa::path(
+    put,
+    path = "/config/interpreters",
+    tag = "config",
+    request_body = Vec<String>,
+    responses(
+        (status = 200, description = "The new interpreters", body = [String]),
+        (status = 400, description = "Not all absolute paths", body = ErrorBody),
+    )
+)]
+pub async fn put_interpreters(State(interpreters): State<Interpreters>, Json(list): Json<Vec<String>>) -> Response {
+    if let Some(path) = list.iter().find(|path| !path.starts_with('/')) {
+        return error_response(StatusCode::BAD_REQUEST, format!("interpreters must be absolute paths, got {path:?}"));
+    }
+    info!("Detecting scripts run by {}", list.join(", "));
+    interpreters.set(list.clone());
+    Json(list).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn script_arguments() {
+        assert_eq!(script_path("bash ./deploy.sh --force").as_deref(), Some("./deploy.sh"));
+        assert_eq!(script_path("python3 -u -B /opt/app/main.py 8080").as_deref(), Some("/opt/app/main.py"));
+        assert_eq!(script_path("sh -- run.sh").as_deref(), Some("run.sh"));
+        for inline in ["sh -c echo hi", "python3 -m http.server", "perl -e print", "bash -", "bash", "bash -l", ""] {
+            assert_eq!(script_path(inline), None, "{inline}");
+        }
+    }
+
+    #[tokio::test]
+    async fn runtime_list() {
+        let interpreters = Interpreters::new(vec!["/bin/sh".to_string()]);
+        assert!(!interpreters.contains("/usr/bin/python3"));
+
+        let list = vec!["/bin/sh".to_string(), "/usr/bin/python3".to_string()];
+        assert_eq!(put_interpreters(State(interpreters.clone()), Json(list.clone())).await.status(), StatusCode::OK);
+        assert_eq!(get_interpreters(State(interpreters.clone())).await.0, list);
+        assert!(interpreters.contains("/usr/bin/python3"));
+
+        let relative = put_interpreters(State(interpreters.clone()), Json(vec!["python3".to_string()])).await;
+        assert_eq!(relative.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(interpreters.list(), list);
+    }
+}
@@ -1,35 +1,266 @@
-use aya::maps::AsyncPerfEventArray;
+use aya::maps::{Array, HashMap, LpmTrie, MapData, PerCpuArray, RingBuf};
 use aya::programs::TracePoint;
-use aya::util::online_cpus;
-use aya::maps::HashMap;
-use bytes::BytesMut;
-use task_common::{ExecEvent, ARGV_OFFSET, COMMAND_LEN};
+use anyhow::Context;
+use task_common::{
+    ExecEvent, ExecResultEvent, ExitEvent, ForkEvent, KernelOffsets, SetuidEvent, ARGV_OFFSET, COMMAND_LEN,
+    ENV_PREFIX_LEN, MAX_ENV_PREFIXES, MAX_EXCLUDED_UIDS,
+};
 use std::convert::TryInto;
+use std::sync::atomic::Ordering;
+use tokio::io::unix::AsyncFd;
+use tokio::io::Interest;
 use tokio::signal;
-use tracing::{info, warn, error};
+use tokio::sync::watch;
+use tracing::{debug, info, warn, error};
 use std::time::{SystemTime, UNIX_EPOCH};
 use chrono::Duration as ChronoDuration;
+use clap::Parser;
+use regex::RegexSet;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
 
+mod auth;
+mod btf;
+mod cgroups;
+mod config;
+mod exclusions;
+mod globs;
+mod interpreters;
+mod metrics;
+mod openapi;
+mod reassembly;
+mod reload;
+mod stdout;
 mod store;
+mod subtrees;
 mod server;
+mod tls;
 mod constant;
-use store::{ProcessExecution, ExecutionStorage};
+use store::{ExecResult, ExitRecord, ForkRecord, ProcessExecution, ExecutionStorage, SetuidRecord, SqliteExecutionStorage, StorageWrite};
+use reassembly::Reassembler;
 use server::start_http_server;
-use crate::constant::EXCLUDE_LIST;
+use server::grpc::start_grpc_server;
+use config::{Config, MAX_EXCLUDED_CMDS};
+use exclusions::{Exclusions, FilterMode, Prefixes};
+use interpreters::Interpreters;
+use globs::GlobExclusions;
+use subtrees::Subtrees;
+use cgroups::{CgroupPaths, CGROUP_ROOT};
+use auth::ApiKey;
+use tls::TlsFiles;
+use stdout::{ExecutionPrinter, StdoutFormat};
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
 
-pub const MAX_EVENTS: usize = 500;
+// How long open /executions/stream connections get to finish before the server is stopped anyway
+const SHUTDOWN_GRACE: std::time::Duration = std::time::Duration::from_secs(5);
+
+// Tracepoints executions are taken from
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+enum AttachMode {
+    /// syscalls/sys_enter_execve{,at}: every exec attempt, including failed ones
+    Syscalls,
+    /// sched/sched_process_exec: only execs that succeeded
+    SchedExec,
+}
+
+// How log lines are written to stderr
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+enum LogFormat {
+    /// Human-readable lines
+    Text,
+    /// One JSON object per line, with the fields of each event (pid, command, ...) as keys
+    Json,
+}
+
+#[derive(Debug, Parser)]
+struct Opt {
+    /// TOML file with defaults for the settings below, flags take precedence over it
+    /// (the built-in defaults are used if this is omitted)
+    #[clap(long, value_name = "PATH")]
+    config: Option<PathBuf>,
+    /// Persist executions to a SQLite database at this path (in-memory only if omitted)
+    #[clap(long)]
+    db_path: Option<PathBuf>,
+    /// Prune persisted executions older than this many days
+    #[clap(long, default_value_t = 7)]
+    max_age_days: u32,
+    /// Number of executions kept in memory (and returned by GET /executions) [default: 500]
+    #[clap(long)]
+    max_events: Option<usize>,
+    /// Address the HTTP API listens on [default: 0.0.0.0]
+    #[clap(long)]
+    bind: Option<IpAddr>,
+    /// Port the HTTP API listens on [default: 3000]
+    #[clap(long)]
+    port: Option<u16>,
+    /// Port the gRPC API listens on, on the address of the HTTP API [default: 50051]
+    #[clap(long)]
+    grpc_port: Option<u16>,
+    /// Full address the HTTP API listens on (e.g. 127.0.0.1:3001), replaces --bind and --port
+    #[clap(long, env = "TASK_LISTEN", conflicts_with_all = ["bind", "port"])]
+    listen: Option<SocketAddr>,
+    /// Evict executions older than this many seconds, even if --max-events isn't reached (disabled if omitted)
+    #[clap(long)]
+    event_ttl_seconds: Option<u64>,
+    /// Collapse repeated executions of the same command line by the same pid within this many
+    /// milliseconds into one record with a `count` (disabled if omitted)
+    #[clap(long)]
+    dedup_window_ms: Option<u64>,
+    /// Drop executions whose full command line (command and arguments) matches this regex, e.g.
+    /// `^/usr/lib/` (repeatable). Checked in user space, after the kernel's exact command filter
+    #[clap(long = "exclude-pattern", value_name = "REGEX")]
+    exclude_patterns: Vec<String>,
+    /// Only report this command, e.g. `/usr/bin/ssh` (repeatable, at most 10 together with
+    /// --allowlist-file). Exact path match in the kernel, replaces the exclusions and `only` in
+    /// the config file
+    #[clap(long = "include-cmd", visible_alias = "only", value_name = "PATH")]
+    include_cmds: Vec<String>,
+    /// Only report the commands listed in this file, one path per line (`#` starts a comment line).
+    /// Like --include-cmd, can't be combined with an exclude_list in the config file
+    #[clap(long, value_name = "PATH")]
+    allowlist_file: Option<PathBuf>,
+    /// Also report execs by kernel threads and the usermode helpers they start (e.g. modprobe),
+    /// which are dropped in the kernel by default
+    #[clap(long)]
+    include_kernel_threads: bool,
+    /// Also report execs by this process and the children it starts, which are dropped in the
+    /// kernel by default
+    #[clap(long)]
+    include_self: bool,
+    /// Drop executions by this real uid in the kernel (repeatable, at most 10). Edited at runtime on
+    /// /exclusions/uid
+    #[clap(long = "exclude-uid", value_name = "UID")]
+    exclude_uids: Vec<u32>,
+    /// Report environment variables starting with this prefix, e.g. `LD_PRELOAD=` (repeatable, at most 4).
+    /// Replaces the built-in list, end a name with `=` to match that variable only
+    #[clap(long = "env-prefix", value_name = "PREFIX", default_values = constant::DEFAULT_ENV_PREFIXES)]
+    env_prefixes: Vec<String>,
+    /// Where executions are traced
+    #[clap(long, value_enum, default_value_t = AttachMode::Syscalls)]
+    attach: AttachMode,
+    /// Format of the log output, `json` for log pipelines such as ELK
+    #[clap(long, value_enum, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+    /// Also print each execution to stdout, `ndjson` for one JSON object per line (logs move to stderr)
+    #[clap(long, value_enum, default_value_t = StdoutFormat::None)]
+    stdout_format: StdoutFormat,
+    /// Require `Authorization: Bearer <key>` on the HTTP API (except on /health and /openapi.json)
+    /// and the gRPC API
+    #[clap(long, value_name = "SECRET", env = "TASK_API_KEY", hide_env_values = true)]
+    api_key: Option<String>,
+    /// Serve the HTTP API over HTTPS with this PEM certificate chain, needs --tls-key.
+    /// Both files are read again on SIGHUP
+    #[clap(long, value_name = "PATH")]
+    tls_cert: Option<PathBuf>,
+    /// PEM private key for --tls-cert
+    #[clap(long, value_name = "PATH")]
+    tls_key: Option<PathBuf>,
+}
+
+impl Opt {
+    // The config file with the flags that were given applied on top
+    fn config(&self) -> anyhow::Result<Config> {
+        let mut config = Config::load(self.config.as_deref())?;
+        if let Some(bind) = self.bind {
+            config.bind = bind;
+        }
+        if let Some(port) = self.port {
+            config.port = port;
+        }
+        if let Some(grpc_port) = self.grpc_port {
+            config.grpc_port = grpc_port;
+        }
+        if let Some(max_events) = self.max_events {
+            config.max_events = max_events;
+        }
+        if let Some(ttl) = self.event_ttl_seconds {
+            config.event_ttl_seconds = Some(ttl);
+        }
+        if let Some(db_path) = &self.db_path {
+            config.db_path = Some(db_path.clone());
+        }
+        let config = config.with_allowlist(self.allowlist()?);
+        config.validate()?;
+        Ok(config)
+    }
+
+    // --include-cmd and the commands of --allowlist-file, empty unless running in allowlist mode
+    fn allowlist(&self) -> anyhow::Result<Vec<String>> {
+        let mut allowlist = self.include_cmds.clone();
+        if let Some(path) = &self.allowlist_file {
+            let listed = config::read_allowlist(path)?;
+            anyhow::ensure!(!listed.is_empty(), "allowlist {} lists no commands", path.display());
+            allowlist.extend(listed);
+        }
+        Ok(allowlist)
+    }
+}
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    // Initialize tracing
-    tracing_subscriber::fmt()
-        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
-        .init();
+    let opt = Opt::parse();
+    let config = opt.config()?;
+    let allowlist = config.only.clone();
+    let mode = if allowlist.is_empty() { FilterMode::Denylist } else { FilterMode::Allowlist };
+    anyhow::ensure!(opt.api_key.as_ref().is_none_or(|key| !key.is_empty()), "--api-key (TASK_API_KEY) is empty");
+    let api_key = opt.api_key.as_deref().map(ApiKey::new);
+    let tls_files = TlsFiles::new(opt.tls_cert.clone(), opt.tls_key.clone())?;
+    // read before attaching anything, a bad certificate fails right away
+    let tls = match &tls_files {
+        Some(files) => Some(files.load().await?),
+        None => None,
+    };
+
+    // Initialize tracing, RUST_LOG takes precedence over log_level
+    // stdout is left to the executions with --stdout-format
+    let log_writer = match opt.stdout_format {
+        StdoutFormat::None => BoxMakeWriter::new(std::io::stdout),
+        StdoutFormat::Ndjson => BoxMakeWriter::new(std::io::stderr),
+    };
+    let subscriber = tracing_subscriber::fmt().with_writer(log_writer).with_env_filter(
+        tracing_subscriber::EnvFilter::try_from_default_env()
+            .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new(&config.log_level)),
+    );
+    match opt.log_format {
+        LogFormat::Text => subscriber.init(),
+        LogFormat::Json => subscriber.json().init(),
+    }
 
     info!("Starting eBPF runtime process monitor with HTTP API");
+    debug!("Effective config:\n{}", config.to_toml());
 
     // Create shared storage
-    let storage = ExecutionStorage::new();
+    let storage = match &config.db_path {
+        Some(path) => {
+            info!("Persisting executions to {}", path.display());
+            ExecutionStorage::sqlite(SqliteExecutionStorage::open(path, opt.max_age_days).await?, config.max_events)
+        }
+        None => ExecutionStorage::new(config.max_events),
+    };
+    let storage = match config.event_ttl_seconds {
+        Some(ttl) => {
+            info!("Evicting executions older than {}s", ttl);
+            let storage = storage.with_ttl(ChronoDuration::seconds(ttl as i64));
+            // add_execution only evicts when something new comes in, this covers quiet periods
+            let evicting = storage.clone();
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(std::time::Duration::from_secs(30));
+                loop {
+                    interval.tick().await;
+                    evicting.evict_expired().await;
+                }
+            });
+            storage
+        }
+        None => storage,
+    };
+    let storage = match opt.dedup_window_ms {
+        Some(window) => {
+            info!("Counting repeated executions within {}ms as one", window);
+            storage.with_dedup(ChronoDuration::milliseconds(window as i64))
+        }
+        None => storage,
+    };
     let storage_clone = storage.clone();
 
     // Establish boot offset: wall_clock_now - monotonic_now
@@ -57,90 +288,455 @@ async fn main() -> anyhow::Result<()> {
     // runtime. This approach is recommended for most real-world use cases. If you would
     // like to specify the eBPF program at runtime rather than at compile-time, you can
     // reach for `Bpf::load_file` instead.
-    let mut ebpf = aya::Ebpf::load(aya::include_bytes_aligned!(concat!(
-        env!("OUT_DIR"),
-        "/task"
-    )))?;
+    //
+    // Kernel struct offsets are resolved from BTF and patched into the program so it can walk
+    // `task_struct` on any kernel; fields depending on them are reported as 0 without BTF.
+    let offsets = btf::Btf::from_sys_fs()
+        .and_then(|btf| btf::kernel_offsets(&btf))
+        .unwrap_or_else(|e| {
+            warn!("failed to resolve kernel offsets from BTF: {e:#}");
+            KernelOffsets::default()
+        });
+    let include_only = u8::from(mode == FilterMode::Allowlist);
+    let include_kernel_threads = u8::from(opt.include_kernel_threads);
+    // the probe sees pids of the root namespace, in a container this needs `pid: host`
+    let monitor_pid = if opt.include_self { 0 } else { std::process::id() };
+    let mut ebpf = aya::EbpfLoader::new()
+        .set_global("KERNEL_OFFSETS", &offsets, true)
+        .set_global("INCLUDE_ONLY", &include_only, true)
+        .set_global("INCLUDE_KERNEL_THREADS", &include_kernel_threads, true)
+        .set_global("MONITOR_PID", &monitor_pid, true)
+        .load(aya::include_bytes_aligned!(concat!(
+            env!("OUT_DIR"),
+            "/task"
+        )))?;
     if let Err(e) = aya_log::EbpfLogger::init(&mut ebpf) {
         // This can happen if you remove all log statements from your eBPF program.
         warn!("failed to initialize eBPF logger: {e}");
     }
-    let program: &mut TracePoint = ebpf.program_mut("task").unwrap().try_into()?;
+    match opt.attach {
+        AttachMode::Syscalls => {
+            let program: &mut TracePoint = ebpf.program_mut("task").unwrap().try_into()?;
+            program.load()?;
+            program.attach("syscalls", "sys_enter_execve")?;
+            // execveat() runs files relative to a directory fd, used by container runtimes among others
+            let program: &mut TracePoint = ebpf.program_mut("task_execveat").unwrap().try_into()?;
+            program.load()?;
+            program.attach("syscalls", "sys_enter_execveat")?;
+            // Fills in `result` once the exec returned
+            let program: &mut TracePoint = ebpf.program_mut("task_exec_result").unwrap().try_into()?;
+            program.load()?;
+            program.attach("syscalls", "sys_exit_execve")?;
+            program.attach("syscalls", "sys_exit_execveat")?;
+            info!(attach = "sys_enter_execve", "Tracing exec attempts, failed execs are reported too");
+        }
+        AttachMode::SchedExec => {
+            // argv is found through current->mm
+            anyhow::ensure!(offsets.task_mm != 0, "--attach sched-exec needs the kernel's BTF");
+            let program: &mut TracePoint = ebpf.program_mut("task_sched_exec").unwrap().try_into()?;
+            program.load()?;
+            program.attach("sched", "sched_process_exec")?;
+            info!(
+                attach = "sched_process_exec",
+                "Tracing successful execs only: syscall is unknown and dirfd -1, caller is the comm of the new program \
+                 and a script's argv starts with its interpreter"
+            );
+        }
+    }
+    // Process exits, so GET /executions/:pid/exit can tell how long something ran
+    let program: &mut TracePoint = ebpf.program_mut("task_exit").unwrap().try_into()?;
+    program.load()?;
+    program.attach("sched", "sched_process_exit")?;
+    // Process creation, so GET /executions/:pid/ancestry can cross forks that never exec'd
+    let program: &mut TracePoint = ebpf.program_mut("task_fork").unwrap().try_into()?;
     program.load()?;
-    program.attach("syscalls", "sys_enter_execve")?;
+    program.attach("sched", "sched_process_fork")?;
 
-    // Populate exclusion map in kernel (EXCLUDED_CMDS)
-    let map = ebpf.map_mut("EXCLUDED_CMDS").unwrap();
-    let mut excluded_cmds: HashMap<_, [u8; COMMAND_LEN], u8> = HashMap::try_from(map)?;
-    for cmd in EXCLUDE_LIST.iter() {
-        let key = cmd_to_key(cmd);
-        excluded_cmds.insert(key, 1, 0)?;
+    // Populate exclusion maps in kernel (EXCLUDED_CMDS for paths, EXCLUDED_BASENAMES for bare
+    // names), owned from here on so /exclusions can edit them. Left empty in allowlist mode
+    let excluded_cmds: HashMap<_, [u8; COMMAND_LEN], u8> = HashMap::try_from(ebpf.take_map("EXCLUDED_CMDS").unwrap())?;
+    let excluded_basenames: HashMap<_, [u8; COMMAND_LEN], u8> = HashMap::try_from(ebpf.take_map("EXCLUDED_BASENAMES").unwrap())?;
+    // EXCLUDED_PIDS starts empty and is only edited on /exclusions/pid
+    let excluded_pids: HashMap<_, u32, u8> = HashMap::try_from(ebpf.take_map("EXCLUDED_PIDS").unwrap())?;
+    // Likewise EXCLUDED_ROOTS, on /exclusions/subtree. The probe fills EXCLUDED_DESCENDANTS from forks
+    let excluded_roots: HashMap<_, u32, u8> = HashMap::try_from(ebpf.take_map("EXCLUDED_ROOTS").unwrap())?;
+    let excluded_descendants: HashMap<_, u32, u32> = HashMap::try_from(ebpf.take_map("EXCLUDED_DESCENDANTS").unwrap())?;
+    let subtrees = Subtrees::new(excluded_roots, excluded_descendants);
+    // EXCLUDED_UIDS starts with --exclude-uid, then /exclusions/uid edits it
+    anyhow::ensure!(opt.exclude_uids.len() <= MAX_EXCLUDED_UIDS, "at most {MAX_EXCLUDED_UIDS} --exclude-uid can be given");
+    let mut excluded_uids: HashMap<_, u32, u8> = HashMap::try_from(ebpf.take_map("EXCLUDED_UIDS").unwrap())?;
+    for uid in &opt.exclude_uids {
+        excluded_uids.insert(uid, 1, 0).with_context(|| format!("failed to exclude uid {uid}"))?;
+    }
+    let exclusions = Exclusions::new(excluded_cmds, excluded_basenames, excluded_pids, excluded_uids, subtrees, MAX_EXCLUDED_CMDS).with_mode(mode);
+    if mode == FilterMode::Denylist {
+        for cmd in &config.exclude_list {
+            exclusions.add(cmd).map_err(|e| anyhow::anyhow!("failed to exclude {cmd}: {e:?}"))?;
+        }
+    }
+
+    // Populate prefix exclusion map in kernel (EXCLUDED_PREFIXES), kept to reload it on SIGHUP
+    let excluded_prefixes: LpmTrie<_, [u8; COMMAND_LEN], u8> = LpmTrie::try_from(ebpf.take_map("EXCLUDED_PREFIXES").unwrap())?;
+    let prefixes = Prefixes::new(excluded_prefixes);
+    prefixes.replace(&config.exclude_prefixes)?;
+    if !config.exclude_prefixes.is_empty() {
+        info!("Excluding commands starting with {}", config.exclude_prefixes.join(", "));
+    }
+
+    // Populate inclusion map in kernel (INCLUDED_CMDS)
+    let mut included_cmds: HashMap<_, [u8; COMMAND_LEN], u8> = HashMap::try_from(ebpf.take_map("INCLUDED_CMDS").unwrap())?;
+    exclusions::allow(&mut included_cmds, &allowlist)?;
+    if mode == FilterMode::Allowlist {
+        info!("Allowlist mode, only reporting {}", allowlist.join(", "));
+    }
+
+    // Populate environment prefixes in kernel (ENV_PREFIXES)
+    anyhow::ensure!(opt.env_prefixes.len() <= MAX_ENV_PREFIXES, "at most {MAX_ENV_PREFIXES} --env-prefix can be given");
+    let map = ebpf.map_mut("ENV_PREFIXES").unwrap();
+    let mut env_prefixes: Array<_, [u8; ENV_PREFIX_LEN]> = Array::try_from(map)?;
+    for (i, prefix) in opt.env_prefixes.iter().enumerate() {
+        env_prefixes.set(i as u32, env_prefix_to_key(prefix)?, 0)?;
     }
 
     info!("eBPF program loaded and attached");
 
-    let mut perf_command_events =
-        AsyncPerfEventArray::try_from(ebpf.take_map("COMMAND_EVENTS").unwrap())?;
+    let exclude_patterns = RegexSet::new(&opt.exclude_patterns).context("invalid --exclude-pattern")?;
+    // Validated with the config, reloaded with it on SIGHUP
+    let glob_excludes = GlobExclusions::new(&config.glob_excludes)?;
+
+    let mut command_events = ring_fd(RingBuf::try_from(ebpf.take_map("COMMAND_EVENTS").unwrap())?)?;
+    let dropped_events: PerCpuArray<_, u64> = PerCpuArray::try_from(ebpf.take_map("DROPPED_EVENTS").unwrap())?;
+    let kernel_thread_execs: PerCpuArray<_, u64> = PerCpuArray::try_from(ebpf.take_map("KERNEL_THREAD_EXECS").unwrap())?;
+    let uid_excluded_execs: PerCpuArray<_, u64> = PerCpuArray::try_from(ebpf.take_map("UID_EXCLUDED_EXECS").unwrap())?;
+    let throttle_hits: PerCpuArray<_, u64> = PerCpuArray::try_from(ebpf.take_map("THROTTLE_HITS").unwrap())?;
+    let mut exit_events = ring_fd(RingBuf::try_from(ebpf.take_map("EXIT_EVENTS").unwrap())?)?;
+    let mut fork_events = ring_fd(RingBuf::try_from(ebpf.take_map("FORK_EVENTS").unwrap())?)?;
+    let mut setuid_events = ring_fd(RingBuf::try_from(ebpf.take_map("SETUID_EVENTS").unwrap())?)?;
+
+    // Scripts are filled in as executions come in, /config/interpreters changes the list
+    let interpreters = Interpreters::new(config.interpreters.clone());
+
+    // Stores executions in the background so a slow database doesn't hold up the ring buffer
+    let (writer, writer_handle) = storage.spawn_writer();
+
+    // Flipped to true on Ctrl-C, the reader and the HTTP server watch it to wind down
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
 
-    // Spawn eBPF event processing tasks
-    for cpu_id in online_cpus().map_err(|(_, error)| error)? {
-        let mut buf = perf_command_events.open(cpu_id, None)?;
-        let storage_task = storage.clone();
+    // Spawn eBPF event processing task
+    let reader = {
+        let writer = writer.clone();
+        let metrics = storage.metrics().clone();
+        let interpreters = interpreters.clone();
+        let stdout_format = opt.stdout_format;
+        let glob_excludes = glob_excludes.clone();
+        let mut shutdown = shutdown_rx.clone();
 
         tokio::task::spawn(async move {
-            let mut buffers = (0..10)
-                .map(|_| BytesMut::with_capacity(1024))
-                .collect::<Vec<_>>();
-            let boot_offset = boot_offset;
+            // --exclude-pattern on the whole command line, then the [exclusions] globs on the command
+            let excluded = |execution: &ProcessExecution| {
+                if exclude_patterns.is_match(&execution.full_command) {
+                    return true;
+                }
+                let matched = glob_excludes.is_match(&execution.commandstr);
+                if matched {
+                    metrics.glob_excluded_total.inc();
+                }
+                matched
+            };
+            // Records of one exec come from one CPU in order, so they're stitched per CPU
+            let mut reassemblers: std::collections::HashMap<u32, Reassembler> = std::collections::HashMap::new();
+            let mut dropped_seen: Vec<u64> = Vec::new();
+            let mut printer = ExecutionPrinter::stdout(stdout_format);
+            let mut cgroups = CgroupPaths::new(CGROUP_ROOT);
 
             loop {
-                match buf.read_events(&mut buffers).await {
-                    Ok(events) => {
-                        for i in 0..events.read {
-                            let buf = &mut buffers[i];
-                            let ptr = buf.as_ptr() as *const ExecEvent;
-                            let raw_event = unsafe { ptr.read_unaligned() };
-
-                            let execution = ProcessExecution::from_event(&raw_event, boot_offset);
-
-                            // Log the execution event with structured logging
-                            info!(
-                                pid = execution.pid,
-                                command = %execution.commandstr,
-                                args = %execution.argstr,
-                                timestamp = %execution.timestamp,
-                                "Process execution captured"
-                            );
-
-                            // Store the execution
-                            storage_task.add_execution(execution).await;
+                // Only waiting for the next batch is interrupted, a batch already read is stored in full
+                let result = tokio::select! {
+                    _ = shutdown.wait_for(|stop| *stop) => break,
+                    result = command_events.readable_mut() => result.map(|mut guard| guard.clear_ready()),
+                    result = exit_events.readable_mut() => result.map(|mut guard| guard.clear_ready()),
+                    result = fork_events.readable_mut() => result.map(|mut guard| guard.clear_ready()),
+                    result = setuid_events.readable_mut() => result.map(|mut guard| guard.clear_ready()),
+                };
+                if let Err(err) = result {
+                    metrics.ring_read_errors_total.inc();
+                    error!("Error reading eBPF events: {:?}", err);
+                    continue;
+                }
+
+                // Exits are read first and stored last. A process exits after the records of its exec
+                // were committed, so those are in this batch (or an earlier one) and stored before it.
+                let mut exits = Vec::new();
+                let ring = exit_events.get_mut();
+                while let Some(record) = ring.next() {
+                    match read_record::<ExitEvent>(&record) {
+                        Some(event) => exits.push(ExitRecord::from_event(&event, boot_offset)),
+                        None => warn!("Skipping exit record that is not an ExitEvent"),
+                    }
+                }
+
+                // Like exits, sent after the executions they belong to
+                let mut setuids = Vec::new();
+                let ring = setuid_events.get_mut();
+                while let Some(record) = ring.next() {
+                    match read_record::<SetuidEvent>(&record) {
+                        Some(event) => setuids.push(SetuidRecord::from_event(&event, boot_offset)),
+                        None => warn!("Skipping setuid record that is not a SetuidEvent"),
+                    }
+                }
+
+                // Lineage is only read when asked for, so forks need no ordering with executions
+                let ring = fork_events.get_mut();
+                while let Some(record) = ring.next() {
+                    match read_record::<ForkEvent>(&record) {
+                        Some(event) => {
+                            let _ = writer.send(StorageWrite::Fork(ForkRecord::from_event(&event, boot_offset)));
                         }
+                        None => warn!("Skipping fork record that is not a ForkEvent"),
                     }
+                }
+
+                let ring = command_events.get_mut();
+                while let Some(record) = ring.next() {
+                    // Results share the ring so they always come after the records of their exec,
+                    // which is stored (or queued to be) by then, unless it's still waiting for lost
+                    // continuations
+                    if let Some(result) = read_record::<ExecResultEvent>(&record) {
+                        for execution in reassemblers.values_mut().filter_map(|r| r.flush_pid(result.pid)) {
+                            if !excluded(&execution) {
+                                emit(execution, &mut cgroups, &interpreters, &mut printer, &writer);
+                            }
+                        }
+                        let result = ExecResult::from_event(&result, boot_offset);
+                        if result.result < 0 {
+                            debug!(pid = result.pid, errno = -result.result, "Exec failed");
+                        }
+                        let _ = writer.send(StorageWrite::Result(result));
+                        continue;
+                    }
+                    metrics.exec_events_total.inc();
+                    let Some(raw_event) = read_record::<ExecEvent>(&record) else {
+                        warn!("Skipping {} byte record, an ExecEvent is {} bytes", record.len(), size_of::<ExecEvent>());
+                        continue;
+                    };
+                    let reassembler = reassemblers.entry(raw_event.cpu).or_insert_with(|| Reassembler::new(boot_offset));
+                    for execution in reassembler.push(&raw_event) {
+                        if !excluded(&execution) {
+                            emit(execution, &mut cgroups, &interpreters, &mut printer, &writer);
+                        }
+                    }
+                }
+                // Their execs can't get any more continuations either, see Reassembler::flush_pid
+                for pid in setuids.iter().map(|s| s.pid).chain(exits.iter().map(|e| e.pid)) {
+                    for execution in reassemblers.values_mut().filter_map(|r| r.flush_pid(pid)) {
+                        if !excluded(&execution) {
+                            emit(execution, &mut cgroups, &interpreters, &mut printer, &writer);
+                        }
+                    }
+                }
+                for setuid in setuids {
+                    warn!(pid = setuid.pid, command = %setuid.command, before_uid = setuid.before_uid, after_uid = setuid.after_uid, "Setuid binary executed");
+                    let _ = writer.send(StorageWrite::Setuid(setuid));
+                }
+                for exit in exits {
+                    let _ = writer.send(StorageWrite::Exit(exit));
+                }
+
+                // Dropped records may include the rest of the pending argv
+                let dropped = match dropped_events.get(&0, 0) {
+                    Ok(dropped) => dropped,
                     Err(err) => {
-                        error!("Error reading eBPF events: {:?}", err);
+                        error!("Error reading dropped eBPF event counts: {:?}", err);
+                        continue;
+                    }
+                };
+                dropped_seen.resize(dropped.len(), 0);
+                for (cpu_id, (&total, seen)) in dropped.iter().zip(dropped_seen.iter_mut()).enumerate() {
+                    if total <= *seen {
+                        continue;
+                    }
+                    metrics.events_dropped_total.inc_by(total - *seen);
+                    metrics.events_lost.fetch_add(total - *seen, Ordering::Relaxed);
+                    warn!("Lost {} eBPF events on CPU {}", total - *seen, cpu_id);
+                    *seen = total;
+                    let execution = reassemblers.get_mut(&(cpu_id as u32)).and_then(Reassembler::flush);
+                    if let Some(execution) = execution.filter(|e| !excluded(e)) {
+                        emit(execution, &mut cgroups, &interpreters, &mut printer, &writer);
                     }
                 }
+
+                // Only totals, nothing is pending for these
+                sync_counter(&kernel_thread_execs, &metrics.kernel_thread_execs_total, "kernel thread exec");
+                sync_counter(&uid_excluded_execs, &metrics.uid_excluded_execs_total, "uid excluded exec");
+                sync_counter(&throttle_hits, &metrics.execs_throttled_total, "throttled exec");
             }
-        });
-    }
+            // Keep execs whose continuations didn't make it before shutdown
+            for execution in reassemblers.values_mut().filter_map(Reassembler::flush).filter(|e| !excluded(e)) {
+                emit(execution, &mut cgroups, &interpreters, &mut printer, &writer);
+            }
+        })
+    };
+    drop(writer);
 
     // Start HTTP server
-    let server_handle = start_http_server(storage_clone).await?;
+    if api_key.is_some() {
+        info!("HTTP API requires an API key, except on {}", auth::PUBLIC_PATHS.join(", "));
+    }
+    if let (Some(files), Some(config)) = (tls_files, &tls) {
+        files.reload_on_sighup(config.clone())?;
+    }
+    reload::reload_on_sighup(opt.config.clone(), exclusions.clone(), prefixes, interpreters.clone(), glob_excludes.clone())?;
+    let listen = opt.listen.unwrap_or(SocketAddr::new(config.bind, config.port));
+    let mut grpc_shutdown = shutdown_rx.clone();
+    let (mut grpc_handle, _) = start_grpc_server(storage_clone.clone(), api_key.clone(), SocketAddr::new(listen.ip(), config.grpc_port), async move {
+        let _ = grpc_shutdown.wait_for(|stop| *stop).await;
+    })
+    .await?;
+    let mut server_shutdown = shutdown_rx.clone();
+    let (mut server_handle, _) = start_http_server(
+        storage_clone,
+        exclusions,
+        interpreters,
+        api_key,
+        tls,
+        listen,
+        async move {
+            let _ = server_shutdown.wait_for(|stop| *stop).await;
+        },
+    )
+    .await?;
 
     // Wait for Ctrl-C
     let ctrl_c = signal::ctrl_c();
-    println!("Waiting for Ctrl-C...");
+    eprintln!("Waiting for Ctrl-C...");
     ctrl_c.await?;
-    println!("Exiting...");
+    info!("Shutting down, draining in-flight executions");
+    let _ = shutdown_tx.send(true);
 
-    // Clean shutdown
-    server_handle.abort();
+    // Stop accepting connections, stream clients never hang up on their own so they get a deadline
+    if tokio::time::timeout(SHUTDOWN_GRACE, &mut server_handle).await.is_err() {
+        warn!("Closing HTTP connections still open after {:?}", SHUTDOWN_GRACE);
+        server_handle.abort();
+    }
+    if tokio::time::timeout(SHUTDOWN_GRACE, &mut grpc_handle).await.is_err() {
+        warn!("Closing gRPC streams still open after {:?}", SHUTDOWN_GRACE);
+        grpc_handle.abort();
+    }
+    // The reader finishes its current batch, then the writer stores whatever it queued
+    let _ = reader.await;
+    let _ = writer_handle.await;
+    eprintln!("Exiting...");
     Ok(())
 }
 
-fn cmd_to_key(cmd: &str) -> [u8; COMMAND_LEN] {
-    let mut key = [0u8; COMMAND_LEN];
-    let bytes = cmd.as_bytes();
+// Fills in what userspace looks up about a complete execution, then logs, prints and stores it
+fn emit(
+    mut execution: ProcessExecution,
+    cgroups: &mut CgroupPaths,
+    interpreters: &Interpreters,
+    printer: &mut ExecutionPrinter<std::io::Stdout>,
+    writer: &tokio::sync::mpsc::UnboundedSender<StorageWrite>,
+) {
+    fill_cwd_from_proc(&mut execution);
+    cgroups.fill(&mut execution);
+    execution.read_setid_bits();
+    interpreters.detect(&mut execution);
+    log_execution(&execution);
+    printer.print(&execution);
+    // the writer outlives the reader, so this only fails if it panicked
+    let _ = writer.send(StorageWrite::Execution(Box::new(execution)));
+}
+
+// Log the execution event with structured logging
+fn log_execution(execution: &ProcessExecution) {
+    info!(
+        pid = execution.pid,
+        tid = execution.tid,
+        ppid = execution.ppid,
+        uid = execution.uid,
+        gid = execution.gid,
+        euid = execution.euid,
+        privilege_elevated = execution.privilege_elevated,
+        loginuid = ?execution.loginuid,
+        cgroup_id = execution.cgroup_id,
+        container_id = ?execution.container_id,
+        pidns = execution.pidns,
+        ns_pid = execution.ns_pid,
+        mntns = execution.mntns,
+        dirfd = execution.dirfd,
+        source = execution.source.as_str(),
+        sid = execution.sid,
+        has_tty = execution.has_tty,
+        command = %execution.commandstr,
+        caller = %execution.caller_comm,
+        parent = %execution.parent_command,
+        args = %execution.argstr,
+        cwd = %execution.cwd,
+        resolved_command = %execution.resolved_command,
+        script = ?execution.script,
+        setuid = ?execution.setuid,
+        setgid = ?execution.setgid,
+        env = ?execution.env,
+        argv_partial = execution.argv_partial,
+        timestamp = %execution.timestamp,
+        process_start = ?execution.process_start,
+        "Process execution captured"
+    );
+    if let Some(ld_preload) = &execution.ld_preload {
+        warn!(pid = execution.pid, command = %execution.commandstr, ld_preload = %ld_preload, "Process started with LD_PRELOAD");
+    }
+}
+
+// The probe leaves cwd empty when it can't resolve it (too deep or too long), /proc still has it
+// unless the process has already exited
+fn fill_cwd_from_proc(execution: &mut ProcessExecution) {
+    if !execution.cwd.is_empty() {
+        return;
+    }
+    match std::fs::read_link(format!("/proc/{}/cwd", execution.pid)) {
+        Ok(cwd) => execution.set_cwd(cwd.to_string_lossy().into_owned()),
+        Err(e) => debug!(pid = execution.pid, "cwd not readable from /proc: {e}"),
+    }
+}
+
+// The probe compares NUL padded prefixes against the first ENV_PREFIX_LEN - 1 bytes of each entry
+fn env_prefix_to_key(prefix: &str) -> anyhow::Result<[u8; ENV_PREFIX_LEN]> {
+    let bytes = prefix.as_bytes();
+    anyhow::ensure!(
+        (1..ENV_PREFIX_LEN).contains(&bytes.len()) && !bytes.contains(&0),
+        "--env-prefix {prefix:?} must be 1 to {} bytes long",
+        ENV_PREFIX_LEN - 1
+    );
+    let mut key = [0u8; ENV_PREFIX_LEN];
     key[..bytes.len()].copy_from_slice(bytes);
-    key
-}
\ No newline at end of file
+    Ok(key)
+}
+
+// Brings `counter` up to the sum of a per-CPU counter of the probe
+fn sync_counter(map: &PerCpuArray<MapData, u64>, counter: &prometheus::IntCounter, what: &str) {
+    match map.get(&0, 0) {
+        Ok(counts) => {
+            let total: u64 = counts.iter().sum();
+            counter.inc_by(total.saturating_sub(counter.get()));
+        }
+        Err(err) => error!("Error reading {what} counts: {:?}", err),
+    }
+}
+
+// Registers a ring buffer with the runtime to be woken when it has records
+fn ring_fd(ring: RingBuf<MapData>) -> anyhow::Result<AsyncFd<RingBuf<MapData>>> {
+    // SAFETY: the ring buffer owns its map fd, which stays open and unchanged until it's dropped
+    // along with the AsyncFd
+    unsafe { AsyncFd::register_with_interest(ring, Interest::READABLE) }.map_err(|err| anyhow::Error::from(err.into_parts().1))
+}
+
+// Copies a probe struct out of a ring buffer record. Records are only byte aligned, and one of
+// the wrong size means the probe was built against a different task-common. Only used for the
+// repr(C) event structs of task-common, whose fields are all integers (flags are u8, not bool), so
+// any bytes of the right length are a valid value
+fn read_record<T>(record: &[u8]) -> Option<T> {
+    if record.len() != size_of::<T>() {
+        return None;
+    }
+    Some(unsafe { (record.as_ptr() as *const T).read_unaligned() })
+}
@@ -0,0 +1,184 @@
+use std::sync::Mutex;
+use std::sync::atomic::AtomicU64;
+use std::collections::HashMap;
+use prometheus::{Encoder, IntCounter, IntGauge, IntGaugeVec, Opts, Registry, TextEncoder};
+
+// Number of commands exported in task_executions_by_command
+pub const TOP_COMMANDS: usize = 20;
+
+// Counters served on GET /metrics, shared by the storage and the ring buffer reader
+pub struct Metrics {
+    // Not prometheus::default_registry(): every ExecutionStorage, and so every test, builds its own
+    // Metrics, and the process-wide registry would reject the second registration of a name
+    registry: Registry,
+    pub executions_total: IntCounter,
+    pub executions_stored: IntGauge,
+    pub executions_evicted_total: IntCounter,
+    pub ring_read_errors_total: IntCounter,
+    // raw records read from the ring buffer, argv continuations included
+    pub exec_events_total: IntCounter,
+    // records that didn't fit in the ring buffer plus executions pushed out by --max-events
+    pub events_dropped_total: IntCounter,
+    // only the records that didn't fit in the ring buffer, not exported, reported on /stats
+    pub events_lost: AtomicU64,
+    // exits without a stored execution to mark, e.g. of processes started before the monitor
+    pub orphan_exits_total: IntCounter,
+    // execs by kernel threads and usermode helpers dropped in the kernel, also reported on /stats
+    pub kernel_thread_execs_total: IntCounter,
+    // execs dropped in the kernel for their uid (--exclude-uid, /exclusions/uid), also on /stats
+    pub uid_excluded_execs_total: IntCounter,
+    // execs dropped in the kernel for exceeding the per-process rate limit
+    pub execs_throttled_total: IntCounter,
+    // executions dropped after the probe by an `[exclusions] globs` entry
+    pub glob_excluded_total: IntCounter,
+    executions_by_command: IntGaugeVec,
+    // every command seen so far, only the top TOP_COMMANDS are exported
+    command_counts: Mutex<HashMap<String, u64>>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let registry = Registry::new();
+        let executions_total = IntCounter::new("task_executions_total", "Executions captured since startup").unwrap();
+        let executions_stored = IntGauge::new("task_executions_stored", "Executions currently held in storage").unwrap();
+        let executions_evicted_total =
+            IntCounter::new("task_executions_evicted_total", "Executions dropped by FIFO eviction or age pruning").unwrap();
+        let ring_read_errors_total =
+            IntCounter::new("task_ring_read_errors_total", "Failed reads from the eBPF ring buffer").unwrap();
+        let exec_events_total = IntCounter::new("task_exec_events_total", "Records read from the eBPF ring buffer").unwrap();
+        let events_dropped_total =
+            IntCounter::new("task_events_dropped_total", "Records dropped on a full eBPF ring buffer or executions evicted to make room").unwrap();
+        let orphan_exits_total = IntCounter::new("task_orphan_exits_total", "Process exits without a stored execution").unwrap();
+        let kernel_thread_execs_total =
+            IntCounter::new("task_kernel_thread_execs_total", "Execs by kernel threads and usermode helpers dropped in the kernel").unwrap();
+        let uid_excluded_execs_total =
+            IntCounter::new("task_uid_excluded_execs_total", "Execs dropped in the kernel because their real uid is excluded").unwrap();
+        let execs_throttled_total =
+            IntCounter::new("task_execs_throttled_total", "Execs dropped in the kernel, their process ran more than 100 per second").unwrap();
+        let glob_excluded_total =
+            IntCounter::new("task_glob_excluded_total", "Executions not stored because their command matched an exclusion glob").unwrap();
+        let executions_by_command = IntGaugeVec::new(
+            Opts::new("task_executions_by_command", "Executions since startup for the most frequent commands"),
+            &["command"],
+        )
+        .unwrap();
+        registry.register(Box::new(executions_total.clone())).unwrap();
+        registry.register(Box::new(executions_stored.clone())).unwrap();
+        registry.register(Box::new(executions_evicted_total.clone())).unwrap();
+        registry.register(Box::new(ring_read_errors_total.clone())).unwrap();
+        registry.register(Box::new(exec_events_total.clone())).unwrap();
+        registry.register(Box::new(events_dropped_total.clone())).unwrap();
+        registry.register(Box::new(orphan_exits_total.clone())).unwrap();
+        registry.register(Box::new(kernel_thread_execs_total.clone())).unwrap();
+        registry.register(Box::new(uid_excluded_execs_total.clone())).unwrap();
+        registry.register(Box::new(execs_throttled_total.clone())).unwrap();
+        registry.register(Box::new(glob_excluded_total.clone())).unwrap();
+        registry.register(Box::new(executions_by_command.clone())).unwrap();
+        Self {
+            registry,
+            executions_total,
+            executions_stored,
+            executions_evicted_total,
+            ring_read_errors_total,
+            exec_events_total,
+            events_dropped_total,
+            events_lost: AtomicU64::new(0),
+            orphan_exits_total,
+            kernel_thread_execs_total,
+            uid_excluded_execs_total,
+            execs_throttled_total,
+            glob_excluded_total,
+            executions_by_command,
+            command_counts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn record_execution(&self, command: &str) {
+        self.executions_total.inc();
+        *self.command_counts.lock().unwrap().entry(command.to_string()).or_default() += 1;
+    }
+
+    // Prometheus text exposition format
+    pub fn render(&self) -> Result<String, prometheus::Error> {
+        let mut top: Vec<(String, u64)> = self.command_counts.lock().unwrap().iter().map(|(c, n)| (c.clone(), *n)).collect();
+        top.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        // drop commands that fell out of the top list
+        self.executions_by_command.reset();
+        for (command, count) in top.into_iter().take(TOP_COMMANDS) {
+            self.executions_by_command.with_label_values(&[&command]).set(count as i64);
+        }
+
+        let mut buf = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buf)?;
+        Ok(String::from_utf8_lossy(&buf).into_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prometheus_parse::{Scrape, Value};
+
+    fn scrape(metrics: &Metrics) -> Scrape {
+        let text = metrics.render().unwrap();
+        Scrape::parse(text.lines().map(|l| Ok(l.to_string()))).unwrap()
+    }
+
+    fn value(scrape: &Scrape, name: &str) -> f64 {
+        match scrape.samples.iter().find(|s| s.metric == name).unwrap().value {
+            Value::Counter(v) | Value::Gauge(v) | Value::Untyped(v) => v,
+            ref other => panic!("unexpected value {other:?}"),
+        }
+    }
+
+    #[test]
+    fn renders_parseable_text() {
+        let metrics = Metrics::new();
+        for _ in 0..3 { metrics.record_execution("/bin/ls"); }
+        metrics.record_execution("/bin/cat");
+        metrics.executions_stored.set(4);
+        metrics.executions_evicted_total.inc();
+        metrics.ring_read_errors_total.inc_by(2);
+        metrics.exec_events_total.inc_by(6);
+        metrics.events_dropped_total.inc_by(3);
+        metrics.kernel_thread_execs_total.inc_by(2);
+        metrics.uid_excluded_execs_total.inc_by(5);
+        metrics.execs_throttled_total.inc_by(7);
+
+        let scrape = scrape(&metrics);
+        assert_eq!(value(&scrape, "task_executions_total"), 4.0);
+        assert_eq!(value(&scrape, "task_executions_stored"), 4.0);
+        assert_eq!(value(&scrape, "task_executions_evicted_total"), 1.0);
+        assert_eq!(value(&scrape, "task_ring_read_errors_total"), 2.0);
+        assert_eq!(value(&scrape, "task_exec_events_total"), 6.0);
+        assert_eq!(value(&scrape, "task_events_dropped_total"), 3.0);
+        assert_eq!(value(&scrape, "task_kernel_thread_execs_total"), 2.0);
+        assert_eq!(value(&scrape, "task_uid_excluded_execs_total"), 5.0);
+        assert_eq!(value(&scrape, "task_execs_throttled_total"), 7.0);
+        let ls = scrape
+            .samples
+            .iter()
+            .find(|s| s.metric == "task_executions_by_command" && s.labels.get("command") == Some("/bin/ls"))
+            .unwrap();
+        assert!(matches!(ls.value, Value::Gauge(v) if v == 3.0));
+    }
+
+    #[test]
+    fn only_top_commands_exported() {
+        let metrics = Metrics::new();
+        for i in 0..TOP_COMMANDS + 5 {
+            for _ in 0..=i { metrics.record_execution(&format!("/bin/cmd{i}")); }
+        }
+        let scrape = scrape(&metrics);
+        let commands: Vec<_> = scrape
+            .samples
+            .iter()
+            .filter(|s| s.metric == "task_executions_by_command")
+            .map(|s| s.labels.get("command").unwrap().to_string())
+            .collect();
+        assert_eq!(commands.len(), TOP_COMMANDS);
+        // the least frequent ones are left out
+        assert!(!commands.contains(&"/bin/cmd0".to_string()));
+        assert!(commands.contains(&format!("/bin/cmd{}", TOP_COMMANDS + 4)));
+    }
+}
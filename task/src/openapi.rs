@@ -0,0 +1,111 @@
+use utoipa::openapi::security::{Http, HttpAuthScheme, SecurityScheme};
+use utoipa::openapi::path::{OperationBuilder, PathItemBuilder, PathItemType};
+use utoipa::openapi::{ContentBuilder, ObjectBuilder, ResponseBuilder};
+use utoipa::{Modify, OpenApi};
+
+use crate::exclusions::{Exclusion, FilterMode, PidExclusion, UidExclusion};
+use crate::store::{
+    Ancestor, CommandStat, CommandStats, ErrorBody, ExecSource, ExecutionStats, ExecutionsPage, ExitRecord, ExportFormat, ProcessExecution,
+    ProcessNode, SortField, SortOrder, Syscall,
+};
+use crate::server::Health;
+use crate::subtrees::Subtree;
+
+// OpenAPI 3.0 description of the routes in server::create_app, served on /openapi.json. Derived
+// from the #[utoipa::path] of each handler, a route added there has to be listed here too
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "task", description = "Process executions traced with eBPF"),
+    paths(
+        crate::store::get_all_executions,
+        crate::store::clear_executions,
+        crate::store::get_executions_csv,
+        crate::store::get_executions_ndjson,
+        crate::store::export_executions,
+        crate::store::get_executions_filtered,
+        crate::server::stream_executions,
+        crate::server::ws_executions,
+        crate::store::get_executions_by_pid,
+        crate::store::get_exit_by_pid,
+        crate::store::get_ancestry_by_pid,
+        crate::store::get_executions_by_ppid,
+        crate::exclusions::list_exclusions,
+        crate::exclusions::add_exclusion,
+        crate::exclusions::remove_exclusion,
+        crate::exclusions::list_pid_exclusions,
+        crate::exclusions::add_pid_exclusion,
+        crate::exclusions::remove_pid_exclusion,
+        crate::exclusions::list_uid_exclusions,
+        crate::exclusions::add_uid_exclusion,
+        crate::exclusions::remove_uid_exclusion,
+        crate::subtrees::list_subtree_exclusions,
+        crate::subtrees::add_subtree_exclusion,
+        crate::subtrees::remove_subtree_exclusion,
+        crate::exclusions::get_mode,
+        crate::interpreters::get_interpreters,
+        crate::interpreters::put_interpreters,
+        crate::store::get_command_stats,
+        crate::store::get_process_tree,
+        crate::store::get_setuid_executions,
+        crate::store::get_stats,
+        crate::server::health,
+        crate::server::metrics,
+        crate::server::openapi_json,
+    ),
+    components(schemas(
+        ProcessExecution,
+        Syscall,
+        ExecSource,
+        ExecutionsPage,
+        ExitRecord,
+        Ancestor,
+        ExecutionStats,
+        ProcessNode,
+        CommandStats,
+        CommandStat,
+        SortField,
+        SortOrder,
+        ExportFormat,
+        Exclusion,
+        Subtree,
+        PidExclusion,
+        UidExclusion,
+        FilterMode,
+        Health,
+        ErrorBody,
+    )),
+    modifiers(&ApiKey, &Aliases),
+    // only required when the server runs with --api-key, hence the empty alternative
+    security(("apiKey" = []), ()),
+)]
+pub struct ApiDoc;
+
+struct ApiKey;
+
+impl Modify for ApiKey {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let mut scheme = Http::new(HttpAuthScheme::Bearer);
+        scheme.description = Some("The --api-key (TASK_API_KEY) of the server".to_string());
+        openapi.components.get_or_insert_with(Default::default).add_security_scheme("apiKey", SecurityScheme::Http(scheme));
+    }
+}
+
+// Routes #[utoipa::path] can't describe: a second path for the same handler, and the Swagger UI
+// that utoipa-swagger-ui serves
+struct Aliases;
+
+impl Modify for Aliases {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let paths = &mut openapi.paths.paths;
+        for (alias, path) in [("/healthz", "/health"), ("/stats/commands", "/executions/stats")] {
+            let item = paths[path].clone();
+            paths.insert(alias.to_string(), item);
+        }
+        let html = ResponseBuilder::new()
+            .description("HTML page")
+            .content("text/html", ContentBuilder::new().schema(ObjectBuilder::new().schema_type(utoipa::openapi::SchemaType::String)).build())
+            .build();
+        let operation = OperationBuilder::new().summary(Some("Swagger UI for /openapi.json")).response("200", html);
+        paths.insert("/swagger-ui".to_string(), PathItemBuilder::new().operation(PathItemType::Get, operation.build()).build());
+    }
+}
@@ -0,0 +1,274 @@
+use chrono::Duration;
+use tracing::{debug, warn};
+
+use crate::ExecEvent;
+use crate::store::ProcessExecution;
+
+// Stitches a primary ExecEvent (seq 0) and its argv continuation records back into one
+// ProcessExecution. The probe writes all records of an exec back to back from the CPU it runs on
+// (ExecEvent::cpu), so a Reassembler per CPU sees them in order and anything else means records
+// were lost. Lost continuations don't lose the execution, it is kept with argv_partial set.
+pub struct Reassembler {
+    boot_offset: Duration,
+    pending: Option<Pending>,
+}
+
+struct Pending {
+    execution: ProcessExecution,
+    // identify the exec the continuations belong to
+    pid: u32,
+    timestamp: u64,
+    next_seq: u32,
+    chunks: u32,
+}
+
+impl Reassembler {
+    pub fn new(boot_offset: Duration) -> Self {
+        Self { boot_offset, pending: None }
+    }
+
+    // Returns the executions this record completes, oldest first
+    pub fn push(&mut self, event: &ExecEvent) -> Vec<ProcessExecution> {
+        let mut done = Vec::new();
+        if event.seq == 0 {
+            // a new exec before the previous one got all of its continuations
+            done.extend(self.flush());
+            self.pending = Some(Pending {
+                execution: ProcessExecution::from_event(event, self.boot_offset),
+                pid: event.tgid,
+                timestamp: event.timestamp,
+                next_seq: 1,
+                chunks: event.chunks.max(1),
+            });
+        } else {
+            match &mut self.pending {
+                Some(pending) if pending.pid == event.tgid && pending.timestamp == event.timestamp => {
+                    if pending.next_seq == event.seq {
+                        pending.execution.append_args(event);
+                        pending.next_seq += 1;
+                    } else {
+                        // a chunk in between is gone, appending later ones would misplace arguments
+                        warn!(pid = event.tgid, expected = pending.next_seq, got = event.seq, "argv continuation lost");
+                        done.extend(self.flush());
+                    }
+                }
+                _ => debug!(pid = event.tgid, seq = event.seq, "dropping argv continuation without its primary record"),
+            }
+        }
+        if self.pending.as_ref().is_some_and(|pending| pending.next_seq >= pending.chunks) {
+            done.extend(self.pending.take().map(|pending| pending.execution));
+        }
+        done
+    }
+
+    // Gives up on the execution still waiting for continuations, e.g. after the probe reported
+    // records dropped on a full ring buffer
+    pub fn flush(&mut self) -> Option<ProcessExecution> {
+        self.pending.take().map(|mut pending| {
+            pending.execution.argv_partial = true;
+            pending.execution
+        })
+    }
+
+    // Flushes the pending execution if it's `pid`'s. Its result, setuid record and exit are read
+    // after all of its records, so continuations still missing when one of those shows up were lost
+    pub fn flush_pid(&mut self, pid: u32) -> Option<ProcessExecution> {
+        if self.pending.as_ref().is_some_and(|pending| pending.pid == pid) {
+            self.flush()
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use task_common::{ARGV_LEN, AUDIT_UID_UNSET, ARGV_OFFSET, CGROUP_NAME_LEN, CWD_LEN, ENV_LEN, MAX_ENV_MATCHES};
+
+    fn record(pid: u32, seq: u32, chunks: u32, args: &[String]) -> ExecEvent {
+        let mut command = [0u8; 64];
+        command[..8].copy_from_slice(b"/bin/cc\0");
+        let mut argvs = [[0u8; ARGV_LEN]; ARGV_OFFSET];
+        let mut argvs_offset = [0usize; ARGV_OFFSET];
+        for (i, arg) in args.iter().enumerate() {
+            argvs[i][..arg.len()].copy_from_slice(arg.as_bytes());
+            argvs_offset[i] = arg.len();
+        }
+        ExecEvent {
+            tgid: pid,
+            tid: pid,
+            ppid: 1,
+            uid: 0,
+            gid: 0,
+            euid: 0,
+            egid: 0,
+            loginuid: AUDIT_UID_UNSET,
+            cgroup_id: 0,
+            cgroup_name: [0; CGROUP_NAME_LEN],
+            pidns: 0,
+            ns_pid: 0,
+            mntns: 0,
+            sid: 0,
+            has_tty: 0,
+            // one exec per pid in these tests
+            timestamp: pid as u64,
+            start_time: 0,
+            exe_ino: 0,
+            exe_dev: 0,
+            dirfd: -1,
+            syscall: 0,
+            at_flags: 0,
+            command,
+            command_len: 7,
+            command_truncated: 0,
+            cwd: [0; CWD_LEN],
+            cwd_len: 0,
+            comm: [0; 16],
+            parent_comm: [0; 16],
+            argvs,
+            argvs_offset,
+            argvs_truncated: [0; ARGV_OFFSET],
+            argc: 0,
+            argv_truncated: 0,
+            argv_missing: 0,
+            args_total_len: 0,
+            args_len_overflow: 0,
+            env: [[0; ENV_LEN]; MAX_ENV_MATCHES],
+            env_len: [0; MAX_ENV_MATCHES],
+            env_truncated: [0; MAX_ENV_MATCHES],
+            seq,
+            chunks,
+            cpu: 0,
+            source: 0,
+        }
+    }
+
+    // argv of `argc` numbered arguments split into records the way the probe sends them
+    fn records(pid: u32, argc: usize) -> Vec<ExecEvent> {
+        let args: Vec<String> = (0..argc).map(|i| format!("a{i}")).collect();
+        let chunks = args.len().div_ceil(ARGV_OFFSET).max(1) as u32;
+        let mut records: Vec<ExecEvent> =
+            args.chunks(ARGV_OFFSET).enumerate().map(|(seq, args)| record(pid, seq as u32, chunks, args)).collect();
+        if records.is_empty() {
+            records.push(record(pid, 0, 1, &[]));
+        }
+        records.iter_mut().for_each(|r| r.argc = argc as u32);
+        records
+    }
+
+    fn numbered(argc: usize) -> String {
+        (0..argc).map(|i| format!("a{i}")).collect::<Vec<_>>().join(" ")
+    }
+
+    #[test]
+    fn reassembles_in_order() {
+        let mut reassembler = Reassembler::new(Duration::zero());
+        let records = records(7, 40);
+        assert_eq!(records.len(), 3);
+
+        // nothing is emitted until the last continuation arrives
+        assert!(reassembler.push(&records[0]).is_empty());
+        assert!(reassembler.push(&records[1]).is_empty());
+        let done = reassembler.push(&records[2]);
+        assert_eq!(done.len(), 1);
+        assert_eq!(done[0].argstr, numbered(40));
+        assert_eq!(done[0].full_command, format!("/bin/cc {}", numbered(40)));
+        assert_eq!(done[0].argc, 40);
+        assert!(!done[0].argv_partial);
+        assert!(reassembler.flush().is_none());
+    }
+
+    #[test]
+    fn thread_exec_keeps_tgid() {
+        let mut reassembler = Reassembler::new(Duration::zero());
+        // every record comes from the thread that called execve, not the group leader
+        let records: Vec<ExecEvent> = records(7, 40).into_iter().map(|r| ExecEvent { tid: 8, ..r }).collect();
+        let done: Vec<_> = records.iter().flat_map(|r| reassembler.push(r)).collect();
+        assert_eq!(done.len(), 1);
+        assert_eq!((done[0].pid, done[0].tid), (7, 8));
+        assert_eq!(done[0].argstr, numbered(40));
+        assert!(!done[0].argv_partial);
+    }
+
+    #[test]
+    fn single_record_completes_immediately() {
+        let mut reassembler = Reassembler::new(Duration::zero());
+        for argc in [0, 3, ARGV_OFFSET] {
+            let records = records(1, argc);
+            assert_eq!(records.len(), 1);
+            let done = reassembler.push(&records[0]);
+            assert_eq!(done.len(), 1);
+            assert_eq!(done[0].argstr, numbered(argc));
+            assert!(!done[0].argv_partial);
+        }
+    }
+
+    #[test]
+    fn lost_last_chunk() {
+        let mut reassembler = Reassembler::new(Duration::zero());
+        let first = records(1, 40);
+        let second = records(2, 2);
+        reassembler.push(&first[0]);
+        reassembler.push(&first[1]);
+        // the next exec's primary arrives instead of first[2]
+        let done = reassembler.push(&second[0]);
+        assert_eq!(done.len(), 2);
+        assert_eq!(done[0].pid, 1);
+        assert!(done[0].argv_partial);
+        assert_eq!(done[0].argstr, numbered(32));
+        assert_eq!(done[0].argc, 40);
+        assert_eq!(done[1].pid, 2);
+        assert!(!done[1].argv_partial);
+    }
+
+    #[test]
+    fn lost_middle_chunk() {
+        let mut reassembler = Reassembler::new(Duration::zero());
+        let records = records(1, 40);
+        reassembler.push(&records[0]);
+        // records[1] never arrives, records[2] must not be appended out of place
+        let done = reassembler.push(&records[2]);
+        assert_eq!(done.len(), 1);
+        assert!(done[0].argv_partial);
+        assert_eq!(done[0].argstr, numbered(ARGV_OFFSET));
+        assert!(reassembler.flush().is_none());
+    }
+
+    #[test]
+    fn lost_primary() {
+        let mut reassembler = Reassembler::new(Duration::zero());
+        let first = records(1, 40);
+        let second = records(2, 20);
+        reassembler.push(&second[0]);
+        // continuations of another exec are dropped and don't disturb the pending one
+        assert!(reassembler.push(&first[1]).is_empty());
+        let done = reassembler.push(&second[1]);
+        assert_eq!(done.len(), 1);
+        assert_eq!(done[0].argstr, numbered(20));
+        assert!(!done[0].argv_partial);
+    }
+
+    #[test]
+    fn flush_after_lost_records() {
+        let mut reassembler = Reassembler::new(Duration::zero());
+        let records = records(1, 20);
+        reassembler.push(&records[0]);
+        let partial = reassembler.flush().unwrap();
+        assert!(partial.argv_partial);
+        assert_eq!(partial.argstr, numbered(ARGV_OFFSET));
+        assert!(reassembler.flush().is_none());
+    }
+
+    #[test]
+    fn flush_pid_only_takes_that_pid() {
+        let mut reassembler = Reassembler::new(Duration::zero());
+        let records = records(1, 20);
+        reassembler.push(&records[0]);
+        assert!(reassembler.flush_pid(2).is_none());
+        let partial = reassembler.flush_pid(1).unwrap();
+        assert!(partial.argv_partial);
+        assert_eq!(partial.argstr, numbered(ARGV_OFFSET));
+        assert!(reassembler.flush().is_none());
+    }
+}
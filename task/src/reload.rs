@@ -0,0 +1,129 @@
+// Re-reads the config file on SIGHUP and applies what can change without a restart: the
+// exclude_list, diffed into EXCLUDED_CMDS, exclude_prefixes, diffed into EXCLUDED_PREFIXES, the
+// [exclusions] globs and the interpreters. Stored executions are kept
+use std::path::{Path, PathBuf};
+
+use anyhow::Context as _;
+use tokio::signal::unix::{SignalKind, signal};
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use crate::config::Config;
+use crate::exclusions::{Exclusions, FilterMode, Prefixes};
+use crate::globs::GlobExclusions;
+use crate::interpreters::Interpreters;
+
+// `path` is --config, None reloads the built-in defaults like at startup. Runs until the runtime stops
+pub fn reload_on_sighup(
+    path: Option<PathBuf>,
+    exclusions: Exclusions,
+    prefixes: Prefixes,
+    interpreters: Interpreters,
+    globs: GlobExclusions,
+) -> anyhow::Result<JoinHandle<()>> {
+    let mut hangup = signal(SignalKind::hangup()).context("failed to listen for SIGHUP")?;
+    Ok(tokio::spawn(async move {
+        while hangup.recv().await.is_some() {
+            info!("SIGHUP received, reloading the config file");
+            if let Err(e) = reload(path.as_deref(), &exclusions, &prefixes, &interpreters, &globs) {
+                warn!("Keeping the current settings: {:#}", e);
+            }
+        }
+    }))
+}
+
+// Nothing is changed unless the whole file loads and validates
+fn reload(
+    path: Option<&Path>,
+    exclusions: &Exclusions,
+    prefixes: &Prefixes,
+    interpreters: &Interpreters,
+    globs: &GlobExclusions,
+) -> anyhow::Result<()> {
+    let config = Config::load(path)?;
+    config.validate()?;
+    if exclusions.mode() == FilterMode::Denylist {
+        let changes = exclusions.replace(&config.exclude_list).map_err(|e| anyhow::anyhow!("failed to update the exclusions: {e:?}"))?;
+        for cmd in &changes.added {
+            info!("Excluding {}", cmd);
+        }
+        for cmd in &changes.removed {
+            info!("No longer excluding {}", cmd);
+        }
+    }
+    let changes = prefixes.replace(&config.exclude_prefixes)?;
+    for prefix in &changes.added {
+        info!("Excluding commands starting with {}", prefix);
+    }
+    for prefix in &changes.removed {
+        info!("No longer excluding commands starting with {}", prefix);
+    }
+    if globs.list() != config.glob_excludes {
+        info!("Excluding commands matching {}", config.glob_excludes.join(", "));
+        globs.set(&config.glob_excludes)?;
+    }
+    if interpreters.list() != config.interpreters {
+        info!("Detecting scripts run by {}", config.interpreters.join(", "));
+        interpreters.set(config.interpreters);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn sighup_updates_exclusions() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("task.toml");
+        std::fs::write(&path, "exclude_list = [\"/bin/sleep\", \"/bin/true\"]\n").unwrap();
+        let exclusions = Exclusions::in_memory(10);
+        let interpreters = Interpreters::new(Vec::new());
+        let prefixes = Prefixes::new(std::collections::BTreeSet::new());
+        let globs = GlobExclusions::default();
+        reload(Some(&path), &exclusions, &prefixes, &interpreters, &globs).unwrap();
+        assert_eq!(exclusions.list().unwrap(), vec!["/bin/sleep", "/bin/true"]);
+        let handle = reload_on_sighup(Some(path.clone()), exclusions.clone(), prefixes.clone(), interpreters.clone(), globs.clone()).unwrap();
+
+        std::fs::write(
+            &path,
+            "exclude_list = [\"/bin/true\", \"/usr/bin/cat\"]\nexclude_prefixes = [\"/usr/lib/\"]\ninterpreters = [\"/bin/sh\"]\n\
+             [exclusions]\nglobs = [\"/usr/lib/jvm/*\"]\n",
+        )
+        .unwrap();
+        unsafe { libc::raise(libc::SIGHUP) };
+        let updated = async {
+            while exclusions.list().unwrap() != ["/bin/true", "/usr/bin/cat"] {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), updated).await.expect("exclusions not reloaded");
+        assert_eq!(interpreters.list(), vec!["/bin/sh"]);
+        assert!(globs.is_match("/usr/lib/jvm/java-17/bin/java"));
+        assert_eq!(prefixes.list().unwrap(), vec!["/usr/lib/"]);
+
+        // a broken file, or one that doesn't validate, leaves everything as it was
+        for bad in ["exclude_list = [\"/bin/true\"", "exclude_list = [\"relative\"]\ninterpreters = [\"sh\"]", "[exclusions]\nglobs = [\"/opt/[a\"]"] {
+            std::fs::write(&path, bad).unwrap();
+            assert!(reload(Some(&path), &exclusions, &prefixes, &interpreters, &globs).is_err(), "{bad}");
+            assert_eq!(exclusions.list().unwrap(), vec!["/bin/true", "/usr/bin/cat"]);
+            assert_eq!(interpreters.list(), vec!["/bin/sh"]);
+            assert_eq!(globs.list(), vec!["/usr/lib/jvm/*"]);
+            assert_eq!(prefixes.list().unwrap(), vec!["/usr/lib/"]);
+        }
+        handle.abort();
+    }
+
+    #[test]
+    fn allowlist_keeps_exclusions_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("task.toml");
+        std::fs::write(&path, "exclude_list = [\"/bin/sleep\"]\n").unwrap();
+        let exclusions = Exclusions::in_memory(10).with_mode(FilterMode::Allowlist);
+        let prefixes = Prefixes::new(std::collections::BTreeSet::new());
+        reload(Some(&path), &exclusions, &prefixes, &Interpreters::new(Vec::new()), &GlobExclusions::default()).unwrap();
+        assert!(exclusions.list().unwrap().is_empty());
+    }
+}
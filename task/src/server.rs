@@ -1,31 +1,769 @@
-use axum::{routing::get, Router};
-use tracing::{info, error};
+use axum::{
+    extract::{
+        State,
+        ws::{Message, WebSocket, WebSocketUpgrade},
+    },
+    http::{StatusCode, header},
+    response::{
+        IntoResponse, Json, Response,
+        sse::{Event, KeepAlive, Sse},
+    },
+    middleware,
+    routing::{delete, get, post, put},
+    Router,
+};
+use serde::Serialize;
+use std::future::Future;
+use std::net::SocketAddr;
+use anyhow::Context as _;
+use axum_server::tls_rustls::RustlsConfig;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{info, warn, error};
 use tokio::task::JoinHandle;
-use crate::store::{ExecutionStorage, get_all_executions, get_executions_by_pid};
+use utoipa::{OpenApi, ToSchema};
+use utoipa_swagger_ui::{Config, SwaggerUi};
+use crate::auth::{ApiKey, require_api_key};
+use crate::subtrees::{add_subtree_exclusion, list_subtree_exclusions, remove_subtree_exclusion};
+use crate::exclusions::{
+    Exclusions, add_exclusion, add_pid_exclusion, add_uid_exclusion, get_mode, list_exclusions, list_pid_exclusions, list_uid_exclusions,
+    remove_exclusion, remove_pid_exclusion, remove_uid_exclusion,
+};
+use crate::interpreters::{Interpreters, get_interpreters, put_interpreters};
+use crate::openapi::ApiDoc;
+use crate::store::{
+    ExecutionStorage, ProcessExecution, clear_executions, export_executions, get_command_stats, get_all_executions,
+    get_ancestry_by_pid, get_executions_by_pid, get_executions_by_ppid, get_executions_csv, get_executions_filtered,
+    get_executions_ndjson, get_exit_by_pid, get_process_tree, get_setuid_executions, get_stats,
+};
 
-pub fn create_app(storage: ExecutionStorage) -> Router {
-    Router::new()
-        .route("/executions", get(get_all_executions))
+pub mod grpc;
+
+// Every route needs the api key when one is given, see auth::PUBLIC_PATHS for the exceptions
+pub fn create_app(storage: ExecutionStorage, exclusions: Exclusions, interpreters: Interpreters, api_key: Option<ApiKey>) -> Router {
+    let exclusions = Router::new()
+        .route("/exclusions", get(list_exclusions).post(add_exclusion))
+        .route("/exclusions/:command", delete(remove_exclusion))
+        .route("/exclusions/pid", get(list_pid_exclusions))
+        .route("/exclusions/pid/:pid", put(add_pid_exclusion).delete(remove_pid_exclusion))
+        .route("/exclusions/uid", get(list_uid_exclusions))
+        .route("/exclusions/uid/:uid", put(add_uid_exclusion).delete(remove_uid_exclusion))
+        .route("/exclusions/subtree", get(list_subtree_exclusions))
+        .route("/exclusions/subtree/:pid", post(add_subtree_exclusion).delete(remove_subtree_exclusion))
+        .route("/config/mode", get(get_mode))
+        .with_state(exclusions);
+    let interpreters = Router::new()
+        .route("/config/interpreters", get(get_interpreters).put(put_interpreters))
+        .with_state(interpreters);
+    let app = Router::new()
+        .route("/executions", get(get_all_executions).delete(clear_executions))
+        .route("/executions.csv", get(get_executions_csv))
+        .route("/executions.ndjson", get(get_executions_ndjson))
+        .route("/executions/export", get(export_executions))
+        .route("/executions/search", get(get_executions_filtered))
+        .route("/executions/stats", get(get_command_stats))
+        .route("/executions/tree", get(get_process_tree))
+        .route("/executions/setuid", get(get_setuid_executions))
+        .route("/executions/stream", get(stream_executions))
+        .route("/ws", get(ws_executions))
         .route("/executions/:pid", get(get_executions_by_pid))
+        .route("/executions/:pid/exit", get(get_exit_by_pid))
+        .route("/executions/:pid/ancestry", get(get_ancestry_by_pid))
+        .route("/executions/ppid/:ppid", get(get_executions_by_ppid))
+        .route("/health", get(health))
+        .route("/healthz", get(health))
+        .route("/stats", get(get_stats))
+        .route("/stats/commands", get(get_command_stats))
+        .route("/metrics", get(metrics))
+        .route("/openapi.json", get(openapi_json))
         .with_state(storage)
+        .merge(exclusions)
+        .merge(interpreters)
+        .merge(SwaggerUi::new("/swagger-ui").config(Config::from("/openapi.json")));
+    match api_key {
+        Some(api_key) => app.layer(middleware::from_fn_with_state(api_key, require_api_key)),
+        None => app,
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct Health {
+    #[schema(example = "ok")]
+    status: &'static str,
+    /// Seconds since the monitor started
+    uptime_secs: u64,
+}
+
+/// Liveness probe, the same on /health and /healthz. Needs no API key
+// Touches neither the stored executions nor the log since it's polled often
+#[utoipa::path(
+    get,
+    path = "/health",
+    tag = "server",
+    security(()),
+    responses((status = 200, description = "Always ok while the server runs", body = Health))
+)]
+async fn health(State(storage): State<ExecutionStorage>) -> Json<Health> {
+    Json(Health { status: "ok", uptime_secs: storage.uptime().as_secs() })
+}
+
+/// Prometheus metrics
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "server",
+    responses((status = 200, description = "Text exposition format", body = String, content_type = "text/plain"))
+)]
+async fn metrics(State(storage): State<ExecutionStorage>) -> Response {
+    storage.metrics().executions_stored.set(storage.len().await as i64);
+    match storage.metrics().render() {
+        Ok(body) => ([(header::CONTENT_TYPE, prometheus::TEXT_FORMAT)], body).into_response(),
+        Err(e) => {
+            error!("Failed to render metrics: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// This description. Needs no API key
+#[utoipa::path(
+    get,
+    path = "/openapi.json",
+    tag = "server",
+    security(()),
+    responses((status = 200, description = "OpenAPI 3.0 document", body = Object))
+)]
+async fn openapi_json() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+/// Every new execution as a WebSocket text frame, or as Server-Sent Events without an upgrade
+// Frames and events have the same schema as /executions entries
+#[utoipa::path(
+    get,
+    path = "/executions/stream",
+    tag = "executions",
+    responses(
+        (status = 101, description = "WebSocket, one ProcessExecution per text frame"),
+        (status = 200, description = "Server-Sent Events, one ProcessExecution per `data:` line",
+         body = String, content_type = "text/event-stream"),
+    )
+)]
+async fn stream_executions(ws: Option<WebSocketUpgrade>, State(storage): State<ExecutionStorage>) -> Response {
+    // Subscribe before upgrading so nothing added during the handshake is missed
+    let events = storage.subscribe();
+    match ws {
+        Some(ws) => ws.on_upgrade(move |socket| forward_executions(socket, events)),
+        None => Sse::new(execution_events(events)).keep_alive(KeepAlive::default()).into_response(),
+    }
+}
+
+/// Every new execution as a WebSocket text frame, like /executions/stream but WebSocket only
+#[utoipa::path(
+    get,
+    path = "/ws",
+    tag = "executions",
+    responses(
+        (status = 101, description = "WebSocket, one ProcessExecution per text frame, client frames are ignored"),
+        (status = 400, description = "Not a WebSocket upgrade request"),
+    )
+)]
+async fn ws_executions(ws: WebSocketUpgrade, State(storage): State<ExecutionStorage>) -> Response {
+    let events = storage.subscribe();
+    ws.on_upgrade(move |socket| forward_executions(socket, events))
 }
 
-pub async fn start_http_server(storage: ExecutionStorage) -> anyhow::Result<JoinHandle<()>> {
-    let app = create_app(storage);
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await?;
-    info!("HTTP server starting on http://0.0.0.0:3000");
-    
+// One `data:` event per execution. Lagging only drops events for this client, add_execution never waits.
+fn execution_events(
+    events: broadcast::Receiver<ProcessExecution>,
+) -> impl futures_util::Stream<Item = Result<Event, axum::Error>> {
+    futures_util::stream::unfold(events, |mut events| async move {
+        loop {
+            match events.recv().await {
+                Ok(execution) => return Some((Event::default().json_data(&execution), events)),
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("Stream client lagging, dropped {} executions", skipped);
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    })
+}
+
+async fn forward_executions(mut socket: WebSocket, mut events: broadcast::Receiver<ProcessExecution>) {
+    loop {
+        tokio::select! {
+            event = events.recv() => match event {
+                Ok(execution) => {
+                    let frame = match serde_json::to_string(&execution) {
+                        Ok(frame) => frame,
+                        Err(e) => {
+                            error!("Failed to serialize execution: {}", e);
+                            continue;
+                        }
+                    };
+                    if socket.send(Message::Text(frame)).await.is_err() {
+                        break;
+                    }
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("Stream client lagging, dropped {} executions", skipped);
+                }
+                Err(RecvError::Closed) => break,
+            },
+            // Client frames are ignored, we only watch for the connection closing
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
+
+// Returns the address actually bound, which differs from `addr` when port 0 is requested.
+// Once `shutdown` completes no new connections are accepted and the task ends when open ones are done.
+pub async fn start_http_server(
+    storage: ExecutionStorage,
+    exclusions: Exclusions,
+    interpreters: Interpreters,
+    api_key: Option<ApiKey>,
+    tls: Option<RustlsConfig>,
+    addr: SocketAddr,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> anyhow::Result<(JoinHandle<()>, SocketAddr)> {
+    let app = create_app(storage, exclusions, interpreters, api_key);
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("failed to bind HTTP server to {addr} (is another instance running?)"))?;
+    let addr = listener.local_addr()?;
+
     // Spawn the server in a separate task
-    let server_handle = tokio::spawn(async move {
-        if let Err(e) = axum::serve(listener, app).await {
-            error!("Server error: {}", e);
+    let server_handle = match tls {
+        None => {
+            info!("HTTP server starting on http://{}", addr);
+            tokio::spawn(async move {
+                if let Err(e) = axum::serve(listener, app).with_graceful_shutdown(shutdown).await {
+                    error!("Server error: {}", e);
+                }
+            })
         }
-    });
+        Some(config) => {
+            info!("HTTP server starting on https://{}", addr);
+            // axum::serve has no TLS, axum_server takes over the bound listener
+            let handle = axum_server::Handle::new();
+            let stop = handle.clone();
+            tokio::spawn(async move {
+                shutdown.await;
+                stop.graceful_shutdown(None);
+            });
+            let server = axum_server::from_tcp_rustls(listener.into_std()?, config).handle(handle);
+            tokio::spawn(async move {
+                if let Err(e) = server.serve(app.into_make_service()).await {
+                    error!("Server error: {}", e);
+                }
+            })
+        }
+    };
 
     info!("System ready - monitoring process executions");
     info!("API endpoints:");
-    info!("  GET /executions - get all executions (max 500)");
+    info!("  GET /executions - get the most recent executions, newest first (see --max-events)");
+    info!("  GET /executions?limit=<n>&offset=<n> - paginate executions (X-Total-Count, Accept-Profile: envelope)");
+    info!("  GET /executions?sort_by=<timestamp|pid|command>&order=<asc|desc> - sort executions");
+    info!("  GET /executions?command=<path>&command_contains=<str> - filter executions by command");
+    info!("  GET /executions?since=<rfc3339>&until=<rfc3339> - filter executions by time range");
+    info!("  GET /executions?from=<rfc3339>&to=<rfc3339> - filter executions by inclusive time range");
+    info!("  GET /executions?uid=<n> - filter executions by real uid");
+    info!("  DELETE /executions - forget all stored executions");
+    info!("  GET /executions.csv - all stored executions as CSV");
+    info!("  GET /executions/export?format=<json|csv> - all stored executions as a download");
+    info!("  GET /executions.ndjson - all stored executions, one JSON object per line");
+    info!("  GET /executions/stats?top=<n> - the most run commands, with counts and first/last seen, also on /stats/commands");
+    info!("  GET /executions/tree?depth=<n> - the stored processes as a tree by parent pid");
+    info!("  GET /executions/setuid - executions of setuid binaries");
+    info!("  GET /executions/search?command=<str> - case-insensitive command search");
+    info!("  GET /executions/stream - WebSocket or Server-Sent Events, one JSON message per new execution");
+    info!("  GET /ws - the same feed over WebSocket only");
     info!("  GET /executions/:pid - get executions for specific PID");
+    info!("  GET /executions/:pid/exit - get how a PID exited");
+    info!("  GET /executions/:pid/ancestry - get the parents of a PID and their executions");
+    info!("  GET /executions/ppid/:ppid - get executions spawned by a specific parent");
+    info!("  GET /health - liveness probe with the uptime, also on /healthz");
+    info!("  GET /stats - aggregate counters over stored executions");
+    info!("  GET /metrics - Prometheus metrics");
+    info!("  GET /exclusions - commands dropped in the kernel, POST {{\"command\": <path>}} to add, DELETE /exclusions/<encoded path> to remove");
+    info!("  GET /exclusions/pid - processes dropped in the kernel, PUT or DELETE /exclusions/pid/<pid> to add or remove one");
+    info!("  GET /exclusions/subtree - process subtrees dropped in the kernel, POST or DELETE /exclusions/subtree/<pid> to add or remove one");
+    info!("  GET /config/mode - \"allowlist\" or \"denylist\"");
+    info!("  GET /config/interpreters - commands whose executions get a script, PUT a JSON array to replace them");
+    info!("  GET /openapi.json - OpenAPI 3.0 description of this API, browsable on /swagger-ui");
+
+    Ok((server_handle, addr))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use crate::store::{ExecSource, ExitRecord, Syscall};
+    use std::time::Duration;
+    use chrono::Utc;
+    use futures_util::{SinkExt, StreamExt};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_tungstenite::{connect_async, tungstenite};
+
+    fn interpreters() -> Interpreters {
+        Interpreters::new(vec!["/bin/sh".to_string()])
+    }
+
+    pub(crate) fn mk_exec(pid: u32, cmd: &str) -> ProcessExecution {
+        ProcessExecution {
+            pid,
+            tid: pid,
+            ppid: 1,
+            uid: 1000,
+            gid: 1000,
+            euid: 1000,
+            egid: 1000,
+            privilege_elevated: false,
+            setuid_detected: false,
+            after_uid: None,
+            loginuid: None,
+            cgroup_id: 0,
+            container_id: None,
+            cgroup_path: None,
+            pidns: 0,
+            ns_pid: 0,
+            mntns: 0,
+            sid: 0,
+            has_tty: false,
+            timestamp: Utc::now(),
+            process_start: None,
+            dirfd: -1,
+            syscall: Syscall::Execve,
+            source: ExecSource::SysEnter,
+            result: None,
+            exited_at: None,
+            duration_ms: None,
+            commandstr: cmd.to_string(),
+            argstr: String::new(),
+            full_command: cmd.to_string(),
+            command_truncated: false,
+            args_truncated: false,
+            cwd: String::new(),
+            resolved_command: cmd.to_string(),
+            script: None,
+            exe_ino: 0,
+            exe_dev: 0,
+            exe_device: "0:0".to_string(),
+            setuid: None,
+            setgid: None,
+            env: BTreeMap::new(),
+            ld_preload: None,
+            caller_comm: "bash".to_string(),
+            parent_command: "sshd".to_string(),
+            argc: 0,
+            argv_truncated: false,
+            args_total_len: 0,
+            args_len_overflow: false,
+            argv_partial: false,
+            count: 1,
+        }
+    }
+
+    #[tokio::test]
+    async fn stream_delivers_new_executions() {
+        let storage = ExecutionStorage::new(500);
+        // added before connecting, must not be replayed
+        storage.add_execution(mk_exec(1, "/bin/before")).await;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = create_app(storage.clone(), Exclusions::in_memory(10), interpreters(), None);
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let (mut ws, _) = connect_async(format!("ws://{addr}/executions/stream")).await.unwrap();
+        storage.add_execution(mk_exec(42, "/bin/after")).await;
+
+        let frame = tokio::time::timeout(Duration::from_secs(5), ws.next())
+            .await
+            .expect("no frame within timeout")
+            .unwrap()
+            .unwrap();
+        let tungstenite::Message::Text(text) = frame else { panic!("expected a text frame, got {frame:?}") };
+        let execution: ProcessExecution = serde_json::from_str(&text).unwrap();
+        assert_eq!(execution.pid, 42);
+        assert_eq!(execution.commandstr, "/bin/after");
+    }
+
+    #[tokio::test]
+    async fn ws_feed() {
+        let storage = ExecutionStorage::new(500);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = create_app(storage.clone(), Exclusions::in_memory(10), interpreters(), None);
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let (mut ws, _) = connect_async(format!("ws://{addr}/ws")).await.unwrap();
+        // inbound frames are ignored, the feed carries on
+        ws.send(tungstenite::Message::Text("hello".into())).await.unwrap();
+        storage.add_execution(mk_exec(42, "/bin/after")).await;
+        let frame = tokio::time::timeout(Duration::from_secs(5), ws.next()).await.expect("no frame within timeout").unwrap().unwrap();
+        let tungstenite::Message::Text(text) = frame else { panic!("expected a text frame, got {frame:?}") };
+        assert_eq!(serde_json::from_str::<ProcessExecution>(&text).unwrap().pid, 42);
+
+        // closing drops the subscription
+        assert_eq!(storage.subscribers(), 1);
+        ws.close(None).await.unwrap();
+        let dropped = async {
+            while storage.subscribers() != 0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), dropped).await.expect("subscription not dropped");
+
+        // no Server-Sent Events fallback here
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream.write_all(format!("GET /ws HTTP/1.1\r\nHost: {addr}\r\nConnection: close\r\n\r\n").as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(!response.starts_with("HTTP/1.1 200"), "{response}");
+    }
+
+    #[tokio::test]
+    async fn serves_on_requested_address() {
+        let storage = ExecutionStorage::new(500);
+        storage.add_execution(mk_exec(7, "/bin/true")).await;
+        let (handle, addr) = start_http_server(
+            storage,
+            Exclusions::in_memory(10),
+            interpreters(),
+            None,
+            None,
+            "127.0.0.1:0".parse().unwrap(),
+            std::future::pending(),
+        )
+        .await
+        .unwrap();
+        assert_ne!(addr.port(), 0);
+
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(format!("GET /executions HTTP/1.1\r\nHost: {addr}\r\nConnection: close\r\n\r\n").as_bytes())
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{response}");
+        let body = response.split("\r\n\r\n").nth(1).unwrap();
+        let executions: Vec<ProcessExecution> = serde_json::from_str(body).unwrap();
+        assert_eq!(executions.len(), 1);
+        assert_eq!(executions[0].pid, 7);
+
+        // a second server on the same port fails cleanly instead of panicking
+        let err = start_http_server(
+            ExecutionStorage::new(500),
+            Exclusions::in_memory(10),
+            interpreters(),
+            None,
+            None,
+            addr,
+            std::future::pending(),
+        )
+        .await
+        .unwrap_err();
+        assert!(err.to_string().contains(&addr.to_string()), "{err}");
+        handle.abort();
+    }
+
+    #[tokio::test]
+    async fn sse_delivers_new_executions() {
+        let storage = ExecutionStorage::new(500);
+        // added before subscribing, must not be replayed
+        storage.add_execution(mk_exec(1, "/bin/before")).await;
+        let (handle, addr) = start_http_server(
+            storage.clone(),
+            Exclusions::in_memory(10),
+            interpreters(),
+            None,
+            None,
+            "127.0.0.1:0".parse().unwrap(),
+            std::future::pending(),
+        )
+        .await
+        .unwrap();
+
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(format!("GET /executions/stream HTTP/1.1\r\nHost: {addr}\r\nAccept: text/event-stream\r\n\r\n").as_bytes())
+            .await
+            .unwrap();
+        // wait for the response headers, by then the handler has subscribed
+        let mut received = Vec::new();
+        let mut buf = [0u8; 4096];
+        while !String::from_utf8_lossy(&received).contains("\r\n\r\n") {
+            let n = stream.read(&mut buf).await.unwrap();
+            received.extend_from_slice(&buf[..n]);
+        }
+        assert!(String::from_utf8_lossy(&received).contains("text/event-stream"));
+
+        storage.add_execution(mk_exec(42, "/bin/after")).await;
+        let data = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                let text = String::from_utf8_lossy(&received).to_string();
+                if let Some(line) = text.lines().find(|l| l.starts_with("data:")) {
+                    return line["data:".len()..].trim().to_string();
+                }
+                let n = stream.read(&mut buf).await.unwrap();
+                received.extend_from_slice(&buf[..n]);
+            }
+        })
+        .await
+        .expect("no event within timeout");
+        let execution: ProcessExecution = serde_json::from_str(&data).unwrap();
+        assert_eq!(execution.pid, 42);
+        handle.abort();
+    }
+
+    #[tokio::test]
+    async fn create_app_routes() {
+        use axum::{body::Body, http::Request};
+        use tower::ServiceExt;
+
+        let storage = ExecutionStorage::new(500);
+        storage.add_execution(mk_exec(7, "/bin/true")).await;
+        storage.add_exit(ExitRecord { pid: 7, exit_code: 0, signal: None, timestamp: Utc::now() }).await;
+        let app = create_app(storage, Exclusions::in_memory(10), interpreters(), None);
+        let get = |uri: &str| {
+            let app = app.clone();
+            let request = Request::get(uri).body(Body::empty()).unwrap();
+            async move { app.oneshot(request).await.unwrap().status() }
+        };
+
+        for uri in [
+            "/executions",
+            "/executions?command=/bin/true",
+            "/executions.csv",
+            "/executions/export?format=csv",
+            "/executions.ndjson",
+            "/executions/stats?top=5",
+            "/executions/tree",
+            "/executions/setuid",
+            "/executions/search?command=true",
+            "/executions/stream",
+            "/executions/7",
+            "/executions/7/exit",
+            "/executions/ppid/1",
+            "/health",
+            "/healthz",
+            "/stats",
+            "/stats/commands?top=5",
+            "/metrics",
+            "/openapi.json",
+            "/swagger-ui/",
+            "/exclusions",
+            "/config/mode",
+            "/config/interpreters",
+        ] {
+            assert_eq!(get(uri).await, StatusCode::OK, "{uri}");
+        }
+        assert_eq!(get("/nope").await, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn health_reports_uptime() {
+        use axum::{body::Body, http::Request};
+        use tower::ServiceExt;
+
+        let app = create_app(ExecutionStorage::new(500), Exclusions::in_memory(10), interpreters(), None);
+        let response = app.oneshot(Request::get("/health").body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let health: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(health, serde_json::json!({ "status": "ok", "uptime_secs": 0 }));
+    }
+
+    #[tokio::test]
+    async fn exclusion_routes() {
+        use axum::{body::Body, http::Request};
+        use tower::ServiceExt;
+
+        let exclusions = Exclusions::in_memory(10);
+        let app = create_app(ExecutionStorage::new(500), exclusions.clone(), interpreters(), None);
+        let post = Request::post("/exclusions")
+            .header("content-type", "application/json")
+            .body(Body::from(r#"{"command":"/usr/bin/foo"}"#))
+            .unwrap();
+        assert_eq!(app.clone().oneshot(post).await.unwrap().status(), StatusCode::CREATED);
+        assert_eq!(exclusions.list().unwrap(), vec!["/usr/bin/foo"]);
+
+        // the path is decoded before it's looked up
+        let delete = || Request::delete("/exclusions/%2Fusr%2Fbin%2Ffoo").body(Body::empty()).unwrap();
+        assert_eq!(app.clone().oneshot(delete()).await.unwrap().status(), StatusCode::NO_CONTENT);
+        assert_eq!(app.clone().oneshot(delete()).await.unwrap().status(), StatusCode::NOT_FOUND);
+
+        // pids next to the commands
+        let pid = std::process::id();
+        let put = Request::put(format!("/exclusions/pid/{pid}")).body(Body::empty()).unwrap();
+        assert_eq!(app.clone().oneshot(put).await.unwrap().status(), StatusCode::CREATED);
+        let list = app.clone().oneshot(Request::get("/exclusions/pid").body(Body::empty()).unwrap()).await.unwrap();
+        let body = axum::body::to_bytes(list.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(serde_json::from_slice::<Vec<u32>>(&body).unwrap(), vec![pid]);
+        let delete = Request::delete(format!("/exclusions/pid/{pid}")).body(Body::empty()).unwrap();
+        assert_eq!(app.clone().oneshot(delete).await.unwrap().status(), StatusCode::NO_CONTENT);
+        let bad = Request::put("/exclusions/pid/gzip").body(Body::empty()).unwrap();
+        assert_eq!(app.clone().oneshot(bad).await.unwrap().status(), StatusCode::BAD_REQUEST);
+
+        let post = Request::post(format!("/exclusions/subtree/{pid}")).body(Body::empty()).unwrap();
+        assert_eq!(app.clone().oneshot(post).await.unwrap().status(), StatusCode::CREATED);
+        assert_eq!(exclusions.subtrees().list().unwrap()[0].pid, pid);
+        let delete = || Request::delete(format!("/exclusions/subtree/{pid}")).body(Body::empty()).unwrap();
+        assert_eq!(app.clone().oneshot(delete()).await.unwrap().status(), StatusCode::NO_CONTENT);
+        assert_eq!(app.oneshot(delete()).await.unwrap().status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn api_key_routes() {
+        use crate::auth::PUBLIC_PATHS;
+        use axum::{body::Body, http::Request};
+        use tower::ServiceExt;
+
+        let app = create_app(ExecutionStorage::new(500), Exclusions::in_memory(10), interpreters(), Some(ApiKey::new("s3cret")));
+        let get = |uri: &str, authorization: Option<&str>| {
+            let app = app.clone();
+            let mut request = Request::get(uri);
+            if let Some(authorization) = authorization {
+                request = request.header(header::AUTHORIZATION, authorization);
+            }
+            async move { app.oneshot(request.body(Body::empty()).unwrap()).await.unwrap() }
+        };
+
+        for authorization in [None, Some("Bearer wrong"), Some("Bearer s3cre"), Some("Basic s3cret"), Some("s3cret")] {
+            let response = get("/executions", authorization).await;
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED, "{authorization:?}");
+            assert_eq!(response.headers()[header::WWW_AUTHENTICATE], "Bearer");
+        }
+        assert_eq!(get("/executions", Some("Bearer s3cret")).await.status(), StatusCode::OK);
+        assert_eq!(get("/config/mode", Some("bearer s3cret")).await.status(), StatusCode::OK);
+        assert_eq!(get("/config/mode", None).await.status(), StatusCode::UNAUTHORIZED);
+
+        for uri in PUBLIC_PATHS {
+            assert_eq!(get(uri, None).await.status(), StatusCode::OK, "{uri}");
+        }
+    }
+
+    #[tokio::test]
+    async fn openapi_describes_routes() {
+        use axum::{body::Body, http::Request};
+        use tower::ServiceExt;
+
+        let app = create_app(ExecutionStorage::new(500), Exclusions::in_memory(10), interpreters(), None);
+        let response = app.oneshot(Request::get("/openapi.json").body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let spec: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let paths = spec["paths"].as_object().unwrap();
+        assert!(paths.contains_key("/executions"));
+        assert!(paths.contains_key("/executions/{pid}"));
+        let params: Vec<&str> =
+            spec["paths"]["/executions"]["get"]["parameters"].as_array().unwrap().iter().filter_map(|p| p["name"].as_str()).collect();
+        for param in ["limit", "offset", "since", "until", "command"] {
+            assert!(params.contains(&param), "{param}");
+        }
+
+        // the derived schema lists exactly the fields that are served, serde attributes included
+        let serialized = serde_json::to_value(mk_exec(1, "/bin/true")).unwrap();
+        let mut fields: Vec<&String> = serialized.as_object().unwrap().keys().collect();
+        let mut described: Vec<&String> = spec["components"]["schemas"]["ProcessExecution"]["properties"].as_object().unwrap().keys().collect();
+        fields.sort();
+        described.sort();
+        assert_eq!(fields, described);
+    }
+
+    #[test]
+    fn proto_mirrors_process_execution() {
+        let proto = include_str!("../proto/task.proto");
+        let message = proto.split("message ProcessExecution {").nth(1).unwrap().split('}').next().unwrap();
+        // `<type> <name> = <tag>;` lines, comments aside
+        let mut described: Vec<&str> = message
+            .lines()
+            .filter_map(|line| line.trim().split_once(" = ").map(|(decl, _)| decl))
+            .filter_map(|decl| decl.rsplit(' ').next())
+            .collect();
+        let serialized = serde_json::to_value(mk_exec(1, "/bin/true")).unwrap();
+        let mut fields: Vec<&str> = serialized.as_object().unwrap().keys().map(String::as_str).collect();
+        described.sort();
+        fields.sort();
+        assert_eq!(fields, described);
+    }
+
+    #[tokio::test]
+    async fn serves_over_tls() {
+        let rcgen::CertifiedKey { cert, key_pair } = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let files = crate::tls::TlsFiles { cert: dir.path().join("cert.pem"), key: dir.path().join("key.pem") };
+        std::fs::write(&files.cert, cert.pem()).unwrap();
+        std::fs::write(&files.key, key_pair.serialize_pem()).unwrap();
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+        let tls = Some(files.load().await.unwrap());
+        let (handle, addr) = start_http_server(
+            ExecutionStorage::new(500),
+            Exclusions::in_memory(10),
+            interpreters(),
+            None,
+            tls,
+            "127.0.0.1:0".parse().unwrap(),
+            async {
+                let _ = rx.await;
+            },
+        )
+        .await
+        .unwrap();
+
+        let mut roots = rustls::RootCertStore::empty();
+        roots.add(cert.der().clone()).unwrap();
+        let client = rustls::ClientConfig::builder().with_root_certificates(roots).with_no_client_auth();
+        let connector = tokio_rustls::TlsConnector::from(std::sync::Arc::new(client));
+        let tcp = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let mut stream = connector.connect("localhost".try_into().unwrap(), tcp).await.unwrap();
+        stream
+            .write_all(format!("GET /health HTTP/1.1\r\nHost: {addr}\r\nConnection: close\r\n\r\n").as_bytes())
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{response}");
+
+        // cleartext on the TLS port gets no HTTP response
+        let mut plain = tokio::net::TcpStream::connect(addr).await.unwrap();
+        plain.write_all(b"GET /health HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+        let mut response = Vec::new();
+        let _ = plain.read_to_end(&mut response).await;
+        assert!(!response.starts_with(b"HTTP/1.1 200"));
+
+        tx.send(()).unwrap();
+        tokio::time::timeout(Duration::from_secs(5), handle).await.expect("server didn't stop").unwrap();
+    }
+
+    #[tokio::test]
+    async fn stops_accepting_on_shutdown() {
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+        let (handle, addr) = start_http_server(
+            ExecutionStorage::new(500),
+            Exclusions::in_memory(10),
+            interpreters(),
+            None,
+            None,
+            "127.0.0.1:0".parse().unwrap(),
+            async {
+                let _ = rx.await;
+            },
+        )
+        .await
+        .unwrap();
+        assert!(tokio::net::TcpStream::connect(addr).await.is_ok());
 
-    Ok(server_handle)
+        tx.send(()).unwrap();
+        tokio::time::timeout(Duration::from_secs(5), handle).await.expect("server didn't stop").unwrap();
+        assert!(tokio::net::TcpStream::connect(addr).await.is_err());
+    }
 }
@@ -0,0 +1,288 @@
+// gRPC API over the same storage as the HTTP API, see proto/task.proto
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+
+use anyhow::Context as _;
+use chrono::{DateTime, Utc};
+use futures_util::Stream;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::task::JoinHandle;
+use tonic::service::Interceptor;
+use tonic::transport::server::TcpIncoming;
+use tonic::{Request, Response, Status};
+use tracing::{error, info, warn};
+
+use crate::auth::ApiKey;
+use crate::store::{self, DEFAULT_PAGE_LIMIT, ExecutionStorage, MAX_PAGE_LIMIT, SortOrder};
+
+pub mod proto {
+    tonic::include_proto!("task");
+}
+
+use proto::task_service_server::{TaskService, TaskServiceServer};
+use proto::{ExitRecord, ExitRequest, ListRequest, ListResponse, ProcessExecution, StreamRequest};
+
+pub struct GrpcService {
+    storage: ExecutionStorage,
+}
+
+#[tonic::async_trait]
+impl TaskService for GrpcService {
+    // Newest first like GET /executions, with the same limits
+    async fn list_executions(&self, request: Request<ListRequest>) -> Result<Response<ListResponse>, Status> {
+        let ListRequest { offset, limit } = request.into_inner();
+        let limit = match limit {
+            0 => DEFAULT_PAGE_LIMIT,
+            limit => limit as usize,
+        };
+        if limit > MAX_PAGE_LIMIT {
+            return Err(Status::invalid_argument(format!("`limit` must be between 1 and {MAX_PAGE_LIMIT}, got {limit}")));
+        }
+        let offset = offset as usize;
+        let (executions, total) = self.storage.get_executions_paginated(offset, limit, SortOrder::Desc).await;
+        if total > 0 && offset >= total {
+            return Err(Status::invalid_argument(format!(
+                "`offset` {offset} is out of range, valid offsets are 0 to {} ({total} executions)",
+                total - 1
+            )));
+        }
+        Ok(Response::new(ListResponse { executions: executions.iter().map(ProcessExecution::from).collect(), total: total as u64 }))
+    }
+
+    type StreamExecutionsStream = Pin<Box<dyn Stream<Item = Result<ProcessExecution, Status>> + Send>>;
+
+    async fn stream_executions(&self, _: Request<StreamRequest>) -> Result<Response<Self::StreamExecutionsStream>, Status> {
+        Ok(Response::new(Box::pin(execution_messages(self.storage.subscribe()))))
+    }
+
+    // Like GET /executions/:pid/exit, NOT_FOUND while the process runs or if its exit wasn't seen
+    async fn get_exit(&self, request: Request<ExitRequest>) -> Result<Response<ExitRecord>, Status> {
+        let pid = request.into_inner().pid;
+        match self.storage.get_exit(pid).await {
+            Some(exit) => Ok(Response::new(ExitRecord::from(&exit))),
+            None => Err(Status::not_found(format!("no exit recorded for PID {pid}"))),
+        }
+    }
+}
+
+// Same feed as /executions/stream, a lagging client only misses executions itself
+fn execution_messages(events: broadcast::Receiver<store::ProcessExecution>) -> impl Stream<Item = Result<ProcessExecution, Status>> {
+    futures_util::stream::unfold(events, |mut events| async move {
+        loop {
+            match events.recv().await {
+                Ok(execution) => return Some((Ok(ProcessExecution::from(&execution)), events)),
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("gRPC stream client lagging, dropped {} executions", skipped);
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    })
+}
+
+// Like start_http_server: returns the address actually bound, and the task ends once `shutdown`
+// completes and open calls are done. With an api key every call needs `authorization: Bearer <key>`
+pub async fn start_grpc_server(
+    storage: ExecutionStorage,
+    api_key: Option<ApiKey>,
+    addr: SocketAddr,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> anyhow::Result<(JoinHandle<()>, SocketAddr)> {
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("failed to bind gRPC server to {addr} (is another instance running?)"))?;
+    let addr = listener.local_addr()?;
+    let incoming = TcpIncoming::from_listener(listener, true, None).map_err(|e| anyhow::anyhow!("gRPC listener: {e}"))?;
+    let service = TaskServiceServer::with_interceptor(GrpcService { storage }, RequireApiKey(api_key));
+
+    info!("gRPC server starting on {}", addr);
+    let server_handle = tokio::spawn(async move {
+        let server = tonic::transport::Server::builder().add_service(service);
+        if let Err(e) = server.serve_with_incoming_shutdown(incoming, shutdown).await {
+            error!("gRPC server error: {}", e);
+        }
+    });
+    Ok((server_handle, addr))
+}
+
+// The gRPC side of auth::require_api_key, every call is checked when a key is set
+#[derive(Clone)]
+struct RequireApiKey(Option<ApiKey>);
+
+impl Interceptor for RequireApiKey {
+    fn call(&mut self, request: Request<()>) -> Result<Request<()>, Status> {
+        let Some(key) = &self.0 else { return Ok(request) };
+        let authorization = request.metadata().get("authorization").and_then(|value| value.to_str().ok());
+        if authorization.is_some_and(|value| key.authorizes(value)) {
+            Ok(request)
+        } else {
+            Err(Status::unauthenticated("missing or invalid API key"))
+        }
+    }
+}
+
+fn timestamp(time: &DateTime<Utc>) -> prost_types::Timestamp {
+    prost_types::Timestamp { seconds: time.timestamp(), nanos: time.timestamp_subsec_nanos() as i32 }
+}
+
+impl From<&store::ProcessExecution> for ProcessExecution {
+    fn from(execution: &store::ProcessExecution) -> Self {
+        let syscall = match execution.syscall {
+            store::Syscall::Execve => proto::Syscall::Execve,
+            store::Syscall::Execveat => proto::Syscall::Execveat,
+            store::Syscall::Unknown => proto::Syscall::Unknown,
+        };
+        let source = match execution.source {
+            store::ExecSource::SysEnter => proto::ExecSource::SysEnter,
+            store::ExecSource::SchedExec => proto::ExecSource::SchedExec,
+        };
+        Self {
+            pid: execution.pid,
+            ppid: execution.ppid,
+            uid: execution.uid,
+            gid: execution.gid,
+            euid: execution.euid,
+            egid: execution.egid,
+            privilege_elevated: execution.privilege_elevated,
+            cgroup_id: execution.cgroup_id,
+            pidns: execution.pidns,
+            ns_pid: execution.ns_pid,
+            mntns: execution.mntns,
+            sid: execution.sid,
+            has_tty: execution.has_tty,
+            timestamp: Some(timestamp(&execution.timestamp)),
+            dirfd: execution.dirfd,
+            syscall: syscall as i32,
+            source: source as i32,
+            result: execution.result,
+            commandstr: execution.commandstr.clone(),
+            argstr: execution.argstr.clone(),
+            command_truncated: execution.command_truncated,
+            args_truncated: execution.args_truncated,
+            full_command: execution.full_command.clone(),
+            cwd: execution.cwd.clone(),
+            resolved_command: execution.resolved_command.clone(),
+            env: execution.env.iter().map(|(name, value)| (name.clone(), value.clone())).collect(),
+            ld_preload: execution.ld_preload.clone(),
+            caller_comm: execution.caller_comm.clone(),
+            parent_command: execution.parent_command.clone(),
+            argc: execution.argc,
+            argv_truncated: execution.argv_truncated,
+            argv_partial: execution.argv_partial,
+            count: execution.count,
+            exited_at: execution.exited_at.as_ref().map(timestamp),
+            duration_ms: execution.duration_ms,
+            tid: execution.tid,
+            loginuid: execution.loginuid,
+            exe_ino: execution.exe_ino,
+            exe_dev: execution.exe_dev,
+            exe_device: execution.exe_device.clone(),
+            container_id: execution.container_id.clone(),
+            args_total_len: execution.args_total_len,
+            args_len_overflow: execution.args_len_overflow,
+            script: execution.script.clone(),
+            setuid_detected: execution.setuid_detected,
+            after_uid: execution.after_uid,
+            setuid: execution.setuid,
+            setgid: execution.setgid,
+            process_start: execution.process_start.as_ref().map(timestamp),
+            cgroup_path: execution.cgroup_path.clone(),
+        }
+    }
+}
+
+impl From<&store::ExitRecord> for ExitRecord {
+    fn from(exit: &store::ExitRecord) -> Self {
+        Self { pid: exit.pid, exit_code: exit.exit_code, signal: exit.signal, timestamp: Some(timestamp(&exit.timestamp)) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::tests::mk_exec;
+    use std::time::Duration;
+    use futures_util::StreamExt;
+    use proto::task_service_client::TaskServiceClient;
+    use tonic::Code;
+
+    #[tokio::test]
+    async fn smoke() {
+        let storage = ExecutionStorage::new(500);
+        storage.add_execution(mk_exec(7, "/bin/true")).await;
+        storage.add_execution(mk_exec(8, "/bin/false")).await;
+        let (handle, addr) = start_grpc_server(storage.clone(), None, "127.0.0.1:0".parse().unwrap(), std::future::pending()).await.unwrap();
+        let mut client = TaskServiceClient::connect(format!("http://{addr}")).await.unwrap();
+
+        // newest first
+        let list = client.list_executions(ListRequest { offset: 0, limit: 0 }).await.unwrap().into_inner();
+        assert_eq!(list.total, 2);
+        assert_eq!(list.executions.iter().map(|e| e.pid).collect::<Vec<_>>(), vec![8, 7]);
+        assert_eq!(list.executions[1].commandstr, "/bin/true");
+        let page = client.list_executions(ListRequest { offset: 1, limit: 1 }).await.unwrap().into_inner();
+        assert_eq!(page.executions.iter().map(|e| e.pid).collect::<Vec<_>>(), vec![7]);
+        let err = client.list_executions(ListRequest { offset: 2, limit: 0 }).await.unwrap_err();
+        assert_eq!(err.code(), Code::InvalidArgument);
+
+        // only executions added after the call
+        let mut stream = client.stream_executions(StreamRequest {}).await.unwrap().into_inner();
+        storage.add_execution(mk_exec(42, "/bin/after")).await;
+        let execution = tokio::time::timeout(Duration::from_secs(5), stream.next()).await.expect("no message within timeout").unwrap().unwrap();
+        assert_eq!((execution.pid, execution.commandstr.as_str()), (42, "/bin/after"));
+        assert_eq!(execution.syscall, proto::Syscall::Execve as i32);
+        handle.abort();
+    }
+
+    #[tokio::test]
+    async fn get_exit() {
+        let storage = ExecutionStorage::new(500);
+        storage.add_execution(mk_exec(7, "/bin/true")).await;
+        storage.add_execution(mk_exec(8, "/bin/sleep")).await;
+        let exited = Utc::now();
+        storage.add_exit(store::ExitRecord { pid: 7, exit_code: 0, signal: Some(9), timestamp: exited }).await;
+        let (handle, addr) = start_grpc_server(storage.clone(), None, "127.0.0.1:0".parse().unwrap(), std::future::pending()).await.unwrap();
+        let mut client = TaskServiceClient::connect(format!("http://{addr}")).await.unwrap();
+
+        let exit = client.get_exit(ExitRequest { pid: 7 }).await.unwrap().into_inner();
+        assert_eq!((exit.pid, exit.exit_code, exit.signal), (7, 0, Some(9)));
+        assert_eq!(exit.timestamp, Some(timestamp(&exited)));
+        // still running, and never seen
+        assert_eq!(client.get_exit(ExitRequest { pid: 8 }).await.unwrap_err().code(), Code::NotFound);
+        assert_eq!(client.get_exit(ExitRequest { pid: 9 }).await.unwrap_err().code(), Code::NotFound);
+
+        // the exit shows on the execution too
+        let list = client.list_executions(ListRequest::default()).await.unwrap().into_inner();
+        let execution = list.executions.iter().find(|e| e.pid == 7).unwrap();
+        assert_eq!(execution.exited_at, Some(timestamp(&exited)));
+        assert!(execution.duration_ms.is_some());
+        handle.abort();
+    }
+
+    #[tokio::test]
+    async fn requires_api_key() {
+        let key = Some(ApiKey::new("s3cret"));
+        let (handle, addr) = start_grpc_server(ExecutionStorage::new(500), key, "127.0.0.1:0".parse().unwrap(), std::future::pending()).await.unwrap();
+        let mut client = TaskServiceClient::connect(format!("http://{addr}")).await.unwrap();
+        let err = client.list_executions(ListRequest::default()).await.unwrap_err();
+        assert_eq!(err.code(), Code::Unauthenticated);
+
+        let mut request = Request::new(ListRequest::default());
+        request.metadata_mut().insert("authorization", "Bearer s3cret".parse().unwrap());
+        assert_eq!(client.list_executions(request).await.unwrap().into_inner().total, 0);
+        handle.abort();
+    }
+
+    #[tokio::test]
+    async fn stops_on_shutdown() {
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+        let (handle, addr) = start_grpc_server(ExecutionStorage::new(500), None, "127.0.0.1:0".parse().unwrap(), async {
+            let _ = rx.await;
+        })
+        .await
+        .unwrap();
+        assert!(tokio::net::TcpStream::connect(addr).await.is_ok());
+        tx.send(()).unwrap();
+        tokio::time::timeout(Duration::from_secs(5), handle).await.expect("server didn't stop").unwrap();
+    }
+}
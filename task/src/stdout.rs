@@ -0,0 +1,91 @@
+// --stdout-format ndjson: every stored execution is also printed to stdout as one JSON object per
+// line, for piping into jq or a log shipper. Logs go to stderr then so stdout only has events
+use std::io::{self, LineWriter, Stdout, Write};
+
+use tracing::error;
+
+use crate::store::ProcessExecution;
+
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum StdoutFormat {
+    /// Nothing but logs on stdout
+    None,
+    /// One JSON object per execution, in the shape of /executions
+    Ndjson,
+}
+
+// Flushed at every newline, a reader never sees half an object
+pub struct ExecutionPrinter<W: Write> {
+    out: Option<LineWriter<W>>,
+}
+
+impl ExecutionPrinter<Stdout> {
+    pub fn stdout(format: StdoutFormat) -> Self {
+        Self::new(format, io::stdout())
+    }
+}
+
+impl<W: Write> ExecutionPrinter<W> {
+    pub fn new(format: StdoutFormat, out: W) -> Self {
+        let out = (format == StdoutFormat::Ndjson).then(|| LineWriter::new(out));
+        Self { out }
+    }
+
+    // A failed write (the reader went away) stops the output instead of logging an error per exec
+    pub fn print(&mut self, execution: &ProcessExecution) {
+        let Some(out) = &mut self.out else { return };
+        if let Err(e) = write_line(out, execution) {
+            error!("Stopping the NDJSON output on stdout: {}", e);
+            self.out = None;
+        }
+    }
+}
+
+fn write_line(out: &mut impl Write, execution: &ProcessExecution) -> io::Result<()> {
+    serde_json::to_writer(&mut *out, execution)?;
+    out.write_all(b"\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::tests::mk_exec;
+    use std::io::{BufRead, BufReader};
+    use std::os::fd::{FromRawFd, OwnedFd};
+    use std::process::Command;
+
+    fn execution(pid: u32, argstr: &str) -> ProcessExecution {
+        ProcessExecution { argstr: argstr.to_string(), ..mk_exec(pid, "/bin/sh") }
+    }
+
+    #[test]
+    fn one_object_per_line() {
+        let (read, write) = {
+            let mut fds = [0; 2];
+            assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+            unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) }
+        };
+        let mut printer = ExecutionPrinter::new(StdoutFormat::Ndjson, std::fs::File::from(write));
+        let mut child = Command::new("/bin/sh").args(["-c", "exit 0"]).spawn().unwrap();
+        // a newline in an argument stays escaped inside its line
+        printer.print(&execution(child.id(), "-c echo a\necho b"));
+        printer.print(&execution(child.id() + 1, "-c exit 0"));
+        drop(printer);
+        child.wait().unwrap();
+
+        let lines: Vec<_> = BufReader::new(std::fs::File::from(read)).lines().map(Result::unwrap).collect();
+        assert_eq!(lines.len(), 2);
+        let first: serde_json::Value = serde_json::from_str(&lines[0]).unwrap();
+        assert_eq!(first["pid"], child.id());
+        assert_eq!(first["argstr"], "-c echo a\necho b");
+        let second: ProcessExecution = serde_json::from_str(&lines[1]).unwrap();
+        assert_eq!(second.pid, child.id() + 1);
+    }
+
+    #[test]
+    fn none_prints_nothing() {
+        let mut buf = Vec::new();
+        ExecutionPrinter::new(StdoutFormat::None, &mut buf).print(&execution(1, "-c true"));
+        assert!(buf.is_empty());
+    }
+}
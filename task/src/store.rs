@@ -1,191 +1,3480 @@
+use std::os::unix::fs::PermissionsExt;
 use std::sync::Arc;
-use std::collections::VecDeque;
-use tokio::sync::RwLock;
+use std::sync::atomic::Ordering;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use tokio::sync::{RwLock, broadcast, mpsc};
+use tokio::task::JoinHandle;
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
-    response::Json,
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Json, Response},
 };
 use serde::{Deserialize, Serialize};
-use tracing::info;
-use chrono::{DateTime, Utc, Duration};
+use utoipa::{IntoParams, ToSchema};
+use tracing::{info, error};
+use chrono::{DateTime, Utc, Duration, SecondsFormat};
 
-use crate::{ExecEvent, MAX_EVENTS};
+use crate::ExecEvent;
+use crate::metrics::Metrics;
 use crate::ARGV_OFFSET;
+use task_common::{
+    ExecResultEvent, SetuidEvent, AUDIT_UID_UNSET, CGROUP_NAME_LEN, CWD_LEN, MAX_ENV_MATCHES, SOURCE_SCHED_EXEC,
+    SYSCALL_EXECVEAT, SYSCALL_UNKNOWN,
+};
+
+mod exit;
+mod lineage;
+mod sqlite;
+pub use exit::ExitRecord;
+use exit::ExitLog;
+pub use lineage::{Ancestor, ForkRecord};
+use lineage::{Lineage, MAX_FORKS};
+pub use sqlite::SqliteExecutionStorage;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct ProcessExecution {
+    /// Process id (tgid), what `ps` shows
     pub pid: u32,
+    /// Thread that called exec, differs from pid for an exec from a thread other than the main one
+    pub tid: u32,
+    /// tgid of the parent process
+    pub ppid: u32,
+    /// Real uid/gid
+    pub uid: u32,
+    pub gid: u32,
+    pub euid: u32,
+    pub egid: u32,
+    /// euid != uid, e.g. running under a setuid binary
+    pub privilege_elevated: bool,
+    /// The exec itself changed the euid, from `euid` to `after_uid`: the binary is setuid. Filled in
+    /// once the exec returned, so never with --attach sched-exec
+    pub setuid_detected: bool,
+    pub after_uid: Option<u32>,
+    /// Audit login uid, the user that logged in even after su/sudo. null if never set (daemons,
+    /// cron without pam_loginuid) or audit isn't built in
+    pub loginuid: Option<u32>,
+    /// Raw cgroup v2 id (inode of the cgroup directory)
+    pub cgroup_id: u64,
+    /// 64 hex digit container id from the cgroup name (docker, containerd, CRI-O, podman), null
+    /// outside a container
+    pub container_id: Option<String>,
+    /// cgroup v2 path of cgroup_id, e.g. `/system.slice/docker-<id>.scope`, null if it couldn't be
+    /// resolved
+    // see cgroups.rs, set after from_event
+    pub cgroup_path: Option<String>,
+    /// pid namespace inode (as in `lsns -t pid`) and the pid inside it
+    pub pidns: u32,
+    pub ns_pid: u32,
+    /// Mount namespace inode (as in `lsns -t mnt`), 0 if unknown
+    pub mntns: u64,
+    /// Session id and whether there is a controlling terminal, i.e. likely typed by a human
+    pub sid: u32,
+    pub has_tty: bool,
     pub timestamp: DateTime<Utc>,
+    /// When the process was started (fork, not exec). Together with pid it identifies the process,
+    /// a pid reused by another process gets another process_start. null if unknown
+    pub process_start: Option<DateTime<Utc>>,
+    /// Directory fd commandstr is relative to for execveat(), -100 being the cwd (AT_FDCWD).
+    /// -1 for execve()
+    pub dirfd: i32,
+    /// Which syscall ran the command, unknown with --attach sched-exec
+    pub syscall: Syscall,
+    /// Tracepoint the execution was seen at
+    pub source: ExecSource,
+    /// Return value of the exec: 0 on success, -errno on failure, null until it is known
+    pub result: Option<i32>,
+    /// When the process exited and how long it ran since this exec, null while it's running
+    pub exited_at: Option<DateTime<Utc>>,
+    pub duration_ms: Option<i64>,
     pub commandstr: String,
     pub argstr: String,
+    /// The command path / at least one argument was cut, the cut strings end with `…`
+    // at COMMAND_LEN / ARGV_LEN, TRUNCATION_MARKER
+    pub command_truncated: bool,
+    pub args_truncated: bool,
     pub full_command: String,
+    /// Working directory the command was started from, empty if unknown
+    pub cwd: String,
+    /// commandstr made absolute against cwd when it's relative to it, commandstr otherwise
+    pub resolved_command: String,
+    /// Script an interpreter (see /config/interpreters) was started on, e.g. `./deploy.sh` for
+    /// `bash ./deploy.sh`. null for other commands and inline code (`sh -c`)
+    pub script: Option<String>,
+    /// Inode and device (as st_ino/st_dev of stat(2)) of the file that was executed, which pins it
+    /// down even if the path was replaced. exe_device is exe_dev as `major:minor`. 0 if unknown,
+    /// and for sys_enter executions until the exec succeeded
+    pub exe_ino: u64,
+    pub exe_dev: u64,
+    pub exe_device: String,
+    /// S_ISUID/S_ISGID of the executed file, looked up by resolved_command when the execution comes
+    /// in. null if the path isn't known in full or the file is gone
+    // see read_setid_bits
+    pub setuid: Option<bool>,
+    pub setgid: Option<bool>,
+    /// Environment variables matching --env-prefix (e.g. PATH, LD_PRELOAD), name to value
+    pub env: BTreeMap<String, String>,
+    /// Value of LD_PRELOAD in env, libraries injected into the command. null if unset or empty
+    pub ld_preload: Option<String>,
+    /// comm of the task that called execve (e.g. `bash`, `make`)
+    pub caller_comm: String,
+    /// comm of the parent process, read in the kernel so it survives the parent exiting. Empty if unknown
+    pub parent_command: String,
+    /// Number of arguments passed, including the ones not in argstr. Capped at the number the probe
+    /// reads
+    // MAX_ARGC
+    pub argc: u32,
+    /// More arguments were passed than the probe reads, argstr only holds the first ones
+    pub argv_truncated: bool,
+    /// Bytes in all of argv (without the NULs) however much of it argstr shows, and whether it was
+    /// too long to measure, which makes args_total_len a lower bound
+    pub args_total_len: u32,
+    pub args_len_overflow: bool,
+    /// Some or all of argv is missing, a continuation record was lost or the probe couldn't read it
+    /// (then argc is 0)
+    pub argv_partial: bool,
+    /// Identical execs this record stands for, more than 1 only with --dedup-window-ms
+    pub count: u32,
+}
+
+// Translate monotonic ns (since boot) to wall-clock
+fn wall_clock(boot_offset: Duration, timestamp: u64) -> DateTime<Utc> {
+    let wall = boot_offset + Duration::nanoseconds(timestamp as i64);
+    DateTime::<Utc>::from_timestamp(wall.num_seconds(), (wall.num_nanoseconds().unwrap_or(0) % 1_000_000_000) as u32)
+        .unwrap_or_else(Utc::now)
 }
 
+// Appended to arguments that were cut at ARGV_LEN
+pub const TRUNCATION_MARKER: &str = "…";
+
 impl ProcessExecution {
     pub fn from_event(event: &ExecEvent, boot_offset: Duration) -> Self {
-        // Translate monotonic ns (since boot) to wall-clock
-        let wall = boot_offset + Duration::nanoseconds(event.timestamp as i64);
-        let commandstr = String::from_utf8_lossy(&event.command[..event.command_len]).to_string();
-        let mut args = Vec::new();
-        for i in 0..ARGV_OFFSET.min(event.argvs_offset.len()) {
-            let argv_len = event.argvs_offset[i];
-            if argv_len == 0 { break; }
-            let arg = String::from_utf8_lossy(&event.argvs[i][..argv_len]).to_string();
-            args.push(arg);
-        }
-        let argstr = args.join(" ");
+        let mut commandstr = String::from_utf8_lossy(&event.command[..event.command_len]).to_string();
+        // fexecve() and friends run the file dirfd refers to, there's no path to show
+        if commandstr.is_empty() && event.at_flags & AT_EMPTY_PATH != 0 {
+            commandstr = format!("/dev/fd/{}", event.dirfd);
+        }
+        if event.command_truncated != 0 {
+            commandstr.push_str(TRUNCATION_MARKER);
+        }
+        let argstr = decode_args(event).join(" ");
+        let cwd = String::from_utf8_lossy(&event.cwd[..event.cwd_len.min(CWD_LEN)]).to_string();
+        let resolved_command = resolve_command(&commandstr, &cwd, event.dirfd);
+        let caller_comm = decode_comm(&event.comm);
+        let parent_command = decode_comm(&event.parent_comm);
+        let env = decode_env(event);
         let full_command = if argstr.is_empty() { commandstr.clone() } else { format!("{} {}", commandstr, argstr) };
-        ProcessExecution { pid: event.pid, timestamp: DateTime::<Utc>::from_timestamp(wall.num_seconds(), (wall.num_nanoseconds().unwrap_or(0) % 1_000_000_000) as u32).unwrap_or_else(|| Utc::now()), commandstr, argstr, full_command }
+        ProcessExecution {
+            pid: event.tgid,
+            tid: event.tid,
+            ppid: event.ppid,
+            uid: event.uid,
+            gid: event.gid,
+            euid: event.euid,
+            egid: event.egid,
+            privilege_elevated: event.euid != event.uid,
+            setuid_detected: false,
+            after_uid: None,
+            loginuid: (event.loginuid != AUDIT_UID_UNSET).then_some(event.loginuid),
+            cgroup_id: event.cgroup_id,
+            container_id: container_id(&decode_comm(&event.cgroup_name[..CGROUP_NAME_LEN])),
+            cgroup_path: None,
+            pidns: event.pidns,
+            ns_pid: event.ns_pid,
+            mntns: event.mntns,
+            sid: event.sid,
+            has_tty: event.has_tty != 0,
+            timestamp: wall_clock(boot_offset, event.timestamp),
+            process_start: (event.start_time != 0).then(|| wall_clock(boot_offset, event.start_time)),
+            dirfd: event.dirfd,
+            syscall: Syscall::from_event(event),
+            source: ExecSource::from_event(event),
+            result: (event.source == SOURCE_SCHED_EXEC).then_some(0),
+            exited_at: None,
+            duration_ms: None,
+            commandstr,
+            argstr,
+            full_command,
+            command_truncated: event.command_truncated != 0,
+            args_truncated: any_arg_truncated(event),
+            cwd,
+            resolved_command,
+            script: None,
+            exe_ino: event.exe_ino,
+            exe_dev: exe_dev(event.exe_dev),
+            exe_device: device_name(exe_dev(event.exe_dev)),
+            setuid: None,
+            setgid: None,
+            ld_preload: ld_preload(&env),
+            env,
+            caller_comm,
+            parent_command,
+            argc: event.argc,
+            argv_truncated: event.argv_truncated != 0,
+            args_total_len: event.args_total_len,
+            args_len_overflow: event.args_len_overflow != 0,
+            argv_partial: event.argv_missing != 0,
+            count: 1,
+        }
+    }
+
+    // Sets the cwd when it's only known after the fact (e.g. from /proc) and re-resolves the command
+    pub fn set_cwd(&mut self, cwd: String) {
+        self.resolved_command = resolve_command(&self.commandstr, &cwd, self.dirfd);
+        self.cwd = cwd;
+    }
+
+    // Stats the command for its setuid and setgid bits. The path is looked up in this process's
+    // mount namespace, and may have been replaced since the exec
+    pub fn read_setid_bits(&mut self) {
+        if self.command_truncated || !self.resolved_command.starts_with('/') {
+            return;
+        }
+        if let Ok(metadata) = std::fs::metadata(&self.resolved_command) {
+            let mode = metadata.permissions().mode();
+            self.setuid = Some(mode & libc::S_ISUID != 0);
+            self.setgid = Some(mode & libc::S_ISGID != 0);
+        }
+    }
+
+    pub fn set_exit(&mut self, exited_at: DateTime<Utc>) {
+        self.exited_at = Some(exited_at);
+        self.duration_ms = Some((exited_at - self.timestamp).num_milliseconds());
+    }
+
+    // Appends the arguments carried by an argv continuation record
+    pub fn append_args(&mut self, chunk: &ExecEvent) {
+        let args = decode_args(chunk);
+        if args.is_empty() {
+            return;
+        }
+        if !self.argstr.is_empty() {
+            self.argstr.push(' ');
+        }
+        self.argstr.push_str(&args.join(" "));
+        self.full_command = format!("{} {}", self.commandstr, self.argstr);
+        self.args_truncated |= any_arg_truncated(chunk);
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Syscall {
+    #[default]
+    Execve,
+    Execveat,
+    Unknown,
+}
+
+impl Syscall {
+    fn from_event(event: &ExecEvent) -> Self {
+        match event.syscall {
+            SYSCALL_EXECVEAT => Self::Execveat,
+            SYSCALL_UNKNOWN => Self::Unknown,
+            _ => Self::Execve,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Execve => "execve",
+            Self::Execveat => "execveat",
+            Self::Unknown => "unknown",
+        }
+    }
+}
+
+impl std::str::FromStr for Syscall {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "execve" => Ok(Self::Execve),
+            "execveat" => Ok(Self::Execveat),
+            "unknown" => Ok(Self::Unknown),
+            _ => Err(format!("unknown syscall {s:?}")),
+        }
+    }
+}
+
+// sys_enter reports exec attempts (including failed ones) with the syscall arguments,
+// sched_exec only successful execs with argv read back from the new program
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ExecSource {
+    #[default]
+    SysEnter,
+    SchedExec,
+}
+
+impl ExecSource {
+    fn from_event(event: &ExecEvent) -> Self {
+        match event.source {
+            SOURCE_SCHED_EXEC => Self::SchedExec,
+            _ => Self::SysEnter,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::SysEnter => "sys_enter",
+            Self::SchedExec => "sched_exec",
+        }
+    }
+}
+
+impl std::str::FromStr for ExecSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sys_enter" => Ok(Self::SysEnter),
+            "sched_exec" => Ok(Self::SchedExec),
+            _ => Err(format!("unknown source {s:?}")),
+        }
+    }
+}
+
+const AT_EMPTY_PATH: u32 = 0x1000;
+
+// execve() and execveat(AT_FDCWD, ..) resolve relative paths against the cwd, other execveat()
+// dirfds point somewhere we don't know
+const AT_FDCWD: i32 = -100;
+
+fn resolve_command(commandstr: &str, cwd: &str, dirfd: i32) -> String {
+    if commandstr.starts_with('/') || cwd.is_empty() || !(dirfd == -1 || dirfd == AT_FDCWD) {
+        return commandstr.to_string();
+    }
+    // drops `.` components, `..` is kept as the target may be a symlink
+    std::path::Path::new(cwd).join(commandstr).components().collect::<std::path::PathBuf>().to_string_lossy().into_owned()
+}
+
+fn any_arg_truncated(event: &ExecEvent) -> bool {
+    event.argvs_truncated.iter().any(|&truncated| truncated != 0)
+}
+
+// The ARGV_OFFSET argument slots of one record, cut arguments marked
+fn decode_args(event: &ExecEvent) -> Vec<String> {
+    let mut args = Vec::new();
+    for i in 0..ARGV_OFFSET.min(event.argvs_offset.len()) {
+        let argv_len = event.argvs_offset[i];
+        if argv_len == 0 { break; }
+        let mut arg = String::from_utf8_lossy(&event.argvs[i][..argv_len]).to_string();
+        if event.argvs_truncated[i] != 0 {
+            arg.push_str(TRUNCATION_MARKER);
+        }
+        args.push(arg);
+    }
+    args
+}
+
+// Matched `NAME=value` entries, cut values marked
+fn decode_env(event: &ExecEvent) -> BTreeMap<String, String> {
+    let mut env = BTreeMap::new();
+    for i in 0..MAX_ENV_MATCHES {
+        let len = event.env_len[i].min(event.env[i].len());
+        if len == 0 { continue; }
+        let mut entry = String::from_utf8_lossy(&event.env[i][..len]).to_string();
+        if event.env_truncated[i] != 0 {
+            entry.push_str(TRUNCATION_MARKER);
+        }
+        let (name, value) = entry.split_once('=').unwrap_or((&entry, ""));
+        env.insert(name.to_string(), value.to_string());
+    }
+    env
+}
+
+fn ld_preload(env: &BTreeMap<String, String>) -> Option<String> {
+    env.get("LD_PRELOAD").filter(|value| !value.is_empty()).cloned()
+}
+
+// An exec's return value, applied to the execution with the same pid and timestamp
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExecResult {
+    pub pid: u32,
+    pub timestamp: DateTime<Utc>,
+    pub result: i32,
+    // ProcessExecution::exe_ino and exe_dev, 0 unless the exec succeeded
+    pub exe_ino: u64,
+    pub exe_dev: u64,
+}
+
+impl ExecResult {
+    pub fn from_event(event: &ExecResultEvent, boot_offset: Duration) -> Self {
+        ExecResult {
+            pid: event.pid,
+            timestamp: wall_clock(boot_offset, event.timestamp),
+            result: event.ret,
+            exe_ino: event.exe_ino,
+            exe_dev: exe_dev(event.exe_dev),
+        }
+    }
+}
+
+// An exec that changed the euid, applied to the execution with the same pid and timestamp
+#[derive(Debug, Clone, PartialEq)]
+pub struct SetuidRecord {
+    pub pid: u32,
+    pub timestamp: DateTime<Utc>,
+    pub before_uid: u32,
+    pub after_uid: u32,
+    // comm of the new program
+    pub command: String,
+}
+
+impl SetuidRecord {
+    pub fn from_event(event: &SetuidEvent, boot_offset: Duration) -> Self {
+        SetuidRecord {
+            pid: event.pid,
+            timestamp: wall_clock(boot_offset, event.timestamp),
+            before_uid: event.before_uid,
+            after_uid: event.after_uid,
+            command: decode_comm(&event.command),
+        }
+    }
+}
+
+// The kernel's dev_t (MAJOR << 20 | MINOR) as stat(2) encodes it
+fn exe_dev(kernel_dev: u64) -> u64 {
+    if kernel_dev == 0 {
+        return 0;
+    }
+    libc::makedev((kernel_dev >> 20) as u32, (kernel_dev & 0xfffff) as u32)
+}
+
+// `major:minor` of a stat(2) dev_t, like /proc/self/mountinfo shows it
+pub(crate) fn device_name(dev: u64) -> String {
+    format!("{}:{}", libc::major(dev), libc::minor(dev))
+}
+
+// What the background writer applies, in the order it was sent
+#[derive(Debug, Clone)]
+pub enum StorageWrite {
+    Execution(Box<ProcessExecution>),
+    Result(ExecResult),
+    Setuid(SetuidRecord),
+    Exit(ExitRecord),
+    Fork(ForkRecord),
+}
+
+// `execution` runs the same command line as `latest` (an earlier execution of its pid) within `window`
+fn is_repeat(latest: &ProcessExecution, execution: &ProcessExecution, window: Duration) -> bool {
+    latest.full_command == execution.full_command
+        && execution.timestamp >= latest.timestamp
+        && execution.timestamp - latest.timestamp <= window
+}
+
+// comm is NUL padded and may not be valid UTF-8
+fn decode_comm(comm: &[u8]) -> String {
+    let len = comm.iter().position(|&b| b == 0).unwrap_or(comm.len());
+    String::from_utf8_lossy(&comm[..len]).to_string()
+}
+
+// Container id in a cgroup name: `docker-<id>.scope`, `cri-containerd-<id>.scope`, `crio-<id>.scope`
+// and `libpod-<id>.scope` with the systemd cgroup driver, the bare `<id>` with cgroupfs. The probe
+// may already have dropped the prefix, leaving `<id>.scope`
+fn container_id(cgroup_name: &str) -> Option<String> {
+    let name = cgroup_name.strip_suffix(".scope").unwrap_or(cgroup_name);
+    let id = name.rsplit('-').next()?;
+    (id.len() == 64 && id.bytes().all(|b| b.is_ascii_hexdigit())).then(|| id.to_ascii_lowercase())
+}
+
+// Aggregate counters over the stored executions, served on /stats
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ExecutionStats {
+    pub total: usize,
+    pub unique_pids: usize,
+    pub unique_commands: usize,
+    pub oldest: Option<DateTime<Utc>>,
+    pub newest: Option<DateTime<Utc>>,
+    /// Records the probe couldn't deliver since startup, nonzero means executions are missing
+    pub events_lost: u64,
+    /// Execs by kernel threads and usermode helpers the probe dropped, unless --include-kernel-threads
+    pub kernel_threads_filtered: u64,
+    /// Execs the probe dropped for their uid, see --exclude-uid and /exclusions/uid
+    pub uid_excluded: u64,
+}
+
+// How often one command was run, served on /executions/stats
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct CommandStat {
+    pub command: String,
+    /// Execs, including the repeats folded into a record by --dedup-window-ms
+    pub count: u64,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+    pub unique_pids: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct CommandStats {
+    /// Execs over all commands, not just the ones listed
+    pub total_events: u64,
+    pub commands: Vec<CommandStat>,
+}
+
+// Most run first, ties by command so the order is stable
+fn command_stats<'a>(executions: impl IntoIterator<Item = &'a ProcessExecution>) -> Vec<CommandStat> {
+    let mut by_command: HashMap<&str, (CommandStat, HashSet<u32>)> = HashMap::new();
+    for e in executions {
+        let (stat, pids) = by_command.entry(&e.commandstr).or_insert_with(|| {
+            let stat = CommandStat { command: e.commandstr.clone(), count: 0, first_seen: e.timestamp, last_seen: e.timestamp, unique_pids: 0 };
+            (stat, HashSet::new())
+        });
+        stat.count += e.count as u64;
+        stat.first_seen = stat.first_seen.min(e.timestamp);
+        stat.last_seen = stat.last_seen.max(e.timestamp);
+        pids.insert(e.pid);
+    }
+    let mut stats: Vec<_> = by_command.into_values().map(|(stat, pids)| CommandStat { unique_pids: pids.len(), ..stat }).collect();
+    stats.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.command.cmp(&b.command)));
+    stats
+}
+
+// Commands listed by /executions/stats unless `top` says otherwise
+pub const DEFAULT_STATS_TOP: usize = 20;
+
+// Most ancestors get_ancestry walks up from one pid
+pub const MAX_ANCESTRY_DEPTH: usize = 64;
+
+// Levels /executions/tree goes down unless `depth` says otherwise
+pub const DEFAULT_TREE_DEPTH: usize = 50;
+
+// A process and the ones it started, served on /executions/tree. command and timestamp are from
+// its latest execution, empty and None for a root that didn't exec while we were watching
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ProcessNode {
+    pub pid: u32,
+    /// Of the latest execution, empty if the process didn't exec
+    pub command: String,
+    pub timestamp: Option<DateTime<Utc>>,
+    pub children: Vec<ProcessNode>,
+}
+
+// Processes (the latest execution of each pid) by parent, children oldest first
+struct ProcessIndex<'a> {
+    by_ppid: HashMap<u32, Vec<&'a ProcessExecution>>,
+    // pids already in the tree, a reused pid can make parents loop
+    placed: HashSet<u32>,
+    max_depth: usize,
+}
+
+impl ProcessIndex<'_> {
+    // Marks everything below a node cut off at max_depth as placed, so it isn't hung off the root
+    fn prune(&mut self, pid: u32) {
+        let mut pending = vec![pid];
+        while let Some(pid) = pending.pop() {
+            for child in self.by_ppid.remove(&pid).unwrap_or_default() {
+                if self.placed.insert(child.pid) {
+                    pending.push(child.pid);
+                }
+            }
+        }
+    }
+
+    fn node(&mut self, pid: u32, execution: Option<&ProcessExecution>, depth: usize) -> ProcessNode {
+        self.placed.insert(pid);
+        let mut children = Vec::new();
+        if depth < self.max_depth {
+            for child in self.by_ppid.remove(&pid).unwrap_or_default() {
+                if !self.placed.contains(&child.pid) {
+                    children.push(self.node(child.pid, Some(child), depth + 1));
+                }
+            }
+        } else {
+            self.prune(pid);
+        }
+        ProcessNode {
+            pid,
+            command: execution.map(|e| e.commandstr.clone()).unwrap_or_default(),
+            timestamp: execution.map(|e| e.timestamp),
+            children,
+        }
+    }
+}
+
+// Rooted at pid 1 if it ran something, else at the lowest ppid seen. Processes whose
+// parent didn't run anything we saw (including the lowest ppid's children) hang off the root, as do
+// ones only reachable through a loop of reused pids. Nothing goes deeper than max_depth below it
+fn process_tree(executions: &[ProcessExecution], max_depth: usize) -> ProcessNode {
+    let mut latest: HashMap<u32, &ProcessExecution> = HashMap::new();
+    for e in executions {
+        let entry = latest.entry(e.pid).or_insert(e);
+        if e.timestamp >= entry.timestamp {
+            *entry = e;
+        }
+    }
+    let mut processes: Vec<_> = latest.values().copied().collect();
+    processes.sort_by_key(|e| (e.timestamp, e.pid));
+    let mut by_ppid: HashMap<u32, Vec<&ProcessExecution>> = HashMap::new();
+    for &e in &processes {
+        by_ppid.entry(e.ppid).or_default().push(e);
     }
+
+    let root_pid = if latest.contains_key(&1) { 1 } else { processes.iter().map(|e| e.ppid).min().unwrap_or(0) };
+    let mut index = ProcessIndex { by_ppid, placed: HashSet::new(), max_depth };
+    let mut root = index.node(root_pid, latest.get(&root_pid).copied(), 0);
+    if max_depth > 0 {
+        // orphans first, then whatever a pid loop kept out of reach
+        let orphans = processes.iter().filter(|e| !latest.contains_key(&e.ppid));
+        for &e in orphans.chain(processes.iter().filter(|e| latest.contains_key(&e.ppid))) {
+            if !index.placed.contains(&e.pid) {
+                root.children.push(index.node(e.pid, Some(e), 1));
+            }
+        }
+    }
+    root
 }
 
+// Buffered executions per stream subscriber before it starts lagging
+pub const STREAM_CAPACITY: usize = 128;
+
 // Thread-safe storage for process executions
 #[derive(Clone)]
 pub struct ExecutionStorage {
-    // Global storage with max 500 events (FIFO)
-    executions: Arc<RwLock<VecDeque<ProcessExecution>>>,
+    backend: Backend,
+    // FIFO limit for the in-memory store, and the most rows returned by get_all_executions
+    max_events: usize,
+    // Executions older than this are evicted regardless of max_events, see evict_expired
+    ttl: Option<Duration>,
+    // Repeats of an execution within this long are counted on it instead of stored, see count_repeat
+    dedup_window: Option<Duration>,
+    // Every added execution is also published here for /executions/stream
+    events: broadcast::Sender<ProcessExecution>,
+    metrics: Arc<Metrics>,
+    // Recent process exits, in memory for either backend and also capped at max_events
+    exits: Arc<RwLock<ExitLog>>,
+    // Parents of recently forked pids for get_ancestry, in memory with a bound of its own
+    lineage: Arc<RwLock<Lineage>>,
+    // When the monitor started, for /health
+    started: std::time::Instant,
+}
+
+#[derive(Clone)]
+enum Backend {
+    // Global storage with max `max_events` events (FIFO)
+    Memory(Arc<RwLock<VecDeque<ProcessExecution>>>),
+    // Persistent storage, see store/sqlite.rs
+    Sqlite(SqliteExecutionStorage),
 }
 
 impl ExecutionStorage {
-    pub fn new() -> Self {
+    // `max` comes from --max-events
+    pub fn new(max: usize) -> Self {
+        Self {
+            backend: Backend::Memory(Arc::new(RwLock::new(VecDeque::with_capacity(max)))),
+            max_events: max,
+            ttl: None,
+            dedup_window: None,
+            events: broadcast::channel(STREAM_CAPACITY).0,
+            metrics: Arc::new(Metrics::new()),
+            exits: Arc::new(RwLock::new(ExitLog::new(max))),
+            lineage: Arc::new(RwLock::new(Lineage::new(MAX_FORKS))),
+            started: std::time::Instant::now(),
+        }
+    }
+
+    pub fn sqlite(storage: SqliteExecutionStorage, max: usize) -> Self {
         Self {
-            executions: Arc::new(RwLock::new(VecDeque::with_capacity(MAX_EVENTS))),
+            backend: Backend::Sqlite(storage),
+            max_events: max,
+            ttl: None,
+            dedup_window: None,
+            events: broadcast::channel(STREAM_CAPACITY).0,
+            metrics: Arc::new(Metrics::new()),
+            exits: Arc::new(RwLock::new(ExitLog::new(max))),
+            lineage: Arc::new(RwLock::new(Lineage::new(MAX_FORKS))),
+            started: std::time::Instant::now(),
+        }
+    }
+
+    // `ttl` comes from --event-ttl-seconds
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    // `window` comes from --dedup-window-ms
+    pub fn with_dedup(mut self, window: Duration) -> Self {
+        self.dedup_window = Some(window);
+        self
+    }
+
+    pub fn metrics(&self) -> &Arc<Metrics> {
+        &self.metrics
+    }
+
+    pub fn uptime(&self) -> std::time::Duration {
+        self.started.elapsed()
+    }
+
+    // Number of executions currently stored
+    pub async fn len(&self) -> usize {
+        match &self.backend {
+            Backend::Memory(executions) => executions.read().await.len(),
+            Backend::Sqlite(db) => db.count().await.unwrap_or_else(|e| {
+                error!("Failed to count executions: {}", e);
+                0
+            }),
+        }
+    }
+
+    pub async fn stats(&self) -> ExecutionStats {
+        let stats = match &self.backend {
+            Backend::Memory(executions) => {
+                // one read lock so all counters describe the same snapshot
+                let executions = executions.read().await;
+                ExecutionStats {
+                    total: executions.len(),
+                    unique_pids: executions.iter().map(|e| e.pid).collect::<HashSet<_>>().len(),
+                    unique_commands: executions.iter().map(|e| e.commandstr.as_str()).collect::<HashSet<_>>().len(),
+                    oldest: executions.iter().map(|e| e.timestamp).min(),
+                    newest: executions.iter().map(|e| e.timestamp).max(),
+                    events_lost: 0,
+                    kernel_threads_filtered: 0,
+                    uid_excluded: 0,
+                }
+            }
+            Backend::Sqlite(db) => db.stats().await.unwrap_or_else(|e| {
+                error!("Failed to compute stats: {}", e);
+                ExecutionStats::default()
+            }),
+        };
+        ExecutionStats {
+            events_lost: self.metrics.events_lost.load(Ordering::Relaxed),
+            kernel_threads_filtered: self.metrics.kernel_thread_execs_total.get(),
+            uid_excluded: self.metrics.uid_excluded_execs_total.get(),
+            ..stats
+        }
+    }
+
+    // Per-command aggregates over the stored executions, see command_stats. Read only
+    pub async fn compute_stats(&self) -> Vec<CommandStat> {
+        match &self.backend {
+            // a single pass under one read lock
+            Backend::Memory(executions) => command_stats(executions.read().await.iter()),
+            Backend::Sqlite(_) => command_stats(&self.get_all_executions().await),
         }
     }
 
+    // Receives every execution added from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<ProcessExecution> {
+        self.events.subscribe()
+    }
+
+    // Connected stream clients, each holds one receiver until it goes away
+    #[cfg(test)]
+    pub fn subscribers(&self) -> usize {
+        self.events.receiver_count()
+    }
+
+    // Queues executions for add_execution (and results for set_result) on a background task, in the
+    // order they are sent, so the ring buffer reader never waits on a SQLite insert. The task stores
+    // whatever is still queued and ends once every sender is dropped, await the handle to flush on shutdown.
+    pub fn spawn_writer(&self) -> (mpsc::UnboundedSender<StorageWrite>, JoinHandle<()>) {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let storage = self.clone();
+        let handle = tokio::spawn(async move {
+            while let Some(write) = rx.recv().await {
+                match write {
+                    StorageWrite::Execution(execution) => storage.add_execution(*execution).await,
+                    StorageWrite::Result(result) => storage.set_result(result).await,
+                    StorageWrite::Setuid(setuid) => storage.set_setuid(setuid).await,
+                    StorageWrite::Exit(exit) => storage.add_exit(exit).await,
+                    StorageWrite::Fork(fork) => storage.add_fork(fork).await,
+                }
+            }
+        });
+        (tx, handle)
+    }
+
     pub async fn add_execution(&self, execution: ProcessExecution) {
-        let mut executions = self.executions.write().await;
-        if executions.len() >= MAX_EVENTS {
-            executions.pop_front();
+        self.metrics.record_execution(&execution.commandstr);
+        // a repeat is neither stored nor streamed, that's the flood dedup is for
+        if let Some(window) = self.dedup_window
+            && self.count_repeat(&execution, window).await
+        {
+            return;
+        }
+        let published = execution.clone();
+        match &self.backend {
+            Backend::Memory(executions) => {
+                let mut executions = executions.write().await;
+                if executions.len() >= self.max_events {
+                    executions.pop_front();
+                    self.metrics.executions_evicted_total.inc();
+                    self.metrics.events_dropped_total.inc();
+                }
+                executions.push_back(execution);
+            }
+            Backend::Sqlite(db) => match db.add_execution(execution).await {
+                Ok(pruned) => self.metrics.executions_evicted_total.inc_by(pruned),
+                Err(e) => error!("Failed to persist execution: {}", e),
+            },
         }
-        executions.push_back(execution);
+        self.evict_expired().await;
+        // Only fails when nobody is subscribed
+        let _ = self.events.send(published);
     }
 
-    pub async fn get_all_executions(&self) -> Vec<ProcessExecution> {
-        let executions = self.executions.read().await;
-        executions.iter().cloned().collect()
+    // Counts `execution` on the pid's latest execution if that has the same command line and
+    // started at most `window` before it. The window isn't extended by repeats, a command looping
+    // for longer gets a record per window.
+    async fn count_repeat(&self, execution: &ProcessExecution, window: Duration) -> bool {
+        match &self.backend {
+            Backend::Memory(executions) => {
+                let mut executions = executions.write().await;
+                match executions.iter_mut().rev().find(|e| e.pid == execution.pid) {
+                    Some(latest) if is_repeat(latest, execution, window) => {
+                        latest.count += 1;
+                        true
+                    }
+                    _ => false,
+                }
+            }
+            Backend::Sqlite(db) => db.count_repeat(execution, window).await.unwrap_or_else(|e| {
+                error!("Failed to count repeated execution: {}", e);
+                false
+            }),
+        }
     }
 
-    pub async fn get_executions_by_pid(&self, pid: u32) -> Vec<ProcessExecution> {
-        let executions = self.executions.read().await;
-        executions.iter().filter(|e| e.pid == pid).cloned().collect()
+    // Fills in the result of the matching execution. Nothing happens if it was evicted already (or
+    // never stored). Stream subscribers saw the execution without its result.
+    pub async fn set_result(&self, result: ExecResult) {
+        match &self.backend {
+            Backend::Memory(executions) => {
+                let mut executions = executions.write().await;
+                // the execution was added moments ago, it's near the back
+                if let Some(execution) = executions.iter_mut().rev().find(|e| e.pid == result.pid && e.timestamp == result.timestamp) {
+                    execution.result = Some(result.result);
+                    if result.exe_ino != 0 {
+                        execution.exe_ino = result.exe_ino;
+                        execution.exe_dev = result.exe_dev;
+                        execution.exe_device = device_name(result.exe_dev);
+                    }
+                }
+            }
+            Backend::Sqlite(db) => {
+                if let Err(e) = db.set_result(&result).await {
+                    error!("Failed to persist exec result: {}", e);
+                }
+            }
+        }
     }
-}
 
-// HTTP API handlers
-pub async fn get_all_executions(State(storage): State<ExecutionStorage>) -> Json<Vec<ProcessExecution>> {
-    let executions = storage.get_all_executions().await;
-    info!("Returning {} executions", executions.len());
-    Json(executions)
-}
+    // Marks the matching execution as having run a setuid binary, like set_result
+    pub async fn set_setuid(&self, setuid: SetuidRecord) {
+        match &self.backend {
+            Backend::Memory(executions) => {
+                let mut executions = executions.write().await;
+                if let Some(execution) = executions.iter_mut().rev().find(|e| e.pid == setuid.pid && e.timestamp == setuid.timestamp) {
+                    execution.setuid_detected = true;
+                    execution.after_uid = Some(setuid.after_uid);
+                }
+            }
+            Backend::Sqlite(db) => {
+                if let Err(e) = db.set_setuid(&setuid).await {
+                    error!("Failed to persist setuid exec: {}", e);
+                }
+            }
+        }
+    }
 
-pub async fn get_executions_by_pid(
-    Path(pid): Path<u32>,
-    State(storage): State<ExecutionStorage>,
-) -> Result<Json<Vec<ProcessExecution>>, StatusCode> {
-    let executions = storage.get_executions_by_pid(pid).await;
-    if executions.is_empty() {
-        info!("No executions found for PID {}", pid);
-        Err(StatusCode::NOT_FOUND)
-    } else {
-        info!("Returning {} executions for PID {}", executions.len(), pid);
-        Ok(Json(executions))
+    // Drops executions older than the ttl, returns how many. Runs on every add_execution and
+    // periodically from main so old events also expire while nothing new comes in.
+    pub async fn evict_expired(&self) -> u64 {
+        let Some(ttl) = self.ttl else { return 0 };
+        let cutoff = Utc::now() - ttl;
+        let evicted = match &self.backend {
+            Backend::Memory(executions) => {
+                // oldest first, so stop at the first one still within the ttl
+                let mut executions = executions.write().await;
+                let mut evicted = 0;
+                while executions.front().is_some_and(|e| e.timestamp < cutoff) {
+                    executions.pop_front();
+                    evicted += 1;
+                }
+                evicted
+            }
+            Backend::Sqlite(db) => db.prune_before(cutoff).await.unwrap_or_else(|e| {
+                error!("Failed to evict expired executions: {}", e);
+                0
+            }),
+        };
+        self.metrics.executions_evicted_total.inc_by(evicted);
+        evicted
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use chrono::Duration;
-    use task_common::{ARGV_LEN, ARGV_OFFSET};
+    // Forgets every execution, along with the exits and forks recorded for them. Not counted as
+    // evictions, nothing was pushed out.
+    pub async fn clear(&self) -> Result<u64, sqlx::Error> {
+        let cleared = match &self.backend {
+            Backend::Memory(executions) => {
+                let mut executions = executions.write().await;
+                let cleared = executions.len() as u64;
+                executions.clear();
+                cleared
+            }
+            Backend::Sqlite(db) => db.clear().await?,
+        };
+        self.exits.write().await.clear();
+        self.lineage.write().await.clear();
+        Ok(cleared)
+    }
 
-    fn mk_exec(pid: u32, ts: u64, cmd: &str, args: &[&str]) -> ProcessExecution {
-        // Build ExecEvent
-        let mut command = [0u8; 64];
-        let cb = cmd.as_bytes(); // command gets converted to bytes
-        let clen = cb.len().min(64); // command buf len
-        command[..clen].copy_from_slice(&cb[..clen]); // copying the bytes from cmd to command (basically &str to [0u8; 64])
-        let mut argvs = [[0u8; ARGV_LEN]; ARGV_OFFSET];
-        let mut arg_lens = [0usize; ARGV_OFFSET];
-        for (i, a) in args.iter().enumerate().take(ARGV_OFFSET) {
-            let ab = a.as_bytes(); // similarly convert &&str to bytes for storing them into argvs
-            let alen = ab.len().min(ARGV_LEN);
-            argvs[i][..alen].copy_from_slice(&ab[..alen]); // copy takes place here
-            arg_lens[i] = alen;
+    pub async fn get_all_executions(&self) -> Vec<ProcessExecution> {
+        match &self.backend {
+            Backend::Memory(executions) => {
+                let executions = executions.read().await;
+                executions.iter().cloned().collect()
+            }
+            Backend::Sqlite(db) => db.get_all_executions(self.max_events).await.unwrap_or_else(|e| {
+                error!("Failed to load executions: {}", e);
+                Vec::new()
+            }),
         }
-        let event = crate::ExecEvent { pid, timestamp: ts, command, command_len: clen, argvs, argvs_offset: arg_lens };
-        ProcessExecution::from_event(&event, Duration::zero())
     }
 
-    // Basic conversion test for ProcessExecution::from_event
-    #[tokio::test]
-    async fn from_event_basic() {
-        // Build ExecEvent manually
-        let cmd = b"/bin/echo"; // 9 bytes
-        let arg0 = b"hello";    // 5 bytes
-        let mut command_arr = [0u8; 64];
-        command_arr[..cmd.len()].copy_from_slice(cmd);
-        let mut argvs = [[0u8; ARGV_LEN]; ARGV_OFFSET];
-        argvs[0][..arg0.len()].copy_from_slice(arg0);
-        let mut arg_lens = [0usize; ARGV_OFFSET];
-        arg_lens[0] = arg0.len();
-        let event = crate::ExecEvent {
-            pid: 42,
-            timestamp: 1_500_000_123, // ns since boot (1.500000123 s)
-            command: command_arr,
-            command_len: cmd.len(),
-            argvs,
-            argvs_offset: arg_lens,
+    // Snapshot of get_all_executions in the requested order, the store itself is left as is
+    pub async fn get_executions_sorted(&self, sort_by: SortField, order: SortOrder) -> Vec<ProcessExecution> {
+        let mut executions = self.get_all_executions().await;
+        sort_executions(&mut executions, sort_by, order);
+        executions
+    }
+
+    // `limit` executions starting at `offset` in insertion order, oldest first for Asc as in
+    // get_all_executions and newest first for Desc, and the total number available
+    pub async fn get_executions_paginated(&self, offset: usize, limit: usize, order: SortOrder) -> (Vec<ProcessExecution>, usize) {
+        match &self.backend {
+            Backend::Memory(executions) => {
+                let executions = executions.read().await;
+                let page = match order {
+                    SortOrder::Asc => executions.iter().skip(offset).take(limit).cloned().collect(),
+                    SortOrder::Desc => executions.iter().rev().skip(offset).take(limit).cloned().collect(),
+                };
+                (page, executions.len())
+            }
+            Backend::Sqlite(db) => db.get_executions_page(self.max_events, offset, limit, order).await.unwrap_or_else(|e| {
+                error!("Failed to load executions: {}", e);
+                (Vec::new(), 0)
+            }),
+        }
+    }
+
+    // Executions matching every condition of `filter`, oldest first
+    pub async fn query(&self, filter: &ExecutionFilter) -> Vec<ProcessExecution> {
+        match &self.backend {
+            Backend::Memory(executions) => {
+                let executions = executions.read().await;
+                executions.iter().filter(|e| filter.matches(e)).cloned().collect()
+            }
+            Backend::Sqlite(db) => db.query(filter).await.unwrap_or_else(|e| {
+                error!("Failed to load executions for {:?}: {}", filter, e);
+                Vec::new()
+            }),
+        }
+    }
+
+    // Marks the pid's latest execution as exited and keeps the exit for get_exit. Exits of processes
+    // without a stored execution (started before us, filtered out or evicted) are only counted.
+    pub async fn add_exit(&self, exit: ExitRecord) {
+        let matched = match &self.backend {
+            Backend::Memory(executions) => {
+                let mut executions = executions.write().await;
+                match executions.iter_mut().rev().find(|e| e.pid == exit.pid) {
+                    Some(execution) if execution.exited_at.is_none() && execution.timestamp <= exit.timestamp => {
+                        execution.set_exit(exit.timestamp);
+                        true
+                    }
+                    _ => false,
+                }
+            }
+            Backend::Sqlite(db) => db.set_exit(&exit).await.unwrap_or_else(|e| {
+                error!("Failed to persist exit: {}", e);
+                false
+            }),
         };
-        let boot_offset = Duration::zero();
-        let pe = ProcessExecution::from_event(&event, boot_offset);
-        assert_eq!(pe.pid, 42);
-        assert_eq!(pe.commandstr, "/bin/echo");
-        assert_eq!(pe.argstr, "hello");
-        assert_eq!(pe.full_command, "/bin/echo hello");
-        // Timestamp should match seconds + nanos from event.timestamp
-        assert_eq!(pe.timestamp.timestamp(), 1); // whole seconds
-        assert_eq!(pe.timestamp.timestamp_subsec_nanos(), 500_000_123); // remaining nanos
+        if matched {
+            self.exits.write().await.push(exit);
+        } else {
+            self.metrics.orphan_exits_total.inc();
+        }
     }
-    #[tokio::test]
-    async fn add_and_get_all() {
-        let storage = ExecutionStorage::new();
-        storage.add_execution(mk_exec(1, 10, "/bin/a", &[])).await;
-        storage.add_execution(mk_exec(2, 20, "/bin/b", &["x"])).await;
-        let all = storage.get_all_executions().await;
-        assert_eq!(all.len(), 2);
-        assert_eq!(all[0].pid, 1);
-        assert_eq!(all[1].pid, 2);
+
+    // How `pid` exited, None while it's still running. An exit older than the pid's latest
+    // execution belongs to an earlier process that had the same pid.
+    pub async fn get_exit(&self, pid: u32) -> Option<ExitRecord> {
+        let exit = self.exits.read().await.latest(pid).cloned()?;
+        let executions = self.query(&ExecutionFilter::for_pid(pid)).await;
+        match executions.iter().map(|e| e.timestamp).max() {
+            Some(exec) if exec > exit.timestamp => None,
+            _ => Some(exit),
+        }
     }
 
-    #[tokio::test]
-    async fn fifo_eviction() {
-        let storage = ExecutionStorage::new();
-        for i in 0..crate::MAX_EVENTS { storage.add_execution(mk_exec(i as u32, i as u64, "/bin/cmd", &[])).await; }
-        // first pid should be 0
-        let first_before = storage.get_all_executions().await.first().unwrap().pid;
-        assert_eq!(first_before, 0);
-        storage.add_execution(mk_exec(9999, 9999, "/bin/extra", &[])).await;
-        let all = storage.get_all_executions().await;
-        assert_eq!(all.len(), crate::MAX_EVENTS);
-        // pid 9999 SHOULD exist
-        assert!(all.iter().any(|e| e.pid == 9999));
-        // pid 0 SHOULDN'T because it gets evicted
-        assert!(!all.iter().any(|e| e.pid == 0));
+    pub async fn add_fork(&self, fork: ForkRecord) {
+        self.lineage.write().await.push(fork);
     }
 
-    #[tokio::test]
+    // Parents of `pid`, nearest first. Follows recorded forks and, where none was seen (processes
+    // older than us or forgotten forks), the ppid of the latest execution. Pids are reused, so each
+    // ancestor only gets the executions it ran before the step below it was created.
+    pub async fn get_ancestry(&self, pid: u32) -> Vec<Ancestor> {
+        let mut ancestry = Vec::new();
+        // a reused pid can make recorded parents loop
+        let mut seen = HashSet::from([pid]);
+        let mut current = pid;
+        // when the process below `current` was created
+        let mut before: Option<DateTime<Utc>> = None;
+        while ancestry.len() < MAX_ANCESTRY_DEPTH {
+            let fork = self.lineage.read().await.parent(current).copied();
+            let step = match fork.filter(|f| before.is_none_or(|b| f.timestamp <= b)) {
+                Some(fork) => Some((fork.parent_pid, fork.timestamp)),
+                None => self.query(&ExecutionFilter::for_pid(current)).await.into_iter()
+                    .filter(|e| before.is_none_or(|b| e.timestamp <= b))
+                    .max_by_key(|e| e.timestamp)
+                    .map(|e| (e.ppid, e.timestamp)),
+            };
+            let Some((parent, created)) = step else { break };
+            if parent == 0 || !seen.insert(parent) {
+                break;
+            }
+            let mut executions = self.query(&ExecutionFilter::for_pid(parent)).await;
+            executions.retain(|e| e.timestamp <= created);
+            ancestry.push(Ancestor { pid: parent, executions });
+            current = parent;
+            before = Some(created);
+        }
+        ancestry
+    }
+
+    // The stored processes as a tree by ppid, see process_tree
+    pub async fn build_process_tree(&self, max_depth: usize) -> ProcessNode {
+        process_tree(&self.get_all_executions().await, max_depth)
+    }
+
+    // Executions of setuid binaries, see ProcessExecution::setuid_detected
+    pub async fn get_setuid_executions(&self) -> Vec<ProcessExecution> {
+        match &self.backend {
+            Backend::Memory(executions) => {
+                let executions = executions.read().await;
+                executions.iter().filter(|e| e.setuid_detected).cloned().collect()
+            }
+            Backend::Sqlite(db) => db.get_setuid_executions().await.unwrap_or_else(|e| {
+                error!("Failed to load setuid executions: {}", e);
+                Vec::new()
+            }),
+        }
+    }
+
+    // Case-insensitive substring match on commandstr or full_command
+    pub async fn search_executions(&self, needle: &str) -> Vec<ProcessExecution> {
+        match &self.backend {
+            Backend::Memory(executions) => {
+                let needle = needle.to_lowercase();
+                let executions = executions.read().await;
+                executions
+                    .iter()
+                    .filter(|e| e.commandstr.to_lowercase().contains(&needle) || e.full_command.to_lowercase().contains(&needle))
+                    .cloned()
+                    .collect()
+            }
+            Backend::Sqlite(db) => db.search_executions(needle).await.unwrap_or_else(|e| {
+                error!("Failed to search executions for {}: {}", needle, e);
+                Vec::new()
+            }),
+        }
+    }
+}
+
+// What ExecutionStorage::query selects, every condition that is set has to hold. The memory
+// backend checks it with `matches`, SQLite turns it into one WHERE clause.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExecutionFilter {
+    pub pid: Option<u32>,
+    pub ppid: Option<u32>,
+    // see ProcessExecution::runs
+    pub command: Option<String>,
+    // substring of commandstr
+    pub command_contains: Option<String>,
+    // The real uid, i.e. the user who ran the command. Running a setuid binary only changes the
+    // euid, so `sudo` or `passwd` still count for the user that started them.
+    pub uid: Option<u32>,
+    pub cgroup_id: Option<u64>,
+    pub container_id: Option<String>,
+    // substring of cgroup_path
+    pub cgroup_contains: Option<String>,
+    // whether the exec failed, those without a result yet count as not failed
+    pub failed: Option<bool>,
+    // whether the binary has the setuid bit, unknown counts as not setuid
+    pub setuid: Option<bool>,
+    // [since, until)
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+}
+
+impl ExecutionFilter {
+    pub fn for_pid(pid: u32) -> Self {
+        Self { pid: Some(pid), ..Default::default() }
+    }
+
+    // Any condition besides the time range
+    pub fn is_set(&self) -> bool {
+        *self != Self { since: self.since, until: self.until, ..Default::default() }
+    }
+
+    pub fn matches(&self, e: &ProcessExecution) -> bool {
+        self.pid.is_none_or(|pid| e.pid == pid)
+            && self.ppid.is_none_or(|ppid| e.ppid == ppid)
+            && self.command.as_deref().is_none_or(|command| e.runs(command))
+            && self.command_contains.as_deref().is_none_or(|needle| e.commandstr.contains(needle))
+            && self.uid.is_none_or(|uid| e.uid == uid)
+            && self.cgroup_id.is_none_or(|cgroup_id| e.cgroup_id == cgroup_id)
+            && self.container_id.as_ref().is_none_or(|id| e.container_id.as_ref() == Some(id))
+            && self.cgroup_contains.as_deref().is_none_or(|needle| e.cgroup_path.as_deref().is_some_and(|path| path.contains(needle)))
+            && self.failed.is_none_or(|failed| e.result.is_some_and(|result| result < 0) == failed)
+            && self.setuid.is_none_or(|setuid| e.setuid.unwrap_or(false) == setuid)
+            && self.since.is_none_or(|since| e.timestamp >= since)
+            && self.until.is_none_or(|until| e.timestamp < until)
+    }
+}
+
+// Query string of /executions, parsed into an ExecutionFilter plus paging and sorting. Numbers
+// are kept as strings because they don't deserialize through the flattened range, they are parsed
+// in the handler instead, value_type gives their real type in the OpenAPI spec
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ExecutionParams {
+    /// Exact match on commandstr, or on script by path or file name
+    // see ProcessExecution::runs
+    pub command: Option<String>,
+    /// Substring match on commandstr
+    pub command_contains: Option<String>,
+    /// Exact match on the real uid, the user who ran the command. A setuid binary only changes the
+    /// euid, which isn't filtered on
+    // see ExecutionFilter::uid
+    #[param(value_type = Option<u32>)]
+    pub uid: Option<String>,
+    /// Exact match on cgroup_id
+    // the id bpf_get_current_cgroup_id() reported
+    #[param(value_type = Option<u64>)]
+    pub cgroup_id: Option<String>,
+    /// Exact match on container_id
+    pub container_id: Option<String>,
+    /// Substring match on cgroup_path, e.g. `docker-` or `kubepods`
+    pub cgroup_contains: Option<String>,
+    /// Page size
+    #[param(value_type = Option<usize>, minimum = 1, maximum = 500, default = json!(DEFAULT_PAGE_LIMIT))]
+    pub limit: Option<String>,
+    /// Executions to skip
+    #[param(value_type = Option<usize>, default = 0)]
+    pub offset: Option<String>,
+    /// `true` for execs that failed, `false` for the others (including those without a result yet)
+    #[param(value_type = Option<bool>)]
+    pub failed: Option<String>,
+    /// `true` for executions of setuid binaries, `false` for the others (including unknown ones)
+    #[param(value_type = Option<bool>)]
+    pub setuid: Option<String>,
+    /// Sort key, insertion order without it
+    #[param(value_type = Option<SortField>)]
+    pub sort_by: Option<String>,
+    /// Direction of sort_by, or of insertion order without it (desc is newest first)
+    #[param(value_type = Option<SortOrder>)]
+    pub order: Option<String>,
+    // Listed next to this in the #[utoipa::path] params, IntoParams can't flatten it. The struct is
+    // never serialized, skip_serializing only keeps it out of the derived parameters
+    #[serde(flatten, skip_serializing)]
+    pub range: TimeRange,
+}
+
+impl ExecutionParams {
+    fn filter(&self) -> Result<ExecutionFilter, String> {
+        Ok(ExecutionFilter {
+            command: self.command.clone(),
+            command_contains: self.command_contains.clone(),
+            uid: parse_param("uid", &self.uid)?,
+            cgroup_id: parse_param("cgroup_id", &self.cgroup_id)?,
+            container_id: self.container_id.clone(),
+            cgroup_contains: self.cgroup_contains.clone(),
+            failed: parse_param("failed", &self.failed)?,
+            setuid: parse_param("setuid", &self.setuid)?,
+            ..self.range.filter()?
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, ToSchema)]
+#[schema(rename_all = "lowercase")]
+pub enum SortField {
+    #[default]
+    Timestamp,
+    Pid,
+    Command,
+}
+
+impl std::str::FromStr for SortField {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "timestamp" => Ok(Self::Timestamp),
+            "pid" => Ok(Self::Pid),
+            "command" => Ok(Self::Command),
+            _ => Err("expected one of `timestamp`, `pid`, `command`".to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, ToSchema)]
+#[schema(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+impl std::str::FromStr for SortOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "asc" => Ok(Self::Asc),
+            "desc" => Ok(Self::Desc),
+            _ => Err("expected `asc` or `desc`".to_string()),
+        }
+    }
+}
+
+// Stable, so equal keys keep their insertion order
+fn sort_executions(executions: &mut [ProcessExecution], sort_by: SortField, order: SortOrder) {
+    executions.sort_by(|a, b| {
+        let ordering = match sort_by {
+            SortField::Timestamp => a.timestamp.cmp(&b.timestamp),
+            SortField::Pid => a.pid.cmp(&b.pid),
+            SortField::Command => a.commandstr.cmp(&b.commandstr),
+        };
+        match order {
+            SortOrder::Asc => ordering,
+            SortOrder::Desc => ordering.reverse(),
+        }
+    });
+}
+
+pub const DEFAULT_PAGE_LIMIT: usize = 100;
+pub const MAX_PAGE_LIMIT: usize = 500;
+// `Accept-Profile` value that wraps /executions in an ExecutionsPage envelope
+pub const ENVELOPE_PROFILE: &str = "envelope";
+
+fn parse_param<T: std::str::FromStr>(name: &str, value: &Option<String>) -> Result<Option<T>, String>
+where
+    T::Err: std::fmt::Display,
+{
+    value.as_deref().map(|value| value.parse::<T>().map_err(|e| format!("invalid `{name}` {value:?}: {e}"))).transpose()
+}
+
+// One page of /executions. The total is always sent as X-Total-Count, the body is the bare
+// list unless the client asked for the envelope, so existing consumers keep working.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ExecutionsPage {
+    pub data: Vec<ProcessExecution>,
+    pub total: usize,
+    pub offset: usize,
+    pub limit: usize,
+    #[serde(skip)]
+    envelope: bool,
+}
+
+impl IntoResponse for ExecutionsPage {
+    fn into_response(self) -> Response {
+        let total = [("x-total-count", self.total.to_string())];
+        if self.envelope {
+            (total, Json(self)).into_response()
+        } else {
+            (total, Json(self.data)).into_response()
+        }
+    }
+}
+
+// Optional RFC 3339 bounds, shared by /executions and /executions/:pid. Either `since`/`until`
+// for [since, until) or `from`/`to` for the inclusive [from, to]
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct TimeRange {
+    /// Only executions at or after this time (RFC 3339)
+    #[param(value_type = Option<DateTime<Utc>>)]
+    pub since: Option<String>,
+    /// Only executions before this time (RFC 3339)
+    #[param(value_type = Option<DateTime<Utc>>)]
+    pub until: Option<String>,
+    /// Inclusive alternative to `since`, can't be combined with it
+    #[param(value_type = Option<DateTime<Utc>>)]
+    pub from: Option<String>,
+    /// Inclusive alternative to `until`, can't be combined with it
+    #[param(value_type = Option<DateTime<Utc>>)]
+    pub to: Option<String>,
+}
+
+impl TimeRange {
+    fn is_set(&self) -> bool {
+        self.since.is_some() || self.until.is_some() || self.from.is_some() || self.to.is_some()
+    }
+
+    // [since, until), open ends default to the beginning of time and now
+    fn bounds(&self) -> Result<(DateTime<Utc>, DateTime<Utc>), String> {
+        let parse = |name: &str, value: &Option<String>| {
+            value
+                .as_ref()
+                .map(|value| {
+                    DateTime::parse_from_rfc3339(value)
+                        .map(|t| t.with_timezone(&Utc))
+                        .map_err(|e| format!("invalid `{name}` timestamp {value:?}: {e} (expected RFC 3339, e.g. 2024-01-01T00:00:00Z)"))
+                })
+                .transpose()
+        };
+        if self.since.is_some() && self.from.is_some() {
+            return Err("`since` and `from` can't be combined".to_string());
+        }
+        if self.until.is_some() && self.to.is_some() {
+            return Err("`until` and `to` can't be combined".to_string());
+        }
+        let since = parse("since", &self.since)?.or(parse("from", &self.from)?).unwrap_or(DateTime::<Utc>::MIN_UTC);
+        // `to` is inclusive, the smallest step past it is the exclusive bound
+        let to = parse("to", &self.to)?.map(|to| to + Duration::nanoseconds(1));
+        let until = parse("until", &self.until)?.or(to).unwrap_or_else(Utc::now);
+        Ok((since, until))
+    }
+
+    // bounds() if any end was given, no bounds otherwise so nothing is hidden by the clock
+    fn filter(&self) -> Result<ExecutionFilter, String> {
+        let (since, until) = self.bounds()?;
+        let set = self.is_set();
+        Ok(ExecutionFilter { since: set.then_some(since), until: set.then_some(until), ..Default::default() })
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub(crate) struct ErrorBody {
+    error: String,
+}
+
+pub(crate) fn error_response(status: StatusCode, error: String) -> Response {
+    (status, Json(ErrorBody { error })).into_response()
+}
+
+fn bad_request(error: String) -> Response {
+    error_response(StatusCode::BAD_REQUEST, error)
+}
+
+// HTTP API handlers
+
+/// Stored executions, oldest first unless sorted
+#[utoipa::path(
+    get,
+    path = "/executions",
+    tag = "executions",
+    params(
+        ExecutionParams,
+        TimeRange,
+        ("Accept-Profile" = Option<String>, Header, description = "`envelope` wraps the page in an ExecutionsPage"),
+    ),
+    responses(
+        (status = 200, body = [ProcessExecution],
+         description = "One page of executions, the total is in X-Total-Count. An ExecutionsPage instead with the envelope profile",
+         headers(("X-Total-Count" = usize, description = "Executions matching the filters over all pages"))),
+        (status = 400, description = "Invalid parameter or offset out of range", body = ErrorBody),
+        (status = 404, description = "No execution matches the filters"),
+    )
+)]
+pub async fn get_all_executions(
+    Query(params): Query<ExecutionParams>,
+    headers: HeaderMap,
+    State(storage): State<ExecutionStorage>,
+) -> Result<ExecutionsPage, Response> {
+    let filter = params.filter().map_err(bad_request)?;
+    let limit = parse_param("limit", &params.limit).map_err(bad_request)?.unwrap_or(DEFAULT_PAGE_LIMIT);
+    if !(1..=MAX_PAGE_LIMIT).contains(&limit) {
+        return Err(bad_request(format!("`limit` must be between 1 and {MAX_PAGE_LIMIT}, got {limit}")));
+    }
+    let offset = parse_param("offset", &params.offset).map_err(bad_request)?.unwrap_or(0);
+    let envelope = headers.get("accept-profile").is_some_and(|profile| profile == ENVELOPE_PROFILE);
+    let sort_by = parse_param::<SortField>("sort_by", &params.sort_by).map_err(bad_request)?;
+    // without sort_by, `order` is the direction of insertion order, newest first by default
+    let order = parse_param::<SortOrder>("order", &params.order).map_err(bad_request)?.unwrap_or_default();
+    let sort = sort_by.map(|sort_by| (sort_by, order));
+
+    let filtered = filter.is_set();
+    let queried = filtered || params.range.is_set();
+    let (data, total) = if queried || sort.is_some() {
+        let mut executions = match sort {
+            Some((sort_by, order)) if !queried => storage.get_executions_sorted(sort_by, order).await,
+            _ => storage.query(&filter).await,
+        };
+        match sort {
+            Some((sort_by, order)) if queried => sort_executions(&mut executions, sort_by, order),
+            // get_executions_sorted is already in order
+            Some(_) => {}
+            None if order == SortOrder::Desc => executions.reverse(),
+            None => {}
+        }
+        let total = executions.len();
+        (executions.into_iter().skip(offset).take(limit).collect(), total)
+    } else {
+        storage.get_executions_paginated(offset, limit, order).await
+    };
+
+    if filtered && total == 0 {
+        info!("No executions found for filter {:?}", filter);
+        return Err(StatusCode::NOT_FOUND.into_response());
+    }
+    if total > 0 && offset >= total {
+        return Err(bad_request(format!("`offset` {offset} is out of range, valid offsets are 0 to {} ({total} executions)", total - 1)));
+    }
+    info!("Returning {} of {} executions", data.len(), total);
+    Ok(ExecutionsPage { data, total, offset, limit, envelope })
+}
+
+/// Forget all stored executions, with their exits and ancestry
+#[utoipa::path(
+    delete,
+    path = "/executions",
+    tag = "executions",
+    responses((status = 204, description = "Cleared"), (status = 500, description = "The database couldn't be cleared"))
+)]
+pub async fn clear_executions(State(storage): State<ExecutionStorage>) -> Response {
+    match storage.clear().await {
+        Ok(cleared) => {
+            info!("Cleared {} executions", cleared);
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Err(e) => {
+            error!("Failed to clear executions: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SearchQuery {
+    /// Substring of commandstr, any case, everything if omitted
+    pub command: Option<String>,
+}
+
+/// Case-insensitive search on the command path
+// Unlike the filters on /executions, no match is an empty list rather than a 404
+#[utoipa::path(
+    get,
+    path = "/executions/search",
+    tag = "executions",
+    params(SearchQuery),
+    responses((status = 200, description = "Matching executions, possibly none", body = [ProcessExecution]))
+)]
+pub async fn get_executions_filtered(
+    Query(query): Query<SearchQuery>,
+    State(storage): State<ExecutionStorage>,
+) -> Json<Vec<ProcessExecution>> {
+    let executions = match query.command.as_deref() {
+        Some(needle) if !needle.is_empty() => storage.search_executions(needle).await,
+        _ => storage.get_all_executions().await,
+    };
+    info!("Returning {} executions for search {:?}", executions.len(), query.command);
+    Json(executions)
+}
+
+// Columns of /executions.csv
+const CSV_HEADER: &str = "pid,timestamp,command,args,full_command";
+
+// Columns of /executions/export?format=csv, see ProcessExecution::to_csv_row
+const EXPORT_CSV_HEADER: &str = "pid,timestamp,command,args,ppid,uid,gid,full_command";
+
+const CSV_CONTENT_TYPE: &str = "text/csv; charset=utf-8";
+
+// RFC 4180: a field with a comma, quote or line break is quoted, with its quotes doubled
+fn csv_field(value: &str) -> std::borrow::Cow<'_, str> {
+    if value.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\"")).into()
+    } else {
+        value.into()
+    }
+}
+
+// One CRLF terminated record
+fn csv_row(fields: &[&str]) -> String {
+    let mut row = fields.iter().map(|field| csv_field(field)).collect::<Vec<_>>().join(",");
+    row.push_str("\r\n");
+    row
+}
+
+impl ProcessExecution {
+    // `command` is the path that was executed, or the script it ran exactly or by file name
+    pub fn runs(&self, command: &str) -> bool {
+        self.commandstr == command
+            || self.script.as_deref().is_some_and(|script| script == command || script.strip_suffix(command).is_some_and(|dir| dir.ends_with('/')))
+    }
+
+    // The EXPORT_CSV_HEADER columns of this execution
+    pub fn to_csv_row(&self) -> String {
+        let timestamp = self.timestamp.to_rfc3339_opts(SecondsFormat::AutoSi, true);
+        let (pid, ppid, uid, gid) = (self.pid.to_string(), self.ppid.to_string(), self.uid.to_string(), self.gid.to_string());
+        csv_row(&[&pid, &timestamp, &self.commandstr, &self.argstr, &ppid, &uid, &gid, &self.full_command])
+    }
+}
+
+/// Stored executions as CSV, oldest first
+// for spreadsheets
+#[utoipa::path(
+    get,
+    path = "/executions.csv",
+    tag = "executions",
+    responses((status = 200, description = "pid,timestamp,command,args,full_command with a header row, quoted per RFC 4180",
+               body = String, content_type = "text/csv"))
+)]
+pub async fn get_executions_csv(State(storage): State<ExecutionStorage>) -> Response {
+    let executions = storage.get_all_executions().await;
+    let mut csv = format!("{CSV_HEADER}\r\n");
+    for e in &executions {
+        let timestamp = e.timestamp.to_rfc3339_opts(SecondsFormat::AutoSi, true);
+        csv += &csv_row(&[&e.pid.to_string(), &timestamp, &e.commandstr, &e.argstr, &e.full_command]);
+    }
+    info!("Returning {} executions as CSV", executions.len());
+    ([(header::CONTENT_TYPE, CSV_CONTENT_TYPE)], csv).into_response()
+}
+
+/// Stored executions as newline-delimited JSON, oldest first
+// Snapshotted first so the lock isn't held while a slow client reads, lines are serialized as they
+// are sent
+#[utoipa::path(
+    get,
+    path = "/executions.ndjson",
+    tag = "executions",
+    responses((status = 200, description = "One ProcessExecution object per line, each ending in \\n",
+               body = String, content_type = "application/x-ndjson"))
+)]
+pub async fn get_executions_ndjson(State(storage): State<ExecutionStorage>) -> Response {
+    let executions = storage.get_all_executions().await;
+    info!("Streaming {} executions as NDJSON", executions.len());
+    let lines = executions.into_iter().map(|e| {
+        let mut line = serde_json::to_vec(&e)?;
+        line.push(b'\n');
+        Ok::<_, serde_json::Error>(line)
+    });
+    let body = axum::body::Body::from_stream(futures_util::stream::iter(lines));
+    ([(header::CONTENT_TYPE, "application/x-ndjson")], body).into_response()
+}
+
+// `format` of /executions/export
+#[derive(Debug, Clone, Copy, Default, PartialEq, ToSchema)]
+#[schema(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+impl std::str::FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Self::Json),
+            "csv" => Ok(Self::Csv),
+            _ => Err("expected `json` or `csv`".to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ExportQuery {
+    /// Download format
+    #[param(value_type = Option<ExportFormat>)]
+    pub format: Option<String>,
+}
+
+/// Stored executions as a file download, oldest first
+// CSV rows are formatted as the body is sent instead of all up front
+#[utoipa::path(
+    get,
+    path = "/executions/export",
+    tag = "executions",
+    params(ExportQuery),
+    responses(
+        (status = 200, description = "executions.json, or executions.csv with the columns pid,timestamp,command,args,ppid,uid,gid,full_command",
+         content(("application/json" = Vec<ProcessExecution>), ("text/csv" = String)),
+         headers(("Content-Disposition" = String))),
+        (status = 400, description = "Unknown format", body = ErrorBody),
+    )
+)]
+pub async fn export_executions(Query(query): Query<ExportQuery>, State(storage): State<ExecutionStorage>) -> Result<Response, Response> {
+    let format = parse_param::<ExportFormat>("format", &query.format).map_err(bad_request)?.unwrap_or_default();
+    let executions = storage.get_all_executions().await;
+    info!("Exporting {} executions as {:?}", executions.len(), format);
+    let response = match format {
+        ExportFormat::Json => ([(header::CONTENT_DISPOSITION, "attachment; filename=\"executions.json\"")], Json(executions)).into_response(),
+        ExportFormat::Csv => {
+            let rows = std::iter::once(format!("{EXPORT_CSV_HEADER}\r\n")).chain(executions.into_iter().map(|e| e.to_csv_row()));
+            let body = axum::body::Body::from_stream(futures_util::stream::iter(rows.map(Ok::<_, std::convert::Infallible>)));
+            let headers = [(header::CONTENT_TYPE, CSV_CONTENT_TYPE), (header::CONTENT_DISPOSITION, "attachment; filename=\"executions.csv\"")];
+            (headers, body).into_response()
+        }
+    };
+    Ok(response)
+}
+
+/// Aggregate counters over the stored executions
+#[utoipa::path(get, path = "/stats", tag = "executions", responses((status = 200, description = "The counters", body = ExecutionStats)))]
+pub async fn get_stats(State(storage): State<ExecutionStorage>) -> Json<ExecutionStats> {
+    let stats = storage.stats().await;
+    info!("Returning stats for {} executions", stats.total);
+    Json(stats)
+}
+
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CommandStatsQuery {
+    /// Commands to list, most run first
+    #[param(value_type = Option<usize>, default = json!(DEFAULT_STATS_TOP))]
+    pub top: Option<String>,
+}
+
+/// The most run commands among the stored executions, also on /stats/commands
+#[utoipa::path(
+    get,
+    path = "/executions/stats",
+    tag = "executions",
+    params(CommandStatsQuery),
+    responses(
+        (status = 200, description = "Per-command counts", body = CommandStats),
+        (status = 400, description = "`top` isn't a number", body = ErrorBody),
+    )
+)]
+pub async fn get_command_stats(
+    Query(query): Query<CommandStatsQuery>,
+    State(storage): State<ExecutionStorage>,
+) -> Result<Json<CommandStats>, Response> {
+    let top = parse_param("top", &query.top).map_err(bad_request)?.unwrap_or(DEFAULT_STATS_TOP);
+    let mut commands = storage.compute_stats().await;
+    let total_events = commands.iter().map(|stat| stat.count).sum();
+    commands.truncate(top);
+    info!("Returning stats for {} of the commands in {} executions", commands.len(), total_events);
+    Ok(Json(CommandStats { total_events, commands }))
+}
+
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PidQuery {
+    /// Only the process started at this time (its process_start), when the pid was reused
+    #[param(value_type = Option<DateTime<Utc>>)]
+    pub process_start: Option<String>,
+    // Listed next to this in the #[utoipa::path] params, IntoParams can't flatten it. The struct is
+    // never serialized, skip_serializing only keeps it out of the derived parameters
+    #[serde(flatten, skip_serializing)]
+    pub range: TimeRange,
+}
+
+/// Executions of a process
+#[utoipa::path(
+    get,
+    path = "/executions/{pid}",
+    tag = "executions",
+    params(("pid" = u32, Path, description = "Process id (tgid)"), PidQuery, TimeRange),
+    responses(
+        (status = 200, description = "Executions of the pid", body = [ProcessExecution]),
+        (status = 400, description = "Invalid time range or process_start", body = ErrorBody),
+        (status = 404, description = "No execution of the pid"),
+    )
+)]
+pub async fn get_executions_by_pid(
+    Path(pid): Path<u32>,
+    Query(query): Query<PidQuery>,
+    State(storage): State<ExecutionStorage>,
+) -> Result<Json<Vec<ProcessExecution>>, Response> {
+    let filter = ExecutionFilter { pid: Some(pid), ..query.range.filter().map_err(bad_request)? };
+    let process_start = parse_param::<DateTime<Utc>>("process_start", &query.process_start).map_err(bad_request)?;
+    let mut executions = storage.query(&filter).await;
+    if let Some(process_start) = process_start {
+        executions.retain(|e| e.process_start == Some(process_start));
+    }
+    if executions.is_empty() {
+        info!("No executions found for PID {}", pid);
+        Err(StatusCode::NOT_FOUND.into_response())
+    } else {
+        info!("Returning {} executions for PID {}", executions.len(), pid);
+        Ok(Json(executions))
+    }
+}
+
+/// How a process exited
+#[utoipa::path(
+    get,
+    path = "/executions/{pid}/exit",
+    tag = "executions",
+    params(("pid" = u32, Path, description = "Process id (tgid)")),
+    responses(
+        (status = 200, description = "The exit", body = ExitRecord),
+        (status = 404, description = "Still running, or its exit wasn't seen"),
+    )
+)]
+pub async fn get_exit_by_pid(Path(pid): Path<u32>, State(storage): State<ExecutionStorage>) -> Result<Json<ExitRecord>, Response> {
+    match storage.get_exit(pid).await {
+        Some(exit) => {
+            info!("Returning exit of PID {}", pid);
+            Ok(Json(exit))
+        }
+        None => {
+            info!("No exit recorded for PID {}", pid);
+            Err(StatusCode::NOT_FOUND.into_response())
+        }
+    }
+}
+
+/// Parents of a process, nearest first
+// at most MAX_ANCESTRY_DEPTH
+#[utoipa::path(
+    get,
+    path = "/executions/{pid}/ancestry",
+    tag = "executions",
+    params(("pid" = u32, Path, description = "Process id (tgid)")),
+    responses(
+        (status = 200, description = "The ancestors", body = [Ancestor]),
+        (status = 404, description = "No parent known"),
+    )
+)]
+pub async fn get_ancestry_by_pid(Path(pid): Path<u32>, State(storage): State<ExecutionStorage>) -> Result<Json<Vec<Ancestor>>, Response> {
+    let ancestry = storage.get_ancestry(pid).await;
+    if ancestry.is_empty() {
+        info!("No ancestry known for PID {}", pid);
+        Err(StatusCode::NOT_FOUND.into_response())
+    } else {
+        info!("Returning {} ancestors of PID {}", ancestry.len(), pid);
+        Ok(Json(ancestry))
+    }
+}
+
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct TreeQuery {
+    /// Levels below the root
+    #[param(value_type = Option<usize>, default = json!(DEFAULT_TREE_DEPTH))]
+    pub depth: Option<String>,
+}
+
+/// The stored processes as a tree by parent pid
+#[utoipa::path(
+    get,
+    path = "/executions/tree",
+    tag = "executions",
+    params(TreeQuery),
+    responses(
+        (status = 200, body = ProcessNode,
+         description = "Rooted at pid 1, or the lowest ppid seen if pid 1 didn't exec. Processes whose parent isn't known hang off the root"),
+        (status = 400, description = "`depth` isn't a number", body = ErrorBody),
+    )
+)]
+pub async fn get_process_tree(Query(query): Query<TreeQuery>, State(storage): State<ExecutionStorage>) -> Result<Json<ProcessNode>, Response> {
+    let depth = parse_param("depth", &query.depth).map_err(bad_request)?.unwrap_or(DEFAULT_TREE_DEPTH);
+    let tree = storage.build_process_tree(depth).await;
+    info!("Returning the process tree under PID {} with {} top level processes", tree.pid, tree.children.len());
+    Ok(Json(tree))
+}
+
+/// Executions of setuid binaries, i.e. that changed the euid
+// Empty list if none, like /executions/search
+#[utoipa::path(
+    get,
+    path = "/executions/setuid",
+    tag = "executions",
+    responses((status = 200, description = "Executions with setuid_detected, possibly none", body = [ProcessExecution]))
+)]
+pub async fn get_setuid_executions(State(storage): State<ExecutionStorage>) -> Json<Vec<ProcessExecution>> {
+    let executions = storage.get_setuid_executions().await;
+    info!("Returning {} setuid executions", executions.len());
+    Json(executions)
+}
+
+/// Executions spawned by a parent process
+#[utoipa::path(
+    get,
+    path = "/executions/ppid/{ppid}",
+    tag = "executions",
+    params(("ppid" = u32, Path, description = "Parent process id (tgid)"), TimeRange),
+    responses(
+        (status = 200, description = "Executions of the children", body = [ProcessExecution]),
+        (status = 400, description = "Invalid time range", body = ErrorBody),
+        (status = 404, description = "No execution with that parent"),
+    )
+)]
+pub async fn get_executions_by_ppid(
+    Path(ppid): Path<u32>,
+    Query(range): Query<TimeRange>,
+    State(storage): State<ExecutionStorage>,
+) -> Result<Json<Vec<ProcessExecution>>, Response> {
+    let filter = ExecutionFilter { ppid: Some(ppid), ..range.filter().map_err(bad_request)? };
+    let executions = storage.query(&filter).await;
+    if executions.is_empty() {
+        info!("No executions found for PPID {}", ppid);
+        Err(StatusCode::NOT_FOUND.into_response())
+    } else {
+        info!("Returning {} executions for PPID {}", executions.len(), ppid);
+        Ok(Json(executions))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use task_common::{ARGV_LEN, ARGV_OFFSET, COMMAND_LEN, ENV_LEN, MAX_ARGC};
+
+    const MAX_EVENTS: usize = 500;
+
+    fn mk_exec(pid: u32, ts: u64, cmd: &str, args: &[&str]) -> ProcessExecution {
+        ProcessExecution::from_event(&mk_event(pid, ts, cmd, args), Duration::zero())
+    }
+
+    fn mk_event(pid: u32, ts: u64, cmd: &str, args: &[&str]) -> crate::ExecEvent {
+        // Build ExecEvent
+        let mut command = [0u8; 64];
+        let cb = cmd.as_bytes(); // command gets converted to bytes
+        let clen = cb.len().min(63); // command buf len, minus the NUL like on the eBPF side
+        command[..clen].copy_from_slice(&cb[..clen]); // copying the bytes from cmd to command (basically &str to [0u8; 64])
+        let mut argvs = [[0u8; ARGV_LEN]; ARGV_OFFSET];
+        let mut arg_lens = [0usize; ARGV_OFFSET];
+        let mut arg_truncated = [0; ARGV_OFFSET];
+        for (i, a) in args.iter().enumerate().take(ARGV_OFFSET) {
+            let ab = a.as_bytes(); // similarly convert &&str to bytes for storing them into argvs
+            let alen = ab.len().min(ARGV_LEN - 1); // the eBPF side keeps one byte for the NUL
+            argvs[i][..alen].copy_from_slice(&ab[..alen]); // copy takes place here
+            arg_lens[i] = alen;
+            arg_truncated[i] = (ab.len() > alen) as u8;
+        }
+        crate::ExecEvent {
+            tgid: pid,
+            tid: pid,
+            ppid: 0,
+            uid: 0,
+            gid: 0,
+            euid: 0,
+            egid: 0,
+            loginuid: AUDIT_UID_UNSET,
+            cgroup_id: 0,
+            cgroup_name: [0; CGROUP_NAME_LEN],
+            pidns: 0,
+            ns_pid: 0,
+            mntns: 0,
+            sid: 0,
+            has_tty: 0,
+            timestamp: ts,
+            start_time: 0,
+            exe_ino: 0,
+            exe_dev: 0,
+            dirfd: -1,
+            syscall: 0,
+            at_flags: 0,
+            command,
+            command_len: clen,
+            command_truncated: (cb.len() > clen) as u8,
+            cwd: [0; CWD_LEN],
+            cwd_len: 0,
+            comm: [0; 16],
+            parent_comm: [0; 16],
+            argvs,
+            argvs_offset: arg_lens,
+            argvs_truncated: arg_truncated,
+            argc: args.len().min(MAX_ARGC) as u32,
+            argv_truncated: (args.len() > MAX_ARGC) as u8,
+            argv_missing: 0,
+            args_total_len: args.iter().map(|a| a.len() as u32).sum(),
+            args_len_overflow: (args.len() > MAX_ARGC) as u8,
+            env: [[0; ENV_LEN]; MAX_ENV_MATCHES],
+            env_len: [0; MAX_ENV_MATCHES],
+            env_truncated: [0; MAX_ENV_MATCHES],
+            seq: 0,
+            chunks: 1,
+            cpu: 0,
+            source: 0,
+        }
+    }
+
+    #[test]
+    fn from_event_args_total_len() {
+        let event = mk_event(42, 0, "/usr/bin/printf", &["%s", "hello"]);
+        let pe = ProcessExecution::from_event(&event, Duration::zero());
+        assert_eq!((pe.args_total_len, pe.args_len_overflow), (7, false));
+
+        // a 4 KiB argument shows as 31 bytes in argstr, the total still has all of it
+        let long = "x".repeat(4000);
+        let pe = ProcessExecution::from_event(&mk_event(42, 0, "/bin/echo", &[&long, "y"]), Duration::zero());
+        assert!(pe.args_truncated);
+        assert_eq!(pe.args_total_len, 4001);
+        let event = crate::ExecEvent { args_total_len: 4095 * 2, args_len_overflow: 1, ..mk_event(42, 0, "/bin/echo", &[]) };
+        let json = serde_json::to_value(ProcessExecution::from_event(&event, Duration::zero())).unwrap();
+        assert_eq!((json["args_total_len"].as_u64(), json["args_len_overflow"].as_bool()), (Some(8190), Some(true)));
+    }
+
+    // the probe couldn't read argv, the exec is kept without it
+    #[test]
+    fn from_event_argv_missing() {
+        let event = crate::ExecEvent { argc: 0, argv_missing: 1, source: SOURCE_SCHED_EXEC, ..mk_event(42, 0, "/usr/bin/make", &[]) };
+        let pe = ProcessExecution::from_event(&event, Duration::zero());
+        assert_eq!((pe.pid, pe.commandstr.as_str(), pe.argc), (42, "/usr/bin/make", 0));
+        assert!(pe.argv_partial);
+        assert!(!ProcessExecution::from_event(&mk_event(42, 0, "/usr/bin/make", &[]), Duration::zero()).argv_partial);
+    }
+
+    // NUL padded like the probe fills ExecEvent::cgroup_name
+    fn cgroup_name(name: &str) -> [u8; CGROUP_NAME_LEN] {
+        let mut buf = [0u8; CGROUP_NAME_LEN];
+        buf[..name.len()].copy_from_slice(name.as_bytes());
+        buf
+    }
+
+    #[test]
+    fn container_id_from_cgroup_name() {
+        let id = "3f4e1b9c2a7d6e5f8091a2b3c4d5e6f708192a3b4c5d6e7f8091a2b3c4d5e6f7";
+        for name in [format!("docker-{id}.scope"), format!("crio-{id}.scope"), format!("libpod-{id}.scope"), format!("{id}.scope"), id.to_string()] {
+            assert_eq!(container_id(&name).as_deref(), Some(id), "{name}");
+        }
+        assert_eq!(container_id(&id.to_uppercase()).as_deref(), Some(id));
+        // not containers, or not a whole id
+        for name in ["", "session-3.scope", "user@1000.service", "init.scope", &format!("docker-{}.scope", &id[..63]), &format!("{id}0")] {
+            assert_eq!(container_id(name), None, "{name}");
+        }
+
+        // a name from outside a container stays None
+        let event = crate::ExecEvent { cgroup_name: cgroup_name("session-3.scope"), ..mk_event(42, 0, "/bin/true", &[]) };
+        let pe = ProcessExecution::from_event(&event, Duration::zero());
+        assert!(serde_json::to_value(&pe).unwrap()["container_id"].is_null());
+    }
+
+    // Basic conversion test for ProcessExecution::from_event
+    #[tokio::test]
+    async fn from_event_basic() {
+        // Build ExecEvent manually
+        let cmd = b"/bin/echo"; // 9 bytes
+        let arg0 = b"hello";    // 5 bytes
+        let mut command_arr = [0u8; 64];
+        command_arr[..cmd.len()].copy_from_slice(cmd);
+        let mut argvs = [[0u8; ARGV_LEN]; ARGV_OFFSET];
+        argvs[0][..arg0.len()].copy_from_slice(arg0);
+        let mut arg_lens = [0usize; ARGV_OFFSET];
+        arg_lens[0] = arg0.len();
+        let event = crate::ExecEvent {
+            tgid: 42,
+            tid: 42,
+            ppid: 7,
+            uid: 1000,
+            gid: 100,
+            euid: 1000,
+            egid: 100,
+            loginuid: 1000,
+            cgroup_id: 4242,
+            // docker-<id>.scope with the prefix dropped by the probe
+            cgroup_name: cgroup_name("3f4e1b9c2a7d6e5f8091a2b3c4d5e6f708192a3b4c5d6e7f8091a2b3c4d5e6f7.scope"),
+            pidns: 4026532281,
+            ns_pid: 1,
+            mntns: 4026532280,
+            sid: 31100,
+            has_tty: 1,
+            timestamp: 1_500_000_123, // ns since boot (1.500000123 s)
+            start_time: 1_000_000_000,
+            // /dev/sda1 in the kernel's encoding
+            exe_ino: 1_311_014,
+            exe_dev: 8 << 20 | 1,
+            // execveat(AT_FDCWD, ...)
+            dirfd: -100,
+            syscall: SYSCALL_EXECVEAT,
+            at_flags: 0,
+            command: command_arr,
+            command_len: cmd.len(),
+            command_truncated: 0,
+            cwd: [0; CWD_LEN],
+            cwd_len: 0,
+            comm: *b"bash\0\0\0\0\0\0\0\0\0\0\0\0",
+            parent_comm: *b"sshd\0\0\0\0\0\0\0\0\0\0\0\0",
+            argvs,
+            argvs_offset: arg_lens,
+            argvs_truncated: [0; ARGV_OFFSET],
+            argc: 1,
+            argv_truncated: 0,
+            argv_missing: 0,
+            args_total_len: 0,
+            args_len_overflow: 0,
+            env: [[0; ENV_LEN]; MAX_ENV_MATCHES],
+            env_len: [0; MAX_ENV_MATCHES],
+            env_truncated: [0; MAX_ENV_MATCHES],
+            seq: 0,
+            chunks: 1,
+            cpu: 0,
+            source: 0,
+        };
+        let boot_offset = Duration::zero();
+        let pe = ProcessExecution::from_event(&event, boot_offset);
+        assert_eq!(pe.pid, 42);
+        assert_eq!(pe.process_start, DateTime::<Utc>::from_timestamp(1, 0));
+        assert_eq!(pe.ppid, 7);
+        assert_eq!(pe.uid, 1000);
+        assert_eq!(pe.gid, 100);
+        assert_eq!(pe.euid, 1000);
+        assert_eq!(pe.egid, 100);
+        assert!(!pe.privilege_elevated);
+        assert_eq!(pe.cgroup_id, 4242);
+        assert_eq!(pe.container_id.as_deref(), Some("3f4e1b9c2a7d6e5f8091a2b3c4d5e6f708192a3b4c5d6e7f8091a2b3c4d5e6f7"));
+        assert_eq!(pe.pidns, 4026532281);
+        assert_eq!(pe.ns_pid, 1);
+        assert_eq!(pe.mntns, 4026532280);
+        assert_eq!(pe.sid, 31100);
+        assert!(pe.has_tty);
+        assert_eq!(pe.exe_ino, 1_311_014);
+        // what `stat -c %d` prints for /dev/sda1
+        assert_eq!(pe.exe_dev, 2049);
+        assert_eq!(pe.exe_device, "8:1");
+        assert_eq!(pe.dirfd, -100);
+        assert_eq!(pe.syscall, Syscall::Execveat);
+        assert_eq!(pe.commandstr, "/bin/echo");
+        assert_eq!(pe.argstr, "hello");
+        assert_eq!(pe.full_command, "/bin/echo hello");
+        assert_eq!(pe.caller_comm, "bash");
+        assert_eq!(pe.parent_command, "sshd");
+        assert!(!pe.argv_truncated);
+        // Timestamp should match seconds + nanos from event.timestamp
+        assert_eq!(pe.timestamp.timestamp(), 1); // whole seconds
+        assert_eq!(pe.timestamp.timestamp_subsec_nanos(), 500_000_123); // remaining nanos
+        // every field survives a JSON round trip
+        let json = serde_json::to_string(&pe).unwrap();
+        let back: ProcessExecution = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.mntns, 4026532280);
+        assert_eq!(back.pidns, pe.pidns);
+        assert_eq!(back.dirfd, -100);
+        assert_eq!(serde_json::to_value(&back).unwrap()["syscall"], "execveat");
+        assert_eq!(back.full_command, pe.full_command);
+        // u64 ids past 2^53 still serialize exactly
+        let big = ProcessExecution { cgroup_id: u64::MAX, ..pe };
+        let json = serde_json::to_string(&big).unwrap();
+        assert!(json.contains(&format!("\"cgroup_id\":{}", u64::MAX)));
+    }
+
+    #[tokio::test]
+    async fn from_event_setuid() {
+        let mut event = crate::ExecEvent {
+            tgid: 42,
+            tid: 42,
+            ppid: 7,
+            uid: 1000,
+            gid: 100,
+            euid: 0,
+            egid: 100,
+            loginuid: 1000,
+            cgroup_id: 0,
+            cgroup_name: [0; CGROUP_NAME_LEN],
+            pidns: 0,
+            ns_pid: 0,
+            mntns: 0,
+            sid: 0,
+            has_tty: 0,
+            timestamp: 0,
+            start_time: 0,
+            exe_ino: 0,
+            exe_dev: 0,
+            dirfd: -1,
+            syscall: 0,
+            at_flags: 0,
+            command: [0u8; 64],
+            command_len: 0,
+            command_truncated: 0,
+            cwd: [0; CWD_LEN],
+            cwd_len: 0,
+            comm: [0; 16],
+            parent_comm: [0; 16],
+            argvs: [[0u8; ARGV_LEN]; ARGV_OFFSET],
+            argvs_offset: [0usize; ARGV_OFFSET],
+            argvs_truncated: [0; ARGV_OFFSET],
+            argc: 0,
+            argv_truncated: 0,
+            argv_missing: 0,
+            args_total_len: 0,
+            args_len_overflow: 0,
+            env: [[0; ENV_LEN]; MAX_ENV_MATCHES],
+            env_len: [0; MAX_ENV_MATCHES],
+            env_truncated: [0; MAX_ENV_MATCHES],
+            seq: 0,
+            chunks: 1,
+            cpu: 0,
+            source: 0,
+        };
+        let pe = ProcessExecution::from_event(&event, Duration::zero());
+        assert_eq!(pe.uid, 1000);
+        assert_eq!(pe.euid, 0);
+        assert!(pe.privilege_elevated);
+        let json = serde_json::to_value(&pe).unwrap();
+        assert_eq!(json["uid"], 1000);
+        assert_eq!(json["euid"], 0);
+        assert_eq!(json["privilege_elevated"], true);
+
+        // a differing egid alone isn't flagged
+        event.euid = 1000;
+        event.egid = 0;
+        assert!(!ProcessExecution::from_event(&event, Duration::zero()).privilege_elevated);
+    }
+    #[tokio::test]
+    async fn from_event_session() {
+        let mut event = crate::ExecEvent {
+            tgid: 31145,
+            tid: 31145,
+            ppid: 31100,
+            uid: 1000,
+            gid: 1000,
+            euid: 1000,
+            egid: 1000,
+            loginuid: AUDIT_UID_UNSET,
+            cgroup_id: 0,
+            cgroup_name: [0; CGROUP_NAME_LEN],
+            pidns: 0,
+            ns_pid: 0,
+            mntns: 0,
+            sid: 31100,
+            has_tty: 1,
+            timestamp: 0,
+            start_time: 0,
+            exe_ino: 0,
+            exe_dev: 0,
+            dirfd: -1,
+            syscall: 0,
+            at_flags: 0,
+            command: [0u8; 64],
+            command_len: 0,
+            command_truncated: 0,
+            cwd: [0; CWD_LEN],
+            cwd_len: 0,
+            comm: [0; 16],
+            parent_comm: [0; 16],
+            argvs: [[0u8; ARGV_LEN]; ARGV_OFFSET],
+            argvs_offset: [0usize; ARGV_OFFSET],
+            argvs_truncated: [0; ARGV_OFFSET],
+            argc: 0,
+            argv_truncated: 0,
+            argv_missing: 0,
+            args_total_len: 0,
+            args_len_overflow: 0,
+            env: [[0; ENV_LEN]; MAX_ENV_MATCHES],
+            env_len: [0; MAX_ENV_MATCHES],
+            env_truncated: [0; MAX_ENV_MATCHES],
+            seq: 0,
+            chunks: 1,
+            cpu: 0,
+            source: 0,
+        };
+        // interactive shell
+        let json = serde_json::to_value(ProcessExecution::from_event(&event, Duration::zero())).unwrap();
+        assert_eq!(json["sid"], 31100);
+        assert_eq!(json["has_tty"], true);
+
+        // cron/systemd job, no controlling terminal
+        event.sid = 1;
+        event.has_tty = 0;
+        let json = serde_json::to_value(ProcessExecution::from_event(&event, Duration::zero())).unwrap();
+        assert_eq!(json["sid"], 1);
+        assert_eq!(json["has_tty"], false);
+        let back: ProcessExecution = serde_json::from_value(json).unwrap();
+        assert!(!back.has_tty);
+    }
+
+    #[test]
+    fn from_event_loginuid() {
+        // root shell from `sudo -i` by the user that logged in as 1000
+        let event = crate::ExecEvent { loginuid: 1000, ..mk_event(42, 0, "/bin/true", &[]) };
+        let pe = ProcessExecution::from_event(&event, Duration::zero());
+        assert_eq!(pe.uid, 0);
+        assert_eq!(pe.loginuid, Some(1000));
+        assert_eq!(serde_json::to_value(&pe).unwrap()["loginuid"], 1000);
+
+        // a daemon that never logged in
+        let event = crate::ExecEvent { loginuid: AUDIT_UID_UNSET, ..mk_event(42, 0, "/bin/true", &[]) };
+        let pe = ProcessExecution::from_event(&event, Duration::zero());
+        assert_eq!(pe.loginuid, None);
+        let json = serde_json::to_value(&pe).unwrap();
+        assert!(json["loginuid"].is_null());
+        assert!(!json.to_string().contains("4294967295"));
+        let back: ProcessExecution = serde_json::from_value(json).unwrap();
+        assert_eq!(back.loginuid, None);
+    }
+
+    #[tokio::test]
+    async fn from_event_comm() {
+        let mut event = crate::ExecEvent {
+            tgid: 1,
+            tid: 1,
+            ppid: 0,
+            uid: 0,
+            gid: 0,
+            euid: 0,
+            egid: 0,
+            loginuid: AUDIT_UID_UNSET,
+            cgroup_id: 0,
+            cgroup_name: [0; CGROUP_NAME_LEN],
+            pidns: 0,
+            ns_pid: 0,
+            mntns: 0,
+            sid: 0,
+            has_tty: 0,
+            timestamp: 0,
+            start_time: 0,
+            exe_ino: 0,
+            exe_dev: 0,
+            dirfd: -1,
+            syscall: 0,
+            at_flags: 0,
+            command: [0u8; 64],
+            command_len: 0,
+            command_truncated: 0,
+            cwd: [0; CWD_LEN],
+            cwd_len: 0,
+            comm: *b"make\0junk\0\0\0\0\0\0\0",
+            parent_comm: [0; 16],
+            argvs: [[0u8; ARGV_LEN]; ARGV_OFFSET],
+            argvs_offset: [0usize; ARGV_OFFSET],
+            argvs_truncated: [0; ARGV_OFFSET],
+            argc: 0,
+            argv_truncated: 0,
+            argv_missing: 0,
+            args_total_len: 0,
+            args_len_overflow: 0,
+            env: [[0; ENV_LEN]; MAX_ENV_MATCHES],
+            env_len: [0; MAX_ENV_MATCHES],
+            env_truncated: [0; MAX_ENV_MATCHES],
+            seq: 0,
+            chunks: 1,
+            cpu: 0,
+            source: 0,
+        };
+        // stops at the first NUL
+        assert_eq!(ProcessExecution::from_event(&event, Duration::zero()).caller_comm, "make");
+        // full 16 bytes without a terminator, with invalid UTF-8
+        event.comm = *b"long-thread-na\xffe";
+        assert_eq!(ProcessExecution::from_event(&event, Duration::zero()).caller_comm, "long-thread-na\u{fffd}e");
+        event.comm = [0; 16];
+        assert_eq!(ProcessExecution::from_event(&event, Duration::zero()).caller_comm, "");
+
+        // an unreadable parent leaves parent_command empty but still produces the execution
+        assert_eq!(ProcessExecution::from_event(&event, Duration::zero()).parent_command, "");
+        event.parent_comm = *b"systemd\0\0\0\0\0\0\0\0\0";
+        assert_eq!(ProcessExecution::from_event(&event, Duration::zero()).parent_command, "systemd");
+
+        // matched environment entries split at the first `=`
+        assert!(ProcessExecution::from_event(&event, Duration::zero()).env.is_empty());
+        let entries: [&[u8]; 2] = [b"LD_PRELOAD=/tmp/hook.so", b"OPTS=a=b"];
+        for (i, entry) in entries.iter().enumerate() {
+            event.env[i][..entry.len()].copy_from_slice(entry);
+            event.env_len[i] = entry.len();
+        }
+        event.env[2][..ENV_LEN - 1].fill(b'p');
+        event.env[2][..5].copy_from_slice(b"PATH=");
+        event.env_len[2] = ENV_LEN - 1;
+        event.env_truncated[2] = 1;
+        let pe = ProcessExecution::from_event(&event, Duration::zero());
+        assert_eq!(pe.ld_preload.as_deref(), Some("/tmp/hook.so"));
+        let env = pe.env;
+        assert_eq!(env.len(), 3);
+        assert_eq!(env["LD_PRELOAD"], "/tmp/hook.so");
+        assert_eq!(env["OPTS"], "a=b");
+        assert!(env["PATH"].ends_with(TRUNCATION_MARKER));
+        assert_eq!(env["PATH"].chars().count(), ENV_LEN - 1 - 5 + 1);
+        // set but empty preloads nothing
+        event.env_len[0] = "LD_PRELOAD=".len();
+        assert_eq!(ProcessExecution::from_event(&event, Duration::zero()).ld_preload, None);
+        event.env_len = [0; MAX_ENV_MATCHES];
+
+        // relative commands are resolved against the cwd once it is known
+        event.command[..12].copy_from_slice(b"./deploy.sh\0");
+        event.command_len = 11;
+        assert_eq!(ProcessExecution::from_event(&event, Duration::zero()).resolved_command, "./deploy.sh");
+        let mut pe = ProcessExecution::from_event(&event, Duration::zero());
+        pe.set_cwd("/srv/app".to_string());
+        assert_eq!(pe.cwd, "/srv/app");
+        assert_eq!(pe.resolved_command, "/srv/app/deploy.sh");
+        event.command_len = 0;
+
+        // fexecve() is execveat(fd, "", argv, envp, AT_EMPTY_PATH)
+        event.syscall = SYSCALL_EXECVEAT;
+        event.dirfd = 3;
+        event.at_flags = AT_EMPTY_PATH;
+        let pe = ProcessExecution::from_event(&event, Duration::zero());
+        assert_eq!(pe.syscall, Syscall::Execveat);
+        assert_eq!(pe.commandstr, "/dev/fd/3");
+        assert_eq!(pe.resolved_command, "/dev/fd/3");
+        // without the flag an empty path is just that
+        event.at_flags = 0;
+        assert_eq!(ProcessExecution::from_event(&event, Duration::zero()).commandstr, "");
+        (event.syscall, event.dirfd) = (0, -1);
+        assert_eq!(ProcessExecution::from_event(&event, Duration::zero()).source, ExecSource::SysEnter);
+
+        // sched_process_exec can't tell the syscall apart
+        (event.syscall, event.source) = (SYSCALL_UNKNOWN, SOURCE_SCHED_EXEC);
+        let json = serde_json::to_value(ProcessExecution::from_event(&event, Duration::zero())).unwrap();
+        assert_eq!((&json["syscall"], &json["source"]), (&"unknown".into(), &"sched_exec".into()));
+        (event.syscall, event.source) = (0, 0);
+
+        // cwd is empty when the probe couldn't resolve it, otherwise cut at cwd_len
+        assert_eq!(ProcessExecution::from_event(&event, Duration::zero()).cwd, "");
+        event.cwd[..14].copy_from_slice(b"/home/user\0old");
+        event.cwd_len = 10;
+        let pe = ProcessExecution::from_event(&event, Duration::zero());
+        assert_eq!(pe.cwd, "/home/user");
+        assert_eq!(serde_json::to_value(&pe).unwrap()["cwd"], "/home/user");
+    }
+
+    #[test]
+    fn resolves_relative_commands() {
+        assert_eq!(resolve_command("/bin/ls", "/home/user", -1), "/bin/ls");
+        assert_eq!(resolve_command("./deploy.sh", "/home/user", -1), "/home/user/deploy.sh");
+        assert_eq!(resolve_command("bin/run", "/", AT_FDCWD), "/bin/run");
+        assert_eq!(resolve_command("../tool", "/home/user", -1), "/home/user/../tool");
+        // unknown cwd or relative to some other directory fd
+        assert_eq!(resolve_command("./deploy.sh", "", -1), "./deploy.sh");
+        assert_eq!(resolve_command("./deploy.sh", "/home/user", 3), "./deploy.sh");
+    }
+
+    #[tokio::test]
+    async fn from_event_truncated_argv() {
+        // more args than ARGV_OFFSET, and one longer than ARGV_LEN
+        let long = "x".repeat(ARGV_LEN + 10);
+        let mut args: Vec<&str> = vec!["-O2", "-Wall", "-c", "foo.c", "-o", "foo.o", &long];
+        args.resize(MAX_ARGC + 4, "-v");
+        let pe = mk_exec(1, 1, "/usr/bin/gcc", &args);
+        assert!(pe.argv_truncated);
+        assert_eq!(pe.argc, MAX_ARGC as u32);
+        let json = serde_json::to_value(&pe).unwrap();
+        assert_eq!(json["argc"], MAX_ARGC);
+        assert_eq!(json["argv_truncated"], true);
+        let captured: Vec<&str> = pe.argstr.split(' ').collect();
+        assert_eq!(captured.len(), ARGV_OFFSET);
+        assert_eq!(captured[..6], ["-O2", "-Wall", "-c", "foo.c", "-o", "foo.o"]);
+        // cut arguments are marked
+        assert_eq!(captured[6], format!("{}{}", "x".repeat(ARGV_LEN - 1), TRUNCATION_MARKER));
+
+        let pe = mk_exec(1, 1, "/usr/bin/gcc", &args[..MAX_ARGC]);
+        assert!(!pe.argv_truncated);
+        assert_eq!(pe.argc, MAX_ARGC as u32);
+    }
+
+    #[tokio::test]
+    async fn from_event_truncated_arg() {
+        let exact = "y".repeat(ARGV_LEN - 1);
+        let long = "z".repeat(200);
+        let pe = mk_exec(1, 1, "/bin/echo", &[&exact, &long]);
+        // an argument that exactly fills the buffer isn't marked, a longer one is
+        assert_eq!(pe.argstr, format!("{} {}{}", exact, &long[..ARGV_LEN - 1], TRUNCATION_MARKER));
+        assert!(pe.full_command.ends_with(TRUNCATION_MARKER));
+        assert!(pe.args_truncated);
+        assert!(!pe.command_truncated);
+        assert_eq!(serde_json::to_value(&pe).unwrap()["args_truncated"], true);
+
+        let pe = mk_exec(1, 1, "/bin/echo", &[&exact, "short"]);
+        assert!(!pe.args_truncated);
+        assert!(!pe.argstr.contains(TRUNCATION_MARKER));
+    }
+
+    #[tokio::test]
+    async fn from_event_truncated_command() {
+        // COMMAND_LEN - 1 bytes fit exactly, one more gets cut
+        let exact = format!("/opt/{}", "c".repeat(COMMAND_LEN - 1 - 5));
+        let pe = mk_exec(1, 1, &exact, &["-v"]);
+        assert!(!pe.command_truncated);
+        assert_eq!(pe.commandstr, exact);
+
+        let long = format!("{exact}d");
+        let pe = mk_exec(1, 1, &long, &["-v"]);
+        assert!(pe.command_truncated);
+        assert!(!pe.args_truncated);
+        assert_eq!(pe.commandstr, format!("{exact}{TRUNCATION_MARKER}"));
+        assert_eq!(pe.full_command, format!("{exact}{TRUNCATION_MARKER} -v"));
+        let json = serde_json::to_value(&pe).unwrap();
+        assert_eq!(json["command_truncated"], true);
+        assert_eq!(json["args_truncated"], false);
+    }
+
+    #[tokio::test]
+    async fn add_and_get_all() {
+        let storage = ExecutionStorage::new(MAX_EVENTS);
+        storage.add_execution(mk_exec(1, 10, "/bin/a", &[])).await;
+        storage.add_execution(mk_exec(2, 20, "/bin/b", &["x"])).await;
+        let all = storage.get_all_executions().await;
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[0].pid, 1);
+        assert_eq!(all[1].pid, 2);
+    }
+
+    #[tokio::test]
+    async fn writer_stores_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = SqliteExecutionStorage::open(&dir.path().join("executions.db"), 7).await.unwrap();
+        let storage = ExecutionStorage::sqlite(db, MAX_EVENTS);
+        let (writer, handle) = storage.spawn_writer();
+        for i in 0..50 {
+            // send never waits on the insert
+            writer.send(StorageWrite::Execution(Box::new(mk_exec(i, Utc::now().timestamp_nanos_opt().unwrap() as u64, "/bin/cmd", &[])))).unwrap();
+        }
+        // dropping the last sender flushes the queue and ends the task
+        drop(writer);
+        tokio::time::timeout(std::time::Duration::from_secs(5), handle).await.unwrap().unwrap();
+
+        let all = storage.get_all_executions().await;
+        assert_eq!(all.iter().map(|e| e.pid).collect::<Vec<_>>(), (0..50).collect::<Vec<_>>());
+    }
+
+    fn mk_exec_at(pid: u32, timestamp: DateTime<Utc>) -> ProcessExecution {
+        ProcessExecution { timestamp, ..mk_exec(pid, 0, "/bin/cmd", &[]) }
+    }
+
+    #[tokio::test]
+    async fn ttl_eviction_on_add() {
+        let storage = ExecutionStorage::new(MAX_EVENTS).with_ttl(Duration::seconds(60));
+        let now = Utc::now();
+        storage.add_execution(mk_exec_at(1, now - Duration::hours(2))).await;
+        storage.add_execution(mk_exec_at(2, now - Duration::seconds(61))).await;
+        assert_eq!(storage.len().await, 0);
+        storage.add_execution(mk_exec_at(3, now)).await;
+        storage.add_execution(mk_exec_at(4, now)).await;
+        let all = storage.get_all_executions().await;
+        assert_eq!(all.iter().map(|e| e.pid).collect::<Vec<_>>(), vec![3, 4]);
+        assert_eq!(storage.metrics().executions_evicted_total.get(), 2);
+    }
+
+    #[tokio::test]
+    async fn ttl_eviction_without_new_events() {
+        let storage = ExecutionStorage::new(MAX_EVENTS).with_ttl(Duration::milliseconds(100));
+        storage.add_execution(mk_exec_at(1, Utc::now())).await;
+        storage.add_execution(mk_exec_at(2, Utc::now())).await;
+        assert_eq!(storage.evict_expired().await, 0);
+        assert_eq!(storage.len().await, 2);
+
+        tokio::time::sleep(std::time::Duration::from_millis(150)).await;
+        assert_eq!(storage.evict_expired().await, 2);
+        assert_eq!(storage.len().await, 0);
+        assert_eq!(storage.metrics().executions_evicted_total.get(), 2);
+
+        // disabled by default
+        let storage = ExecutionStorage::new(MAX_EVENTS);
+        storage.add_execution(mk_exec_at(1, Utc::now() - Duration::days(365))).await;
+        assert_eq!(storage.evict_expired().await, 0);
+        assert_eq!(storage.len().await, 1);
+    }
+
+    #[tokio::test]
+    async fn fifo_eviction() {
+        let storage = ExecutionStorage::new(MAX_EVENTS);
+        for i in 0..MAX_EVENTS { storage.add_execution(mk_exec(i as u32, i as u64, "/bin/cmd", &[])).await; }
+        // first pid should be 0
+        let first_before = storage.get_all_executions().await.first().unwrap().pid;
+        assert_eq!(first_before, 0);
+        storage.add_execution(mk_exec(9999, 9999, "/bin/extra", &[])).await;
+        let all = storage.get_all_executions().await;
+        assert_eq!(all.len(), MAX_EVENTS);
+        // pid 9999 SHOULD exist
+        assert!(all.iter().any(|e| e.pid == 9999));
+        // pid 0 SHOULDN'T because it gets evicted
+        assert!(!all.iter().any(|e| e.pid == 0));
+        assert_eq!(storage.metrics().executions_total.get(), MAX_EVENTS as u64 + 1);
+        assert_eq!(storage.metrics().executions_evicted_total.get(), 1);
+        assert_eq!(storage.metrics().events_dropped_total.get(), 1);
+        assert_eq!(storage.len().await, MAX_EVENTS);
+    }
+
+    #[tokio::test]
+    async fn fifo_eviction_custom_capacity() {
+        let storage = ExecutionStorage::new(3);
+        for pid in 1..=3 { storage.add_execution(mk_exec(pid, pid as u64, "/bin/cmd", &[])).await; }
+        assert_eq!(storage.get_all_executions().await.len(), 3);
+        // fourth insert evicts the oldest
+        storage.add_execution(mk_exec(4, 4, "/bin/cmd", &[])).await;
+        let all = storage.get_all_executions().await;
+        assert_eq!(all.iter().map(|e| e.pid).collect::<Vec<_>>(), vec![2, 3, 4]);
+    }
+
+    #[tokio::test]
     async fn get_by_pid() {
-        let storage = ExecutionStorage::new();
+        let storage = ExecutionStorage::new(MAX_EVENTS);
         storage.add_execution(mk_exec(1, 1, "/bin/a", &[])).await;
         storage.add_execution(mk_exec(2, 2, "/bin/b", &[])).await;
         storage.add_execution(mk_exec(1, 3, "/bin/c", &[])).await;
-        let p1 = storage.get_executions_by_pid(1).await;
+        let p1 = storage.query(&ExecutionFilter::for_pid(1)).await;
         assert_eq!(p1.len(), 2);
         assert!(p1.iter().all(|e| e.pid == 1));
-        let p2 = storage.get_executions_by_pid(2).await;
+        let p2 = storage.query(&ExecutionFilter::for_pid(2)).await;
         assert_eq!(p2.len(), 1);
     }
+
+    #[tokio::test]
+    async fn clear_handler() {
+        let storage = ExecutionStorage::new(MAX_EVENTS);
+        storage.add_execution(mk_exec(1, 1_000_000_000, "/bin/a", &[])).await;
+        storage.add_execution(mk_exec(2, 2_000_000_000, "/bin/b", &[])).await;
+        storage.add_exit(ExitRecord { pid: 1, exit_code: 0, signal: None, timestamp: DateTime::<Utc>::from_timestamp(3, 0).unwrap() }).await;
+
+        assert_eq!(clear_executions(State(storage.clone())).await.status(), StatusCode::NO_CONTENT);
+        assert!(storage.get_all_executions().await.is_empty());
+        assert!(storage.get_exit(1).await.is_none());
+        // still usable afterwards
+        storage.add_execution(mk_exec(3, 4_000_000_000, "/bin/c", &[])).await;
+        assert_eq!(storage.len().await, 1);
+    }
+
+    #[tokio::test]
+    async fn reused_pid() {
+        // pid 4242 started at 1s and exec'd twice, then a new process got the pid at 5s
+        let event = |timestamp: u64, start_time: u64, cmd| crate::ExecEvent { start_time, ..mk_event(4242, timestamp, cmd, &[]) };
+        let first = ProcessExecution::from_event(&event(2_000_000_000, 1_000_000_000, "/bin/sh"), Duration::zero());
+        let again = ProcessExecution::from_event(&event(3_000_000_000, 1_000_000_000, "/usr/bin/make"), Duration::zero());
+        let reused = ProcessExecution::from_event(&event(6_000_000_000, 5_000_000_000, "/usr/bin/curl"), Duration::zero());
+        assert_eq!(first.process_start, DateTime::<Utc>::from_timestamp(1, 0));
+        assert_eq!(first.process_start, again.process_start);
+        assert_ne!(first.process_start, reused.process_start);
+        assert_eq!(ProcessExecution::from_event(&event(1, 0, "/bin/true"), Duration::zero()).process_start, None);
+
+        let storage = ExecutionStorage::new(MAX_EVENTS);
+        for execution in [first, again, reused] {
+            storage.add_execution(execution).await;
+        }
+        let query = |process_start: &str| Query(PidQuery { process_start: Some(process_start.to_string()), ..Default::default() });
+        let commands = |executions: Vec<ProcessExecution>| executions.iter().map(|e| e.commandstr.clone()).collect::<Vec<_>>();
+        let Json(all) = get_executions_by_pid(Path(4242), Query(PidQuery::default()), State(storage.clone())).await.unwrap();
+        assert_eq!(all.iter().map(|e| e.process_start.unwrap().timestamp()).collect::<Vec<_>>(), vec![1, 1, 5]);
+        let Json(earlier) = get_executions_by_pid(Path(4242), query("1970-01-01T00:00:01Z"), State(storage.clone())).await.unwrap();
+        assert_eq!(commands(earlier), vec!["/bin/sh", "/usr/bin/make"]);
+        let Json(later) = get_executions_by_pid(Path(4242), query("1970-01-01T00:00:05Z"), State(storage.clone())).await.unwrap();
+        assert_eq!(commands(later), vec!["/usr/bin/curl"]);
+        let none = get_executions_by_pid(Path(4242), query("1970-01-01T00:00:03Z"), State(storage.clone())).await;
+        assert_eq!(none.unwrap_err().status(), StatusCode::NOT_FOUND);
+        let invalid = get_executions_by_pid(Path(4242), query("soon"), State(storage)).await;
+        assert_eq!(invalid.unwrap_err().status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn exec_from_thread() {
+        // thread 1235 of process 1234 calls execve
+        let event = crate::ExecEvent { tid: 1235, ..mk_event(1234, 1_000_000_000, "/bin/true", &[]) };
+        let execution = ProcessExecution::from_event(&event, Duration::zero());
+        assert_eq!((execution.pid, execution.tid), (1234, 1235));
+
+        let storage = ExecutionStorage::new(MAX_EVENTS);
+        storage.add_execution(execution).await;
+        // results and exits are reported for the process
+        let event = ExecResultEvent { pid: 1234, ret: 0, timestamp: 1_000_000_000, exe_ino: 0, exe_dev: 0 };
+        storage.set_result(ExecResult::from_event(&event, Duration::zero())).await;
+        storage.add_exit(ExitRecord { pid: 1234, exit_code: 0, signal: None, timestamp: DateTime::<Utc>::from_timestamp(2, 0).unwrap() }).await;
+        let Json(executions) = get_executions_by_pid(Path(1234), Query(PidQuery::default()), State(storage.clone())).await.unwrap();
+        assert_eq!((executions[0].tid, executions[0].result, executions[0].duration_ms), (1235, Some(0), Some(1_000)));
+        let by_tid = get_executions_by_pid(Path(1235), Query(PidQuery::default()), State(storage)).await;
+        assert_eq!(by_tid.unwrap_err().status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn exe_from_result() {
+        let storage = ExecutionStorage::new(MAX_EVENTS);
+        storage.add_execution(mk_exec(1, 1_000_000_000, "/bin/true", &[])).await;
+        storage.add_execution(mk_exec(2, 2_000_000_000, "/bin/nope", &[])).await;
+        // a sys_enter execution doesn't know the file yet
+        assert_eq!(storage.query(&ExecutionFilter::for_pid(1)).await[0].exe_ino, 0);
+
+        let result = |pid, timestamp, ret, exe_ino, exe_dev| {
+            ExecResult::from_event(&ExecResultEvent { pid, ret, timestamp, exe_ino, exe_dev }, Duration::zero())
+        };
+        storage.set_result(result(1, 1_000_000_000, 0, 1_311_014, 259 << 20 | 2)).await;
+        // a failed exec has no file, and keeps the zeros
+        storage.set_result(result(2, 2_000_000_000, -2, 0, 0)).await;
+        let ok = &storage.query(&ExecutionFilter::for_pid(1)).await[0];
+        assert_eq!((ok.exe_ino, ok.exe_dev, ok.exe_device.as_str()), (1_311_014, libc::makedev(259, 2), "259:2"));
+        let failed = &storage.query(&ExecutionFilter::for_pid(2)).await[0];
+        assert_eq!((failed.exe_ino, failed.exe_dev, failed.exe_device.as_str()), (0, 0, "0:0"));
+    }
+
+    #[tokio::test]
+    async fn get_by_ppid() {
+        let storage = ExecutionStorage::new(MAX_EVENTS);
+        // a shell (pid 10) spawning two children, and an unrelated process
+        storage.add_execution(ProcessExecution { ppid: 1, ..mk_exec(10, 1, "/bin/bash", &[]) }).await;
+        storage.add_execution(ProcessExecution { ppid: 10, ..mk_exec(11, 2, "/bin/ls", &[]) }).await;
+        storage.add_execution(ProcessExecution { ppid: 10, ..mk_exec(12, 3, "/usr/bin/curl", &[]) }).await;
+        storage.add_execution(ProcessExecution { ppid: 20, ..mk_exec(21, 4, "/bin/true", &[]) }).await;
+
+        let children = storage.query(&ExecutionFilter { ppid: Some(10), ..Default::default() }).await;
+        assert_eq!(children.iter().map(|e| e.pid).collect::<Vec<_>>(), vec![11, 12]);
+
+        let Json(children) = get_executions_by_ppid(Path(10), Query(TimeRange::default()), State(storage.clone())).await.unwrap();
+        assert_eq!(children.len(), 2);
+        assert!(children.iter().all(|e| e.ppid == 10));
+        let missing = get_executions_by_ppid(Path(99), Query(TimeRange::default()), State(storage.clone())).await;
+        assert_eq!(missing.unwrap_err().status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn stats() {
+        let Json(empty) = get_stats(State(ExecutionStorage::new(MAX_EVENTS))).await;
+        assert_eq!(empty, ExecutionStats::default());
+
+        let storage = ExecutionStorage::new(MAX_EVENTS);
+        storage.add_execution(mk_exec(1, 3_000_000_000, "/bin/a", &[])).await;
+        storage.add_execution(mk_exec(2, 1_000_000_000, "/bin/b", &[])).await;
+        storage.add_execution(mk_exec(1, 5_000_000_000, "/bin/a", &["x"])).await;
+        storage.add_execution(mk_exec(3, 2_000_000_000, "/bin/c", &[])).await;
+        let Json(stats) = get_stats(State(storage)).await;
+        assert_eq!(stats.total, 4);
+        assert_eq!(stats.unique_pids, 3);
+        assert_eq!(stats.unique_commands, 3);
+        // by timestamp, not insertion order
+        assert_eq!(stats.oldest.unwrap().timestamp(), 1);
+        assert_eq!(stats.newest.unwrap().timestamp(), 5);
+        assert_eq!(stats.events_lost, 0);
+    }
+
+    #[tokio::test]
+    async fn exit_handler() {
+        let storage = ExecutionStorage::new(MAX_EVENTS);
+        let at = |secs: i64| DateTime::<Utc>::from_timestamp(secs, 0).unwrap();
+        storage.add_execution(mk_exec(1, 1_000_000_000, "/bin/sleep", &["5"])).await;
+
+        // not yet exited
+        let running = get_exit_by_pid(Path(1), State(storage.clone())).await.unwrap_err();
+        assert_eq!(running.status(), StatusCode::NOT_FOUND);
+
+        storage.add_exit(ExitRecord { pid: 1, exit_code: 0, signal: Some(15), timestamp: at(6) }).await;
+        let Json(exit) = get_exit_by_pid(Path(1), State(storage.clone())).await.unwrap();
+        assert_eq!(exit.signal, Some(15));
+        // what the exit is for: how long the process ran
+        let exec = storage.query(&ExecutionFilter::for_pid(1)).await[0].timestamp;
+        assert_eq!((exit.timestamp - exec).num_seconds(), 5);
+        let execution = &storage.query(&ExecutionFilter::for_pid(1)).await[0];
+        assert_eq!((execution.exited_at, execution.duration_ms), (Some(at(6)), Some(5_000)));
+        // exits of processes that were never seen are only counted
+        storage.add_exit(ExitRecord { pid: 2, exit_code: 1, signal: None, timestamp: at(7) }).await;
+        assert_eq!(get_exit_by_pid(Path(2), State(storage.clone())).await.unwrap_err().status(), StatusCode::NOT_FOUND);
+        assert_eq!(storage.metrics().orphan_exits_total.get(), 1);
+
+        // pid 1 reused by a new, still running process
+        storage.add_execution(mk_exec(1, 8_000_000_000, "/bin/sleep", &["9"])).await;
+        let reused = get_exit_by_pid(Path(1), State(storage.clone())).await.unwrap_err();
+        assert_eq!(reused.status(), StatusCode::NOT_FOUND);
+    }
+
+    fn child(pid: u32, ppid: u32, secs: u64, cmd: &str) -> ProcessExecution {
+        ProcessExecution { ppid, ..mk_exec(pid, secs * 1_000_000_000, cmd, &[]) }
+    }
+
+    // (pid, command) of each node, depth first
+    fn flatten(node: &ProcessNode) -> Vec<(u32, &str)> {
+        std::iter::once((node.pid, node.command.as_str())).chain(node.children.iter().flat_map(flatten)).collect()
+    }
+
+    #[test]
+    fn tree_single_child() {
+        let tree = process_tree(&[child(1, 0, 1, "/sbin/init"), child(100, 1, 2, "/usr/sbin/sshd")], DEFAULT_TREE_DEPTH);
+        assert_eq!((tree.pid, tree.command.as_str()), (1, "/sbin/init"));
+        assert_eq!(tree.timestamp, Some(DateTime::<Utc>::from_timestamp(1, 0).unwrap()));
+        assert_eq!(tree.children.len(), 1);
+        assert_eq!((tree.children[0].pid, tree.children[0].command.as_str()), (100, "/usr/sbin/sshd"));
+        assert!(tree.children[0].children.is_empty());
+    }
+
+    #[test]
+    fn tree_siblings() {
+        // pid 1 didn't exec, the lowest ppid stands in for it. 10 ran twice, its latest exec counts
+        let executions = [
+            child(10, 1, 1, "/bin/login"),
+            child(10, 1, 2, "/bin/bash"),
+            child(12, 10, 4, "/usr/bin/vim"),
+            child(11, 10, 3, "/usr/bin/git"),
+        ];
+        let tree = process_tree(&executions, DEFAULT_TREE_DEPTH);
+        assert_eq!((tree.pid, tree.command.as_str(), tree.timestamp), (1, "", None));
+        assert_eq!(flatten(&tree), [(1, ""), (10, "/bin/bash"), (11, "/usr/bin/git"), (12, "/usr/bin/vim")]);
+        assert_eq!(tree.children[0].children.len(), 2);
+    }
+
+    #[test]
+    fn tree_orphans() {
+        // neither parent ran anything we saw, 5 is the root and 20 hangs off it after its own children
+        let executions = [child(20, 7, 1, "/usr/bin/cron"), child(10, 5, 2, "/bin/bash"), child(11, 10, 3, "/bin/ls")];
+        let tree = process_tree(&executions, DEFAULT_TREE_DEPTH);
+        assert_eq!(tree.pid, 5);
+        assert_eq!(tree.children.iter().map(|n| n.pid).collect::<Vec<_>>(), [10, 20]);
+        assert_eq!(flatten(&tree).len(), 4);
+
+        // reused pids claiming each other as parent still show up once
+        let executions = [child(10, 1, 1, "/bin/bash"), child(30, 31, 2, "/bin/a"), child(31, 30, 3, "/bin/b")];
+        let tree = process_tree(&executions, DEFAULT_TREE_DEPTH);
+        assert_eq!(flatten(&tree), [(1, ""), (10, "/bin/bash"), (30, "/bin/a"), (31, "/bin/b")]);
+    }
+
+    #[tokio::test]
+    async fn tree_handler_depth() {
+        let storage = ExecutionStorage::new(MAX_EVENTS);
+        for e in [child(40, 1, 1, "/bin/a"), child(41, 40, 2, "/bin/b"), child(42, 41, 3, "/bin/c")] {
+            storage.add_execution(e).await;
+        }
+        let Json(tree) = get_process_tree(Query(TreeQuery::default()), State(storage.clone())).await.unwrap();
+        assert_eq!(flatten(&tree).len(), 4);
+        let Json(tree) = get_process_tree(Query(TreeQuery { depth: Some("2".to_string()) }), State(storage.clone())).await.unwrap();
+        assert_eq!(flatten(&tree), [(1, ""), (40, "/bin/a"), (41, "/bin/b")]);
+        let json = serde_json::to_value(&tree).unwrap();
+        assert!(json["timestamp"].is_null());
+        assert_eq!(json["children"][0]["children"][0]["children"], serde_json::json!([]));
+        let bad = get_process_tree(Query(TreeQuery { depth: Some("deep".to_string()) }), State(storage)).await;
+        assert_eq!(bad.unwrap_err().status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn ancestry_handler() {
+        let storage = ExecutionStorage::new(MAX_EVENTS);
+        let (writer, handle) = storage.spawn_writer();
+        let fork = |parent_pid, child_pid, secs: u64| {
+            StorageWrite::Fork(ForkRecord::from_event(&crate::ForkEvent { parent_pid, child_pid, timestamp: secs * 1_000_000_000 }, Duration::zero()))
+        };
+        let exec = |pid, secs: u64, cmd| StorageWrite::Execution(Box::new(mk_exec(pid, secs * 1_000_000_000, cmd, &[])));
+        // bash forks make, make forks sh, sh forks a subshell that doesn't exec, which forks cc
+        for write in [
+            exec(10, 1, "/bin/bash"),
+            fork(10, 11, 2),
+            exec(11, 3, "/usr/bin/make"),
+            fork(11, 12, 4),
+            exec(12, 5, "/bin/sh"),
+            fork(12, 13, 6),
+            fork(13, 14, 7),
+            exec(14, 8, "/usr/bin/cc"),
+            // pid 11 reused after it forked sh
+            exec(11, 9, "/usr/bin/unrelated"),
+        ] {
+            writer.send(write).unwrap();
+        }
+        drop(writer);
+        handle.await.unwrap();
+
+        let Json(ancestry) = get_ancestry_by_pid(Path(14), State(storage.clone())).await.unwrap();
+        assert_eq!(ancestry.iter().map(|a| a.pid).collect::<Vec<_>>(), vec![13, 12, 11, 10]);
+        let commands: Vec<Vec<&str>> =
+            ancestry.iter().map(|a| a.executions.iter().map(|e| e.commandstr.as_str()).collect()).collect();
+        assert_eq!(commands, vec![vec![], vec!["/bin/sh"], vec!["/usr/bin/make"], vec!["/bin/bash"]]);
+
+        // bash was never seen forked, its exec's ppid (0) ends the chain
+        let missing = get_ancestry_by_pid(Path(10), State(storage.clone())).await;
+        assert_eq!(missing.unwrap_err().status(), StatusCode::NOT_FOUND);
+        assert_eq!(get_ancestry_by_pid(Path(99), State(storage)).await.unwrap_err().status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn dedup_repeats() {
+        let repeats = |storage: ExecutionStorage| async move {
+            for ms in [0, 10, 20] {
+                storage.add_execution(mk_exec(7, 1_000_000_000 + ms * 1_000_000, "/bin/true", &["-x"])).await;
+            }
+            storage.query(&ExecutionFilter::for_pid(7)).await
+        };
+        // disabled by default
+        let stored = repeats(ExecutionStorage::new(MAX_EVENTS)).await;
+        assert_eq!(stored.iter().map(|e| e.count).collect::<Vec<_>>(), vec![1, 1, 1]);
+
+        let storage = ExecutionStorage::new(MAX_EVENTS).with_dedup(Duration::milliseconds(100));
+        let stored = repeats(storage.clone()).await;
+        assert_eq!(stored.len(), 1);
+        assert_eq!((stored[0].count, stored[0].timestamp.timestamp_subsec_millis()), (3, 0));
+        assert_eq!(storage.metrics().executions_total.get(), 3);
+
+        // other arguments, and the same command past the window, are new records
+        storage.add_execution(mk_exec(7, 1_030_000_000, "/bin/true", &["-y"])).await;
+        storage.add_execution(mk_exec(7, 1_200_000_000, "/bin/true", &["-y"])).await;
+        assert_eq!(storage.query(&ExecutionFilter::for_pid(7)).await.iter().map(|e| e.count).collect::<Vec<_>>(), vec![3, 1, 1]);
+    }
+
+    #[tokio::test]
+    async fn stats_report_lost_events() {
+        let storage = ExecutionStorage::new(MAX_EVENTS);
+        storage.metrics().events_lost.fetch_add(3, Ordering::Relaxed);
+        // evictions don't count, nothing was lost before it reached storage
+        storage.metrics().events_dropped_total.inc_by(5);
+        let Json(stats) = get_stats(State(storage)).await;
+        assert_eq!(stats.events_lost, 3);
+        assert_eq!(serde_json::to_value(&stats).unwrap()["events_lost"], 3);
+    }
+
+    #[tokio::test]
+    async fn executions_csv() {
+        let storage = ExecutionStorage::new(MAX_EVENTS);
+        storage.add_execution(mk_exec(7, 1_000_000_000, "/usr/bin/awk", &["-F,", "{print $1}"])).await;
+        storage.add_execution(mk_exec(8, 2_000_000_000, "/bin/echo", &["say \"hi\""])).await;
+        let response = get_executions_csv(State(storage)).await;
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "text/csv; charset=utf-8");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let csv = String::from_utf8(body.to_vec()).unwrap();
+        let rows: Vec<&str> = csv.split_terminator("\r\n").collect();
+        assert_eq!(rows[0], "pid,timestamp,command,args,full_command");
+        // the comma is inside a quoted field
+        assert_eq!(rows[1], "7,1970-01-01T00:00:01Z,/usr/bin/awk,\"-F, {print $1}\",\"/usr/bin/awk -F, {print $1}\"");
+        assert_eq!(rows[2], "8,1970-01-01T00:00:02Z,/bin/echo,\"say \"\"hi\"\"\",\"/bin/echo say \"\"hi\"\"\"");
+        assert_eq!(rows.len(), 3);
+    }
+
+    // RFC 4180 records, the inverse of csv_row
+    fn parse_csv(text: &str) -> Vec<Vec<String>> {
+        let (mut records, mut record, mut field) = (Vec::new(), Vec::new(), String::new());
+        let mut chars = text.chars().peekable();
+        let mut quoted = false;
+        while let Some(c) = chars.next() {
+            match c {
+                '"' if quoted && chars.peek() == Some(&'"') => {
+                    field.push('"');
+                    chars.next();
+                }
+                '"' => quoted = !quoted,
+                ',' if !quoted => record.push(std::mem::take(&mut field)),
+                '\r' if !quoted && chars.peek() == Some(&'\n') => {
+                    chars.next();
+                    record.push(std::mem::take(&mut field));
+                    records.push(std::mem::take(&mut record));
+                }
+                c => field.push(c),
+            }
+        }
+        records
+    }
+
+    #[tokio::test]
+    async fn export_csv_round_trip() {
+        let storage = ExecutionStorage::new(MAX_EVENTS);
+        let executions = [
+            mk_exec(7, 1_000_000_000, "/usr/bin/awk", &["-F,", "{print $1}"]),
+            mk_exec(8, 2_500_000_000, "/bin/sh", &["-c", "echo \"a\"\r\necho b"]),
+            mk_exec(9, 3_000_000_000, "/bin/true", &[]),
+        ];
+        for execution in &executions {
+            storage.add_execution(execution.clone()).await;
+        }
+
+        let query = Query(ExportQuery { format: Some("csv".to_string()) });
+        let response = export_executions(query, State(storage.clone())).await.unwrap();
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "text/csv; charset=utf-8");
+        assert_eq!(response.headers()[header::CONTENT_DISPOSITION], "attachment; filename=\"executions.csv\"");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let records = parse_csv(std::str::from_utf8(&body).unwrap());
+        assert_eq!(records[0], ["pid", "timestamp", "command", "args", "ppid", "uid", "gid", "full_command"]);
+        assert_eq!(records.len(), executions.len() + 1);
+        for (record, execution) in records[1..].iter().zip(&executions) {
+            assert_eq!(record[0].parse::<u32>().unwrap(), execution.pid);
+            assert_eq!(record[1].parse::<DateTime<Utc>>().unwrap(), execution.timestamp);
+            assert_eq!((record[2].as_str(), record[3].as_str()), (execution.commandstr.as_str(), execution.argstr.as_str()));
+            assert_eq!(record[4..7], [execution.ppid.to_string(), execution.uid.to_string(), execution.gid.to_string()]);
+            assert_eq!(record[7], execution.full_command);
+        }
+
+        // JSON by default, anything else is rejected
+        let response = export_executions(Query(ExportQuery::default()), State(storage.clone())).await.unwrap();
+        assert_eq!(response.headers()[header::CONTENT_DISPOSITION], "attachment; filename=\"executions.json\"");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(serde_json::from_slice::<Vec<ProcessExecution>>(&body).unwrap().len(), 3);
+        let query = Query(ExportQuery { format: Some("xml".to_string()) });
+        assert_eq!(export_executions(query, State(storage)).await.unwrap_err().status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn executions_ndjson() {
+        let storage = ExecutionStorage::new(MAX_EVENTS);
+        let response = get_executions_ndjson(State(storage.clone())).await;
+        assert!(axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap().is_empty());
+
+        storage.add_execution(mk_exec(1, 1, "/bin/echo", &["a\nb"])).await;
+        storage.add_execution(mk_exec(2, 2, "/bin/true", &[])).await;
+        let response = get_executions_ndjson(State(storage)).await;
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/x-ndjson");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let text = std::str::from_utf8(&body).unwrap();
+        assert!(text.ends_with('\n'));
+        // the newline in the argument is escaped, so records never span lines
+        let lines: Vec<ProcessExecution> = text.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(lines.iter().map(|e| e.pid).collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(lines[0].argstr, "a\nb");
+    }
+
+    #[tokio::test]
+    async fn command_stats_counts() {
+        let storage = ExecutionStorage::new(MAX_EVENTS);
+        let at = |secs| mk_exec_at(0, DateTime::<Utc>::from_timestamp(secs, 0).unwrap());
+        // the same command from several pids, one of them twice
+        for (pid, secs) in [(10, 100), (11, 50), (10, 300), (12, 200)] {
+            storage.add_execution(ProcessExecution { pid, commandstr: "/bin/ls".to_string(), ..at(secs) }).await;
+        }
+        storage.add_execution(ProcessExecution { pid: 20, commandstr: "/bin/sh".to_string(), ..at(150) }).await;
+        storage.add_execution(ProcessExecution { pid: 21, commandstr: "/bin/cat".to_string(), count: 3, ..at(160) }).await;
+
+        let stats = storage.compute_stats().await;
+        let counts = stats.iter().map(|s| (s.command.as_str(), s.count, s.unique_pids)).collect::<Vec<_>>();
+        assert_eq!(counts, [("/bin/ls", 4, 3), ("/bin/cat", 3, 1), ("/bin/sh", 1, 1)]);
+        assert_eq!((stats[0].first_seen.timestamp(), stats[0].last_seen.timestamp()), (50, 300));
+        // read only
+        assert_eq!(storage.get_all_executions().await.len(), 6);
+
+        let query = Query(CommandStatsQuery { top: Some("2".to_string()) });
+        let Json(top) = get_command_stats(query, State(storage.clone())).await.unwrap();
+        assert_eq!(top.total_events, 8);
+        assert_eq!(top.commands.len(), 2);
+        let Json(all) = get_command_stats(Query(CommandStatsQuery::default()), State(storage.clone())).await.unwrap();
+        assert_eq!(all.commands, stats);
+        let query = Query(CommandStatsQuery { top: Some("-1".to_string()) });
+        assert_eq!(get_command_stats(query, State(storage)).await.unwrap_err().status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn stats_report_kernel_threads() {
+        let storage = ExecutionStorage::new(MAX_EVENTS);
+        storage.metrics().kernel_thread_execs_total.inc_by(4);
+        let Json(stats) = get_stats(State(storage)).await;
+        assert_eq!(stats.kernel_threads_filtered, 4);
+        // filtered in the kernel, not lost
+        assert_eq!(stats.events_lost, 0);
+        assert_eq!(serde_json::to_value(&stats).unwrap()["kernel_threads_filtered"], 4);
+    }
+
+    #[tokio::test]
+    async fn stats_report_uid_exclusions() {
+        let storage = ExecutionStorage::new(MAX_EVENTS);
+        let Json(stats) = get_stats(State(storage.clone())).await;
+        assert_eq!(stats.uid_excluded, 0);
+        storage.metrics().uid_excluded_execs_total.inc_by(3);
+        let Json(stats) = get_stats(State(storage)).await;
+        assert_eq!(serde_json::to_value(&stats).unwrap()["uid_excluded"], 3);
+        assert_eq!(stats.kernel_threads_filtered, 0);
+    }
+
+    #[tokio::test]
+    async fn get_by_command() {
+        let storage = ExecutionStorage::new(MAX_EVENTS);
+        storage.add_execution(mk_exec(1, 1, "/bin/bash", &[])).await;
+        storage.add_execution(mk_exec(2, 2, "/usr/bin/bash", &[])).await;
+        storage.add_execution(mk_exec(3, 3, "/bin/bash", &["-c", "ls"])).await;
+        let by_command = |command: &str| ExecutionFilter { command: Some(command.to_string()), ..Default::default() };
+        let bash = storage.query(&by_command("/bin/bash")).await;
+        assert_eq!(bash.len(), 2);
+        assert!(bash.iter().all(|e| e.commandstr == "/bin/bash"));
+        // exact match only, no prefix/substring matching
+        assert!(storage.query(&by_command("/bin/bas")).await.is_empty());
+        assert!(storage.query(&by_command("/bin/zsh")).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn get_by_uid() {
+        let storage = ExecutionStorage::new(MAX_EVENTS);
+        // pid 3 runs a setuid root binary, it still counts for the real uid
+        for (pid, uid, euid) in [(1, 0, 0), (2, 1000, 1000), (3, 1000, 0)] {
+            storage.add_execution(ProcessExecution { uid, euid, ..mk_exec(pid, pid as u64, "/bin/bash", &[]) }).await;
+        }
+        let by_uid = |uid| ExecutionFilter { uid: Some(uid), ..Default::default() };
+        assert_eq!(storage.query(&by_uid(1000)).await.iter().map(|e| e.pid).collect::<Vec<_>>(), vec![2, 3]);
+        assert_eq!(storage.query(&by_uid(0)).await.iter().map(|e| e.pid).collect::<Vec<_>>(), vec![1]);
+        assert!(storage.query(&by_uid(1001)).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn uid_and_command_filter() {
+        let storage = ExecutionStorage::new(MAX_EVENTS);
+        for (pid, uid, command) in [(1, 1000, "/bin/bash"), (2, 0, "/bin/bash"), (3, 1000, "/usr/bin/git"), (4, 1000, "/bin/bash")] {
+            storage.add_execution(ProcessExecution { uid, ..mk_exec(pid, pid as u64, command, &[]) }).await;
+        }
+        let pids = |uri: &str| {
+            let filter = Query::<ExecutionParams>::try_from_uri(&uri.parse().unwrap()).unwrap();
+            let storage = storage.clone();
+            async move { get_all_executions(filter, HeaderMap::new(), State(storage)).await.map(|page| page.data.iter().map(|e| e.pid).collect::<Vec<_>>()) }
+        };
+        assert_eq!(pids("/executions?uid=1000&command=/bin/bash").await.unwrap(), vec![4, 1]);
+        assert_eq!(pids("/executions?uid=1000&sort_by=pid&order=asc").await.unwrap(), vec![1, 3, 4]);
+        assert_eq!(pids("/executions?uid=0&command=/usr/bin/git").await.unwrap_err().status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn command_filter_handler() {
+        let storage = ExecutionStorage::new(MAX_EVENTS);
+        storage.add_execution(mk_exec(1, 1, "/bin/bash", &[])).await;
+        storage.add_execution(mk_exec(2, 2, "/usr/bin/bash", &[])).await;
+        let filter = |command: Option<&str>, command_contains: Option<&str>| {
+            Query(ExecutionParams {
+                command: command.map(String::from),
+                command_contains: command_contains.map(String::from),
+                ..Default::default()
+            })
+        };
+
+        let exact = get_all_executions(filter(Some("/bin/bash"), None), HeaderMap::new(), State(storage.clone())).await.unwrap().data;
+        assert_eq!(exact.len(), 1);
+        assert_eq!(exact[0].pid, 1);
+
+        let contains = get_all_executions(filter(None, Some("bash")), HeaderMap::new(), State(storage.clone())).await.unwrap().data;
+        assert_eq!(contains.len(), 2);
+
+        let missing = get_all_executions(filter(Some("/bin/zsh"), None), HeaderMap::new(), State(storage.clone())).await;
+        assert_eq!(missing.unwrap_err().status(), StatusCode::NOT_FOUND);
+
+        // no filter: empty list is still a 200
+        let all = get_all_executions(Query(ExecutionParams::default()), HeaderMap::new(), State(ExecutionStorage::new(MAX_EVENTS))).await.unwrap().data;
+        assert!(all.is_empty());
+    }
+
+    fn uid_filter(uid: &str) -> Query<ExecutionParams> {
+        Query(ExecutionParams { uid: Some(uid.to_string()), ..Default::default() })
+    }
+
+    #[tokio::test]
+    async fn uid_filter_root() {
+        let storage = ExecutionStorage::new(MAX_EVENTS);
+        // mk_exec events come from uid 0
+        storage.add_execution(mk_exec(1, 1, "/usr/sbin/iptables", &["-L"])).await;
+        let mut user = mk_exec(2, 2, "/bin/ls", &[]);
+        user.uid = 1000;
+        storage.add_execution(user).await;
+
+        let root = get_all_executions(uid_filter("0"), HeaderMap::new(), State(storage.clone())).await.unwrap().data;
+        assert_eq!(root.len(), 1);
+        assert_eq!(root[0].pid, 1);
+        assert_eq!(serde_json::to_value(&root[0]).unwrap()["uid"], 0);
+
+        // parses from a real query string alongside the flattened range
+        let uri = "/executions?uid=0&since=1970-01-01T00:00:00Z".parse().unwrap();
+        let filter = Query::<ExecutionParams>::try_from_uri(&uri).unwrap();
+        let root = get_all_executions(filter, HeaderMap::new(), State(storage.clone())).await.unwrap().data;
+        assert_eq!(root.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn uid_filter_non_root() {
+        let storage = ExecutionStorage::new(MAX_EVENTS);
+        storage.add_execution(mk_exec(1, 1, "/usr/sbin/iptables", &[])).await;
+        for (pid, uid) in [(2, 1000), (3, 1001), (4, 1000)] {
+            let mut e = mk_exec(pid, pid as u64, "/bin/ls", &[]);
+            e.uid = uid;
+            e.gid = uid;
+            storage.add_execution(e).await;
+        }
+
+        let user = get_all_executions(uid_filter("1000"), HeaderMap::new(), State(storage.clone())).await.unwrap().data;
+        assert_eq!(user.iter().map(|e| e.pid).collect::<Vec<_>>(), vec![4, 2]);
+        assert!(user.iter().all(|e| e.uid == 1000 && e.gid == 1000));
+
+        // combines with the command filter
+        let filter = Query(ExecutionParams { command: Some("/usr/sbin/iptables".to_string()), uid: Some("1000".to_string()), ..Default::default() });
+        assert_eq!(get_all_executions(filter, HeaderMap::new(), State(storage.clone())).await.unwrap_err().status(), StatusCode::NOT_FOUND);
+        assert_eq!(get_all_executions(uid_filter("4242"), HeaderMap::new(), State(storage.clone())).await.unwrap_err().status(), StatusCode::NOT_FOUND);
+        assert_eq!(get_all_executions(uid_filter("-1"), HeaderMap::new(), State(storage.clone())).await.unwrap_err().status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn command_filter_matches_scripts() {
+        let storage = ExecutionStorage::new(MAX_EVENTS);
+        storage.add_execution(mk_exec(1, 1, "/bin/bash", &["bash"])).await;
+        let script = ProcessExecution { script: Some("./foo.sh".to_string()), ..mk_exec(2, 2, "/bin/bash", &["bash", "./foo.sh"]) };
+        assert!(script.runs("/bin/bash") && script.runs("./foo.sh") && script.runs("foo.sh"));
+        assert!(!script.runs("o.sh") && !script.runs("/bin/foo.sh"));
+        storage.add_execution(script).await;
+
+        let filter = |command: &str| Query(ExecutionParams { command: Some(command.to_string()), ..Default::default() });
+        let pids = |page: ExecutionsPage| page.data.iter().map(|e| e.pid).collect::<Vec<_>>();
+        assert_eq!(pids(get_all_executions(filter("foo.sh"), HeaderMap::new(), State(storage.clone())).await.unwrap()), [2]);
+        assert_eq!(pids(get_all_executions(filter("/bin/bash"), HeaderMap::new(), State(storage.clone())).await.unwrap()), [2, 1]);
+    }
+
+    #[tokio::test]
+    async fn container_id_filter() {
+        let id = "3f4e1b9c2a7d6e5f8091a2b3c4d5e6f708192a3b4c5d6e7f8091a2b3c4d5e6f7";
+        let storage = ExecutionStorage::new(MAX_EVENTS);
+        storage.add_execution(mk_exec(1, 1, "/usr/sbin/sshd", &[])).await;
+        for pid in [2, 3] {
+            let mut e = mk_exec(pid, pid as u64, "/usr/bin/python3", &[]);
+            e.container_id = Some(id.to_string());
+            storage.add_execution(e).await;
+        }
+
+        let uri = format!("/executions?container_id={id}").parse().unwrap();
+        let filter = Query::<ExecutionParams>::try_from_uri(&uri).unwrap();
+        let data = get_all_executions(filter, HeaderMap::new(), State(storage.clone())).await.unwrap().data;
+        assert_eq!(data.iter().map(|e| e.pid).collect::<Vec<_>>(), vec![3, 2]);
+
+        let filter = Query(ExecutionParams { container_id: Some(id[..12].to_string()), ..Default::default() });
+        assert_eq!(get_all_executions(filter, HeaderMap::new(), State(storage)).await.unwrap_err().status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn cgroup_id_filter() {
+        let storage = ExecutionStorage::new(MAX_EVENTS);
+        for (pid, cgroup_id) in [(1, 8812), (2, 4242), (3, 8812)] {
+            storage.add_execution(ProcessExecution { cgroup_id, ..mk_exec(pid, pid as u64, "/bin/sh", &[]) }).await;
+        }
+        let filter = |value: &str| Query::<ExecutionParams>::try_from_uri(&format!("/executions?cgroup_id={value}").parse().unwrap()).unwrap();
+        let data = get_all_executions(filter("8812"), HeaderMap::new(), State(storage.clone())).await.unwrap().data;
+        assert_eq!(data.iter().map(|e| e.pid).collect::<Vec<_>>(), vec![3, 1]);
+        assert_eq!(get_all_executions(filter("1"), HeaderMap::new(), State(storage.clone())).await.unwrap_err().status(), StatusCode::NOT_FOUND);
+        assert_eq!(get_all_executions(filter("docker"), HeaderMap::new(), State(storage)).await.unwrap_err().status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn cgroup_contains_filter() {
+        let storage = ExecutionStorage::new(MAX_EVENTS);
+        storage.add_execution(mk_exec(1, 1, "/usr/sbin/sshd", &[])).await;
+        for (pid, path) in [
+            (2, "/system.slice/docker-3f4e1b9c.scope"),
+            (3, "/kubepods.slice/pod1/cri-containerd-9a8b.scope"),
+            (4, "/user.slice/session-3.scope"),
+        ] {
+            let mut e = mk_exec(pid, pid as u64, "/usr/bin/python3", &[]);
+            e.cgroup_path = Some(path.to_string());
+            storage.add_execution(e).await;
+        }
+
+        let pids = |filter| {
+            let storage = storage.clone();
+            async move { get_all_executions(filter, HeaderMap::new(), State(storage)).await.map(|page| page.data.iter().map(|e| e.pid).collect::<Vec<_>>()) }
+        };
+        let uri = "/executions?cgroup_contains=kubepods".parse().unwrap();
+        assert_eq!(pids(Query::<ExecutionParams>::try_from_uri(&uri).unwrap()).await.unwrap(), vec![3]);
+        let filter = Query(ExecutionParams { cgroup_contains: Some(".scope".to_string()), ..Default::default() });
+        assert_eq!(pids(filter).await.unwrap(), vec![4, 3, 2]);
+        let filter = Query(ExecutionParams { cgroup_contains: Some("machine.slice".to_string()), ..Default::default() });
+        assert_eq!(pids(filter).await.unwrap_err().status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn setid_bits() {
+        let dir = tempfile::tempdir().unwrap();
+        let binary = |name: &str, mode: u32| {
+            let path = dir.path().join(name);
+            std::fs::write(&path, b"").unwrap();
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode)).unwrap();
+            path.to_string_lossy().into_owned()
+        };
+        let bits = |command: &str| {
+            let mut e = mk_exec(1, 1, command, &[]);
+            e.read_setid_bits();
+            (e.setuid, e.setgid)
+        };
+        assert_eq!(bits(&binary("passwd", 0o4755)), (Some(true), Some(false)));
+        assert_eq!(bits(&binary("wall", 0o2755)), (Some(false), Some(true)));
+        assert_eq!(bits(&binary("ls", 0o755)), (Some(false), Some(false)));
+        // relative without a cwd, and deleted
+        assert_eq!(bits("./passwd"), (None, None));
+        assert_eq!(bits(&dir.path().join("gone").to_string_lossy()), (None, None));
+
+        let storage = ExecutionStorage::new(MAX_EVENTS);
+        for (pid, setuid) in [(1, Some(true)), (2, Some(false)), (3, None)] {
+            storage.add_execution(ProcessExecution { setuid, ..mk_exec(pid, pid as u64, "/usr/bin/passwd", &[]) }).await;
+        }
+        let filter = |value: &str| Query::<ExecutionParams>::try_from_uri(&format!("/executions?setuid={value}").parse().unwrap()).unwrap();
+        let pids = |page: ExecutionsPage| page.data.iter().map(|e| e.pid).collect::<Vec<_>>();
+        assert_eq!(pids(get_all_executions(filter("true"), HeaderMap::new(), State(storage.clone())).await.unwrap()), vec![1]);
+        assert_eq!(pids(get_all_executions(filter("false"), HeaderMap::new(), State(storage.clone())).await.unwrap()), vec![3, 2]);
+        let response = get_all_executions(filter("1"), HeaderMap::new(), State(storage)).await.unwrap_err();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    fn page(limit: Option<&str>, offset: Option<&str>) -> Query<ExecutionParams> {
+        Query(ExecutionParams { limit: limit.map(String::from), offset: offset.map(String::from), ..Default::default() })
+    }
+
+    async fn body_json(response: Response) -> serde_json::Value {
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn get_paginated() {
+        let storage = ExecutionStorage::new(MAX_EVENTS);
+        for i in 0..10 {
+            storage.add_execution(mk_exec(i, i as u64, "/bin/cmd", &[])).await;
+        }
+        let (data, total) = storage.get_executions_paginated(2, 3, SortOrder::Asc).await;
+        assert_eq!(data.iter().map(|e| e.pid).collect::<Vec<_>>(), vec![2, 3, 4]);
+        assert_eq!(total, 10);
+        let (data, _) = storage.get_executions_paginated(2, 3, SortOrder::Desc).await;
+        assert_eq!(data.iter().map(|e| e.pid).collect::<Vec<_>>(), vec![7, 6, 5]);
+        let (data, total) = storage.get_executions_paginated(8, 5, SortOrder::Asc).await;
+        assert_eq!(data.len(), 2);
+        assert_eq!(total, 10);
+    }
+
+    #[tokio::test]
+    async fn pagination_handler() {
+        let storage = ExecutionStorage::new(MAX_EVENTS);
+        for i in 0..150 {
+            let cmd = if i % 2 == 0 { "/bin/even" } else { "/bin/odd" };
+            storage.add_execution(mk_exec(i, i as u64 * 1_000_000_000, cmd, &[])).await;
+        }
+
+        // default page
+        let all = get_all_executions(page(None, None), HeaderMap::new(), State(storage.clone())).await.unwrap();
+        assert_eq!(all.data.len(), DEFAULT_PAGE_LIMIT);
+        assert_eq!(all.total, 150);
+        let response = get_all_executions(page(Some("20"), Some("140")), HeaderMap::new(), State(storage.clone())).await.unwrap().into_response();
+        assert_eq!(response.headers()["x-total-count"], "150");
+        // bare list without the envelope profile
+        let body = body_json(response).await;
+        assert_eq!(body.as_array().unwrap().iter().map(|e| e["pid"].as_u64().unwrap()).collect::<Vec<_>>(), (0..10).rev().collect::<Vec<_>>());
+
+        let mut headers = HeaderMap::new();
+        headers.insert("accept-profile", ENVELOPE_PROFILE.parse().unwrap());
+        let response = get_all_executions(page(Some("5"), Some("10")), headers, State(storage.clone())).await.unwrap().into_response();
+        let body = body_json(response).await;
+        assert_eq!(body["total"], 150);
+        assert_eq!(body["offset"], 10);
+        assert_eq!(body["limit"], 5);
+        assert_eq!(body["data"][0]["pid"], 139);
+        assert_eq!(body["data"].as_array().unwrap().len(), 5);
+
+        // composes with the command and time range filters, total counts the matches
+        let filter = Query(ExecutionParams {
+            command: Some("/bin/odd".to_string()),
+            limit: Some("3".to_string()),
+            offset: Some("1".to_string()),
+            range: TimeRange { since: Some("1970-01-01T00:01:00Z".to_string()), ..Default::default() },
+            ..Default::default()
+        });
+        let odd = get_all_executions(filter, HeaderMap::new(), State(storage.clone())).await.unwrap();
+        assert_eq!(odd.data.iter().map(|e| e.pid).collect::<Vec<_>>(), vec![147, 145, 143]);
+        assert_eq!(odd.total, 45);
+
+        // out of range offsets and limits are rejected with an explanation
+        let response = get_all_executions(page(None, Some("150")), HeaderMap::new(), State(storage.clone())).await.unwrap_err();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert!(body_json(response).await["error"].as_str().unwrap().contains("0 to 149"));
+        for (limit, offset) in [(Some("0"), None), (Some("501"), None), (Some("ten"), None), (None, Some("-1"))] {
+            let response = get_all_executions(page(limit, offset), HeaderMap::new(), State(storage.clone())).await.unwrap_err();
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{limit:?} {offset:?}");
+        }
+
+        // newest first, pages walk back in time
+        let filter = Query(ExecutionParams { order: Some("desc".to_string()), ..page(Some("3"), Some("3")).0 });
+        let newest = get_all_executions(filter, HeaderMap::new(), State(storage.clone())).await.unwrap();
+        assert_eq!(newest.data.iter().map(|e| e.pid).collect::<Vec<_>>(), vec![146, 145, 144]);
+        assert_eq!(newest.total, 150);
+
+        // an empty store has no out of range offsets
+        let empty = get_all_executions(page(None, Some("5")), HeaderMap::new(), State(ExecutionStorage::new(MAX_EVENTS))).await.unwrap();
+        assert!(empty.data.is_empty());
+        assert_eq!(empty.total, 0);
+    }
+
+    #[tokio::test]
+    async fn get_sorted() {
+        let storage = ExecutionStorage::new(MAX_EVENTS);
+        // inserted neither in pid nor in time order
+        storage.add_execution(mk_exec(30, 2_000, "/bin/b", &[])).await;
+        storage.add_execution(mk_exec(10, 3_000, "/bin/c", &[])).await;
+        storage.add_execution(mk_exec(20, 1_000, "/bin/a", &[])).await;
+        let pids = |executions: Vec<ProcessExecution>| executions.iter().map(|e| e.pid).collect::<Vec<_>>();
+
+        let cases = [
+            (SortField::Timestamp, SortOrder::Asc, vec![20, 30, 10]),
+            (SortField::Timestamp, SortOrder::Desc, vec![10, 30, 20]),
+            (SortField::Pid, SortOrder::Asc, vec![10, 20, 30]),
+            (SortField::Pid, SortOrder::Desc, vec![30, 20, 10]),
+            (SortField::Command, SortOrder::Asc, vec![20, 30, 10]),
+            (SortField::Command, SortOrder::Desc, vec![10, 30, 20]),
+        ];
+        for (sort_by, order, expected) in cases {
+            assert_eq!(pids(storage.get_executions_sorted(sort_by, order).await), expected, "{sort_by:?} {order:?}");
+        }
+        // the store keeps insertion order
+        assert_eq!(pids(storage.get_all_executions().await), vec![30, 10, 20]);
+    }
+
+    #[tokio::test]
+    async fn sort_handler() {
+        let storage = ExecutionStorage::new(MAX_EVENTS);
+        storage.add_execution(mk_exec(30, 2_000, "/bin/b", &[])).await;
+        storage.add_execution(mk_exec(10, 3_000, "/bin/c", &[])).await;
+        storage.add_execution(mk_exec(20, 1_000, "/bin/a", &[])).await;
+        let sort = |sort_by: Option<&str>, order: Option<&str>| {
+            Query(ExecutionParams { sort_by: sort_by.map(String::from), order: order.map(String::from), ..Default::default() })
+        };
+        let pids = |page: ExecutionsPage| page.data.iter().map(|e| e.pid).collect::<Vec<_>>();
+
+        // newest first once sorting is asked for
+        let newest = get_all_executions(sort(Some("timestamp"), None), HeaderMap::new(), State(storage.clone())).await.unwrap();
+        assert_eq!(pids(newest), vec![10, 30, 20]);
+        let by_pid = get_all_executions(sort(Some("pid"), Some("asc")), HeaderMap::new(), State(storage.clone())).await.unwrap();
+        assert_eq!(pids(by_pid), vec![10, 20, 30]);
+        // without sort_by, `order` goes by insertion, newest (the last inserted) first by default
+        let desc = get_all_executions(sort(None, Some("desc")), HeaderMap::new(), State(storage.clone())).await.unwrap();
+        assert_eq!(pids(desc), vec![20, 10, 30]);
+        let unsorted = get_all_executions(sort(None, None), HeaderMap::new(), State(storage.clone())).await.unwrap();
+        assert_eq!(pids(unsorted), vec![20, 10, 30]);
+        let asc = get_all_executions(sort(None, Some("asc")), HeaderMap::new(), State(storage.clone())).await.unwrap();
+        assert_eq!(pids(asc), vec![30, 10, 20]);
+
+        // sorts the filtered set before paginating
+        let filter = Query(ExecutionParams {
+            command_contains: Some("/bin/".to_string()),
+            sort_by: Some("command".to_string()),
+            limit: Some("2".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(pids(get_all_executions(filter, HeaderMap::new(), State(storage.clone())).await.unwrap()), vec![10, 30]);
+
+        for (sort_by, order) in [(Some("name"), None), (None, Some("up")), (Some("PID"), Some("asc"))] {
+            let response = get_all_executions(sort(sort_by, order), HeaderMap::new(), State(storage.clone())).await.unwrap_err();
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+            let error = body_json(response).await["error"].as_str().unwrap().to_string();
+            assert!(error.contains("expected"), "{error}");
+        }
+    }
+
+    #[tokio::test]
+    async fn exec_results() {
+        let storage = ExecutionStorage::new(MAX_EVENTS);
+        let (writer, handle) = storage.spawn_writer();
+        for pid in 1..=3 {
+            writer.send(StorageWrite::Execution(Box::new(mk_exec(pid, pid as u64, "/bin/cmd", &[])))).unwrap();
+        }
+        let result = |pid, timestamp, ret| {
+            let event = ExecResultEvent { pid, ret, timestamp, exe_ino: 0, exe_dev: 0 };
+            StorageWrite::Result(ExecResult::from_event(&event, Duration::zero()))
+        };
+        writer.send(result(1, 1, 0)).unwrap();
+        writer.send(result(2, 2, -2)).unwrap();
+        // pid 3 but a later exec, not the stored one
+        writer.send(result(3, 4, -13)).unwrap();
+        drop(writer);
+        handle.await.unwrap();
+        let results: Vec<_> = storage.get_all_executions().await.iter().map(|e| e.result).collect();
+        assert_eq!(results, vec![Some(0), Some(-2), None]);
+
+        let failed = |value: &str| Query(ExecutionParams { failed: Some(value.to_string()), ..Default::default() });
+        let pids = |page: ExecutionsPage| page.data.iter().map(|e| e.pid).collect::<Vec<_>>();
+        assert_eq!(pids(get_all_executions(failed("true"), HeaderMap::new(), State(storage.clone())).await.unwrap()), vec![2]);
+        // pending results count as not failed
+        assert_eq!(pids(get_all_executions(failed("false"), HeaderMap::new(), State(storage.clone())).await.unwrap()), vec![3, 1]);
+        let response = get_all_executions(failed("yes"), HeaderMap::new(), State(storage.clone())).await.unwrap_err();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn setuid_execs() {
+        let storage = ExecutionStorage::new(MAX_EVENTS);
+        let (writer, handle) = storage.spawn_writer();
+        for pid in 1..=3 {
+            let execution = ProcessExecution { uid: 1000, euid: 1000, ..mk_exec(pid, pid as u64, "/usr/bin/passwd", &[]) };
+            writer.send(StorageWrite::Execution(Box::new(execution))).unwrap();
+        }
+        let mut command = [0u8; 16];
+        command[..6].copy_from_slice(b"passwd");
+        let setuid = |pid, timestamp| SetuidRecord::from_event(&SetuidEvent { pid, before_uid: 1000, after_uid: 0, timestamp, command }, Duration::zero());
+        assert_eq!(setuid(2, 2).command, "passwd");
+        writer.send(StorageWrite::Setuid(setuid(2, 2))).unwrap();
+        // pid 3 but a later exec, not the stored one
+        writer.send(StorageWrite::Setuid(setuid(3, 4))).unwrap();
+        drop(writer);
+        handle.await.unwrap();
+
+        let all = storage.get_all_executions().await;
+        assert_eq!(all.iter().map(|e| (e.setuid_detected, e.after_uid)).collect::<Vec<_>>(), vec![(false, None), (true, Some(0)), (false, None)]);
+        let Json(setuid) = get_setuid_executions(State(storage.clone())).await;
+        assert_eq!(setuid.iter().map(|e| (e.pid, e.euid)).collect::<Vec<_>>(), vec![(2, 1000)]);
+    }
+
+    #[tokio::test]
+    async fn get_in_range() {
+        let storage = ExecutionStorage::new(MAX_EVENTS);
+        for secs in 1..=5u64 {
+            storage.add_execution(mk_exec(secs as u32, secs * 1_000_000_000, "/bin/cmd", &[])).await;
+        }
+        let at = |secs: i64| DateTime::<Utc>::from_timestamp(secs, 0).unwrap();
+        // since is inclusive, until exclusive
+        let range = |since, until| ExecutionFilter { since: Some(at(since)), until: Some(at(until)), ..Default::default() };
+        let hits = storage.query(&range(2, 4)).await;
+        assert_eq!(hits.iter().map(|e| e.pid).collect::<Vec<_>>(), vec![2, 3]);
+        assert!(storage.query(&range(10, 20)).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn time_range_handler() {
+        let storage = ExecutionStorage::new(MAX_EVENTS);
+        storage.add_execution(mk_exec(1, 1_000_000_000, "/bin/a", &[])).await; // 1970-01-01T00:00:01Z
+        storage.add_execution(mk_exec(2, 2_000_000_000, "/bin/b", &[])).await; // 1970-01-01T00:00:02Z
+        storage.add_execution(mk_exec(1, 3_000_000_000, "/bin/c", &[])).await; // 1970-01-01T00:00:03Z
+        let range = |since: Option<&str>, until: Option<&str>| TimeRange {
+            since: since.map(String::from),
+            until: until.map(String::from),
+            ..Default::default()
+        };
+        let filter = |range: TimeRange| Query(ExecutionParams { range, ..Default::default() });
+
+        let since = get_all_executions(filter(range(Some("1970-01-01T00:00:02Z"), None)), HeaderMap::new(), State(storage.clone())).await.unwrap().data;
+        assert_eq!(since.iter().map(|e| e.pid).collect::<Vec<_>>(), vec![1, 2]);
+        let until = get_all_executions(filter(range(None, Some("1970-01-01T00:00:02Z"))), HeaderMap::new(), State(storage.clone())).await.unwrap().data;
+        assert_eq!(until.iter().map(|e| e.pid).collect::<Vec<_>>(), vec![1]);
+
+        // combined with the pid path segment
+        let by_pid = |range: TimeRange| Query(PidQuery { range, ..Default::default() });
+        let Json(pid) = get_executions_by_pid(Path(1), by_pid(range(Some("1970-01-01T00:00:02Z"), None)), State(storage.clone())).await.unwrap();
+        assert_eq!(pid.len(), 1);
+        assert_eq!(pid[0].commandstr, "/bin/c");
+        let none = get_executions_by_pid(Path(2), by_pid(range(Some("1970-01-01T00:00:03Z"), None)), State(storage.clone())).await;
+        assert_eq!(none.unwrap_err().status(), StatusCode::NOT_FOUND);
+
+        let invalid = get_all_executions(filter(range(Some("yesterday"), None)), HeaderMap::new(), State(storage.clone())).await;
+        assert_eq!(invalid.unwrap_err().status(), StatusCode::BAD_REQUEST);
+        let invalid = get_executions_by_pid(Path(1), by_pid(range(None, Some("2024-13-01"))), State(storage.clone())).await;
+        assert_eq!(invalid.unwrap_err().status(), StatusCode::BAD_REQUEST);
+
+        // without a range nothing is hidden, not even an execution stamped after now
+        let future = mk_exec(3, 7_000_000_000 * 1_000_000_000, "/bin/d", &[]);
+        storage.add_execution(ProcessExecution { ppid: 9, ..future }).await;
+        let Json(pid) = get_executions_by_pid(Path(3), Query(PidQuery::default()), State(storage.clone())).await.unwrap();
+        assert_eq!(pid.len(), 1);
+        let Json(children) = get_executions_by_ppid(Path(9), Query(TimeRange::default()), State(storage.clone())).await.unwrap();
+        assert_eq!(children.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn inclusive_time_range_handler() {
+        let storage = ExecutionStorage::new(MAX_EVENTS);
+        for secs in 1..=5u64 {
+            storage.add_execution(mk_exec(secs as u32, secs * 1_000_000_000, "/bin/cmd", &[])).await;
+        }
+        let range = |from: Option<&str>, to: Option<&str>| {
+            Query(ExecutionParams { range: TimeRange { from: from.map(String::from), to: to.map(String::from), ..Default::default() }, ..Default::default() })
+        };
+        let pids = |page: ExecutionsPage| page.data.iter().map(|e| e.pid).collect::<Vec<_>>();
+
+        // both ends are included
+        let hits = get_all_executions(range(Some("1970-01-01T00:00:02Z"), Some("1970-01-01T00:00:04Z")), HeaderMap::new(), State(storage.clone())).await;
+        assert_eq!(pids(hits.unwrap()), vec![4, 3, 2]);
+        let from = get_all_executions(range(Some("1970-01-01T00:00:04Z"), None), HeaderMap::new(), State(storage.clone())).await;
+        assert_eq!(pids(from.unwrap()), vec![5, 4]);
+        let to = get_all_executions(range(None, Some("1970-01-01T00:00:01Z")), HeaderMap::new(), State(storage.clone())).await;
+        assert_eq!(pids(to.unwrap()), vec![1]);
+
+        let invalid = get_all_executions(range(Some("14:00"), None), HeaderMap::new(), State(storage.clone())).await.unwrap_err();
+        assert_eq!(invalid.status(), StatusCode::BAD_REQUEST);
+        assert!(body_json(invalid).await["error"].as_str().unwrap().contains("`from`"));
+        let mixed = Query(ExecutionParams {
+            range: TimeRange { since: Some("1970-01-01T00:00:01Z".to_string()), from: Some("1970-01-01T00:00:01Z".to_string()), ..Default::default() },
+            ..Default::default()
+        });
+        assert_eq!(get_all_executions(mixed, HeaderMap::new(), State(storage.clone())).await.unwrap_err().status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn search_handler() {
+        let storage = ExecutionStorage::new(MAX_EVENTS);
+        storage.add_execution(mk_exec(1, 1, "/usr/bin/curl", &["https://example.com"])).await;
+        storage.add_execution(mk_exec(2, 2, "/usr/bin/wget", &[])).await;
+        storage.add_execution(mk_exec(3, 3, "/usr/bin/python3", &["-m", "CURLtool"])).await;
+        let search = |command: Option<&str>| Query(SearchQuery { command: command.map(String::from) });
+
+        // matches commandstr or full_command, ignoring case
+        let Json(curl) = get_executions_filtered(search(Some("Curl")), State(storage.clone())).await;
+        assert_eq!(curl.iter().map(|e| e.pid).collect::<Vec<_>>(), vec![1, 3]);
+
+        let Json(none) = get_executions_filtered(search(Some("ssh")), State(storage.clone())).await;
+        assert!(none.is_empty());
+
+        // empty or missing parameter returns everything
+        let Json(all) = get_executions_filtered(search(Some("")), State(storage.clone())).await;
+        assert_eq!(all.len(), 3);
+        let Json(all) = get_executions_filtered(search(None), State(storage.clone())).await;
+        assert_eq!(all.len(), 3);
+    }
 }
 
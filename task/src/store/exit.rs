@@ -0,0 +1,97 @@
+use std::collections::VecDeque;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::wall_clock;
+use crate::ExitEvent;
+
+// How a process ended, served on /executions/:pid/exit
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ExitRecord {
+    pub pid: u32,
+    /// Status passed to exit(), 0 when killed by a signal
+    pub exit_code: i32,
+    /// Signal that terminated the process, null if it exited on its own
+    pub signal: Option<i32>,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl ExitRecord {
+    pub fn from_event(event: &ExitEvent, boot_offset: Duration) -> Self {
+        // decoded like WEXITSTATUS / WTERMSIG, 0x80 is the core dump flag
+        let signal = event.exit_code & 0x7f;
+        ExitRecord {
+            pid: event.pid,
+            exit_code: (event.exit_code >> 8) & 0xff,
+            signal: (signal != 0).then_some(signal),
+            timestamp: wall_clock(boot_offset, event.timestamp),
+        }
+    }
+}
+
+// The most recent exits (FIFO), kept in memory only. Exits are cheap to lose, a process that
+// exited long ago simply reports no exit.
+pub struct ExitLog {
+    exits: VecDeque<ExitRecord>,
+    max: usize,
+}
+
+impl ExitLog {
+    pub fn new(max: usize) -> Self {
+        Self { exits: VecDeque::with_capacity(max), max }
+    }
+
+    pub fn push(&mut self, exit: ExitRecord) {
+        if self.exits.len() >= self.max {
+            self.exits.pop_front();
+        }
+        self.exits.push_back(exit);
+    }
+
+    pub fn clear(&mut self) {
+        self.exits.clear();
+    }
+
+    // Latest exit of `pid`, pids are reused so earlier ones belong to other processes
+    pub fn latest(&self, pid: u32) -> Option<&ExitRecord> {
+        self.exits.iter().rev().find(|e| e.pid == pid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exit(pid: u32, exit_code: i32, secs: u64) -> ExitRecord {
+        ExitRecord::from_event(&ExitEvent { pid, exit_code, timestamp: secs * 1_000_000_000 }, Duration::zero())
+    }
+
+    #[test]
+    fn decodes_wait_status() {
+        let exited = exit(1, 3 << 8, 2);
+        assert_eq!((exited.exit_code, exited.signal), (3, None));
+        assert_eq!(exited.timestamp.timestamp(), 2);
+        // SIGKILL, and SIGSEGV with a core dump
+        assert_eq!((exit(1, 9, 0).exit_code, exit(1, 9, 0).signal), (0, Some(9)));
+        assert_eq!(exit(1, 0x80 | 11, 0).signal, Some(11));
+    }
+
+    #[test]
+    fn latest_exit_per_pid() {
+        let mut log = ExitLog::new(3);
+        log.push(exit(1, 1 << 8, 1));
+        log.push(exit(2, 0, 2));
+        // pid 1 reused
+        log.push(exit(1, 2 << 8, 3));
+        assert_eq!(log.latest(1).unwrap().exit_code, 2);
+        assert!(log.latest(3).is_none());
+
+        // the oldest exit makes room
+        log.push(exit(3, 0, 4));
+        log.push(exit(4, 0, 5));
+        assert!(log.latest(2).is_none());
+        assert_eq!(log.latest(4).unwrap().timestamp.timestamp(), 5);
+    }
+}
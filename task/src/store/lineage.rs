@@ -0,0 +1,93 @@
+use std::collections::{HashMap, VecDeque};
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::{wall_clock, ProcessExecution};
+use crate::ForkEvent;
+
+// Forks remembered for /executions/:pid/ancestry, the oldest are forgotten first
+pub const MAX_FORKS: usize = 16_384;
+
+// A process (or thread) `child_pid` created by `parent_pid`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ForkRecord {
+    pub parent_pid: u32,
+    pub child_pid: u32,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl ForkRecord {
+    pub fn from_event(event: &ForkEvent, boot_offset: Duration) -> Self {
+        ForkRecord { parent_pid: event.parent_pid, child_pid: event.child_pid, timestamp: wall_clock(boot_offset, event.timestamp) }
+    }
+}
+
+// One step up from a process, served on /executions/:pid/ancestry. `executions` are the ones the
+// ancestor ran before the step below it was created, empty if it forked without exec'ing
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Ancestor {
+    pub pid: u32,
+    pub executions: Vec<ProcessExecution>,
+}
+
+// Parent of every recently forked pid, bounded to `max` forks (FIFO). Pids are reused, the latest
+// fork of a pid replaces earlier ones.
+pub struct Lineage {
+    parents: HashMap<u32, ForkRecord>,
+    order: VecDeque<ForkRecord>,
+    max: usize,
+}
+
+impl Lineage {
+    pub fn new(max: usize) -> Self {
+        Self { parents: HashMap::new(), order: VecDeque::with_capacity(max), max }
+    }
+
+    pub fn push(&mut self, fork: ForkRecord) {
+        let oldest = if self.order.len() >= self.max { self.order.pop_front() } else { None };
+        // unless the pid was reused by a later fork
+        if let Some(oldest) = oldest.filter(|oldest| self.parents.get(&oldest.child_pid) == Some(oldest)) {
+            self.parents.remove(&oldest.child_pid);
+        }
+        self.parents.insert(fork.child_pid, fork);
+        self.order.push_back(fork);
+    }
+
+    pub fn clear(&mut self) {
+        self.parents.clear();
+        self.order.clear();
+    }
+
+    pub fn parent(&self, pid: u32) -> Option<&ForkRecord> {
+        self.parents.get(&pid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fork(parent_pid: u32, child_pid: u32, secs: i64) -> ForkRecord {
+        ForkRecord { parent_pid, child_pid, timestamp: DateTime::<Utc>::from_timestamp(secs, 0).unwrap() }
+    }
+
+    #[test]
+    fn bounded_and_reused() {
+        let mut lineage = Lineage::new(3);
+        lineage.push(fork(1, 10, 1));
+        lineage.push(fork(10, 11, 2));
+        // pid 10 reused
+        lineage.push(fork(5, 10, 3));
+        assert_eq!(lineage.parent(10).unwrap().parent_pid, 5);
+
+        // evicting the first fork of pid 10 keeps the later one
+        lineage.push(fork(11, 12, 4));
+        assert_eq!(lineage.parent(10).unwrap().parent_pid, 5);
+        lineage.push(fork(12, 13, 5));
+        assert!(lineage.parent(11).is_none());
+        assert_eq!(lineage.parent(13).unwrap().parent_pid, 12);
+        assert_eq!(lineage.parent(12).unwrap().parent_pid, 11);
+    }
+}
@@ -0,0 +1,771 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use sqlx::{
+    QueryBuilder, Row,
+    sqlite::{Sqlite, SqliteConnectOptions, SqlitePool, SqlitePoolOptions, SqliteRow},
+};
+
+use super::{device_name, ld_preload, resolve_command, ExecResult, ExecutionFilter, ExecutionStats, ExitRecord, ProcessExecution, SetuidRecord, SortOrder};
+
+// Everything `from_row` reads back, in the order add_execution binds them
+const COLUMNS: &str = "pid, tid, ppid, uid, gid, euid, egid, after_uid, loginuid, cgroup_id, container_id, cgroup_path, \
+    pidns, ns_pid, mntns, sid, has_tty, timestamp, process_start, dirfd, syscall, source, result, \
+    exited_at, duration_ms, commandstr, argstr, full_command, command_truncated, args_truncated, cwd, \
+    script, exe_ino, exe_dev, setuid, setgid, env, caller_comm, parent_command, argc, argv_truncated, \
+    args_total_len, args_len_overflow, argv_partial, count";
+
+// SQLite-backed storage, survives daemon restarts
+#[derive(Clone)]
+pub struct SqliteExecutionStorage {
+    pool: SqlitePool,
+    max_age: Duration,
+}
+
+impl SqliteExecutionStorage {
+    pub async fn open(path: impl AsRef<Path>, max_age_days: u32) -> anyhow::Result<Self> {
+        let options = SqliteConnectOptions::new()
+            .filename(path)
+            .create_if_missing(true);
+        let pool = SqlitePoolOptions::new().connect_with(options).await?;
+        sqlx::migrate!("./migrations").run(&pool).await?;
+        Ok(Self {
+            pool,
+            max_age: Duration::days(max_age_days as i64),
+        })
+    }
+
+    // Returns the number of rows pruned for being older than max_age
+    pub async fn add_execution(&self, execution: ProcessExecution) -> Result<u64, sqlx::Error> {
+        sqlx::query(&format!(
+            "INSERT INTO executions ({COLUMNS})
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
+        ))
+        .bind(execution.pid)
+        .bind(execution.tid)
+        .bind(execution.ppid)
+        .bind(execution.uid)
+        .bind(execution.gid)
+        .bind(execution.euid)
+        .bind(execution.egid)
+        .bind(execution.after_uid)
+        .bind(execution.loginuid)
+        // SQLite integers are signed, the bit pattern round-trips through i64
+        .bind(execution.cgroup_id as i64)
+        .bind(&execution.container_id)
+        .bind(&execution.cgroup_path)
+        .bind(execution.pidns)
+        .bind(execution.ns_pid)
+        .bind(execution.mntns as i64)
+        .bind(execution.sid)
+        .bind(execution.has_tty)
+        .bind(format_timestamp(&execution.timestamp))
+        .bind(execution.process_start.as_ref().map(format_timestamp))
+        .bind(execution.dirfd)
+        .bind(execution.syscall.as_str())
+        .bind(execution.source.as_str())
+        .bind(execution.result)
+        .bind(execution.exited_at.as_ref().map(format_timestamp))
+        .bind(execution.duration_ms)
+        .bind(&execution.commandstr)
+        .bind(&execution.argstr)
+        .bind(&execution.full_command)
+        .bind(execution.command_truncated)
+        .bind(execution.args_truncated)
+        .bind(&execution.cwd)
+        .bind(&execution.script)
+        .bind(execution.exe_ino as i64)
+        .bind(execution.exe_dev as i64)
+        .bind(execution.setuid)
+        .bind(execution.setgid)
+        // a JSON object, the variables are only ever read back as a whole
+        .bind(serde_json::to_string(&execution.env).unwrap_or_default())
+        .bind(&execution.caller_comm)
+        .bind(&execution.parent_command)
+        .bind(execution.argc)
+        .bind(execution.argv_truncated)
+        .bind(execution.args_total_len)
+        .bind(execution.args_len_overflow)
+        .bind(execution.argv_partial)
+        .bind(execution.count)
+        .execute(&self.pool)
+        .await?;
+
+        // Prune on insertion so the database file doesn't grow unbounded
+        self.prune_before(Utc::now() - self.max_age).await
+    }
+
+    // Matched on pid and timestamp, which identify an exec
+    pub async fn set_result(&self, result: &ExecResult) -> Result<(), sqlx::Error> {
+        // the file is only known once the exec succeeded, a result without one keeps the zeros
+        sqlx::query(
+            "UPDATE executions SET result = ?, exe_ino = COALESCE(NULLIF(?, 0), exe_ino), exe_dev = COALESCE(NULLIF(?, 0), exe_dev)
+             WHERE pid = ? AND timestamp = ?",
+        )
+            .bind(result.result)
+            .bind(result.exe_ino as i64)
+            .bind(result.exe_dev as i64)
+            .bind(result.pid)
+            .bind(format_timestamp(&result.timestamp))
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    // Matched like set_result, setuid_detected is derived from after_uid
+    pub async fn set_setuid(&self, setuid: &SetuidRecord) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE executions SET after_uid = ? WHERE pid = ? AND timestamp = ?")
+            .bind(setuid.after_uid)
+            .bind(setuid.pid)
+            .bind(format_timestamp(&setuid.timestamp))
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    // Marks the pid's latest execution as exited, false if there is none still running
+    pub async fn set_exit(&self, exit: &ExitRecord) -> Result<bool, sqlx::Error> {
+        let Some(row) = sqlx::query("SELECT id, timestamp, exited_at FROM executions WHERE pid = ? ORDER BY timestamp DESC LIMIT 1")
+            .bind(exit.pid)
+            .fetch_optional(&self.pool)
+            .await?
+        else {
+            return Ok(false);
+        };
+        let timestamp = parse_timestamp(row.try_get("timestamp")?)?;
+        if row.try_get::<Option<&str>, _>("exited_at")?.is_some() || timestamp > exit.timestamp {
+            return Ok(false);
+        }
+        sqlx::query("UPDATE executions SET exited_at = ?, duration_ms = ? WHERE id = ?")
+            .bind(format_timestamp(&exit.timestamp))
+            .bind((exit.timestamp - timestamp).num_milliseconds())
+            .bind(row.try_get::<i64, _>("id")?)
+            .execute(&self.pool)
+            .await?;
+        Ok(true)
+    }
+
+    // Bumps the count of the pid's latest execution if `execution` repeats it, see
+    // ExecutionStorage::count_repeat
+    pub async fn count_repeat(&self, execution: &ProcessExecution, window: Duration) -> Result<bool, sqlx::Error> {
+        let Some(row) = sqlx::query("SELECT id, timestamp, full_command FROM executions WHERE pid = ? ORDER BY timestamp DESC LIMIT 1")
+            .bind(execution.pid)
+            .fetch_optional(&self.pool)
+            .await?
+        else {
+            return Ok(false);
+        };
+        let timestamp = parse_timestamp(row.try_get("timestamp")?)?;
+        let full_command: &str = row.try_get("full_command")?;
+        if full_command != execution.full_command || execution.timestamp < timestamp || execution.timestamp - timestamp > window {
+            return Ok(false);
+        }
+        sqlx::query("UPDATE executions SET count = count + 1 WHERE id = ?")
+            .bind(row.try_get::<i64, _>("id")?)
+            .execute(&self.pool)
+            .await?;
+        Ok(true)
+    }
+
+    // Deletes executions older than cutoff, returns how many
+    pub async fn prune_before(&self, cutoff: DateTime<Utc>) -> Result<u64, sqlx::Error> {
+        let pruned = sqlx::query("DELETE FROM executions WHERE timestamp < ?")
+            .bind(format_timestamp(&cutoff))
+            .execute(&self.pool)
+            .await?;
+        Ok(pruned.rows_affected())
+    }
+
+    // Deletes every execution, returns how many
+    pub async fn clear(&self) -> Result<u64, sqlx::Error> {
+        let cleared = sqlx::query("DELETE FROM executions").execute(&self.pool).await?;
+        Ok(cleared.rows_affected())
+    }
+
+    pub async fn count(&self) -> Result<usize, sqlx::Error> {
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM executions").fetch_one(&self.pool).await?;
+        Ok(count as usize)
+    }
+
+    // Most recent `limit` rows, oldest first (same ordering as the in-memory store)
+    pub async fn get_all_executions(&self, limit: usize) -> Result<Vec<ProcessExecution>, sqlx::Error> {
+        let rows = sqlx::query(&format!(
+            "SELECT * FROM (
+                 SELECT id, {COLUMNS} FROM executions ORDER BY id DESC LIMIT ?
+             ) ORDER BY id ASC"
+        ))
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(from_row).collect()
+    }
+
+    // Page of the rows get_all_executions(max) would return, in insertion order or reversed for
+    // Desc, and how many of those there are
+    pub async fn get_executions_page(&self, max: usize, offset: usize, limit: usize, order: SortOrder) -> Result<(Vec<ProcessExecution>, usize), sqlx::Error> {
+        let total = self.count().await?.min(max);
+        let direction = match order {
+            SortOrder::Asc => "ASC",
+            SortOrder::Desc => "DESC",
+        };
+        let rows = sqlx::query(&format!(
+            "SELECT * FROM (
+                 SELECT id, {COLUMNS} FROM executions ORDER BY id DESC LIMIT ?
+             ) ORDER BY id {direction} LIMIT ? OFFSET ?"
+        ))
+        .bind(max as i64)
+        .bind(limit as i64)
+        .bind(offset as i64)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok((rows.iter().map(from_row).collect::<Result<_, _>>()?, total))
+    }
+
+    // ExecutionFilter::matches as a WHERE clause
+    pub async fn query(&self, filter: &ExecutionFilter) -> Result<Vec<ProcessExecution>, sqlx::Error> {
+        let mut query = QueryBuilder::<Sqlite>::new(format!("SELECT {COLUMNS} FROM executions WHERE 1"));
+        if let Some(pid) = filter.pid {
+            query.push(" AND pid = ").push_bind(pid);
+        }
+        if let Some(ppid) = filter.ppid {
+            query.push(" AND ppid = ").push_bind(ppid);
+        }
+        if let Some(command) = &filter.command {
+            // or the script by file name, see ProcessExecution::runs
+            query.push(" AND (commandstr = ").push_bind(command);
+            query.push(" OR script = ").push_bind(command);
+            query.push(" OR substr(script, -").push_bind(command.chars().count() as i64 + 1);
+            query.push(") = ").push_bind(format!("/{command}")).push(")");
+        }
+        if let Some(needle) = &filter.command_contains {
+            query.push(" AND instr(commandstr, ").push_bind(needle).push(") > 0");
+        }
+        if let Some(uid) = filter.uid {
+            query.push(" AND uid = ").push_bind(uid);
+        }
+        if let Some(cgroup_id) = filter.cgroup_id {
+            query.push(" AND cgroup_id = ").push_bind(cgroup_id as i64);
+        }
+        if let Some(container_id) = &filter.container_id {
+            query.push(" AND container_id = ").push_bind(container_id);
+        }
+        if let Some(needle) = &filter.cgroup_contains {
+            query.push(" AND instr(cgroup_path, ").push_bind(needle).push(") > 0");
+        }
+        match filter.failed {
+            Some(true) => query.push(" AND result < 0"),
+            Some(false) => query.push(" AND (result IS NULL OR result >= 0)"),
+            None => &mut query,
+        };
+        match filter.setuid {
+            Some(true) => query.push(" AND setuid = 1"),
+            Some(false) => query.push(" AND (setuid IS NULL OR setuid = 0)"),
+            None => &mut query,
+        };
+        if let Some(since) = filter.since {
+            query.push(" AND timestamp >= ").push_bind(format_timestamp(&since));
+        }
+        if let Some(until) = filter.until {
+            query.push(" AND timestamp < ").push_bind(format_timestamp(&until));
+        }
+        query.push(" ORDER BY id ASC");
+        let rows = query.build().fetch_all(&self.pool).await?;
+        rows.iter().map(from_row).collect()
+    }
+
+    pub async fn stats(&self) -> Result<ExecutionStats, sqlx::Error> {
+        let row = sqlx::query(
+            "SELECT COUNT(*) AS total, COUNT(DISTINCT pid) AS unique_pids,
+                    COUNT(DISTINCT commandstr) AS unique_commands,
+                    MIN(timestamp) AS oldest, MAX(timestamp) AS newest
+             FROM executions",
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        let timestamp = |column: &str| -> Result<Option<DateTime<Utc>>, sqlx::Error> {
+            row.try_get::<Option<String>, _>(column)?.as_deref().map(parse_timestamp).transpose()
+        };
+        Ok(ExecutionStats {
+            total: row.try_get::<i64, _>("total")? as usize,
+            unique_pids: row.try_get::<i64, _>("unique_pids")? as usize,
+            unique_commands: row.try_get::<i64, _>("unique_commands")? as usize,
+            oldest: timestamp("oldest")?,
+            newest: timestamp("newest")?,
+            // not a property of the stored rows, ExecutionStorage::stats fills it in
+            events_lost: 0,
+            kernel_threads_filtered: 0,
+            uid_excluded: 0,
+        })
+    }
+
+    pub async fn get_setuid_executions(&self) -> Result<Vec<ProcessExecution>, sqlx::Error> {
+        let rows = sqlx::query(&format!(
+            "SELECT {COLUMNS} FROM executions WHERE after_uid IS NOT NULL ORDER BY id ASC"
+        ))
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(from_row).collect()
+    }
+
+    // SQLite's lower() only folds ASCII, which is enough for command paths
+    pub async fn search_executions(&self, needle: &str) -> Result<Vec<ProcessExecution>, sqlx::Error> {
+        let rows = sqlx::query(&format!(
+            "SELECT {COLUMNS} FROM executions
+             WHERE instr(lower(commandstr), lower(?1)) > 0 OR instr(lower(full_command), lower(?1)) > 0
+             ORDER BY id ASC"
+        ))
+        .bind(needle)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(from_row).collect()
+    }
+}
+
+fn format_timestamp(timestamp: &DateTime<Utc>) -> String {
+    // Fixed-width nanoseconds keep lexicographic order == chronological order
+    timestamp.to_rfc3339_opts(SecondsFormat::Nanos, true)
+}
+
+fn parse_timestamp(timestamp: &str) -> Result<DateTime<Utc>, sqlx::Error> {
+    Ok(DateTime::parse_from_rfc3339(timestamp)
+        .map_err(|e| sqlx::Error::Decode(Box::new(e)))?
+        .with_timezone(&Utc))
+}
+
+fn from_row(row: &SqliteRow) -> Result<ProcessExecution, sqlx::Error> {
+    let timestamp = parse_timestamp(&row.try_get::<String, _>("timestamp")?)?;
+    let uid: u32 = row.try_get("uid")?;
+    let euid: u32 = row.try_get("euid")?;
+    let after_uid: Option<u32> = row.try_get("after_uid")?;
+    let dirfd: i32 = row.try_get("dirfd")?;
+    let exe_dev = row.try_get::<i64, _>("exe_dev")? as u64;
+    let commandstr: String = row.try_get("commandstr")?;
+    let cwd: String = row.try_get("cwd")?;
+    let env: BTreeMap<String, String> = serde_json::from_str(row.try_get("env")?).unwrap_or_default();
+    Ok(ProcessExecution {
+        pid: row.try_get("pid")?,
+        tid: row.try_get("tid")?,
+        ppid: row.try_get("ppid")?,
+        uid,
+        gid: row.try_get("gid")?,
+        euid,
+        egid: row.try_get("egid")?,
+        // derived, not stored
+        privilege_elevated: euid != uid,
+        setuid_detected: after_uid.is_some(),
+        after_uid,
+        loginuid: row.try_get("loginuid")?,
+        cgroup_id: row.try_get::<i64, _>("cgroup_id")? as u64,
+        container_id: row.try_get("container_id")?,
+        cgroup_path: row.try_get("cgroup_path")?,
+        pidns: row.try_get("pidns")?,
+        ns_pid: row.try_get("ns_pid")?,
+        mntns: row.try_get::<i64, _>("mntns")? as u64,
+        sid: row.try_get("sid")?,
+        has_tty: row.try_get("has_tty")?,
+        timestamp,
+        process_start: row.try_get::<Option<&str>, _>("process_start")?.map(parse_timestamp).transpose()?,
+        dirfd,
+        syscall: row.try_get::<String, _>("syscall")?.parse().unwrap_or_default(),
+        source: row.try_get::<String, _>("source")?.parse().unwrap_or_default(),
+        result: row.try_get("result")?,
+        exited_at: row.try_get::<Option<&str>, _>("exited_at")?.map(parse_timestamp).transpose()?,
+        duration_ms: row.try_get("duration_ms")?,
+        // derived, not stored
+        resolved_command: resolve_command(&commandstr, &cwd, dirfd),
+        script: row.try_get("script")?,
+        commandstr,
+        argstr: row.try_get("argstr")?,
+        full_command: row.try_get("full_command")?,
+        command_truncated: row.try_get("command_truncated")?,
+        args_truncated: row.try_get("args_truncated")?,
+        cwd,
+        exe_ino: row.try_get::<i64, _>("exe_ino")? as u64,
+        exe_dev,
+        // derived, not stored
+        exe_device: device_name(exe_dev),
+        setuid: row.try_get("setuid")?,
+        setgid: row.try_get("setgid")?,
+        // derived, not stored
+        ld_preload: ld_preload(&env),
+        env,
+        caller_comm: row.try_get("caller_comm")?,
+        parent_command: row.try_get("parent_command")?,
+        argc: row.try_get("argc")?,
+        argv_truncated: row.try_get("argv_truncated")?,
+        args_total_len: row.try_get("args_total_len")?,
+        args_len_overflow: row.try_get("args_len_overflow")?,
+        argv_partial: row.try_get("argv_partial")?,
+        count: row.try_get("count")?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::{ExecSource, Syscall};
+
+    fn mk_exec(pid: u32, timestamp: DateTime<Utc>, cmd: &str) -> ProcessExecution {
+        ProcessExecution {
+            pid,
+            tid: pid,
+            ppid: 1,
+            uid: 1000,
+            gid: 1000,
+            euid: 0,
+            egid: 1000,
+            privilege_elevated: true,
+            setuid_detected: false,
+            after_uid: None,
+            loginuid: Some(1000),
+            cgroup_id: u64::MAX,
+            container_id: Some("3f4e1b9c2a7d6e5f8091a2b3c4d5e6f708192a3b4c5d6e7f8091a2b3c4d5e6f7".to_string()),
+            cgroup_path: Some("/system.slice/docker-3f4e1b9c2a7d6e5f8091a2b3c4d5e6f708192a3b4c5d6e7f8091a2b3c4d5e6f7.scope".to_string()),
+            pidns: 4026531836,
+            ns_pid: pid,
+            mntns: 4026531841,
+            sid: 31100,
+            has_tty: true,
+            timestamp,
+            process_start: Some(timestamp - Duration::seconds(5)),
+            dirfd: -100,
+            syscall: Syscall::Execveat,
+            source: ExecSource::SchedExec,
+            result: Some(0),
+            exited_at: None,
+            duration_ms: None,
+            commandstr: cmd.to_string(),
+            argstr: String::new(),
+            full_command: cmd.to_string(),
+            command_truncated: false,
+            args_truncated: false,
+            cwd: "/home/user".to_string(),
+            resolved_command: cmd.to_string(),
+            script: None,
+            exe_ino: 1_311_014,
+            exe_dev: 2049,
+            exe_device: "8:1".to_string(),
+            setuid: Some(true),
+            setgid: None,
+            env: BTreeMap::from([("LD_PRELOAD".to_string(), "/tmp/hook.so".to_string())]),
+            ld_preload: Some("/tmp/hook.so".to_string()),
+            caller_comm: "bash".to_string(),
+            parent_command: "sshd".to_string(),
+            argc: 0,
+            argv_truncated: false,
+            args_total_len: 131_072,
+            args_len_overflow: true,
+            argv_partial: false,
+            count: 1,
+        }
+    }
+
+    #[tokio::test]
+    async fn survives_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("executions.db");
+
+        let storage = SqliteExecutionStorage::open(&path, 7).await.unwrap();
+        let now = Utc::now();
+        for i in 0..10 {
+            storage.add_execution(mk_exec(i, now, "/bin/cmd")).await.unwrap();
+        }
+        storage.pool.close().await;
+        drop(storage);
+
+        // reopen the same file, everything written before should still be there
+        let storage = SqliteExecutionStorage::open(&path, 7).await.unwrap();
+        let all = storage.get_all_executions(500).await.unwrap();
+        assert_eq!(all.len(), 10);
+        for (i, e) in all.iter().enumerate() {
+            assert_eq!(e.pid, i as u32);
+            assert_eq!(e.ppid, 1);
+            assert_eq!(e.uid, 1000);
+            assert_eq!(e.gid, 1000);
+            assert_eq!(e.euid, 0);
+            assert!(e.privilege_elevated);
+            assert_eq!(e.cgroup_id, u64::MAX);
+            assert_eq!(e.container_id.as_deref(), Some("3f4e1b9c2a7d6e5f8091a2b3c4d5e6f708192a3b4c5d6e7f8091a2b3c4d5e6f7"));
+            assert!(e.cgroup_path.as_deref().is_some_and(|path| path.starts_with("/system.slice/docker-")));
+            assert_eq!(e.pidns, 4026531836);
+            assert_eq!(e.mntns, 4026531841);
+            assert_eq!(e.sid, 31100);
+            assert!(e.has_tty);
+            assert_eq!((e.exe_ino, e.exe_device.as_str()), (1_311_014, "8:1"));
+            assert_eq!((e.setuid, e.setgid), (Some(true), None));
+            assert_eq!(e.loginuid, Some(1000));
+            assert_eq!(e.dirfd, -100);
+            assert_eq!(e.syscall, Syscall::Execveat);
+            assert_eq!(e.source, ExecSource::SchedExec);
+            assert_eq!(e.result, Some(0));
+            assert_eq!(e.cwd, "/home/user");
+            assert_eq!(e.env["LD_PRELOAD"], "/tmp/hook.so");
+            assert_eq!(e.ld_preload.as_deref(), Some("/tmp/hook.so"));
+            assert_eq!(e.caller_comm, "bash");
+            assert_eq!(e.parent_command, "sshd");
+            assert_eq!(e.timestamp, now);
+        }
+    }
+
+    #[tokio::test]
+    async fn get_by_pid() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = SqliteExecutionStorage::open(dir.path().join("executions.db"), 7).await.unwrap();
+        let now = Utc::now();
+        storage.add_execution(mk_exec(1, now, "/bin/a")).await.unwrap();
+        storage.add_execution(mk_exec(2, now, "/bin/b")).await.unwrap();
+        storage.add_execution(mk_exec(1, now, "/bin/c")).await.unwrap();
+        let p1 = storage.query(&ExecutionFilter::for_pid(1)).await.unwrap();
+        assert_eq!(p1.len(), 2);
+        assert_eq!(p1[1].commandstr, "/bin/c");
+    }
+
+    #[tokio::test]
+    async fn thread_exec_by_tgid() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = SqliteExecutionStorage::open(dir.path().join("executions.db"), 7).await.unwrap();
+        // execve from a thread other than the group leader
+        storage.add_execution(ProcessExecution { tid: 4243, ..mk_exec(4242, Utc::now(), "/bin/true") }).await.unwrap();
+        let by_tgid = storage.query(&ExecutionFilter::for_pid(4242)).await.unwrap();
+        assert_eq!(by_tgid.iter().map(|e| (e.pid, e.tid)).collect::<Vec<_>>(), vec![(4242, 4243)]);
+        assert!(storage.query(&ExecutionFilter::for_pid(4243)).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn set_result() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = SqliteExecutionStorage::open(dir.path().join("executions.db"), 7).await.unwrap();
+        let now = Utc::now();
+        let earlier = now - Duration::seconds(1);
+        storage.add_execution(ProcessExecution { result: None, ..mk_exec(1, earlier, "/bin/a") }).await.unwrap();
+        storage.add_execution(ProcessExecution { result: None, ..mk_exec(1, now, "/bin/b") }).await.unwrap();
+        storage.set_result(&ExecResult { pid: 1, timestamp: now, result: -2, exe_ino: 0, exe_dev: 0 }).await.unwrap();
+        let results: Vec<_> = storage.query(&ExecutionFilter::for_pid(1)).await.unwrap().iter().map(|e| e.result).collect();
+        assert_eq!(results, vec![None, Some(-2)]);
+
+        // a successful sys_enter exec learns its file from the result
+        let pending = ProcessExecution { result: None, exe_ino: 0, exe_dev: 0, exe_device: "0:0".to_string(), ..mk_exec(2, now, "/bin/c") };
+        storage.add_execution(pending).await.unwrap();
+        storage.set_result(&ExecResult { pid: 2, timestamp: now, result: 0, exe_ino: 42, exe_dev: 2050 }).await.unwrap();
+        let execution = &storage.query(&ExecutionFilter::for_pid(2)).await.unwrap()[0];
+        assert_eq!((execution.exe_ino, execution.exe_dev, execution.exe_device.as_str()), (42, 2050, "8:2"));
+    }
+
+    #[tokio::test]
+    async fn set_setuid() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = SqliteExecutionStorage::open(dir.path().join("executions.db"), 7).await.unwrap();
+        let now = Utc::now();
+        storage.add_execution(mk_exec(1, now, "/usr/bin/passwd")).await.unwrap();
+        storage.add_execution(mk_exec(2, now, "/bin/ls")).await.unwrap();
+        storage.set_setuid(&SetuidRecord { pid: 1, timestamp: now, before_uid: 1000, after_uid: 0, command: "passwd".to_string() }).await.unwrap();
+
+        let setuid = storage.get_setuid_executions().await.unwrap();
+        assert_eq!(setuid.len(), 1);
+        assert_eq!((setuid[0].pid, setuid[0].setuid_detected, setuid[0].after_uid), (1, true, Some(0)));
+        assert!(!storage.query(&ExecutionFilter::for_pid(2)).await.unwrap()[0].setuid_detected);
+    }
+
+    #[tokio::test]
+    async fn set_exit() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = SqliteExecutionStorage::open(dir.path().join("executions.db"), 7).await.unwrap();
+        let now = Utc::now();
+        let exit = |pid, timestamp| ExitRecord { pid, exit_code: 0, signal: None, timestamp };
+        // no execution for the pid
+        assert!(!storage.set_exit(&exit(1, now)).await.unwrap());
+        storage.add_execution(mk_exec(1, now - Duration::milliseconds(1500), "/bin/a")).await.unwrap();
+        assert!(storage.set_exit(&exit(1, now)).await.unwrap());
+        // only exits once
+        assert!(!storage.set_exit(&exit(1, now + Duration::seconds(1))).await.unwrap());
+        let e = &storage.query(&ExecutionFilter::for_pid(1)).await.unwrap()[0];
+        assert_eq!((e.exited_at, e.duration_ms), (Some(now), Some(1500)));
+    }
+
+    #[tokio::test]
+    async fn count_repeat() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = SqliteExecutionStorage::open(dir.path().join("executions.db"), 7).await.unwrap();
+        let now = Utc::now();
+        let window = Duration::milliseconds(100);
+        assert!(!storage.count_repeat(&mk_exec(1, now, "/bin/a"), window).await.unwrap());
+        storage.add_execution(mk_exec(1, now, "/bin/a")).await.unwrap();
+        assert!(storage.count_repeat(&mk_exec(1, now + Duration::milliseconds(50), "/bin/a"), window).await.unwrap());
+        assert!(!storage.count_repeat(&mk_exec(1, now + Duration::milliseconds(50), "/bin/b"), window).await.unwrap());
+        assert!(!storage.count_repeat(&mk_exec(1, now + Duration::seconds(1), "/bin/a"), window).await.unwrap());
+        assert_eq!(storage.query(&ExecutionFilter::for_pid(1)).await.unwrap()[0].count, 2);
+    }
+
+    #[tokio::test]
+    async fn clear() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = SqliteExecutionStorage::open(dir.path().join("executions.db"), 7).await.unwrap();
+        storage.add_execution(mk_exec(1, Utc::now(), "/bin/a")).await.unwrap();
+        storage.add_execution(mk_exec(2, Utc::now(), "/bin/b")).await.unwrap();
+        assert_eq!(storage.clear().await.unwrap(), 2);
+        assert_eq!(storage.count().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn get_by_ppid() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = SqliteExecutionStorage::open(dir.path().join("executions.db"), 7).await.unwrap();
+        let now = Utc::now();
+        storage.add_execution(ProcessExecution { ppid: 10, ..mk_exec(11, now, "/bin/ls") }).await.unwrap();
+        storage.add_execution(ProcessExecution { ppid: 20, ..mk_exec(21, now, "/bin/ls") }).await.unwrap();
+        let children = storage.query(&ExecutionFilter { ppid: Some(10), ..Default::default() }).await.unwrap();
+        assert_eq!(children.len(), 1);
+        assert_eq!(children[0].pid, 11);
+    }
+
+    #[tokio::test]
+    async fn stats() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = SqliteExecutionStorage::open(dir.path().join("executions.db"), 7).await.unwrap();
+        assert_eq!(storage.stats().await.unwrap(), ExecutionStats::default());
+
+        let now = Utc::now();
+        storage.add_execution(mk_exec(1, now - Duration::hours(2), "/bin/a")).await.unwrap();
+        storage.add_execution(mk_exec(1, now, "/bin/b")).await.unwrap();
+        storage.add_execution(mk_exec(2, now - Duration::hours(1), "/bin/b")).await.unwrap();
+        let stats = storage.stats().await.unwrap();
+        assert_eq!(stats.total, 3);
+        assert_eq!(stats.unique_pids, 2);
+        assert_eq!(stats.unique_commands, 2);
+        assert_eq!(stats.oldest, Some(now - Duration::hours(2)));
+        assert_eq!(stats.newest, Some(now));
+    }
+
+    #[tokio::test]
+    async fn get_page() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = SqliteExecutionStorage::open(&dir.path().join("executions.db"), 7).await.unwrap();
+        for i in 0..10 {
+            storage.add_execution(mk_exec(i, Utc::now(), "/bin/cmd")).await.unwrap();
+        }
+        let (page, total) = storage.get_executions_page(500, 2, 3, SortOrder::Asc).await.unwrap();
+        assert_eq!(page.iter().map(|e| e.pid).collect::<Vec<_>>(), vec![2, 3, 4]);
+        assert_eq!(total, 10);
+        let (page, _) = storage.get_executions_page(500, 2, 3, SortOrder::Desc).await.unwrap();
+        assert_eq!(page.iter().map(|e| e.pid).collect::<Vec<_>>(), vec![7, 6, 5]);
+        // only the newest `max` rows are paged, like the in-memory store
+        let (page, total) = storage.get_executions_page(4, 0, 100, SortOrder::Asc).await.unwrap();
+        assert_eq!(page.iter().map(|e| e.pid).collect::<Vec<_>>(), vec![6, 7, 8, 9]);
+        assert_eq!(total, 4);
+        let (page, _) = storage.get_executions_page(4, 0, 100, SortOrder::Desc).await.unwrap();
+        assert_eq!(page.iter().map(|e| e.pid).collect::<Vec<_>>(), vec![9, 8, 7, 6]);
+    }
+
+    #[tokio::test]
+    async fn get_by_command() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = SqliteExecutionStorage::open(dir.path().join("executions.db"), 7).await.unwrap();
+        let now = Utc::now();
+        storage.add_execution(mk_exec(1, now, "/bin/bash")).await.unwrap();
+        storage.add_execution(mk_exec(2, now, "/usr/bin/bash")).await.unwrap();
+        let by_command = |command: &str| ExecutionFilter { command: Some(command.to_string()), ..Default::default() };
+        let bash = storage.query(&by_command("/bin/bash")).await.unwrap();
+        assert_eq!(bash.len(), 1);
+        assert_eq!(bash[0].pid, 1);
+        assert!(storage.query(&by_command("/bin/zsh")).await.unwrap().is_empty());
+
+        // scripts match too, by path or file name
+        let script = ProcessExecution { script: Some("./deploy.sh".to_string()), ..mk_exec(3, now, "/bin/bash") };
+        storage.add_execution(script).await.unwrap();
+        for command in ["./deploy.sh", "deploy.sh"] {
+            assert_eq!(storage.query(&by_command(command)).await.unwrap().iter().map(|e| e.pid).collect::<Vec<_>>(), [3], "{command}");
+        }
+        assert_eq!(storage.query(&by_command("/bin/bash")).await.unwrap().len(), 2);
+        assert!(storage.query(&by_command("ploy.sh")).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn get_by_uid() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = SqliteExecutionStorage::open(dir.path().join("executions.db"), 7).await.unwrap();
+        let now = Utc::now();
+        for (pid, uid) in [(1, 1000), (2, 0), (3, 1000)] {
+            storage.add_execution(ProcessExecution { uid, ..mk_exec(pid, now, "/bin/ls") }).await.unwrap();
+        }
+        let by_uid = |uid| ExecutionFilter { uid: Some(uid), ..Default::default() };
+        assert_eq!(storage.query(&by_uid(1000)).await.unwrap().iter().map(|e| e.pid).collect::<Vec<_>>(), vec![1, 3]);
+        assert!(storage.query(&by_uid(1001)).await.unwrap().is_empty());
+    }
+
+    // the WHERE clause selects what ExecutionFilter::matches does, alone and combined
+    #[tokio::test]
+    async fn query_matches_filter() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = SqliteExecutionStorage::open(dir.path().join("executions.db"), 7).await.unwrap();
+        let now = Utc::now();
+        let executions = [
+            ProcessExecution { result: Some(0), setuid: Some(true), ..mk_exec(1, now - Duration::hours(3), "/usr/bin/sudo") },
+            ProcessExecution { uid: 0, result: Some(-2), setuid: Some(false), ..mk_exec(2, now - Duration::hours(2), "/bin/ls") },
+            ProcessExecution { cgroup_id: 7, cgroup_path: None, container_id: None, ..mk_exec(3, now - Duration::hours(1), "/bin/ls") },
+            ProcessExecution { ppid: 3, script: Some("/opt/deploy.sh".to_string()), ..mk_exec(4, now, "/bin/bash") },
+        ];
+        for execution in &executions {
+            storage.add_execution(execution.clone()).await.unwrap();
+        }
+        let some = |value: &str| Some(value.to_string());
+        let filters = [
+            ExecutionFilter::default(),
+            ExecutionFilter::for_pid(2),
+            ExecutionFilter { ppid: Some(3), ..Default::default() },
+            ExecutionFilter { command: some("deploy.sh"), ..Default::default() },
+            ExecutionFilter { command: some("/bin/ls"), uid: Some(1000), ..Default::default() },
+            ExecutionFilter { command_contains: some("/bin/"), failed: Some(false), ..Default::default() },
+            ExecutionFilter { failed: Some(true), ..Default::default() },
+            ExecutionFilter { setuid: Some(true), ..Default::default() },
+            ExecutionFilter { setuid: Some(false), ..Default::default() },
+            ExecutionFilter { cgroup_id: Some(7), ..Default::default() },
+            ExecutionFilter { cgroup_contains: some("docker-"), since: Some(now - Duration::hours(2)), ..Default::default() },
+            ExecutionFilter { container_id: executions[0].container_id.clone(), until: Some(now), ..Default::default() },
+        ];
+        for filter in filters {
+            let expected: Vec<_> = executions.iter().filter(|e| filter.matches(e)).map(|e| e.pid).collect();
+            let pids: Vec<_> = storage.query(&filter).await.unwrap().iter().map(|e| e.pid).collect();
+            assert_eq!(pids, expected, "{filter:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn search() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = SqliteExecutionStorage::open(dir.path().join("executions.db"), 7).await.unwrap();
+        let now = Utc::now();
+        storage.add_execution(mk_exec(1, now, "/usr/bin/curl")).await.unwrap();
+        storage.add_execution(mk_exec(2, now, "/usr/bin/wget")).await.unwrap();
+        let curl = storage.search_executions("CURL").await.unwrap();
+        assert_eq!(curl.len(), 1);
+        assert_eq!(curl[0].pid, 1);
+        assert!(storage.search_executions("ssh").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn get_in_range() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = SqliteExecutionStorage::open(dir.path().join("executions.db"), 7).await.unwrap();
+        let now = Utc::now();
+        for i in 0..5 {
+            storage.add_execution(mk_exec(i, now - Duration::hours(i as i64), "/bin/cmd")).await.unwrap();
+        }
+        let range = ExecutionFilter { since: Some(now - Duration::hours(3)), until: Some(now - Duration::hours(1)), ..Default::default() };
+        let hits = storage.query(&range).await.unwrap();
+        assert_eq!(hits.iter().map(|e| e.pid).collect::<Vec<_>>(), vec![2, 3]);
+    }
+
+    #[tokio::test]
+    async fn prunes_old_rows() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = SqliteExecutionStorage::open(dir.path().join("executions.db"), 1).await.unwrap();
+        // an already expired row is pruned by its own insert
+        assert_eq!(storage.add_execution(mk_exec(1, Utc::now() - Duration::days(2), "/bin/old")).await.unwrap(), 1);
+        assert_eq!(storage.add_execution(mk_exec(2, Utc::now(), "/bin/new")).await.unwrap(), 0);
+        assert_eq!(storage.count().await.unwrap(), 1);
+        let all = storage.get_all_executions(500).await.unwrap();
+        assert_eq!(all.len(), 1);
+        assert_eq!(all[0].pid, 2);
+    }
+}
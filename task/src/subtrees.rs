@@ -0,0 +1,337 @@
+// Process subtrees the probe drops on /exclusions/subtree, e.g. a supervisor and every worker it
+// forks. EXCLUDED_ROOTS holds the roots and task_fork adds children to EXCLUDED_DESCENDANTS as
+// they're forked, the processes already below a root are added from /proc when it's excluded
+use std::collections::HashMap as StdHashMap;
+use std::sync::{Arc, Mutex};
+
+use aya::maps::{HashMap, MapData};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+};
+use serde::{Deserialize, Serialize};
+use task_common::MAX_EXCLUDED_ROOTS;
+use tracing::info;
+use utoipa::ToSchema;
+
+use crate::exclusions::{Added, ExclusionError, Exclusions, PidMap};
+
+// What Subtrees needs from EXCLUDED_DESCENDANTS, pid -> root
+pub trait DescendantMap: Send {
+    fn insert(&mut self, pid: u32, root: u32) -> anyhow::Result<()>;
+    fn remove(&mut self, pid: u32) -> anyhow::Result<()>;
+    fn entries(&self) -> anyhow::Result<Vec<(u32, u32)>>;
+}
+
+impl DescendantMap for HashMap<MapData, u32, u32> {
+    fn insert(&mut self, pid: u32, root: u32) -> anyhow::Result<()> {
+        Ok(HashMap::insert(self, pid, root, 0)?)
+    }
+
+    fn remove(&mut self, pid: u32) -> anyhow::Result<()> {
+        Ok(HashMap::remove(self, &pid)?)
+    }
+
+    fn entries(&self) -> anyhow::Result<Vec<(u32, u32)>> {
+        Ok(self.iter().collect::<Result<_, _>>()?)
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Subtree {
+    pub pid: u32,
+    /// Processes and threads below it the probe tracks
+    // exited ones are removed
+    pub descendants: usize,
+}
+
+#[derive(Clone)]
+pub struct Subtrees {
+    roots: Arc<Mutex<Box<dyn PidMap>>>,
+    descendants: Arc<Mutex<Box<dyn DescendantMap>>>,
+}
+
+impl Subtrees {
+    pub fn new(roots: impl PidMap + 'static, descendants: impl DescendantMap + 'static) -> Self {
+        Self { roots: Arc::new(Mutex::new(Box::new(roots))), descendants: Arc::new(Mutex::new(Box::new(descendants))) }
+    }
+
+    // A root that exits while it's being added is taken out again, its pid could be handed out next
+    pub fn exclude(&self, root: u32) -> Result<(Added, Subtree), ExclusionError> {
+        let (added, subtree) = self.exclude_in(root, &process_table())?;
+        if added == Added::New && !crate::exclusions::is_process(root) {
+            self.include(root)?;
+            return Err(ExclusionError::NoProcess(root));
+        }
+        Ok((added, subtree))
+    }
+
+    // `processes` is (pid, ppid) of every running process. A process forked by one that isn't
+    // added yet while this runs can be missed, the ones forked after are added by the probe
+    fn exclude_in(&self, root: u32, processes: &[(u32, u32)]) -> Result<(Added, Subtree), ExclusionError> {
+        if !processes.iter().any(|&(pid, _)| pid == root) {
+            return Err(ExclusionError::NoProcess(root));
+        }
+        let mut roots = self.roots.lock().unwrap();
+        let existing = roots.pids().map_err(ExclusionError::Map)?;
+        if existing.contains(&root) {
+            drop(roots);
+            let descendants = self.count(root)?;
+            return Ok((Added::Existing, Subtree { pid: root, descendants }));
+        }
+        if existing.len() >= MAX_EXCLUDED_ROOTS {
+            return Err(ExclusionError::SubtreesFull(MAX_EXCLUDED_ROOTS));
+        }
+        let mut map = self.descendants.lock().unwrap();
+        // left from an earlier process with this pid, they'd count again once it's a root
+        purge(map.as_mut(), root)?;
+        // the root first, so the probe adds what it forks from here on
+        roots.insert(root).map_err(ExclusionError::Map)?;
+        let descendants = descendants_of(root, processes);
+        for &pid in &descendants {
+            map.insert(pid, root).map_err(ExclusionError::Map)?;
+        }
+        Ok((Added::New, Subtree { pid: root, descendants: descendants.len() }))
+    }
+
+    pub fn include(&self, root: u32) -> Result<(), ExclusionError> {
+        let mut roots = self.roots.lock().unwrap();
+        if !roots.pids().map_err(ExclusionError::Map)?.contains(&root) {
+            return Err(ExclusionError::NotFound);
+        }
+        roots.remove(root).map_err(ExclusionError::Map)?;
+        // already ignored by the probe without their root, this only frees the entries
+        purge(self.descendants.lock().unwrap().as_mut(), root)
+    }
+
+    pub fn list(&self) -> Result<Vec<Subtree>, ExclusionError> {
+        let mut roots = self.roots.lock().unwrap().pids().map_err(ExclusionError::Map)?;
+        roots.sort();
+        let entries = self.descendants.lock().unwrap().entries().map_err(ExclusionError::Map)?;
+        Ok(roots
+            .into_iter()
+            .map(|pid| Subtree { pid, descendants: entries.iter().filter(|&&(_, root)| root == pid).count() })
+            .collect())
+    }
+
+    fn count(&self, root: u32) -> Result<usize, ExclusionError> {
+        let entries = self.descendants.lock().unwrap().entries().map_err(ExclusionError::Map)?;
+        Ok(entries.iter().filter(|&&(_, r)| r == root).count())
+    }
+}
+
+fn purge(map: &mut dyn DescendantMap, root: u32) -> Result<(), ExclusionError> {
+    for (pid, r) in map.entries().map_err(ExclusionError::Map)? {
+        if r == root {
+            map.remove(pid).map_err(ExclusionError::Map)?;
+        }
+    }
+    Ok(())
+}
+
+// Every process below `root`, sorted
+fn descendants_of(root: u32, processes: &[(u32, u32)]) -> Vec<u32> {
+    let mut children: StdHashMap<u32, Vec<u32>> = StdHashMap::new();
+    for &(pid, ppid) in processes {
+        children.entry(ppid).or_default().push(pid);
+    }
+    let mut found = Vec::new();
+    let mut pending = vec![root];
+    while let Some(parent) = pending.pop() {
+        for &child in children.get(&parent).into_iter().flatten() {
+            // pid 0 is the parent of init and kthreadd, guards against a cycle through it
+            if child != root && child != 0 {
+                found.push(child);
+                pending.push(child);
+            }
+        }
+    }
+    found.sort();
+    found
+}
+
+// (pid, ppid) of every process in /proc, ones that exit while it's read are skipped
+fn process_table() -> Vec<(u32, u32)> {
+    let Ok(entries) = std::fs::read_dir("/proc") else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse::<u32>().ok())
+        .filter_map(|pid| Some((pid, parent_pid(&std::fs::read_to_string(format!("/proc/{pid}/stat")).ok()?)?)))
+        .collect()
+}
+
+// The 4th field of /proc/<pid>/stat. The command before it is in parentheses and may contain
+// spaces or `)`, so the fields are counted from the last `)`
+fn parent_pid(stat: &str) -> Option<u32> {
+    let (_, fields) = stat.rsplit_once(')')?;
+    fields.split_whitespace().nth(1)?.parse().ok()
+}
+
+/// Processes the probe drops along with everything they fork
+#[utoipa::path(
+    get,
+    path = "/exclusions/subtree",
+    tag = "exclusions",
+    responses((status = 200, description = "The roots, sorted, with how many descendants are tracked", body = [Subtree]))
+)]
+pub async fn list_subtree_exclusions(State(exclusions): State<Exclusions>) -> Result<Json<Vec<Subtree>>, ExclusionError> {
+    exclusions.subtrees().list().map(Json)
+}
+
+/// Exclude a running process and all its descendants until it exits
+// 201 for a new exclusion, 200 if it was already there, 404 without a running process
+#[utoipa::path(
+    post,
+    path = "/exclusions/subtree/{pid}",
+    tag = "exclusions",
+    params(("pid" = u32, Path, description = "Process id (tgid) of the root")),
+    responses(
+        (status = 200, description = "Already excluded", body = Subtree),
+        (status = 201, description = "Excluded, with the descendants found in /proc", body = Subtree),
+        (status = 404, description = "No running process with that pid", body = ErrorBody),
+        (status = 507, description = "The subtree exclusion map is full", body = ErrorBody),
+    )
+)]
+pub async fn add_subtree_exclusion(State(exclusions): State<Exclusions>, Path(pid): Path<u32>) -> Result<Response, ExclusionError> {
+    let (added, subtree) = exclusions.subtrees().exclude(pid)?;
+    let status = match added {
+        Added::New => {
+            info!("Excluding pid {} and its {} descendants", pid, subtree.descendants);
+            StatusCode::CREATED
+        }
+        Added::Existing => StatusCode::OK,
+    };
+    Ok((status, Json(subtree)).into_response())
+}
+
+/// Stop excluding a subtree
+#[utoipa::path(
+    delete,
+    path = "/exclusions/subtree/{pid}",
+    tag = "exclusions",
+    params(("pid" = u32, Path, description = "Process id (tgid) of the root")),
+    responses((status = 204, description = "No longer excluded"), (status = 404, description = "Not excluded"))
+)]
+pub async fn remove_subtree_exclusion(State(exclusions): State<Exclusions>, Path(pid): Path<u32>) -> Result<StatusCode, ExclusionError> {
+    exclusions.subtrees().include(pid)?;
+    info!("No longer excluding the subtree of pid {}", pid);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// Stands in for EXCLUDED_DESCENDANTS in tests
+#[cfg(test)]
+impl DescendantMap for std::collections::BTreeMap<u32, u32> {
+    fn insert(&mut self, pid: u32, root: u32) -> anyhow::Result<()> {
+        std::collections::BTreeMap::insert(self, pid, root);
+        Ok(())
+    }
+
+    fn remove(&mut self, pid: u32) -> anyhow::Result<()> {
+        std::collections::BTreeMap::remove(self, &pid);
+        Ok(())
+    }
+
+    fn entries(&self) -> anyhow::Result<Vec<(u32, u32)>> {
+        Ok(self.iter().map(|(&pid, &root)| (pid, root)).collect())
+    }
+}
+
+#[cfg(test)]
+impl Subtrees {
+    pub fn in_memory() -> Self {
+        Self::new(std::collections::BTreeSet::new(), std::collections::BTreeMap::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The (pid, ppid) table after running `forks` (parent, child) in order from init
+    fn after(forks: &[(u32, u32)]) -> Vec<(u32, u32)> {
+        let mut table = vec![(1, 0)];
+        table.extend(forks.iter().map(|&(parent, child)| (child, parent)));
+        table
+    }
+
+    #[test]
+    fn descendants_from_forks() {
+        // a supervisor (10) forks two workers, one of them a helper, next to an unrelated 20
+        let table = after(&[(1, 10), (10, 11), (10, 12), (11, 13), (1, 20), (20, 21)]);
+        assert_eq!(descendants_of(10, &table), vec![11, 12, 13]);
+        assert_eq!(descendants_of(11, &table), vec![13]);
+        assert!(descendants_of(13, &table).is_empty());
+        assert_eq!(descendants_of(1, &table), vec![10, 11, 12, 13, 20, 21]);
+    }
+
+    #[test]
+    fn stat_parent() {
+        assert_eq!(parent_pid("1234 (gzip) S 1200 1234 1200 0 -1"), Some(1200));
+        assert_eq!(parent_pid("99 (odd ) name) R 1 99 99"), Some(1));
+        assert_eq!(parent_pid("99 (truncated"), None);
+    }
+
+    #[test]
+    fn exclude_and_include() {
+        let subtrees = Subtrees::in_memory();
+        let table = after(&[(1, 10), (10, 11), (11, 12), (1, 20)]);
+        let (added, subtree) = subtrees.exclude_in(10, &table).unwrap();
+        assert_eq!((added, subtree), (Added::New, Subtree { pid: 10, descendants: 2 }));
+        assert_eq!(subtrees.exclude_in(10, &table).unwrap().0, Added::Existing);
+
+        // what the probe does when 12 forks a worker: the child gets its parent's root
+        subtrees.descendants.lock().unwrap().insert(13, 10).unwrap();
+        assert_eq!(subtrees.list().unwrap(), vec![Subtree { pid: 10, descendants: 3 }]);
+
+        assert!(matches!(subtrees.exclude_in(30, &table), Err(ExclusionError::NoProcess(30))));
+        subtrees.include(10).unwrap();
+        assert!(subtrees.list().unwrap().is_empty());
+        assert!(subtrees.descendants.lock().unwrap().entries().unwrap().is_empty());
+        assert!(matches!(subtrees.include(10), Err(ExclusionError::NotFound)));
+    }
+
+    #[test]
+    fn reused_root_starts_over() {
+        let subtrees = Subtrees::in_memory();
+        subtrees.exclude_in(10, &after(&[(1, 10), (10, 11)])).unwrap();
+        // 10 exited (the probe drops it from the roots, not its descendants) and the pid came back
+        subtrees.roots.lock().unwrap().remove(10).unwrap();
+        let (_, subtree) = subtrees.exclude_in(10, &after(&[(1, 10), (10, 15)])).unwrap();
+        assert_eq!(subtree.descendants, 1);
+        assert_eq!(subtrees.descendants.lock().unwrap().entries().unwrap(), vec![(15, 10)]);
+    }
+
+    #[test]
+    fn bounded_roots() {
+        let subtrees = Subtrees::in_memory();
+        let table: Vec<(u32, u32)> = (100..100 + MAX_EXCLUDED_ROOTS as u32 + 1).map(|pid| (pid, 1)).collect();
+        for &(pid, _) in &table[..MAX_EXCLUDED_ROOTS] {
+            subtrees.exclude_in(pid, &table).unwrap();
+        }
+        let last = table[MAX_EXCLUDED_ROOTS].0;
+        assert!(matches!(subtrees.exclude_in(last, &table), Err(ExclusionError::SubtreesFull(_))));
+    }
+
+    #[tokio::test]
+    async fn running_processes() {
+        let exclusions = Exclusions::in_memory(10);
+        let mut child = std::process::Command::new("sleep").arg("10").spawn().unwrap();
+        let response = add_subtree_exclusion(State(exclusions.clone()), Path(std::process::id())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        // the sleep started by this test is below it
+        let listed = exclusions.subtrees().list().unwrap();
+        assert!(listed[0].descendants >= 1, "{listed:?}");
+        assert!(exclusions.subtrees().descendants.lock().unwrap().entries().unwrap().contains(&(child.id(), std::process::id())));
+        let again = add_subtree_exclusion(State(exclusions.clone()), Path(std::process::id())).await.unwrap();
+        assert_eq!(again.status(), StatusCode::OK);
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+        let gone = add_subtree_exclusion(State(exclusions.clone()), Path(child.id())).await.unwrap_err();
+        assert_eq!(gone.into_response().status(), StatusCode::NOT_FOUND);
+        let removed = remove_subtree_exclusion(State(exclusions.clone()), Path(std::process::id())).await.unwrap();
+        assert_eq!(removed, StatusCode::NO_CONTENT);
+    }
+}
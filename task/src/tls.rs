@@ -0,0 +1,71 @@
+// HTTPS for the HTTP API with --tls-cert and --tls-key, reloaded from the same files on SIGHUP
+use std::path::PathBuf;
+
+use anyhow::Context;
+use axum_server::tls_rustls::RustlsConfig;
+use tokio::signal::unix::{SignalKind, signal};
+use tracing::{error, info};
+
+#[derive(Debug, Clone)]
+pub struct TlsFiles {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+impl TlsFiles {
+    // None for plain HTTP, a certificate without its key (or the other way around) is an error
+    pub fn new(cert: Option<PathBuf>, key: Option<PathBuf>) -> anyhow::Result<Option<Self>> {
+        match (cert, key) {
+            (Some(cert), Some(key)) => Ok(Some(Self { cert, key })),
+            (None, None) => Ok(None),
+            (Some(_), None) => anyhow::bail!("--tls-cert needs --tls-key"),
+            (None, Some(_)) => anyhow::bail!("--tls-key needs --tls-cert"),
+        }
+    }
+
+    // PEM certificate chain and private key (PKCS#8, PKCS#1 or SEC1)
+    pub async fn load(&self) -> anyhow::Result<RustlsConfig> {
+        RustlsConfig::from_pem_file(&self.cert, &self.key).await.with_context(|| {
+            format!("failed to load TLS certificate {} and key {}", self.cert.display(), self.key.display())
+        })
+    }
+
+    // Swaps in the files' current contents on every SIGHUP, e.g. after a renewal. New connections
+    // get the new certificate, a failed reload keeps serving the old one
+    pub fn reload_on_sighup(self, config: RustlsConfig) -> anyhow::Result<()> {
+        let mut hangup = signal(SignalKind::hangup()).context("failed to listen for SIGHUP")?;
+        tokio::spawn(async move {
+            while hangup.recv().await.is_some() {
+                match config.reload_from_pem_file(&self.cert, &self.key).await {
+                    Ok(()) => info!("Reloaded TLS certificate {}", self.cert.display()),
+                    Err(e) => error!("Keeping the current TLS certificate, reloading {} failed: {}", self.cert.display(), e),
+                }
+            }
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn both_or_neither() {
+        assert!(TlsFiles::new(None, None).unwrap().is_none());
+        let files = TlsFiles::new(Some("cert.pem".into()), Some("key.pem".into())).unwrap().unwrap();
+        assert_eq!(files.key, PathBuf::from("key.pem"));
+        let err = TlsFiles::new(Some("cert.pem".into()), None).unwrap_err();
+        assert!(err.to_string().contains("--tls-key"), "{err}");
+        let err = TlsFiles::new(None, Some("key.pem".into())).unwrap_err();
+        assert!(err.to_string().contains("--tls-cert"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn load_errors_name_the_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = TlsFiles { cert: dir.path().join("cert.pem"), key: dir.path().join("key.pem") };
+        let err = files.load().await.unwrap_err();
+        assert!(format!("{err}").contains("cert.pem"), "{err}");
+    }
+}